@@ -0,0 +1,311 @@
+//! Scheduled database backups
+//!
+//! Backups are single SQLite files named `jlivertool-YYYYMMDD-HHMMSS.db`,
+//! written with the online backup API while messages keep being stored.
+//! After each backup the oldest files beyond the configured count are removed.
+
+use crate::database::Database;
+use anyhow::Result;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+/// Delay of the first scheduled backup after startup
+const STARTUP_DELAY: Duration = Duration::from_secs(120);
+
+const FILE_PREFIX: &str = "jlivertool-";
+const FILE_EXTENSION: &str = "db";
+
+/// Backup settings
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupConfig {
+    /// Back up on a schedule
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Hours between scheduled backups
+    #[serde(default = "default_interval_hours")]
+    pub interval_hours: u32,
+
+    /// Backups kept, older ones are removed, 0 keeps all
+    #[serde(default = "default_keep")]
+    pub keep: u32,
+
+    /// Directory of the backups, empty for `backups` in the data directory
+    #[serde(default)]
+    pub dir: String,
+}
+
+fn default_interval_hours() -> u32 {
+    24
+}
+
+fn default_keep() -> u32 {
+    7
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_hours: default_interval_hours(),
+            keep: default_keep(),
+            dir: String::new(),
+        }
+    }
+}
+
+impl BackupConfig {
+    /// Directory of the backups, `default_dir` if none is set
+    pub fn dir_or(&self, default_dir: &Path) -> PathBuf {
+        if self.dir.trim().is_empty() {
+            default_dir.to_path_buf()
+        } else {
+            PathBuf::from(self.dir.trim())
+        }
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_hours.max(1) as u64 * 60 * 60)
+    }
+}
+
+/// A backup file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupFile {
+    pub path: PathBuf,
+    pub size: u64,
+    /// Unix timestamp of the last modification
+    pub created: i64,
+}
+
+impl BackupFile {
+    pub fn file_name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+}
+
+/// Backups in `dir`, newest first
+pub fn list_backups(dir: &Path) -> Result<Vec<BackupFile>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut backups = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_backup = path.extension().is_some_and(|ext| ext == FILE_EXTENSION)
+            && path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with(FILE_PREFIX));
+        if !is_backup {
+            continue;
+        }
+        let metadata = std::fs::metadata(&path)?;
+        let created = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+        backups.push(BackupFile {
+            path,
+            size: metadata.len(),
+            created,
+        });
+    }
+    // Names contain the time, so they also order backups copied from elsewhere
+    backups.sort_by(|a, b| b.path.file_stem().cmp(&a.path.file_stem()));
+    Ok(backups)
+}
+
+/// Back up `database` into `dir`, then remove all but the newest `keep` backups
+pub fn create_backup(database: &Database, dir: &Path, keep: u32) -> Result<PathBuf> {
+    let stem = format!(
+        "{}{}",
+        FILE_PREFIX,
+        chrono::Local::now().format("%Y%m%d-%H%M%S")
+    );
+    // Never overwrite a backup made in the same second
+    let path = (0..)
+        .map(|n| match n {
+            0 => dir.join(format!("{}.{}", stem, FILE_EXTENSION)),
+            n => dir.join(format!("{}-{}.{}", stem, n, FILE_EXTENSION)),
+        })
+        .find(|path| !path.exists())
+        .expect("unbounded range");
+    database.backup_to(&path)?;
+    info!("Database backed up to {}", path.display());
+
+    if keep > 0 {
+        for old in list_backups(dir)?.into_iter().skip(keep as usize) {
+            if let Err(e) = std::fs::remove_file(&old.path) {
+                warn!("Failed to remove old backup {}: {}", old.path.display(), e);
+            }
+        }
+    }
+    Ok(path)
+}
+
+/// Called with the result of every backup
+pub type BackupCallback = Box<dyn Fn(Result<PathBuf>) + Send + Sync>;
+
+enum BackupCommand {
+    Reconfigure(BackupConfig),
+    RunNow,
+}
+
+/// Reconfigure or trigger the backup worker
+#[derive(Clone)]
+pub struct BackupHandle {
+    config: Arc<RwLock<BackupConfig>>,
+    default_dir: PathBuf,
+    tx: mpsc::UnboundedSender<BackupCommand>,
+}
+
+impl BackupHandle {
+    pub fn config(&self) -> BackupConfig {
+        self.config.read().clone()
+    }
+
+    /// Directory backups are currently written to
+    pub fn dir(&self) -> PathBuf {
+        self.config.read().dir_or(&self.default_dir)
+    }
+
+    /// Replace the settings, the schedule restarts from the latest backup
+    pub fn reconfigure(&self, config: BackupConfig) {
+        *self.config.write() = config.clone();
+        let _ = self.tx.send(BackupCommand::Reconfigure(config));
+    }
+
+    /// Back up now, also when scheduled backups are disabled
+    pub fn run_now(&self) {
+        let _ = self.tx.send(BackupCommand::RunNow);
+    }
+}
+
+/// Makes scheduled and requested backups, one at a time
+pub struct BackupWorker {
+    database: Database,
+    config: BackupConfig,
+    default_dir: PathBuf,
+    rx: mpsc::UnboundedReceiver<BackupCommand>,
+    on_result: BackupCallback,
+}
+
+/// Create a handle and the worker it feeds, backups go to `default_dir`
+/// unless the config names a directory
+pub fn channel(
+    database: Database,
+    config: BackupConfig,
+    default_dir: PathBuf,
+    on_result: BackupCallback,
+) -> (BackupHandle, BackupWorker) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let handle = BackupHandle {
+        config: Arc::new(RwLock::new(config.clone())),
+        default_dir: default_dir.clone(),
+        tx,
+    };
+    let worker = BackupWorker {
+        database,
+        config,
+        default_dir,
+        rx,
+        on_result,
+    };
+    (handle, worker)
+}
+
+impl BackupWorker {
+    /// Run until all handles are dropped
+    pub async fn run(mut self) {
+        let mut next = self.next_backup();
+        loop {
+            let wait = async {
+                match next {
+                    Some(at) => tokio::time::sleep_until(at).await,
+                    None => std::future::pending().await,
+                }
+            };
+            tokio::select! {
+                command = self.rx.recv() => match command {
+                    Some(BackupCommand::Reconfigure(config)) => self.config = config,
+                    Some(BackupCommand::RunNow) => self.run_once().await,
+                    None => break,
+                },
+                _ = wait => self.run_once().await,
+            }
+            next = self.next_backup();
+        }
+    }
+
+    /// Time of the next scheduled backup, one interval after the latest
+    /// backup so restarts don't reset the schedule
+    fn next_backup(&self) -> Option<tokio::time::Instant> {
+        if !self.config.enabled {
+            return None;
+        }
+        let now = tokio::time::Instant::now();
+        let dir = self.config.dir_or(&self.default_dir);
+        let since_latest = list_backups(&dir)
+            .ok()
+            .and_then(|backups| backups.first().map(|b| b.created))
+            .map(|created| (chrono::Utc::now().timestamp() - created).max(0) as u64)
+            .map(Duration::from_secs);
+        let wait = match since_latest {
+            Some(elapsed) => self.config.interval().saturating_sub(elapsed),
+            None => Duration::ZERO,
+        };
+        Some(now + wait.max(STARTUP_DELAY))
+    }
+
+    async fn run_once(&self) {
+        let database = self.database.clone();
+        let dir = self.config.dir_or(&self.default_dir);
+        let keep = self.config.keep;
+        let result = tokio::task::spawn_blocking(move || create_backup(&database, &dir, keep))
+            .await
+            .unwrap_or_else(|e| Err(e.into()));
+        (self.on_result)(result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backup_rotation() {
+        let dir = std::env::temp_dir().join(format!("jlivertool-backups-{}", uuid::Uuid::new_v4()));
+        let database = Database::in_memory().unwrap();
+        assert!(list_backups(&dir).unwrap().is_empty());
+
+        // Older backups, as if made on earlier days
+        std::fs::create_dir_all(&dir).unwrap();
+        for day in 1..=3 {
+            database
+                .backup_to(&dir.join(format!("jlivertool-2024010{}-000000.db", day)))
+                .unwrap();
+        }
+        std::fs::write(dir.join("notes.txt"), "kept").unwrap();
+        let first = create_backup(&database, &dir, 2).unwrap();
+        let second = create_backup(&database, &dir, 2).unwrap();
+        assert_ne!(first, second);
+
+        let backups = list_backups(&dir).unwrap();
+        assert_eq!(backups.len(), 2);
+        assert_eq!(backups[0].path, second);
+        assert_eq!(backups[1].path, first);
+        assert!(backups[0].size > 0);
+        assert!(dir.join("notes.txt").exists());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
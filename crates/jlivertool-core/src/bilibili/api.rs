@@ -1,10 +1,11 @@
 //! Bilibili REST API client
 
 use crate::bilibili::wbi::WbiSigner;
+use crate::network::SharedClient;
 use crate::types::{Cookies, RoomId};
 use anyhow::{anyhow, Result};
-use reqwest::{header, Client};
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use reqwest::header;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Duration;
@@ -12,6 +13,51 @@ use std::time::Duration;
 /// Base URLs
 const LIVE_API_BASE: &str = "https://api.live.bilibili.com";
 const WEB_API_BASE: &str = "https://api.bilibili.com";
+const PASSPORT_BASE: &str = "https://passport.bilibili.com";
+
+/// Base URLs of the Bilibili services, replaced to talk to a local fake
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoints {
+    pub live_api: String,
+    pub web_api: String,
+    pub passport: String,
+    /// Connect to danmu hosts at `wss://{host}:{wss_port}/sub`,
+    /// otherwise at `ws://{host}:{ws_port}/sub`
+    pub danmu_tls: bool,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            live_api: LIVE_API_BASE.to_string(),
+            web_api: WEB_API_BASE.to_string(),
+            passport: PASSPORT_BASE.to_string(),
+            danmu_tls: true,
+        }
+    }
+}
+
+impl Endpoints {
+    /// Every service on one server without TLS
+    pub fn local(base: &str) -> Self {
+        let base = base.trim_end_matches('/').to_string();
+        Self {
+            live_api: base.clone(),
+            web_api: base.clone(),
+            passport: base,
+            danmu_tls: false,
+        }
+    }
+
+    /// WebSocket address of a danmu host
+    pub fn danmu_ws_url(&self, host: &DanmuHost) -> String {
+        if self.danmu_tls {
+            format!("wss://{}:{}/sub", host.host, host.wss_port)
+        } else {
+            format!("ws://{}:{}/sub", host.host, host.ws_port)
+        }
+    }
+}
 
 /// Common API response wrapper
 #[derive(Debug, Deserialize)]
@@ -89,6 +135,68 @@ pub struct RoomInfoData {
     pub description: String,
 }
 
+/// Room play info response, `playurl_info` is missing while the room is offline
+#[derive(Debug, Deserialize)]
+pub struct PlayInfoData {
+    pub room_id: u64,
+    pub live_status: u8,
+    #[serde(default)]
+    pub playurl_info: Option<PlayUrlInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlayUrlInfo {
+    pub playurl: PlayUrl,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlayUrl {
+    #[serde(default)]
+    pub stream: Vec<PlayStream>,
+}
+
+/// Streams of one protocol, "http_stream" (FLV) or "http_hls"
+#[derive(Debug, Deserialize)]
+pub struct PlayStream {
+    pub protocol_name: String,
+    #[serde(default)]
+    pub format: Vec<PlayFormat>,
+}
+
+/// Container of a stream: "flv", "ts" or "fmp4"
+#[derive(Debug, Deserialize)]
+pub struct PlayFormat {
+    pub format_name: String,
+    #[serde(default)]
+    pub codec: Vec<PlayCodec>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlayCodec {
+    /// "avc" or "hevc"
+    pub codec_name: String,
+    pub current_qn: u32,
+    pub base_url: String,
+    #[serde(default)]
+    pub url_info: Vec<PlayUrlHost>,
+}
+
+impl PlayCodec {
+    /// Full stream URLs, one per CDN host
+    pub fn urls(&self) -> Vec<String> {
+        self.url_info
+            .iter()
+            .map(|info| format!("{}{}{}", info.host, self.base_url, info.extra))
+            .collect()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlayUrlHost {
+    pub host: String,
+    pub extra: String,
+}
+
 /// Danmu info (WebSocket connection info)
 #[derive(Debug, Deserialize)]
 pub struct DanmuInfoData {
@@ -123,7 +231,7 @@ pub struct GiftConfigData {
 }
 
 /// Online gold rank item (from queryContributionRank API)
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OnlineGoldRankItem {
     pub uid: u64,
     #[serde(default)]
@@ -140,7 +248,7 @@ pub struct OnlineGoldRankItem {
     pub medal_info: Option<OnlineRankMedalInfo>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OnlineRankMedalInfo {
     #[serde(default)]
     pub guard_level: u8,
@@ -159,7 +267,7 @@ pub struct OnlineGoldRankData {
 }
 
 /// Guard list item
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GuardListItem {
     pub uid: u64,
     #[serde(default)]
@@ -174,7 +282,7 @@ pub struct GuardListItem {
     pub medal_info: Option<GuardMedalInfo>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GuardMedalInfo {
     #[serde(default)]
     pub medal_name: String,
@@ -203,6 +311,54 @@ pub struct GuardListInfo {
     pub now: u32,
 }
 
+/// Blocked (muted) user in a room
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SilentUserItem {
+    /// UID of the blocked user
+    #[serde(default)]
+    pub tuid: u64,
+    #[serde(default)]
+    pub tname: String,
+    /// UID of the operator
+    #[serde(default)]
+    pub uid: u64,
+    #[serde(default)]
+    pub name: String,
+    /// Time the block was created, e.g. "2024-01-01 20:00:00"
+    #[serde(default)]
+    pub ctime: String,
+    /// Time the block expires, empty for permanent blocks
+    #[serde(default)]
+    pub block_end_time: String,
+}
+
+/// Blocked user list response
+#[derive(Debug, Deserialize)]
+pub struct SilentUserListData {
+    #[serde(default)]
+    pub data: Vec<SilentUserItem>,
+    #[serde(default)]
+    pub total: u64,
+    #[serde(default)]
+    pub total_page: u32,
+}
+
+/// Room-wide silence mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoomSilence {
+    /// Lift the room silence
+    Off,
+    /// Only users with at least this user level can send danmu
+    Level(u32),
+    /// Only users with at least this fan medal level can send danmu
+    Medal(u32),
+}
+
+/// Block duration lasting until the current live ends
+pub const BLOCK_THIS_LIVE: i32 = 0;
+/// Permanent user block
+pub const BLOCK_PERMANENT: i32 = -1;
+
 /// Nav response (for login status and WBI keys)
 #[derive(Debug, Deserialize)]
 pub struct NavData {
@@ -221,7 +377,7 @@ pub struct WbiImg {
 }
 
 /// User info response
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserInfoData {
     pub mid: u64,
     pub name: String,
@@ -245,7 +401,7 @@ pub struct UserInfoData {
 }
 
 /// User official verification info
-#[derive(Debug, Clone, Default, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct UserOfficialInfo {
     #[serde(default)]
     pub role: u8,
@@ -258,7 +414,7 @@ pub struct UserOfficialInfo {
 }
 
 /// User VIP info
-#[derive(Debug, Clone, Default, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct UserVipInfo {
     #[serde(rename = "type", default)]
     pub vip_type: u8,
@@ -269,7 +425,7 @@ pub struct UserVipInfo {
 }
 
 /// User VIP label
-#[derive(Debug, Clone, Default, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct UserVipLabel {
     #[serde(default)]
     pub text: String,
@@ -278,7 +434,7 @@ pub struct UserVipLabel {
 }
 
 /// User live room info
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserLiveRoom {
     #[serde(default)]
     pub roomid: u64,
@@ -292,6 +448,106 @@ pub struct UserLiveRoom {
     pub cover: String,
 }
 
+/// Area ids are numbers on parent areas and strings on child areas
+fn de_area_id<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Id {
+        Number(u64),
+        Text(String),
+    }
+    match Id::deserialize(deserializer)? {
+        Id::Number(id) => Ok(id),
+        Id::Text(id) => id.parse().map_err(serde::de::Error::custom),
+    }
+}
+
+/// A live area (分区), `id` is the `area_v2` to start live with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LiveArea {
+    #[serde(deserialize_with = "de_area_id")]
+    pub id: u64,
+    #[serde(deserialize_with = "de_area_id")]
+    pub parent_id: u64,
+    pub name: String,
+    #[serde(default)]
+    pub parent_name: String,
+    #[serde(default)]
+    pub pinyin: String,
+}
+
+/// A parent area with its child areas
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LiveAreaGroup {
+    #[serde(deserialize_with = "de_area_id")]
+    pub id: u64,
+    pub name: String,
+    #[serde(default)]
+    pub list: Vec<LiveArea>,
+}
+
+/// All live areas, grouped by parent area
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AreaList {
+    pub groups: Vec<LiveAreaGroup>,
+}
+
+impl AreaList {
+    /// Child area with the given id
+    pub fn find(&self, id: u64) -> Option<&LiveArea> {
+        self.groups
+            .iter()
+            .flat_map(|group| group.list.iter())
+            .find(|area| area.id == id)
+    }
+
+    /// Child areas matching `query` by name, pinyin or parent name
+    /// Exact and prefix name matches come first, an empty query matches all
+    pub fn search(&self, query: &str) -> Vec<&LiveArea> {
+        let query = query.trim().to_lowercase();
+        let mut matches: Vec<(u8, &LiveArea)> = self
+            .groups
+            .iter()
+            .flat_map(|group| group.list.iter())
+            .filter_map(|area| {
+                if query.is_empty() {
+                    return Some((0, area));
+                }
+                let name = area.name.to_lowercase();
+                let rank = if name == query {
+                    0
+                } else if name.starts_with(&query) {
+                    1
+                } else if name.contains(&query) {
+                    2
+                } else if area.pinyin.to_lowercase().contains(&query) {
+                    3
+                } else if area.parent_name.to_lowercase().contains(&query) {
+                    4
+                } else {
+                    return None;
+                };
+                Some((rank, area))
+            })
+            .collect();
+        matches.sort_by_key(|(rank, _)| *rank);
+        matches.into_iter().map(|(_, area)| area).collect()
+    }
+}
+
+/// Room announcement response
+#[derive(Debug, Deserialize)]
+pub struct RoomNewsData {
+    #[serde(default)]
+    pub content: String,
+}
+
+/// Uploaded image response
+#[derive(Debug, Deserialize)]
+pub struct UploadImageData {
+    pub location: String,
+}
+
 /// QR code login response
 #[derive(Debug, Deserialize)]
 pub struct QrCodeData {
@@ -309,7 +565,8 @@ pub struct QrCodePollData {
 }
 
 /// QR code status
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum QrCodeStatus {
     /// Waiting for scan
     NeedScan,
@@ -326,14 +583,51 @@ pub enum QrCodeStatus {
 /// Bilibili API client
 #[derive(Clone)]
 pub struct BiliApi {
-    client: Client,
+    client: SharedClient,
     cookies: Option<Cookies>,
     wbi_signer: Arc<WbiSigner>,
+    endpoints: Arc<Endpoints>,
 }
 
-impl BiliApi {
-    /// Create a new API client
-    pub fn new() -> Result<Self> {
+/// Builder of a `BiliApi` with custom endpoints
+#[derive(Debug, Default)]
+pub struct BiliApiBuilder {
+    endpoints: Endpoints,
+}
+
+impl BiliApiBuilder {
+    /// Replace all endpoints
+    pub fn endpoints(mut self, endpoints: Endpoints) -> Self {
+        self.endpoints = endpoints;
+        self
+    }
+
+    /// Base URL of api.live.bilibili.com
+    pub fn live_api(mut self, url: impl Into<String>) -> Self {
+        self.endpoints.live_api = url.into();
+        self
+    }
+
+    /// Base URL of api.bilibili.com
+    pub fn web_api(mut self, url: impl Into<String>) -> Self {
+        self.endpoints.web_api = url.into();
+        self
+    }
+
+    /// Base URL of passport.bilibili.com
+    pub fn passport(mut self, url: impl Into<String>) -> Self {
+        self.endpoints.passport = url.into();
+        self
+    }
+
+    /// Whether danmu hosts are connected with TLS
+    pub fn danmu_tls(mut self, tls: bool) -> Self {
+        self.endpoints.danmu_tls = tls;
+        self
+    }
+
+    /// Create the client
+    pub fn build(self) -> Result<BiliApi> {
         let mut headers = header::HeaderMap::new();
         headers.insert(
             header::USER_AGENT,
@@ -346,20 +640,39 @@ impl BiliApi {
             header::HeaderValue::from_static("https://live.bilibili.com/"),
         );
 
-        let client = Client::builder()
-            .default_headers(headers)
-            // Connection pool configuration for better performance
-            .pool_max_idle_per_host(5)
-            .pool_idle_timeout(Duration::from_secs(90))
-            .tcp_keepalive(Duration::from_secs(60))
-            .build()?;
+        let client = SharedClient::new(move |builder| {
+            builder
+                .default_headers(headers.clone())
+                // Connection pool configuration for better performance
+                .pool_max_idle_per_host(5)
+                .pool_idle_timeout(Duration::from_secs(90))
+                .tcp_keepalive(Duration::from_secs(60))
+        })?;
 
-        Ok(Self {
+        Ok(BiliApi {
             client,
             cookies: None,
             wbi_signer: Arc::new(WbiSigner::new()),
+            endpoints: Arc::new(self.endpoints),
         })
     }
+}
+
+impl BiliApi {
+    /// Create a new API client for bilibili.com
+    pub fn new() -> Result<Self> {
+        Self::builder().build()
+    }
+
+    /// Start building a client with custom endpoints
+    pub fn builder() -> BiliApiBuilder {
+        BiliApiBuilder::default()
+    }
+
+    /// Endpoints the client talks to
+    pub fn endpoints(&self) -> &Endpoints {
+        &self.endpoints
+    }
 
     /// Set cookies for authenticated requests
     pub fn with_cookies(mut self, cookies: Cookies) -> Self {
@@ -379,7 +692,7 @@ impl BiliApi {
 
     /// Make a GET request
     async fn get<T: DeserializeOwned>(&self, url: &str) -> Result<ApiResponse<T>> {
-        let mut req = self.client.get(url);
+        let mut req = self.client.get().get(url);
 
         if let Some(ref cookies) = self.cookies {
             req = req.header(header::COOKIE, cookies.to_cookie_string());
@@ -396,7 +709,7 @@ impl BiliApi {
         url: &str,
         form: &HashMap<String, String>,
     ) -> Result<ApiResponse<T>> {
-        let mut req = self.client.post(url).form(form);
+        let mut req = self.client.get().post(url).form(form);
 
         if let Some(ref cookies) = self.cookies {
             req = req.header(header::COOKIE, cookies.to_cookie_string());
@@ -409,7 +722,7 @@ impl BiliApi {
 
     /// Initialize room info (get real room_id from short_id)
     pub async fn room_init(&self, room_id: u64) -> Result<RoomInitData> {
-        let url = format!("{}/room/v1/Room/room_init?id={}", LIVE_API_BASE, room_id);
+        let url = format!("{}/room/v1/Room/room_init?id={}", self.endpoints.live_api, room_id);
         self.get(&url).await?.into_result()
     }
 
@@ -417,7 +730,17 @@ impl BiliApi {
     pub async fn get_room_info(&self, room_id: u64) -> Result<RoomInfoData> {
         let url = format!(
             "{}/room/v1/Room/get_info?room_id={}",
-            LIVE_API_BASE, room_id
+            self.endpoints.live_api, room_id
+        );
+        self.get(&url).await?.into_result()
+    }
+
+    /// Get the stream URLs of a live room, `qn` selects the quality
+    /// (10000 is the original quality)
+    pub async fn get_play_info(&self, room_id: u64, qn: u32) -> Result<PlayInfoData> {
+        let url = format!(
+            "{}/xlive/web-room/v2/index/getRoomPlayInfo?room_id={}&protocol=0,1&format=0,1,2&codec=0,1&qn={}&platform=web&ptype=8",
+            self.endpoints.live_api, room_id, qn
         );
         self.get(&url).await?.into_result()
     }
@@ -436,7 +759,7 @@ impl BiliApi {
         let query = self.wbi_signer.get_signed_query(&mut params)?;
         let url = format!(
             "{}/xlive/web-room/v1/index/getDanmuInfo?{}",
-            LIVE_API_BASE, query
+            self.endpoints.live_api, query
         );
 
         self.get(&url).await?.into_result()
@@ -446,7 +769,7 @@ impl BiliApi {
     pub async fn get_gift_config(&self, room_id: u64) -> Result<GiftConfigData> {
         let url = format!(
             "{}/xlive/web-room/v1/giftPanel/giftConfig?platform=pc&room_id={}",
-            LIVE_API_BASE, room_id
+            self.endpoints.live_api, room_id
         );
         self.get(&url).await?.into_result()
     }
@@ -455,7 +778,7 @@ impl BiliApi {
     pub async fn get_online_gold_rank(&self, room_id: u64, ruid: u64, page: u32, page_size: u32) -> Result<OnlineGoldRankData> {
         let url = format!(
             "{}/xlive/general-interface/v1/rank/queryContributionRank?ruid={}&room_id={}&page={}&page_size={}&type=online_rank&switch=contribution_rank",
-            LIVE_API_BASE, ruid, room_id, page, page_size
+            self.endpoints.live_api, ruid, room_id, page, page_size
         );
         self.get(&url).await?.into_result()
     }
@@ -464,14 +787,14 @@ impl BiliApi {
     pub async fn get_guard_list(&self, room_id: u64, ruid: u64, page: u32) -> Result<GuardListData> {
         let url = format!(
             "{}/xlive/app-room/v2/guardTab/topList?roomid={}&ruid={}&page={}&page_size=30",
-            LIVE_API_BASE, room_id, ruid, page
+            self.endpoints.live_api, room_id, ruid, page
         );
         self.get(&url).await?.into_result()
     }
 
     /// Get nav info (login status + WBI keys)
     pub async fn nav(&self) -> Result<NavData> {
-        let url = format!("{}/x/web-interface/nav", WEB_API_BASE);
+        let url = format!("{}/x/web-interface/nav", self.endpoints.web_api);
         let data = self.get::<NavData>(&url).await?.into_result()?;
 
         // Update WBI keys
@@ -494,7 +817,7 @@ impl BiliApi {
         params.insert("mid".to_string(), mid.to_string());
 
         let query = self.wbi_signer.get_signed_query(&mut params)?;
-        let url = format!("{}/x/space/wbi/acc/info?{}", WEB_API_BASE, query);
+        let url = format!("{}/x/space/wbi/acc/info?{}", self.endpoints.web_api, query);
 
         self.get(&url).await?.into_result()
     }
@@ -524,7 +847,7 @@ impl BiliApi {
         form.insert("csrf".to_string(), cookies.bili_jct.clone());
         form.insert("csrf_token".to_string(), cookies.bili_jct.clone());
 
-        let url = format!("{}/msg/send", LIVE_API_BASE);
+        let url = format!("{}/msg/send", self.endpoints.live_api);
         let resp: ApiResponse<serde_json::Value> = self.post_form(&url, &form).await?;
 
         if resp.code == 0 {
@@ -536,25 +859,127 @@ impl BiliApi {
 
     /// Update room title
     pub async fn update_room_title(&self, room_id: u64, title: &str) -> Result<()> {
+        self.update_room(room_id, "title", title, "update room title")
+            .await
+    }
+
+    /// Change the area of the room
+    pub async fn update_room_area(&self, room_id: u64, area_id: u64) -> Result<()> {
+        self.update_room(room_id, "area_id", &area_id.to_string(), "update room area")
+            .await
+    }
+
+    /// Update the room description (个人简介)
+    pub async fn update_room_description(&self, room_id: u64, description: &str) -> Result<()> {
+        self.update_room(room_id, "description", description, "update room description")
+            .await
+    }
+
+    /// Replace the room tags, adding and removing tags one by one
+    pub async fn update_room_tags(&self, room_id: u64, tags: &[String]) -> Result<()> {
+        let info = self.get_room_info(room_id).await?;
+        let current = split_tags(&info.tags);
+        let wanted: Vec<&str> = tags.iter().map(|t| t.trim()).filter(|t| !t.is_empty()).collect();
+
+        for tag in current.iter().filter(|t| !wanted.contains(t)) {
+            self.update_room(room_id, "del_tag", tag, "remove room tag")
+                .await?;
+        }
+        for tag in wanted.iter().filter(|t| !current.contains(t)) {
+            self.update_room(room_id, "add_tag", tag, "add room tag")
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Set one field through Room/update
+    async fn update_room(&self, room_id: u64, field: &str, value: &str, action: &str) -> Result<()> {
+        let mut form = self.csrf_form()?;
+        form.insert("room_id".to_string(), room_id.to_string());
+        form.insert(field.to_string(), value.to_string());
+
+        self.post_live_form("/room/v1/Room/update", form, action)
+            .await
+    }
+
+    /// Get all live areas
+    pub async fn get_area_list(&self) -> Result<AreaList> {
+        let url = format!(
+            "{}/room/v1/Area/getList?show_pinyin=1",
+            self.endpoints.live_api
+        );
+        let groups: Vec<LiveAreaGroup> = self.get(&url).await?.into_result()?;
+        Ok(AreaList { groups })
+    }
+
+    /// Get the room announcement (主播公告)
+    pub async fn get_room_news(&self, room_id: u64) -> Result<String> {
+        let url = format!(
+            "{}/room_ex/v1/RoomNews/get?roomid={}",
+            self.endpoints.live_api, room_id
+        );
+        let data: RoomNewsData = self.get(&url).await?.into_result()?;
+        Ok(data.content)
+    }
+
+    /// Update the room announcement, `uid` is the streamer
+    pub async fn update_room_news(&self, room_id: u64, uid: u64, content: &str) -> Result<()> {
+        let mut form = self.csrf_form()?;
+        form.insert("room_id".to_string(), room_id.to_string());
+        form.insert("uid".to_string(), uid.to_string());
+        form.insert("content".to_string(), content.to_string());
+
+        self.post_live_form("/xlive/app-blink/v1/index/updateRoomNews", form, "update room announcement")
+            .await
+    }
+
+    /// Upload a JPG or PNG image as the cover of the logged-in user's room
+    /// Returns the URL of the uploaded cover
+    pub async fn upload_room_cover(&self, image: Vec<u8>, file_name: &str) -> Result<String> {
+        let mime = match file_name.rsplit('.').next().map(|e| e.to_lowercase()).as_deref() {
+            Some("jpg") | Some("jpeg") => "image/jpeg",
+            Some("png") => "image/png",
+            _ => return Err(anyhow!("Cover must be a JPG or PNG image")),
+        };
         let cookies = self
             .cookies
             .as_ref()
             .ok_or_else(|| anyhow!("Not logged in"))?;
 
-        let mut form = HashMap::new();
-        form.insert("room_id".to_string(), room_id.to_string());
-        form.insert("title".to_string(), title.to_string());
-        form.insert("csrf".to_string(), cookies.bili_jct.clone());
-        form.insert("csrf_token".to_string(), cookies.bili_jct.clone());
-
-        let url = format!("{}/room/v1/Room/update", LIVE_API_BASE);
-        let resp: ApiResponse<serde_json::Value> = self.post_form(&url, &form).await?;
-
-        if resp.code == 0 {
-            Ok(())
-        } else {
-            Err(anyhow!("Failed to update room title: {}", resp.message))
-        }
+        let part = reqwest::multipart::Part::bytes(image)
+            .file_name(file_name.to_string())
+            .mime_str(mime)?;
+        let multipart = reqwest::multipart::Form::new()
+            .text("bucket", "live")
+            .text("dir", "new_room_cover")
+            .part("file", part);
+        let url = format!(
+            "{}/x/upload/web/image?csrf={}",
+            self.endpoints.web_api, cookies.bili_jct
+        );
+        let resp: ApiResponse<UploadImageData> = self
+            .client
+            .get()
+            .post(&url)
+            .header(header::COOKIE, cookies.to_cookie_string())
+            .multipart(multipart)
+            .send()
+            .await?
+            .json()
+            .await?;
+        let location = resp
+            .into_result()
+            .map_err(|e| anyhow!("Failed to upload cover: {}", e))?
+            .location;
+
+        let mut form = self.csrf_form()?;
+        form.insert("platform".to_string(), "web".to_string());
+        form.insert("mobi_app".to_string(), "web".to_string());
+        form.insert("build".to_string(), "1".to_string());
+        form.insert("cover".to_string(), location.clone());
+        self.post_live_form("/xlive/app-blink/v1/preLive/UpdatePreLiveInfo", form, "update room cover")
+            .await?;
+        Ok(location)
     }
 
     /// Start live stream
@@ -572,7 +997,7 @@ impl BiliApi {
         form.insert("csrf".to_string(), cookies.bili_jct.clone());
         form.insert("csrf_token".to_string(), cookies.bili_jct.clone());
 
-        let url = format!("{}/room/v1/Room/startLive", LIVE_API_BASE);
+        let url = format!("{}/room/v1/Room/startLive", self.endpoints.live_api);
         let resp: ApiResponse<StartLiveData> = self.post_form(&url, &form).await?;
 
         Ok(StartLiveResponse {
@@ -595,10 +1020,106 @@ impl BiliApi {
         form.insert("csrf".to_string(), cookies.bili_jct.clone());
         form.insert("csrf_token".to_string(), cookies.bili_jct.clone());
 
-        let url = format!("{}/room/v1/Room/stopLive", LIVE_API_BASE);
+        let url = format!("{}/room/v1/Room/stopLive", self.endpoints.live_api);
         self.post_form(&url, &form).await?.into_result()
     }
 
+    /// Build a form with the CSRF token of the logged-in user
+    fn csrf_form(&self) -> Result<HashMap<String, String>> {
+        let cookies = self
+            .cookies
+            .as_ref()
+            .ok_or_else(|| anyhow!("Not logged in"))?;
+
+        let mut form = HashMap::new();
+        form.insert("csrf".to_string(), cookies.bili_jct.clone());
+        form.insert("csrf_token".to_string(), cookies.bili_jct.clone());
+        Ok(form)
+    }
+
+    /// Post a form to the live API, mapping API errors to a readable message
+    async fn post_live_form(&self, path: &str, form: HashMap<String, String>, action: &str) -> Result<()> {
+        let url = format!("{}{}", self.endpoints.live_api, path);
+        let resp: ApiResponse<serde_json::Value> = self.post_form(&url, &form).await?;
+
+        if resp.code == 0 {
+            Ok(())
+        } else {
+            Err(anyhow!("Failed to {}: {}", action, resp.message))
+        }
+    }
+
+    /// Block (mute) a user in a room
+    /// `hours` is the block duration, `BLOCK_THIS_LIVE` for the current live or `BLOCK_PERMANENT`
+    pub async fn block_user(&self, room_id: u64, uid: u64, hours: i32) -> Result<()> {
+        let mut form = self.csrf_form()?;
+        form.insert("room_id".to_string(), room_id.to_string());
+        form.insert("tuid".to_string(), uid.to_string());
+        form.insert("mobile_app".to_string(), "web".to_string());
+        form.insert("hour".to_string(), hours.to_string());
+
+        self.post_live_form("/xlive/web-ucenter/v1/banned/AddSilentUser", form, "block user")
+            .await
+    }
+
+    /// Unblock (unmute) a user in a room
+    pub async fn unblock_user(&self, room_id: u64, uid: u64) -> Result<()> {
+        let mut form = self.csrf_form()?;
+        form.insert("room_id".to_string(), room_id.to_string());
+        form.insert("tuid".to_string(), uid.to_string());
+
+        self.post_live_form("/xlive/web-ucenter/v1/banned/DelSilentUser", form, "unblock user")
+            .await
+    }
+
+    /// Get blocked users of a room (page starts at 1)
+    pub async fn get_blocked_users(&self, room_id: u64, page: u32) -> Result<SilentUserListData> {
+        let mut form = self.csrf_form()?;
+        form.insert("room_id".to_string(), room_id.to_string());
+        form.insert("ps".to_string(), page.to_string());
+
+        let url = format!("{}/xlive/web-ucenter/v1/banned/GetSilentUserList", self.endpoints.live_api);
+        self.post_form(&url, &form).await?.into_result()
+    }
+
+    /// Set room-wide silence
+    /// `minutes` is the silence duration, 0 keeps it until lifted
+    pub async fn set_room_silence(&self, room_id: u64, silence: RoomSilence, minutes: u32) -> Result<()> {
+        let (silence_type, level) = match silence {
+            RoomSilence::Off => ("off", 0),
+            RoomSilence::Level(level) => ("level", level),
+            RoomSilence::Medal(level) => ("medal", level),
+        };
+
+        let mut form = self.csrf_form()?;
+        form.insert("room_id".to_string(), room_id.to_string());
+        form.insert("type".to_string(), silence_type.to_string());
+        form.insert("level".to_string(), level.to_string());
+        form.insert("minute".to_string(), minutes.to_string());
+
+        self.post_live_form("/xlive/web-room/v1/banned/RoomSilent", form, "set room silence")
+            .await
+    }
+
+    /// Appoint a user as admin of the logged-in user's room
+    pub async fn add_room_admin(&self, uid: u64) -> Result<()> {
+        let mut form = self.csrf_form()?;
+        form.insert("admin".to_string(), uid.to_string());
+        form.insert("admin_level".to_string(), "1".to_string());
+
+        self.post_live_form("/xlive/web-ucenter/v1/roomAdmin/appoint", form, "add room admin")
+            .await
+    }
+
+    /// Dismiss an admin of the logged-in user's room
+    pub async fn remove_room_admin(&self, uid: u64) -> Result<()> {
+        let mut form = self.csrf_form()?;
+        form.insert("uid".to_string(), uid.to_string());
+
+        self.post_live_form("/xlive/web-ucenter/v1/roomAdmin/dismiss", form, "remove room admin")
+            .await
+    }
+
     /// Helper: Get full room info with RoomId
     pub async fn get_room(&self, room_id: u64) -> Result<RoomId> {
         let init = self.room_init(room_id).await?;
@@ -607,15 +1128,18 @@ impl BiliApi {
 
     /// Generate QR code for login
     pub async fn qr_generate(&self) -> Result<QrCodeData> {
-        let url = "https://passport.bilibili.com/x/passport-login/web/qrcode/generate";
-        self.get::<QrCodeData>(url).await?.into_result()
+        let url = format!(
+            "{}/x/passport-login/web/qrcode/generate",
+            self.endpoints.passport
+        );
+        self.get::<QrCodeData>(&url).await?.into_result()
     }
 
     /// Poll QR code login status
     pub async fn qr_poll(&self, qrcode_key: &str) -> Result<(QrCodeStatus, Option<Cookies>)> {
         let url = format!(
-            "https://passport.bilibili.com/x/passport-login/web/qrcode/poll?qrcode_key={}",
-            qrcode_key
+            "{}/x/passport-login/web/qrcode/poll?qrcode_key={}",
+            self.endpoints.passport, qrcode_key
         );
         let resp: ApiResponse<QrCodePollData> = self.get(&url).await?;
 
@@ -651,12 +1175,17 @@ impl BiliApi {
         let mut form = HashMap::new();
         form.insert("biliCSRF".to_string(), cookies.bili_jct.clone());
 
-        let url = "https://passport.bilibili.com/login/exit/v2";
-        let _: ApiResponse<serde_json::Value> = self.post_form(url, &form).await?;
+        let url = format!("{}/login/exit/v2", self.endpoints.passport);
+        let _: ApiResponse<serde_json::Value> = self.post_form(&url, &form).await?;
         Ok(())
     }
 }
 
+/// Tags of `RoomInfoData::tags`, which is comma separated
+pub fn split_tags(tags: &str) -> Vec<&str> {
+    tags.split(',').map(str::trim).filter(|t| !t.is_empty()).collect()
+}
+
 impl Default for BiliApi {
     fn default() -> Self {
         Self::new().expect("Failed to create BiliApi client")
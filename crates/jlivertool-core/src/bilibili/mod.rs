@@ -1,5 +1,6 @@
 //! Bilibili API and WebSocket modules
 
 pub mod api;
+pub mod replay;
 pub mod wbi;
 pub mod ws;
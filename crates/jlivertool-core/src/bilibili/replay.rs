@@ -0,0 +1,429 @@
+//! Recording and replay of raw danmu WebSocket sessions
+//!
+//! File format (`.jlrec`):
+//! - Header: [magic "JLREC"(5)][version(1)][roomId(8)][startedAt ms(8)], big endian
+//! - Body: zlib stream of records [offset ms(8)][len(4)][frame]
+//!
+//! Frames are compressed and written on a dedicated thread, so disk stalls
+//! never hold up the WebSocket task. The body is flushed periodically, so a
+//! file cut short by a crash still replays up to the last flush.
+
+use super::ws::{dispatch_frame, WsEvent};
+use anyhow::{anyhow, Result};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use parking_lot::Mutex;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self as std_mpsc, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::mpsc;
+use tokio::time::Duration;
+use tracing::{info, warn};
+
+/// File extension of session recordings
+pub const SESSION_EXTENSION: &str = "jlrec";
+
+const MAGIC: &[u8; 5] = b"JLREC";
+const VERSION: u8 = 1;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+/// Frames above this size are treated as corruption
+const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+/// Header of a session recording
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionHeader {
+    pub room_id: u64,
+    /// Unix timestamp in milliseconds
+    pub started_at: i64,
+}
+
+struct SessionWriter {
+    path: PathBuf,
+    encoder: ZlibEncoder<BufWriter<File>>,
+    started: Instant,
+    last_flush: Instant,
+}
+
+impl SessionWriter {
+    fn create(path: &Path, room_id: u64) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&[VERSION])?;
+        file.write_all(&room_id.to_be_bytes())?;
+        file.write_all(&chrono::Utc::now().timestamp_millis().to_be_bytes())?;
+        let now = Instant::now();
+        Ok(Self {
+            path: path.to_path_buf(),
+            encoder: ZlibEncoder::new(file, flate2::Compression::default()),
+            started: now,
+            last_flush: now,
+        })
+    }
+
+    /// Append a frame received at `received`
+    fn write(&mut self, received: Instant, frame: &[u8]) -> Result<()> {
+        let offset = received.saturating_duration_since(self.started).as_millis() as u64;
+        self.encoder.write_all(&offset.to_be_bytes())?;
+        self.encoder
+            .write_all(&(frame.len() as u32).to_be_bytes())?;
+        self.encoder.write_all(frame)?;
+        if self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.encoder.flush()?;
+        self.last_flush = Instant::now();
+        Ok(())
+    }
+
+    fn finish(self) -> Result<PathBuf> {
+        self.encoder.finish()?.flush()?;
+        Ok(self.path)
+    }
+}
+
+enum RecorderCommand {
+    Start {
+        path: PathBuf,
+        room_id: u64,
+        ack: std_mpsc::Sender<Result<()>>,
+    },
+    Stop(std_mpsc::Sender<Result<Option<PathBuf>>>),
+    Record(Instant, Vec<u8>),
+}
+
+/// Shared handle writing received frames to a `.jlrec` file
+///
+/// Idle until `start` is called; `record` is a no-op while idle. Frames are
+/// queued to a writer thread that exits when every handle is dropped.
+#[derive(Clone)]
+pub struct SessionRecorder {
+    tx: std_mpsc::Sender<RecorderCommand>,
+    /// File being recorded to, cleared by the writer when a write fails
+    path: Arc<Mutex<Option<PathBuf>>>,
+}
+
+impl Default for SessionRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SessionRecorder {
+    pub fn new() -> Self {
+        let (tx, rx) = std_mpsc::channel();
+        let path = Arc::new(Mutex::new(None));
+        let writer_path = path.clone();
+        if let Err(e) = std::thread::Builder::new()
+            .name("session-recorder".into())
+            .spawn(move || run_writer(rx, writer_path))
+        {
+            warn!("Failed to start the session recorder: {}", e);
+        }
+        Self { tx, path }
+    }
+
+    /// Start recording to `path`, finishing any running recording
+    pub fn start(&self, path: &Path, room_id: u64) -> Result<()> {
+        let (ack, result) = std_mpsc::channel();
+        self.tx
+            .send(RecorderCommand::Start {
+                path: path.to_path_buf(),
+                room_id,
+                ack,
+            })
+            .map_err(|_| anyhow!("Session recorder is not running"))?;
+        result
+            .recv()
+            .map_err(|_| anyhow!("Session recorder is not running"))??;
+        info!("Recording WebSocket session to {}", path.display());
+        Ok(())
+    }
+
+    /// Stop recording once the queued frames are written, returns the finished file
+    pub fn stop(&self) -> Result<Option<PathBuf>> {
+        let (ack, result) = std_mpsc::channel();
+        if self.tx.send(RecorderCommand::Stop(ack)).is_err() {
+            return Ok(None);
+        }
+        result.recv().unwrap_or(Ok(None))
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.path.lock().is_some()
+    }
+
+    /// File being recorded to
+    pub fn path(&self) -> Option<PathBuf> {
+        self.path.lock().clone()
+    }
+
+    /// Queue one raw frame
+    pub fn record(&self, frame: &[u8]) {
+        if self.is_recording() {
+            let _ = self
+                .tx
+                .send(RecorderCommand::Record(Instant::now(), frame.to_vec()));
+        }
+    }
+}
+
+/// Writer thread, owns the open recording
+fn run_writer(rx: std_mpsc::Receiver<RecorderCommand>, path: Arc<Mutex<Option<PathBuf>>>) {
+    let mut writer: Option<SessionWriter> = None;
+    let finish = |writer: &mut Option<SessionWriter>| -> Result<Option<PathBuf>> {
+        match writer.take() {
+            Some(w) => Ok(Some(w.finish()?)),
+            None => Ok(None),
+        }
+    };
+
+    loop {
+        let command = match rx.recv_timeout(FLUSH_INTERVAL) {
+            Ok(command) => command,
+            Err(RecvTimeoutError::Timeout) => {
+                // Flush while no frames arrive so the file stays replayable
+                if let Some(w) = writer.as_mut() {
+                    if let Err(e) = w.flush() {
+                        warn!("Failed to flush recording: {}", e);
+                    }
+                }
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        match command {
+            RecorderCommand::Start {
+                path: new_path,
+                room_id,
+                ack,
+            } => {
+                let result = finish(&mut writer).and_then(|_| {
+                    writer = Some(SessionWriter::create(&new_path, room_id)?);
+                    Ok(())
+                });
+                *path.lock() = writer.as_ref().map(|w| w.path.clone());
+                let _ = ack.send(result);
+            }
+            RecorderCommand::Stop(ack) => {
+                *path.lock() = None;
+                let _ = ack.send(finish(&mut writer));
+            }
+            RecorderCommand::Record(received, frame) => {
+                if let Some(w) = writer.as_mut() {
+                    if let Err(e) = w.write(received, &frame) {
+                        warn!("Failed to record frame, recording stopped: {}", e);
+                        writer = None;
+                        *path.lock() = None;
+                    }
+                }
+            }
+        }
+    }
+
+    if let Err(e) = finish(&mut writer) {
+        warn!("Failed to finish recording: {}", e);
+    }
+}
+
+/// Sequential reader of a `.jlrec` file
+pub struct SessionReader {
+    header: SessionHeader,
+    decoder: ZlibDecoder<BufReader<File>>,
+}
+
+impl SessionReader {
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut header = [0u8; 22];
+        file.read_exact(&mut header)
+            .map_err(|_| anyhow!("Not a session recording"))?;
+        if &header[0..5] != MAGIC {
+            return Err(anyhow!("Not a session recording"));
+        }
+        if header[5] != VERSION {
+            return Err(anyhow!("Unsupported recording version {}", header[5]));
+        }
+        Ok(Self {
+            header: SessionHeader {
+                room_id: u64::from_be_bytes(header[6..14].try_into()?),
+                started_at: i64::from_be_bytes(header[14..22].try_into()?),
+            },
+            decoder: ZlibDecoder::new(file),
+        })
+    }
+
+    pub fn header(&self) -> SessionHeader {
+        self.header
+    }
+
+    /// Next frame with its offset from the start of the recording
+    ///
+    /// Returns `None` at the end of the file, including a truncated tail.
+    pub fn next_frame(&mut self) -> Result<Option<(Duration, Vec<u8>)>> {
+        let mut record = [0u8; 12];
+        if !self.read_or_eof(&mut record)? {
+            return Ok(None);
+        }
+        let offset = u64::from_be_bytes(record[0..8].try_into()?);
+        let len = u32::from_be_bytes(record[8..12].try_into()?) as usize;
+        if len > MAX_FRAME_LEN {
+            return Err(anyhow!("Invalid frame length {}", len));
+        }
+        let mut frame = vec![0u8; len];
+        if !self.read_or_eof(&mut frame)? {
+            return Ok(None);
+        }
+        Ok(Some((Duration::from_millis(offset), frame)))
+    }
+
+    fn read_or_eof(&mut self, buf: &mut [u8]) -> Result<bool> {
+        match self.decoder.read_exact(buf) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Replays a recording as the events of a live connection
+pub struct SessionReplay {
+    reader: SessionReader,
+    speed: f64,
+    event_tx: mpsc::UnboundedSender<WsEvent>,
+}
+
+impl SessionReplay {
+    /// Open `path` for replay at `speed` times real time, 0 for as fast as possible
+    pub fn open(path: &Path, speed: f64) -> Result<(Self, mpsc::UnboundedReceiver<WsEvent>)> {
+        let reader = SessionReader::open(path)?;
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        Ok((
+            Self {
+                reader,
+                speed: speed.max(0.0),
+                event_tx,
+            },
+            event_rx,
+        ))
+    }
+
+    pub fn header(&self) -> SessionHeader {
+        self.reader.header()
+    }
+
+    /// Feed all frames, then report the connection as closed
+    pub async fn run(mut self) -> Result<()> {
+        let _ = self.event_tx.send(WsEvent::Connected);
+        let start = tokio::time::Instant::now();
+        let result = loop {
+            let (offset, frame) = match self.reader.next_frame() {
+                Ok(Some(next)) => next,
+                Ok(None) => break Ok(()),
+                Err(e) => break Err(e),
+            };
+            if self.speed > 0.0 {
+                tokio::time::sleep_until(start + offset.div_f64(self.speed)).await;
+            } else {
+                tokio::task::yield_now().await;
+            }
+            dispatch_frame(frame, &self.event_tx);
+        };
+        if let Err(ref e) = result {
+            let _ = self.event_tx.send(WsEvent::Error(e.to_string()));
+        }
+        let _ = self.event_tx.send(WsEvent::Disconnected);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bilibili::ws::{MessageOp, WsBodyVer};
+
+    fn packet(op: MessageOp, ver: WsBodyVer, body: &[u8]) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        buffer.extend_from_slice(&((16 + body.len()) as u32).to_be_bytes());
+        buffer.extend_from_slice(&16u16.to_be_bytes());
+        buffer.extend_from_slice(&(ver as u16).to_be_bytes());
+        buffer.extend_from_slice(&(op as u32).to_be_bytes());
+        buffer.extend_from_slice(&1u32.to_be_bytes());
+        buffer.extend_from_slice(body);
+        buffer
+    }
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("jlivertool-session-{}.jlrec", uuid::Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let path = temp_path();
+        let recorder = SessionRecorder::new();
+        recorder.record(b"ignored while idle");
+        recorder.start(&path, 21484828).unwrap();
+        assert!(recorder.is_recording());
+        recorder.record(&packet(
+            MessageOp::AuthReply,
+            WsBodyVer::Heartbeat,
+            br#"{"code":0}"#,
+        ));
+        recorder.record(&packet(
+            MessageOp::SendMsgReply,
+            WsBodyVer::Normal,
+            br#"{"cmd":"LIVE"}"#,
+        ));
+        assert_eq!(recorder.stop().unwrap(), Some(path.clone()));
+        assert!(!recorder.is_recording());
+
+        let (replay, mut rx) = SessionReplay::open(&path, 0.0).unwrap();
+        assert_eq!(replay.header().room_id, 21484828);
+        replay.run().await.unwrap();
+
+        assert!(matches!(rx.recv().await, Some(WsEvent::Connected)));
+        assert!(matches!(rx.recv().await, Some(WsEvent::Authenticated)));
+        match rx.recv().await {
+            Some(WsEvent::Message(body)) => assert_eq!(body["cmd"], "LIVE"),
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert!(matches!(rx.recv().await, Some(WsEvent::Disconnected)));
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_truncated_recording() {
+        let path = temp_path();
+        let recorder = SessionRecorder::new();
+        recorder.start(&path, 1).unwrap();
+        for _ in 0..100 {
+            recorder.record(&[7u8; 64]);
+        }
+        recorder.stop().unwrap();
+
+        // Cut the file short, as after a crash
+        let data = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &data[..data.len() - 4]).unwrap();
+
+        let mut reader = SessionReader::open(&path).unwrap();
+        let mut frames = 0;
+        while let Ok(Some((_, frame))) = reader.next_frame() {
+            assert_eq!(frame, vec![7u8; 64]);
+            frames += 1;
+        }
+        assert!(frames > 0);
+
+        std::fs::write(&path, b"not a recording").unwrap();
+        assert!(SessionReader::open(&path).is_err());
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -7,6 +7,8 @@
 //! - Ver 2: Deflate compressed
 //! - Ver 3: Brotli compressed
 
+use crate::bilibili::replay::SessionRecorder;
+use crate::network;
 use anyhow::{anyhow, Result};
 use futures::{SinkExt, StreamExt};
 use serde_json::Value;
@@ -15,7 +17,7 @@ use std::io::Read;
 use std::sync::Arc;
 use tokio::sync::mpsc;
 use tokio::time::{interval, Duration};
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
+use tokio_tungstenite::tungstenite::protocol::Message;
 use tracing::{debug, error, info, warn};
 
 // Thread-local buffer pool for decompression to reduce allocations
@@ -298,11 +300,40 @@ pub enum WsEvent {
     Error(String),
 }
 
+/// Parse a received frame and emit its events
+pub(crate) fn dispatch_frame(data: Vec<u8>, event_tx: &mpsc::UnboundedSender<WsEvent>) {
+    let ws_msg = BiliWsMessage::from_buffer(data);
+    match ws_msg.parse() {
+        Ok(pack) => match pack.op {
+            MessageOp::AuthReply => {
+                let _ = event_tx.send(WsEvent::Authenticated);
+            }
+            MessageOp::KeepAliveReply => {
+                if let Some(count_json) = pack.body.first() {
+                    if let Some(count) = count_json.get("count").and_then(|v| v.as_u64()) {
+                        let _ = event_tx.send(WsEvent::HeartbeatReply(count as u32));
+                    }
+                }
+            }
+            MessageOp::SendMsgReply => {
+                for body in pack.body {
+                    let _ = event_tx.send(WsEvent::Message(body));
+                }
+            }
+            _ => {}
+        },
+        Err(e) => {
+            warn!("Failed to parse WebSocket message: {}", e);
+        }
+    }
+}
+
 /// Bilibili WebSocket client
 pub struct BiliWebSocket {
     ws_info: WsInfo,
     event_tx: mpsc::UnboundedSender<WsEvent>,
     is_running: Arc<std::sync::atomic::AtomicBool>,
+    recorder: Option<SessionRecorder>,
 }
 
 impl BiliWebSocket {
@@ -314,11 +345,17 @@ impl BiliWebSocket {
             ws_info,
             event_tx,
             is_running: Arc::new(std::sync::atomic::AtomicBool::new(false)),
+            recorder: None,
         };
 
         (client, event_rx)
     }
 
+    /// Write every received frame to `recorder` while it is recording
+    pub fn set_recorder(&mut self, recorder: SessionRecorder) {
+        self.recorder = Some(recorder);
+    }
+
     /// Start the WebSocket connection
     pub async fn connect(&self) -> Result<()> {
         self.is_running
@@ -327,7 +364,7 @@ impl BiliWebSocket {
         let url = &self.ws_info.server;
         info!("Connecting to WebSocket: {}", url);
 
-        let (ws_stream, _) = connect_async(url).await?;
+        let ws_stream = network::connect_websocket(url).await?;
         let (mut write, mut read) = ws_stream.split();
 
         let _ = self.event_tx.send(WsEvent::Connected);
@@ -374,35 +411,10 @@ impl BiliWebSocket {
         while let Some(msg) = read.next().await {
             match msg {
                 Ok(Message::Binary(data)) => {
-                    let ws_msg = BiliWsMessage::from_buffer(data);
-                    match ws_msg.parse() {
-                        Ok(pack) => {
-                            match pack.op {
-                                MessageOp::AuthReply => {
-                                    let _ = event_tx.send(WsEvent::Authenticated);
-                                }
-                                MessageOp::KeepAliveReply => {
-                                    if let Some(count_json) = pack.body.first() {
-                                        if let Some(count) =
-                                            count_json.get("count").and_then(|v| v.as_u64())
-                                        {
-                                            let _ =
-                                                event_tx.send(WsEvent::HeartbeatReply(count as u32));
-                                        }
-                                    }
-                                }
-                                MessageOp::SendMsgReply => {
-                                    for body in pack.body {
-                                        let _ = event_tx.send(WsEvent::Message(body));
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
-                        Err(e) => {
-                            warn!("Failed to parse WebSocket message: {}", e);
-                        }
+                    if let Some(ref recorder) = self.recorder {
+                        recorder.record(&data);
                     }
+                    dispatch_frame(data, &event_tx);
                 }
                 Ok(Message::Close(_)) => {
                     info!("WebSocket closed by server");
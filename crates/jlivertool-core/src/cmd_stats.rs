@@ -0,0 +1,137 @@
+//! Statistics of WebSocket message cmds
+//!
+//! Tracks every `cmd` received from the danmaku server with a count and the
+//! latest payload, so messages that are not decoded yet can be inspected.
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Maximum length of a stored sample payload in bytes
+pub const MAX_SAMPLE_LEN: usize = 8 * 1024;
+/// Maximum number of distinct cmds tracked
+pub const MAX_TRACKED_CMDS: usize = 500;
+
+/// Statistics of a single cmd
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CmdStat {
+    pub cmd: String,
+    pub count: u64,
+    /// Whether the cmd is decoded into a typed event
+    pub decoded: bool,
+    /// Unix timestamp (seconds) of the latest message
+    pub last_seen: i64,
+    /// Pretty-printed latest payload, truncated to `MAX_SAMPLE_LEN`
+    pub sample: String,
+}
+
+/// Tracked state of a cmd, the payload is only formatted on `snapshot`
+struct Entry {
+    count: u64,
+    decoded: bool,
+    last_seen: i64,
+    latest: Arc<serde_json::Value>,
+}
+
+/// Thread-safe collector of cmd statistics
+#[derive(Default)]
+pub struct CmdStats {
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl CmdStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a received message
+    pub fn record(&self, cmd: &str, body: &Arc<serde_json::Value>, decoded: bool) {
+        let now = chrono::Utc::now().timestamp();
+        let mut entries = self.entries.lock();
+        if let Some(entry) = entries.get_mut(cmd) {
+            entry.count += 1;
+            entry.last_seen = now;
+            entry.decoded = decoded;
+            entry.latest = body.clone();
+            return;
+        }
+        if entries.len() >= MAX_TRACKED_CMDS {
+            return;
+        }
+        entries.insert(
+            cmd.to_string(),
+            Entry {
+                count: 1,
+                decoded,
+                last_seen: now,
+                latest: body.clone(),
+            },
+        );
+    }
+
+    /// Get all tracked cmds, most recently seen first
+    pub fn snapshot(&self) -> Vec<CmdStat> {
+        let entries: Vec<(String, u64, bool, i64, Arc<serde_json::Value>)> = self
+            .entries
+            .lock()
+            .iter()
+            .map(|(cmd, e)| (cmd.clone(), e.count, e.decoded, e.last_seen, e.latest.clone()))
+            .collect();
+        // Format outside the lock so recording is never blocked by it
+        let mut stats: Vec<CmdStat> = entries
+            .into_iter()
+            .map(|(cmd, count, decoded, last_seen, latest)| CmdStat {
+                cmd,
+                count,
+                decoded,
+                last_seen,
+                sample: sample_of(&latest),
+            })
+            .collect();
+        stats.sort_by(|a, b| b.last_seen.cmp(&a.last_seen).then_with(|| a.cmd.cmp(&b.cmd)));
+        stats
+    }
+
+    /// Forget all tracked cmds
+    pub fn clear(&self) {
+        self.entries.lock().clear();
+    }
+}
+
+fn sample_of(body: &serde_json::Value) -> String {
+    let mut sample = serde_json::to_string_pretty(body).unwrap_or_default();
+    if sample.len() > MAX_SAMPLE_LEN {
+        let mut end = MAX_SAMPLE_LEN;
+        while !sample.is_char_boundary(end) {
+            end -= 1;
+        }
+        sample.truncate(end);
+        sample.push_str("\n…");
+    }
+    sample
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_counts_and_keeps_latest_sample() {
+        let stats = CmdStats::new();
+        let body = |value: serde_json::Value| Arc::new(value);
+        stats.record("LIKE_INFO_V3_CLICK", &body(serde_json::json!({"n": 1})), false);
+        stats.record("LIKE_INFO_V3_CLICK", &body(serde_json::json!({"n": 2})), false);
+        stats.record("DANMU_MSG", &body(serde_json::json!({})), true);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.len(), 2);
+        let like = snapshot.iter().find(|s| s.cmd == "LIKE_INFO_V3_CLICK").unwrap();
+        assert_eq!(like.count, 2);
+        assert!(!like.decoded);
+        assert!(like.sample.contains('2'));
+
+        stats.clear();
+        assert!(stats.snapshot().is_empty());
+    }
+}
@@ -1,6 +1,12 @@
 //! Configuration storage with persistence and change notifications
 
+use crate::backup::BackupConfig;
+use crate::network::NetworkConfig;
+use crate::obs::ObsConfig;
+use crate::recorder::RecorderConfig;
+use crate::retention::RetentionConfig;
 use crate::types::{Cookies, RoomId, WindowType};
+use crate::webhook::WebhookEndpoint;
 use anyhow::Result;
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use parking_lot::RwLock;
@@ -184,6 +190,24 @@ pub struct Config {
     #[serde(default = "default_plugin_http_port")]
     pub plugin_http_port: u16,
 
+    #[serde(default)]
+    pub obs: ObsConfig,
+
+    #[serde(default)]
+    pub webhooks: Vec<WebhookEndpoint>,
+
+    #[serde(default)]
+    pub recorder: RecorderConfig,
+
+    #[serde(default)]
+    pub network: NetworkConfig,
+
+    #[serde(default)]
+    pub retention: RetentionConfig,
+
+    #[serde(default)]
+    pub backup: BackupConfig,
+
     // Extra fields for extensibility
     #[serde(flatten)]
     pub extra: HashMap<String, Value>,
@@ -268,6 +292,12 @@ impl Default for Config {
             auto_update_check: default_auto_update_check(),
             plugin_ws_port: default_plugin_ws_port(),
             plugin_http_port: default_plugin_http_port(),
+            obs: ObsConfig::default(),
+            webhooks: Vec::new(),
+            recorder: RecorderConfig::default(),
+            network: NetworkConfig::default(),
+            retention: RetentionConfig::default(),
+            backup: BackupConfig::default(),
             extra: HashMap::new(),
         }
     }
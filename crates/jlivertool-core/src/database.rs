@@ -1,41 +1,580 @@
-//! SQLite database for storing danmus, gifts, guards, and superchats
-
-use crate::messages::{DanmuMessage, GiftMessage, GuardMessage, SuperChatMessage};
+//! SQLite database for storing danmus, gifts, guards, superchats, lotteries, goals,
+//! webhook deliveries and recordings
+//!
+//! File databases run in WAL mode: messages from the live connection are
+//! written by a `BatchWriter` thread, queries use a small pool of read
+//! connections so they don't wait for writes.
+
+use crate::db_writer::{BatchWriter, PendingWrite, DEFAULT_BATCH_SIZE, DEFAULT_FLUSH_INTERVAL};
+use crate::earnings::EarningKind;
+use crate::events::{Event, EventType};
+use crate::goals::Goal;
+use crate::messages::{
+    AnchorLotStartMessage, DanmuMessage, GiftMessage, GuardMessage, LotteryWinner,
+    RedPocketStartMessage, SuperChatMessage,
+};
+use crate::recorder::Recording;
 use crate::types::{MedalInfo, Sender};
-use anyhow::Result;
-use parking_lot::Mutex;
-use rusqlite::{params, Connection};
+use crate::webhook::WebhookDelivery;
+use anyhow::{anyhow, Result};
+use parking_lot::{Mutex, MutexGuard};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
 use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+/// Columns read by `danmu_from_row`, rows stored before the full danmu was
+/// kept fall back to the defaults of a parsed danmu and to a lighted medal
+const DANMU_COLUMNS: &str = "sender_uid, sender_uname, sender_face,
+    medal_level, medal_name, medal_anchor_uname, medal_anchor_roomid, medal_guard_level,
+    content, is_special, timestamp,
+    COALESCE(medal_color, 0), COALESCE(medal_color_border, 0),
+    COALESCE(medal_color_start, 0), COALESCE(medal_color_end, 0),
+    COALESCE(medal_is_lighted, medal_name IS NOT NULL AND medal_name <> ''),
+    COALESCE(is_generated, 0), COALESCE(is_mirror, 0), COALESCE(side_index, -1),
+    emoji_content, reply_uname";
+
+/// Columns read by `gift_from_row`
+const GIFT_COLUMNS: &str = "id, room_id, sender_uid, sender_uname, sender_face,
+    medal_level, medal_name,
+    gift_id, gift_name, gift_price, coin_type, action, num, timestamp,
+    COALESCE(archived, 0) as archived";
+
+/// Columns read by `guard_from_row`
+const GUARD_COLUMNS: &str = "id, room_id, sender_uid, sender_uname, sender_face,
+    num, unit, guard_level, price, timestamp,
+    COALESCE(archived, 0) as archived";
+
+/// Columns read by `superchat_from_row`
+const SUPERCHAT_COLUMNS: &str = "id, room_id, sender_uid, sender_uname, sender_face,
+    medal_level, medal_name,
+    message, price, start_time, end_time,
+    background_color, background_bottom_color, timestamp,
+    COALESCE(archived, 0) as archived";
+
+/// Columns read by `lottery_from_row`
+const LOTTERY_COLUMNS: &str = "id, room_id, kind, sender_uid, sender_uname,
+    award_name, requirement, total_price, winners, start_time, end_time";
+
+/// Timestamp to store for a message, falling back to now for messages without one
+fn stored_timestamp(timestamp: i64) -> i64 {
+    if timestamp > 0 {
+        timestamp
+    } else {
+        chrono::Utc::now().timestamp()
+    }
+}
+
+/// Map a row selected with `DANMU_COLUMNS` to (timestamp, danmu)
+fn danmu_from_row(row: &rusqlite::Row) -> rusqlite::Result<(i64, DanmuMessage)> {
+    let danmu = DanmuMessage {
+        sender: Sender {
+            uid: row.get::<_, i64>(0)? as u64,
+            uname: row.get(1)?,
+            face: row.get(2)?,
+            medal_info: MedalInfo {
+                medal_level: row.get::<_, i64>(3)? as u8,
+                medal_name: row.get(4)?,
+                anchor_uname: row.get(5)?,
+                anchor_roomid: row.get::<_, i64>(6)? as u64,
+                guard_level: row.get::<_, i64>(7)? as u8,
+                medal_color: row.get::<_, i64>(11)? as u32,
+                medal_color_border: row.get::<_, i64>(12)? as u32,
+                medal_color_start: row.get::<_, i64>(13)? as u32,
+                medal_color_end: row.get::<_, i64>(14)? as u32,
+                is_lighted: row.get::<_, i64>(15)? != 0,
+            },
+        },
+        content: row.get(8)?,
+        is_special: row.get::<_, i64>(9)? != 0,
+        is_generated: row.get::<_, i64>(16)? != 0,
+        is_mirror: row.get::<_, i64>(17)? != 0,
+        side_index: row.get::<_, i64>(18)? as i32,
+        emoji_content: row
+            .get::<_, Option<String>>(19)?
+            .and_then(|json| serde_json::from_str(&json).ok()),
+        reply_uname: row.get(20)?,
+        timestamp: row.get(10)?,
+    };
+    Ok((danmu.timestamp, danmu))
+}
+
+/// Map a row selected with `GIFT_COLUMNS`
+fn gift_from_row(row: &rusqlite::Row) -> rusqlite::Result<GiftMessage> {
+    Ok(GiftMessage {
+        id: row.get(0)?,
+        room: row.get::<_, i64>(1)? as u64,
+        sender: Sender {
+            uid: row.get::<_, i64>(2)? as u64,
+            uname: row.get(3)?,
+            face: row.get(4)?,
+            medal_info: MedalInfo {
+                medal_level: row.get::<_, i64>(5)? as u8,
+                medal_name: row.get(6)?,
+                ..Default::default()
+            },
+        },
+        gift_info: crate::messages::GiftInfo {
+            id: row.get::<_, i64>(7)? as u64,
+            name: row.get(8)?,
+            price: row.get::<_, i64>(9)? as u64,
+            coin_type: row.get(10)?,
+            img_basic: String::new(),
+            img_dynamic: String::new(),
+            gif: String::new(),
+            webp: String::new(),
+        },
+        action: row.get(11)?,
+        num: row.get::<_, i64>(12)? as u32,
+        timestamp: row.get(13)?,
+        archived: row.get::<_, i64>(14)? != 0,
+    })
+}
+
+/// Map a row selected with `GUARD_COLUMNS`
+fn guard_from_row(row: &rusqlite::Row) -> rusqlite::Result<GuardMessage> {
+    Ok(GuardMessage {
+        id: row.get(0)?,
+        room: row.get::<_, i64>(1)? as u64,
+        sender: Sender {
+            uid: row.get::<_, i64>(2)? as u64,
+            uname: row.get(3)?,
+            face: row.get(4)?,
+            medal_info: MedalInfo::default(),
+        },
+        num: row.get::<_, i64>(5)? as u32,
+        unit: row.get(6)?,
+        guard_level: row.get::<_, i64>(7)? as u8,
+        price: row.get::<_, i64>(8)? as u64,
+        timestamp: row.get(9)?,
+        archived: row.get::<_, i64>(10)? != 0,
+    })
+}
+
+/// Map a row selected with `SUPERCHAT_COLUMNS`
+fn superchat_from_row(row: &rusqlite::Row) -> rusqlite::Result<SuperChatMessage> {
+    Ok(SuperChatMessage {
+        id: row.get(0)?,
+        room: row.get::<_, i64>(1)? as u64,
+        sender: Sender {
+            uid: row.get::<_, i64>(2)? as u64,
+            uname: row.get(3)?,
+            face: row.get(4)?,
+            medal_info: MedalInfo {
+                medal_level: row.get::<_, i64>(5)? as u8,
+                medal_name: row.get(6)?,
+                ..Default::default()
+            },
+        },
+        message: row.get(7)?,
+        price: row.get::<_, i64>(8)? as u64,
+        start_time: row.get(9)?,
+        end_time: row.get(10)?,
+        background_color: row.get(11)?,
+        background_bottom_color: row.get(12)?,
+        timestamp: row.get(13)?,
+        archived: row.get::<_, i64>(14)? != 0,
+    })
+}
+
+/// Columns read by `goal_from_row`
+const GOAL_COLUMNS: &str = "id, room_id, title, target, start_time, sources, completed_at";
+
+/// Map a row selected with `LOTTERY_COLUMNS`
+fn lottery_from_row(row: &rusqlite::Row) -> rusqlite::Result<LotteryRecord> {
+    let winners: Option<String> = row.get(8)?;
+    Ok(LotteryRecord {
+        id: row.get(0)?,
+        room: row.get::<_, i64>(1)? as u64,
+        kind: row.get(2)?,
+        sender_uid: row.get::<_, i64>(3)? as u64,
+        sender_uname: row.get(4)?,
+        award_name: row.get(5)?,
+        requirement: row.get(6)?,
+        total_price: row.get::<_, i64>(7)? as u64,
+        winners: winners
+            .and_then(|w| serde_json::from_str(&w).ok())
+            .unwrap_or_default(),
+        start_time: row.get(9)?,
+        end_time: row.get(10)?,
+    })
+}
+
+/// Map a row selected with `GOAL_COLUMNS`, progress is left at 0
+fn goal_from_row(row: &rusqlite::Row) -> rusqlite::Result<Goal> {
+    let sources: String = row.get(5)?;
+    Ok(Goal {
+        id: row.get(0)?,
+        room_id: row.get::<_, i64>(1)? as u64,
+        title: row.get(2)?,
+        target: row.get::<_, i64>(3)? as u64,
+        start_time: row.get(4)?,
+        sources: Goal::parse_sources(&sources),
+        progress: 0,
+        completed_at: row.get(6)?,
+    })
+}
+
+/// Map a row of `webhook_deliveries`
+fn webhook_delivery_from_row(row: &rusqlite::Row) -> rusqlite::Result<WebhookDelivery> {
+    Ok(WebhookDelivery {
+        id: row.get(0)?,
+        url: row.get(1)?,
+        channels: row.get(2)?,
+        event_count: row.get::<_, i64>(3)? as usize,
+        status: row.get::<_, i64>(4)? as u16,
+        success: row.get::<_, i64>(5)? != 0,
+        attempts: row.get::<_, i64>(6)? as u32,
+        error: row.get(7)?,
+        timestamp: row.get(8)?,
+    })
+}
+
+/// Map a row of `recordings`
+fn recording_from_row(row: &rusqlite::Row) -> rusqlite::Result<Recording> {
+    Ok(Recording {
+        id: row.get(0)?,
+        room_id: row.get::<_, i64>(1)? as u64,
+        session_start: row.get(2)?,
+        start_time: row.get(3)?,
+        end_time: row.get(4)?,
+        path: row.get(5)?,
+        size: row.get::<_, i64>(6)? as u64,
+    })
+}
+
+/// Number of webhook deliveries kept in the log
+const WEBHOOK_DELIVERY_LOG_SIZE: i64 = 200;
+
+/// Read connections of a file database
+const READ_POOL_SIZE: usize = 4;
+
+/// How long a statement waits for a lock held by another connection
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Insert a danmu with an open connection or transaction
+fn insert_danmu_with(conn: &Connection, room_id: u64, danmu: &DanmuMessage) -> Result<()> {
+    conn.prepare_cached(
+        "INSERT INTO danmus (
+            room_id, sender_uid, sender_uname, sender_face,
+            medal_level, medal_name, medal_anchor_uname, medal_anchor_roomid, medal_guard_level,
+            content, is_special, timestamp,
+            medal_color, medal_color_border, medal_color_start, medal_color_end, medal_is_lighted,
+            is_generated, is_mirror, side_index, emoji_content, reply_uname
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12,
+            ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22)",
+    )?
+    .execute(params![
+        room_id as i64,
+        danmu.sender.uid as i64,
+        danmu.sender.uname,
+        danmu.sender.face,
+        danmu.sender.medal_info.medal_level as i64,
+        danmu.sender.medal_info.medal_name,
+        danmu.sender.medal_info.anchor_uname,
+        danmu.sender.medal_info.anchor_roomid as i64,
+        danmu.sender.medal_info.guard_level as i64,
+        danmu.content,
+        danmu.is_special as i64,
+        stored_timestamp(danmu.timestamp),
+        danmu.sender.medal_info.medal_color as i64,
+        danmu.sender.medal_info.medal_color_border as i64,
+        danmu.sender.medal_info.medal_color_start as i64,
+        danmu.sender.medal_info.medal_color_end as i64,
+        danmu.sender.medal_info.is_lighted as i64,
+        danmu.is_generated as i64,
+        danmu.is_mirror as i64,
+        danmu.side_index as i64,
+        danmu
+            .emoji_content
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()?,
+        danmu.reply_uname,
+    ])?;
+    Ok(())
+}
+
+/// Insert a gift with an open connection or transaction
+fn insert_gift_with(conn: &Connection, gift: &GiftMessage) -> Result<()> {
+    conn.prepare_cached(
+        "INSERT OR REPLACE INTO gifts (
+            id, room_id, sender_uid, sender_uname, sender_face,
+            medal_level, medal_name,
+            gift_id, gift_name, gift_price, coin_type, action, num, timestamp
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+    )?
+    .execute(params![
+        gift.id,
+        gift.room as i64,
+        gift.sender.uid as i64,
+        gift.sender.uname,
+        gift.sender.face,
+        gift.sender.medal_info.medal_level as i64,
+        gift.sender.medal_info.medal_name,
+        gift.gift_info.id as i64,
+        gift.gift_info.name,
+        gift.gift_info.price as i64,
+        gift.gift_info.coin_type,
+        gift.action,
+        gift.num as i64,
+        gift.timestamp,
+    ])?;
+    Ok(())
+}
+
+/// Insert a guard with an open connection or transaction
+fn insert_guard_with(conn: &Connection, guard: &GuardMessage) -> Result<()> {
+    conn.prepare_cached(
+        "INSERT OR REPLACE INTO guards (
+            id, room_id, sender_uid, sender_uname, sender_face,
+            num, unit, guard_level, price, timestamp
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+    )?
+    .execute(params![
+        guard.id,
+        guard.room as i64,
+        guard.sender.uid as i64,
+        guard.sender.uname,
+        guard.sender.face,
+        guard.num as i64,
+        guard.unit,
+        guard.guard_level as i64,
+        guard.price as i64,
+        guard.timestamp,
+    ])?;
+    Ok(())
+}
+
+/// Insert a superchat with an open connection or transaction
+fn insert_superchat_with(conn: &Connection, sc: &SuperChatMessage) -> Result<()> {
+    conn.prepare_cached(
+        "INSERT OR REPLACE INTO superchats (
+            id, room_id, sender_uid, sender_uname, sender_face,
+            medal_level, medal_name,
+            message, price, start_time, end_time,
+            background_color, background_bottom_color, timestamp
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+    )?
+    .execute(params![
+        sc.id,
+        sc.room as i64,
+        sc.sender.uid as i64,
+        sc.sender.uname,
+        sc.sender.face,
+        sc.sender.medal_info.medal_level as i64,
+        sc.sender.medal_info.medal_name,
+        sc.message,
+        sc.price as i64,
+        sc.start_time,
+        sc.end_time,
+        sc.background_color,
+        sc.background_bottom_color,
+        sc.timestamp,
+    ])?;
+    Ok(())
+}
+
+fn insert_pending_with(conn: &Connection, write: &PendingWrite) -> Result<()> {
+    match write {
+        PendingWrite::Danmu { room_id, danmu } => insert_danmu_with(conn, *room_id, danmu),
+        PendingWrite::Gift(gift) => insert_gift_with(conn, gift),
+        PendingWrite::Guard(guard) => insert_guard_with(conn, guard),
+        PendingWrite::SuperChat(sc) => insert_superchat_with(conn, sc),
+    }
+}
+
+fn insert_pending_transaction(conn: &mut Connection, batch: &[PendingWrite]) -> Result<()> {
+    let tx = conn.transaction()?;
+    for write in batch {
+        insert_pending_with(&tx, write)?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Store a batch in one transaction, falling back to single inserts so one
+/// bad message doesn't lose the whole batch
+fn insert_pending_batch(conn: &mut Connection, batch: &[PendingWrite]) -> Result<()> {
+    if let Err(e) = insert_pending_transaction(conn, batch) {
+        warn!("Batch insert failed, storing messages one by one: {}", e);
+        for write in batch {
+            if let Err(e) = insert_pending_with(conn, write) {
+                warn!("Failed to store message: {}", e);
+            }
+        }
+    }
+    Ok(())
+}
 
 /// Database store for JLiverTool
 #[derive(Clone)]
 pub struct Database {
+    /// Connection for writes, also used for queries of in-memory databases
     conn: Arc<Mutex<Connection>>,
+    readers: Arc<Vec<Mutex<Connection>>>,
+    next_reader: Arc<AtomicUsize>,
+    writer: Arc<BatchWriter>,
 }
 
 impl Database {
     /// Create a new database connection
     pub fn new(path: &Path) -> Result<Self> {
         let conn = Connection::open(path)?;
-        let db = Self {
-            conn: Arc::new(Mutex::new(conn)),
-        };
-        db.init_tables()?;
-        Ok(db)
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        // WAL lets the read connections query while the writer commits
+        conn.query_row("PRAGMA journal_mode = WAL", [], |row| {
+            row.get::<_, String>(0)
+        })?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+
+        let readers = (0..READ_POOL_SIZE)
+            .map(|_| {
+                let reader = Connection::open(path)?;
+                reader.busy_timeout(BUSY_TIMEOUT)?;
+                Ok(Mutex::new(reader))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Self::with_connections(conn, readers)
     }
 
-    /// Create an in-memory database (for testing)
-    #[allow(dead_code)]
+    /// Create an in-memory database (for testing and replays)
     pub fn in_memory() -> Result<Self> {
-        let conn = Connection::open_in_memory()?;
+        Self::with_connections(Connection::open_in_memory()?, Vec::new())
+    }
+
+    fn with_connections(conn: Connection, readers: Vec<Mutex<Connection>>) -> Result<Self> {
+        let conn = Arc::new(Mutex::new(conn));
+        let writer_conn = conn.clone();
+        let writer = BatchWriter::spawn(
+            DEFAULT_BATCH_SIZE,
+            DEFAULT_FLUSH_INTERVAL,
+            Box::new(move |batch| insert_pending_batch(&mut writer_conn.lock(), batch)),
+        )?;
         let db = Self {
-            conn: Arc::new(Mutex::new(conn)),
+            conn,
+            readers: Arc::new(readers),
+            next_reader: Arc::new(AtomicUsize::new(0)),
+            writer: Arc::new(writer),
         };
         db.init_tables()?;
         Ok(db)
     }
 
+    /// Connection for queries, a free pooled read connection for file databases
+    fn read_conn(&self) -> MutexGuard<'_, Connection> {
+        if self.readers.is_empty() {
+            return self.conn.lock();
+        }
+        let start = self.next_reader.fetch_add(1, Ordering::Relaxed);
+        let len = self.readers.len();
+        (0..len)
+            .find_map(|i| self.readers[(start + i) % len].try_lock())
+            .unwrap_or_else(|| self.readers[start % len].lock())
+    }
+
+    /// Queue a write for the background writer, storing it directly once
+    /// the writer has been shut down
+    fn queue(&self, write: PendingWrite) {
+        if let Some(write) = self.writer.push(write) {
+            if let Err(e) = insert_pending_with(&self.conn.lock(), &write) {
+                warn!("Failed to store message: {}", e);
+            }
+        }
+    }
+
+    /// Queue a danmu to be stored in the next batch
+    pub fn queue_danmu(&self, room_id: u64, danmu: DanmuMessage) {
+        self.queue(PendingWrite::Danmu { room_id, danmu });
+    }
+
+    /// Queue a gift to be stored in the next batch
+    pub fn queue_gift(&self, gift: GiftMessage) {
+        self.queue(PendingWrite::Gift(gift));
+    }
+
+    /// Queue a guard to be stored in the next batch
+    pub fn queue_guard(&self, guard: GuardMessage) {
+        self.queue(PendingWrite::Guard(guard));
+    }
+
+    /// Queue a superchat to be stored in the next batch
+    pub fn queue_superchat(&self, sc: SuperChatMessage) {
+        self.queue(PendingWrite::SuperChat(sc));
+    }
+
+    /// Events that carry something to store
+    pub const STORED_EVENT_TYPES: &'static [EventType] = &[
+        EventType::NewDanmu,
+        EventType::NewGift,
+        EventType::NewGuard,
+        EventType::NewSuperChat,
+        EventType::RedPocketStart,
+        EventType::RedPocketWinners,
+        EventType::AnchorLotStart,
+        EventType::AnchorLotAward,
+        EventType::SuperChatDeleted,
+    ];
+
+    /// Store what a live event of `room_id` carries
+    /// Messages are queued for the background writer, lotteries and
+    /// superchat deletions are written directly
+    pub fn store_event(&self, room_id: u64, event: &Event) {
+        let result = match event {
+            // Generated danmus are not sent by viewers
+            Event::NewDanmu(danmu) if !danmu.is_generated => {
+                self.queue_danmu(room_id, danmu.clone());
+                Ok(())
+            }
+            Event::NewGift(gift) => {
+                self.queue_gift(gift.clone());
+                Ok(())
+            }
+            Event::NewGuard(guard) => {
+                self.queue_guard(guard.clone());
+                Ok(())
+            }
+            Event::NewSuperChat(sc) => {
+                self.queue_superchat(sc.clone());
+                Ok(())
+            }
+            Event::RedPocketStart(pocket) => {
+                self.insert_lottery(&LotteryRecord::from_red_pocket(room_id, pocket))
+            }
+            Event::RedPocketWinners(result) => self.set_lottery_winners(
+                &LotteryRecord::red_pocket_id(result.lot_id),
+                &result.winners,
+            ),
+            Event::AnchorLotStart(lot) => {
+                self.insert_lottery(&LotteryRecord::from_anchor_lot(room_id, lot))
+            }
+            Event::AnchorLotAward(award) => {
+                self.set_lottery_winners(&LotteryRecord::anchor_id(award.id), &award.winners)
+            }
+            Event::SuperChatDeleted { ids } => {
+                ids.iter().try_for_each(|id| self.delete_superchat(id))
+            }
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            warn!("Failed to store event: {}", e);
+        }
+    }
+
+    /// Wait until all queued messages are stored
+    pub fn flush(&self) {
+        self.writer.flush();
+    }
+
+    /// Store all queued messages and stop the background writer, later
+    /// writes are stored directly
+    pub fn close(&self) {
+        self.writer.shutdown();
+    }
+
     /// Initialize database tables
     fn init_tables(&self) -> Result<()> {
         let conn = self.conn.lock();
@@ -56,11 +595,38 @@ impl Database {
                 content TEXT NOT NULL,
                 is_special INTEGER DEFAULT 0,
                 timestamp INTEGER NOT NULL,
-                created_at INTEGER DEFAULT (strftime('%s', 'now'))
+                created_at INTEGER DEFAULT (strftime('%s', 'now')),
+                medal_color INTEGER,
+                medal_color_border INTEGER,
+                medal_color_start INTEGER,
+                medal_color_end INTEGER,
+                medal_is_lighted INTEGER,
+                is_generated INTEGER,
+                is_mirror INTEGER,
+                side_index INTEGER,
+                emoji_content TEXT,
+                reply_uname TEXT
             )",
             [],
         )?;
 
+        // Add the columns of the full danmu if they don't exist (migration for
+        // existing databases), old rows keep NULL and are read with defaults
+        for column in [
+            "medal_color INTEGER",
+            "medal_color_border INTEGER",
+            "medal_color_start INTEGER",
+            "medal_color_end INTEGER",
+            "medal_is_lighted INTEGER",
+            "is_generated INTEGER",
+            "is_mirror INTEGER",
+            "side_index INTEGER",
+            "emoji_content TEXT",
+            "reply_uname TEXT",
+        ] {
+            let _ = conn.execute(&format!("ALTER TABLE danmus ADD COLUMN {}", column), []);
+        }
+
         // Gift messages table
         conn.execute(
             "CREATE TABLE IF NOT EXISTS gifts (
@@ -135,6 +701,70 @@ impl Database {
         // Add archived column if it doesn't exist (migration for existing databases)
         let _ = conn.execute("ALTER TABLE superchats ADD COLUMN archived INTEGER DEFAULT 0", []);
 
+        // Lotteries table (red pockets and anchor lotteries)
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS lotteries (
+                id TEXT PRIMARY KEY,
+                room_id INTEGER NOT NULL,
+                kind TEXT NOT NULL,
+                sender_uid INTEGER NOT NULL,
+                sender_uname TEXT NOT NULL,
+                award_name TEXT NOT NULL,
+                requirement TEXT,
+                total_price INTEGER DEFAULT 0,
+                winners TEXT,
+                start_time INTEGER NOT NULL,
+                end_time INTEGER NOT NULL,
+                created_at INTEGER DEFAULT (strftime('%s', 'now'))
+            )",
+            [],
+        )?;
+
+        // Gift goals
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS goals (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                room_id INTEGER NOT NULL,
+                title TEXT NOT NULL,
+                target INTEGER NOT NULL,
+                start_time INTEGER NOT NULL,
+                sources TEXT NOT NULL,
+                completed_at INTEGER,
+                created_at INTEGER DEFAULT (strftime('%s', 'now'))
+            )",
+            [],
+        )?;
+
+        // Webhook delivery log, only the latest entries are kept
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS webhook_deliveries (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                url TEXT NOT NULL,
+                channels TEXT NOT NULL,
+                event_count INTEGER NOT NULL,
+                status INTEGER NOT NULL,
+                success INTEGER NOT NULL,
+                attempts INTEGER NOT NULL,
+                error TEXT NOT NULL,
+                timestamp INTEGER NOT NULL
+            )",
+            [],
+        )?;
+
+        // Recorded stream files, times in milliseconds
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS recordings (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                room_id INTEGER NOT NULL,
+                session_start INTEGER NOT NULL,
+                start_time INTEGER NOT NULL,
+                end_time INTEGER,
+                path TEXT NOT NULL,
+                size INTEGER NOT NULL DEFAULT 0
+            )",
+            [],
+        )?;
+
         // Create indexes for common queries
         conn.execute(
             "CREATE INDEX IF NOT EXISTS idx_danmus_room_timestamp ON danmus(room_id, timestamp DESC)",
@@ -152,35 +782,17 @@ impl Database {
             "CREATE INDEX IF NOT EXISTS idx_superchats_room_timestamp ON superchats(room_id, timestamp DESC)",
             [],
         )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_lotteries_room_start ON lotteries(room_id, start_time DESC)",
+            [],
+        )?;
 
         Ok(())
     }
 
     /// Insert a danmu message
     pub fn insert_danmu(&self, room_id: u64, danmu: &DanmuMessage) -> Result<()> {
-        let conn = self.conn.lock();
-        conn.execute(
-            "INSERT INTO danmus (
-                room_id, sender_uid, sender_uname, sender_face,
-                medal_level, medal_name, medal_anchor_uname, medal_anchor_roomid, medal_guard_level,
-                content, is_special, timestamp
-            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
-            params![
-                room_id as i64,
-                danmu.sender.uid as i64,
-                danmu.sender.uname,
-                danmu.sender.face,
-                danmu.sender.medal_info.medal_level as i64,
-                danmu.sender.medal_info.medal_name,
-                danmu.sender.medal_info.anchor_uname,
-                danmu.sender.medal_info.anchor_roomid as i64,
-                danmu.sender.medal_info.guard_level as i64,
-                danmu.content,
-                danmu.is_special as i64,
-                chrono::Utc::now().timestamp(),
-            ],
-        )?;
-        Ok(())
+        insert_danmu_with(&self.conn.lock(), room_id, danmu)
     }
 
     /// Insert multiple danmu messages in a single transaction (batch insert)
@@ -191,66 +803,16 @@ impl Database {
 
         let mut conn = self.conn.lock();
         let tx = conn.transaction()?;
-        let timestamp = chrono::Utc::now().timestamp();
-
-        {
-            let mut stmt = tx.prepare_cached(
-                "INSERT INTO danmus (
-                    room_id, sender_uid, sender_uname, sender_face,
-                    medal_level, medal_name, medal_anchor_uname, medal_anchor_roomid, medal_guard_level,
-                    content, is_special, timestamp
-                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)"
-            )?;
-
-            for danmu in danmus {
-                stmt.execute(params![
-                    room_id as i64,
-                    danmu.sender.uid as i64,
-                    danmu.sender.uname,
-                    danmu.sender.face,
-                    danmu.sender.medal_info.medal_level as i64,
-                    danmu.sender.medal_info.medal_name,
-                    danmu.sender.medal_info.anchor_uname,
-                    danmu.sender.medal_info.anchor_roomid as i64,
-                    danmu.sender.medal_info.guard_level as i64,
-                    danmu.content,
-                    danmu.is_special as i64,
-                    timestamp,
-                ])?;
-            }
+        for danmu in danmus {
+            insert_danmu_with(&tx, room_id, danmu)?;
         }
-
         tx.commit()?;
         Ok(())
     }
 
     /// Insert a gift message
     pub fn insert_gift(&self, gift: &GiftMessage) -> Result<()> {
-        let conn = self.conn.lock();
-        conn.execute(
-            "INSERT OR REPLACE INTO gifts (
-                id, room_id, sender_uid, sender_uname, sender_face,
-                medal_level, medal_name,
-                gift_id, gift_name, gift_price, coin_type, action, num, timestamp
-            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
-            params![
-                gift.id,
-                gift.room as i64,
-                gift.sender.uid as i64,
-                gift.sender.uname,
-                gift.sender.face,
-                gift.sender.medal_info.medal_level as i64,
-                gift.sender.medal_info.medal_name,
-                gift.gift_info.id as i64,
-                gift.gift_info.name,
-                gift.gift_info.price as i64,
-                gift.gift_info.coin_type,
-                gift.action,
-                gift.num as i64,
-                gift.timestamp,
-            ],
-        )?;
-        Ok(())
+        insert_gift_with(&self.conn.lock(), gift)
     }
 
     /// Insert multiple gift messages in a single transaction (batch insert)
@@ -261,177 +823,91 @@ impl Database {
 
         let mut conn = self.conn.lock();
         let tx = conn.transaction()?;
-
-        {
-            let mut stmt = tx.prepare_cached(
-                "INSERT OR REPLACE INTO gifts (
-                    id, room_id, sender_uid, sender_uname, sender_face,
-                    medal_level, medal_name,
-                    gift_id, gift_name, gift_price, coin_type, action, num, timestamp
-                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)"
-            )?;
-
-            for gift in gifts {
-                stmt.execute(params![
-                    gift.id,
-                    gift.room as i64,
-                    gift.sender.uid as i64,
-                    gift.sender.uname,
-                    gift.sender.face,
-                    gift.sender.medal_info.medal_level as i64,
-                    gift.sender.medal_info.medal_name,
-                    gift.gift_info.id as i64,
-                    gift.gift_info.name,
-                    gift.gift_info.price as i64,
-                    gift.gift_info.coin_type,
-                    gift.action,
-                    gift.num as i64,
-                    gift.timestamp,
-                ])?;
-            }
+        for gift in gifts {
+            insert_gift_with(&tx, gift)?;
         }
-
         tx.commit()?;
         Ok(())
     }
 
     /// Insert a guard message
     pub fn insert_guard(&self, guard: &GuardMessage) -> Result<()> {
-        let conn = self.conn.lock();
-        conn.execute(
-            "INSERT OR REPLACE INTO guards (
-                id, room_id, sender_uid, sender_uname, sender_face,
-                num, unit, guard_level, price, timestamp
-            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
-            params![
-                guard.id,
-                guard.room as i64,
-                guard.sender.uid as i64,
-                guard.sender.uname,
-                guard.sender.face,
-                guard.num as i64,
-                guard.unit,
-                guard.guard_level as i64,
-                guard.price as i64,
-                guard.timestamp,
-            ],
-        )?;
-        Ok(())
+        insert_guard_with(&self.conn.lock(), guard)
     }
 
     /// Insert a superchat message
     pub fn insert_superchat(&self, sc: &SuperChatMessage) -> Result<()> {
-        let conn = self.conn.lock();
-        conn.execute(
-            "INSERT OR REPLACE INTO superchats (
-                id, room_id, sender_uid, sender_uname, sender_face,
-                medal_level, medal_name,
-                message, price, start_time, end_time,
-                background_color, background_bottom_color, timestamp
-            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
-            params![
-                sc.id,
-                sc.room as i64,
-                sc.sender.uid as i64,
-                sc.sender.uname,
-                sc.sender.face,
-                sc.sender.medal_info.medal_level as i64,
-                sc.sender.medal_info.medal_name,
-                sc.message,
-                sc.price as i64,
-                sc.start_time,
-                sc.end_time,
-                sc.background_color,
-                sc.background_bottom_color,
-                sc.timestamp,
-            ],
-        )?;
-        Ok(())
+        insert_superchat_with(&self.conn.lock(), sc)
     }
 
     /// Get recent gifts for a room
     pub fn get_recent_gifts(&self, room_id: u64, limit: usize) -> Result<Vec<GiftMessage>> {
-        let conn = self.conn.lock();
-        let mut stmt = conn.prepare(
-            "SELECT id, room_id, sender_uid, sender_uname, sender_face,
-                    medal_level, medal_name,
-                    gift_id, gift_name, gift_price, coin_type, action, num, timestamp,
-                    COALESCE(archived, 0) as archived
-             FROM gifts
+        let conn = self.read_conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM gifts
              WHERE room_id = ?1
              ORDER BY timestamp DESC
              LIMIT ?2",
-        )?;
+            GIFT_COLUMNS
+        ))?;
 
         let gifts = stmt
-            .query_map(params![room_id as i64, limit as i64], |row| {
-                Ok(GiftMessage {
-                    id: row.get(0)?,
-                    room: row.get::<_, i64>(1)? as u64,
-                    sender: Sender {
-                        uid: row.get::<_, i64>(2)? as u64,
-                        uname: row.get(3)?,
-                        face: row.get(4)?,
-                        medal_info: MedalInfo {
-                            medal_level: row.get::<_, i64>(5)? as u8,
-                            medal_name: row.get(6)?,
-                            ..Default::default()
-                        },
-                    },
-                    gift_info: crate::messages::GiftInfo {
-                        id: row.get::<_, i64>(7)? as u64,
-                        name: row.get(8)?,
-                        price: row.get::<_, i64>(9)? as u64,
-                        coin_type: row.get(10)?,
-                        img_basic: String::new(),
-                        img_dynamic: String::new(),
-                        gif: String::new(),
-                        webp: String::new(),
-                    },
-                    action: row.get(11)?,
-                    num: row.get::<_, i64>(12)? as u32,
-                    timestamp: row.get(13)?,
-                    archived: row.get::<_, i64>(14)? != 0,
-                })
-            })?
+            .query_map(params![room_id as i64, limit as i64], gift_from_row)?
             .collect::<Result<Vec<_>, _>>()?;
 
         // Reverse to get chronological order
         Ok(gifts.into_iter().rev().collect())
     }
 
+    /// Get the latest gifts at or after a unix timestamp, in chronological order
+    pub fn get_gifts_since(&self, room_id: u64, since: i64, limit: usize) -> Result<Vec<GiftMessage>> {
+        let conn = self.read_conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM gifts
+             WHERE room_id = ?1 AND timestamp >= ?2
+             ORDER BY timestamp DESC
+             LIMIT ?3",
+            GIFT_COLUMNS
+        ))?;
+
+        let gifts = stmt
+            .query_map(params![room_id as i64, since, limit as i64], gift_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(gifts.into_iter().rev().collect())
+    }
+
     /// Get recent guards for a room
     pub fn get_recent_guards(&self, room_id: u64, limit: usize) -> Result<Vec<GuardMessage>> {
-        let conn = self.conn.lock();
-        let mut stmt = conn.prepare(
-            "SELECT id, room_id, sender_uid, sender_uname, sender_face,
-                    num, unit, guard_level, price, timestamp,
-                    COALESCE(archived, 0) as archived
-             FROM guards
+        let conn = self.read_conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM guards
              WHERE room_id = ?1
              ORDER BY timestamp DESC
              LIMIT ?2",
-        )?;
+            GUARD_COLUMNS
+        ))?;
 
         let guards = stmt
-            .query_map(params![room_id as i64, limit as i64], |row| {
-                Ok(GuardMessage {
-                    id: row.get(0)?,
-                    room: row.get::<_, i64>(1)? as u64,
-                    sender: Sender {
-                        uid: row.get::<_, i64>(2)? as u64,
-                        uname: row.get(3)?,
-                        face: row.get(4)?,
-                        medal_info: MedalInfo::default(),
-                    },
-                    num: row.get::<_, i64>(5)? as u32,
-                    unit: row.get(6)?,
-                    guard_level: row.get::<_, i64>(7)? as u8,
-                    price: row.get::<_, i64>(8)? as u64,
-                    timestamp: row.get(9)?,
-                    archived: row.get::<_, i64>(10)? != 0,
-                })
-            })?
+            .query_map(params![room_id as i64, limit as i64], guard_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(guards.into_iter().rev().collect())
+    }
+
+    /// Get the latest guards at or after a unix timestamp, in chronological order
+    pub fn get_guards_since(&self, room_id: u64, since: i64, limit: usize) -> Result<Vec<GuardMessage>> {
+        let conn = self.read_conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM guards
+             WHERE room_id = ?1 AND timestamp >= ?2
+             ORDER BY timestamp DESC
+             LIMIT ?3",
+            GUARD_COLUMNS
+        ))?;
+
+        let guards = stmt
+            .query_map(params![room_id as i64, since, limit as i64], guard_from_row)?
             .collect::<Result<Vec<_>, _>>()?;
 
         Ok(guards.into_iter().rev().collect())
@@ -439,173 +915,518 @@ impl Database {
 
     /// Get recent superchats for a room
     pub fn get_recent_superchats(&self, room_id: u64, limit: usize) -> Result<Vec<SuperChatMessage>> {
-        let conn = self.conn.lock();
-        let mut stmt = conn.prepare(
-            "SELECT id, room_id, sender_uid, sender_uname, sender_face,
-                    medal_level, medal_name,
-                    message, price, start_time, end_time,
-                    background_color, background_bottom_color, timestamp,
-                    COALESCE(archived, 0) as archived
-             FROM superchats
+        let conn = self.read_conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM superchats
              WHERE room_id = ?1
              ORDER BY timestamp DESC
              LIMIT ?2",
-        )?;
+            SUPERCHAT_COLUMNS
+        ))?;
+
+        let scs = stmt
+            .query_map(params![room_id as i64, limit as i64], superchat_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(scs.into_iter().rev().collect())
+    }
+
+    /// Get the latest superchats at or after a unix timestamp, in chronological order
+    pub fn get_superchats_since(
+        &self,
+        room_id: u64,
+        since: i64,
+        limit: usize,
+    ) -> Result<Vec<SuperChatMessage>> {
+        let conn = self.read_conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM superchats
+             WHERE room_id = ?1 AND timestamp >= ?2
+             ORDER BY timestamp DESC
+             LIMIT ?3",
+            SUPERCHAT_COLUMNS
+        ))?;
 
         let scs = stmt
+            .query_map(params![room_id as i64, since, limit as i64], superchat_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(scs.into_iter().rev().collect())
+    }
+
+    /// Get recent danmus for a room
+    pub fn get_recent_danmus(&self, room_id: u64, limit: usize) -> Result<Vec<DanmuMessage>> {
+        let conn = self.read_conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM danmus
+             WHERE room_id = ?1
+             ORDER BY timestamp DESC
+             LIMIT ?2",
+            DANMU_COLUMNS
+        ))?;
+
+        let danmus = stmt
             .query_map(params![room_id as i64, limit as i64], |row| {
-                Ok(SuperChatMessage {
-                    id: row.get(0)?,
-                    room: row.get::<_, i64>(1)? as u64,
-                    sender: Sender {
-                        uid: row.get::<_, i64>(2)? as u64,
-                        uname: row.get(3)?,
-                        face: row.get(4)?,
-                        medal_info: MedalInfo {
-                            medal_level: row.get::<_, i64>(5)? as u8,
-                            medal_name: row.get(6)?,
-                            ..Default::default()
-                        },
-                    },
-                    message: row.get(7)?,
-                    price: row.get::<_, i64>(8)? as u64,
-                    start_time: row.get(9)?,
-                    end_time: row.get(10)?,
-                    background_color: row.get(11)?,
-                    background_bottom_color: row.get(12)?,
-                    timestamp: row.get(13)?,
-                    archived: row.get::<_, i64>(14)? != 0,
-                })
+                danmu_from_row(row).map(|(_, danmu)| danmu)
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(danmus.into_iter().rev().collect())
+    }
+
+    /// Get danmus from the last N minutes
+    pub fn get_danmus_since(&self, room_id: u64, minutes: i64) -> Result<Vec<DanmuMessage>> {
+        let since_timestamp = chrono::Utc::now().timestamp() - (minutes * 60);
+        let danmus = self.get_timed_danmus_since(room_id, since_timestamp, usize::MAX)?;
+        Ok(danmus.into_iter().map(|(_, danmu)| danmu).collect())
+    }
+
+    /// Get the latest danmus at or after a unix timestamp with their timestamps,
+    /// in chronological order
+    pub fn get_timed_danmus_since(
+        &self,
+        room_id: u64,
+        since: i64,
+        limit: usize,
+    ) -> Result<Vec<(i64, DanmuMessage)>> {
+        let conn = self.read_conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM danmus
+             WHERE room_id = ?1 AND timestamp >= ?2
+             ORDER BY timestamp DESC
+             LIMIT ?3",
+            DANMU_COLUMNS
+        ))?;
+
+        let limit = limit.min(i64::MAX as usize) as i64;
+        let danmus = stmt
+            .query_map(params![room_id as i64, since, limit], danmu_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(danmus.into_iter().rev().collect())
+    }
+
+    /// Get danmus by user UID with timestamp
+    pub fn get_danmus_by_user(
+        &self,
+        room_id: u64,
+        uid: u64,
+        limit: usize,
+    ) -> Result<Vec<(String, i64)>> {
+        let conn = self.read_conn();
+        let mut stmt = conn.prepare(
+            "SELECT content, timestamp
+             FROM danmus
+             WHERE room_id = ?1 AND sender_uid = ?2
+             ORDER BY timestamp DESC
+             LIMIT ?3",
+        )?;
+
+        let danmus = stmt
+            .query_map(params![room_id as i64, uid as i64, limit as i64], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
             })?
             .collect::<Result<Vec<_>, _>>()?;
 
-        Ok(scs.into_iter().rev().collect())
+        Ok(danmus)
+    }
+
+    /// Get danmus sent in `[start, end)`, in chronological order
+    pub fn get_danmus_between(&self, room_id: u64, start: i64, end: i64) -> Result<Vec<DanmuMessage>> {
+        let conn = self.read_conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM danmus
+             WHERE room_id = ?1 AND timestamp >= ?2 AND timestamp < ?3
+             ORDER BY timestamp ASC, id ASC",
+            DANMU_COLUMNS
+        ))?;
+
+        let danmus = stmt
+            .query_map(params![room_id as i64, start, end], danmu_from_row)?
+            .map(|r| r.map(|(_, danmu)| danmu))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(danmus)
+    }
+
+    /// Get gifts sent in `[start, end)`, in chronological order
+    pub fn get_gifts_between(&self, room_id: u64, start: i64, end: i64) -> Result<Vec<GiftMessage>> {
+        let conn = self.read_conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM gifts
+             WHERE room_id = ?1 AND timestamp >= ?2 AND timestamp < ?3
+             ORDER BY timestamp ASC",
+            GIFT_COLUMNS
+        ))?;
+
+        let gifts = stmt
+            .query_map(params![room_id as i64, start, end], gift_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(gifts)
+    }
+
+    /// Get superchats sent in `[start, end)`, in chronological order
+    pub fn get_superchats_between(
+        &self,
+        room_id: u64,
+        start: i64,
+        end: i64,
+    ) -> Result<Vec<SuperChatMessage>> {
+        let conn = self.read_conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM superchats
+             WHERE room_id = ?1 AND timestamp >= ?2 AND timestamp < ?3
+             ORDER BY timestamp ASC",
+            SUPERCHAT_COLUMNS
+        ))?;
+
+        let scs = stmt
+            .query_map(params![room_id as i64, start, end], superchat_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(scs)
+    }
+
+    /// Get gifts matching `filter`, in chronological order
+    pub fn query_gifts(&self, filter: &RecordFilter) -> Result<Vec<GiftMessage>> {
+        let (clause, values) = filter.where_clause();
+        let conn = self.read_conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM gifts WHERE {} ORDER BY timestamp ASC",
+            GIFT_COLUMNS, clause
+        ))?;
+
+        let gifts = stmt
+            .query_map(params_from_iter(values), gift_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(gifts)
+    }
+
+    /// Get guards matching `filter`, in chronological order
+    pub fn query_guards(&self, filter: &RecordFilter) -> Result<Vec<GuardMessage>> {
+        let (clause, values) = filter.where_clause();
+        let conn = self.read_conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM guards WHERE {} ORDER BY timestamp ASC",
+            GUARD_COLUMNS, clause
+        ))?;
+
+        let guards = stmt
+            .query_map(params_from_iter(values), guard_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(guards)
+    }
+
+    /// Get superchats matching `filter`, in chronological order
+    pub fn query_superchats(&self, filter: &RecordFilter) -> Result<Vec<SuperChatMessage>> {
+        let (clause, values) = filter.where_clause();
+        let conn = self.read_conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM superchats WHERE {} ORDER BY timestamp ASC",
+            SUPERCHAT_COLUMNS, clause
+        ))?;
+
+        let scs = stmt
+            .query_map(params_from_iter(values), superchat_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(scs)
+    }
+
+    /// Split the stored danmus of a room into sessions
+    /// A new session starts when no danmu was received for more than `max_gap` seconds
+    pub fn get_live_sessions(&self, room_id: u64, max_gap: i64) -> Result<Vec<LiveSession>> {
+        let conn = self.read_conn();
+        let mut stmt = conn.prepare(
+            "SELECT timestamp FROM danmus
+             WHERE room_id = ?1
+             ORDER BY timestamp ASC",
+        )?;
+        let timestamps = stmt
+            .query_map(params![room_id as i64], |row| row.get::<_, i64>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut sessions: Vec<LiveSession> = Vec::new();
+        for timestamp in timestamps {
+            match sessions.last_mut() {
+                Some(session) if timestamp - session.end <= max_gap => {
+                    session.end = timestamp;
+                    session.danmu_count += 1;
+                }
+                _ => sessions.push(LiveSession {
+                    start: timestamp,
+                    end: timestamp,
+                    danmu_count: 1,
+                }),
+            }
+        }
+
+        Ok(sessions)
+    }
+
+    /// Clear all data for a room
+    #[allow(dead_code)]
+    pub fn clear_room_data(&self, room_id: u64) -> Result<()> {
+        self.flush();
+        let conn = self.conn.lock();
+        conn.execute("DELETE FROM danmus WHERE room_id = ?1", params![room_id as i64])?;
+        conn.execute("DELETE FROM gifts WHERE room_id = ?1", params![room_id as i64])?;
+        conn.execute("DELETE FROM guards WHERE room_id = ?1", params![room_id as i64])?;
+        conn.execute("DELETE FROM superchats WHERE room_id = ?1", params![room_id as i64])?;
+        conn.execute("DELETE FROM lotteries WHERE room_id = ?1", params![room_id as i64])?;
+        Ok(())
+    }
+
+    /// Insert or update a lottery, keeping already recorded winners
+    pub fn insert_lottery(&self, lottery: &LotteryRecord) -> Result<()> {
+        let conn = self.conn.lock();
+        conn.execute(
+            "INSERT INTO lotteries (
+                id, room_id, kind, sender_uid, sender_uname,
+                award_name, requirement, total_price, start_time, end_time
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+            ON CONFLICT(id) DO UPDATE SET
+                award_name = excluded.award_name,
+                requirement = excluded.requirement,
+                total_price = excluded.total_price,
+                start_time = excluded.start_time,
+                end_time = excluded.end_time",
+            params![
+                lottery.id,
+                lottery.room as i64,
+                lottery.kind,
+                lottery.sender_uid as i64,
+                lottery.sender_uname,
+                lottery.award_name,
+                lottery.requirement,
+                lottery.total_price as i64,
+                lottery.start_time,
+                lottery.end_time,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Record the winners of a lottery
+    /// Does nothing if the lottery start was not recorded
+    pub fn set_lottery_winners(&self, id: &str, winners: &[LotteryWinner]) -> Result<()> {
+        let conn = self.conn.lock();
+        conn.execute(
+            "UPDATE lotteries SET winners = ?1 WHERE id = ?2",
+            params![serde_json::to_string(winners)?, id],
+        )?;
+        Ok(())
+    }
+
+    /// Get recent lotteries for a room, in chronological order
+    pub fn get_recent_lotteries(&self, room_id: u64, limit: usize) -> Result<Vec<LotteryRecord>> {
+        let conn = self.read_conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM lotteries
+             WHERE room_id = ?1
+             ORDER BY start_time DESC
+             LIMIT ?2",
+            LOTTERY_COLUMNS
+        ))?;
+
+        let lotteries = stmt
+            .query_map(params![room_id as i64, limit as i64], lottery_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(lotteries.into_iter().rev().collect())
+    }
+
+    /// Insert a goal, returns its id
+    pub fn insert_goal(&self, goal: &Goal) -> Result<i64> {
+        let conn = self.conn.lock();
+        conn.execute(
+            "INSERT INTO goals (room_id, title, target, start_time, sources, completed_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                goal.room_id as i64,
+                goal.title,
+                goal.target as i64,
+                goal.start_time,
+                goal.sources_str(),
+                goal.completed_at,
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Get the goals of a room that are not completed, oldest first
+    pub fn get_active_goals(&self, room_id: u64) -> Result<Vec<Goal>> {
+        let conn = self.read_conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM goals
+             WHERE room_id = ?1 AND completed_at IS NULL
+             ORDER BY id ASC",
+            GOAL_COLUMNS
+        ))?;
+
+        let goals = stmt
+            .query_map(params![room_id as i64], goal_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(goals)
     }
 
-    /// Get recent danmus for a room
-    pub fn get_recent_danmus(&self, room_id: u64, limit: usize) -> Result<Vec<DanmuMessage>> {
+    /// Restart a goal at `start_time`
+    pub fn set_goal_start_time(&self, id: i64, start_time: i64) -> Result<()> {
         let conn = self.conn.lock();
-        let mut stmt = conn.prepare(
-            "SELECT sender_uid, sender_uname, sender_face,
-                    medal_level, medal_name, medal_anchor_uname, medal_anchor_roomid, medal_guard_level,
-                    content, is_special
-             FROM danmus
-             WHERE room_id = ?1
-             ORDER BY timestamp DESC
-             LIMIT ?2",
+        conn.execute(
+            "UPDATE goals SET start_time = ?1 WHERE id = ?2",
+            params![start_time, id],
         )?;
-
-        let danmus = stmt
-            .query_map(params![room_id as i64, limit as i64], |row| {
-                Ok(DanmuMessage {
-                    sender: Sender {
-                        uid: row.get::<_, i64>(0)? as u64,
-                        uname: row.get(1)?,
-                        face: row.get(2)?,
-                        medal_info: MedalInfo {
-                            medal_level: row.get::<_, i64>(3)? as u8,
-                            medal_name: row.get(4)?,
-                            anchor_uname: row.get(5)?,
-                            anchor_roomid: row.get::<_, i64>(6)? as u64,
-                            guard_level: row.get::<_, i64>(7)? as u8,
-                            ..Default::default()
-                        },
-                    },
-                    content: row.get(8)?,
-                    is_special: row.get::<_, i64>(9)? != 0,
-                    is_mirror: false,
-                    is_generated: false,
-                    emoji_content: None,
-                    side_index: -1,
-                    reply_uname: None,
-                })
-            })?
-            .collect::<Result<Vec<_>, _>>()?;
-
-        Ok(danmus.into_iter().rev().collect())
+        Ok(())
     }
 
-    /// Get danmus from the last N minutes
-    pub fn get_danmus_since(&self, room_id: u64, minutes: i64) -> Result<Vec<DanmuMessage>> {
+    /// Mark a goal as completed
+    pub fn set_goal_completed(&self, id: i64, completed_at: i64) -> Result<()> {
         let conn = self.conn.lock();
-        let since_timestamp = chrono::Utc::now().timestamp() - (minutes * 60);
-        let mut stmt = conn.prepare(
-            "SELECT sender_uid, sender_uname, sender_face,
-                    medal_level, medal_name, medal_anchor_uname, medal_anchor_roomid, medal_guard_level,
-                    content, is_special
-             FROM danmus
-             WHERE room_id = ?1 AND timestamp >= ?2
-             ORDER BY timestamp ASC",
+        conn.execute(
+            "UPDATE goals SET completed_at = ?1 WHERE id = ?2",
+            params![completed_at, id],
         )?;
+        Ok(())
+    }
 
-        let danmus = stmt
-            .query_map(params![room_id as i64, since_timestamp], |row| {
-                Ok(DanmuMessage {
-                    sender: Sender {
-                        uid: row.get::<_, i64>(0)? as u64,
-                        uname: row.get(1)?,
-                        face: row.get(2)?,
-                        medal_info: MedalInfo {
-                            medal_level: row.get::<_, i64>(3)? as u8,
-                            medal_name: row.get(4)?,
-                            anchor_uname: row.get(5)?,
-                            anchor_roomid: row.get::<_, i64>(6)? as u64,
-                            guard_level: row.get::<_, i64>(7)? as u8,
-                            ..Default::default()
-                        },
-                    },
-                    content: row.get(8)?,
-                    is_special: row.get::<_, i64>(9)? != 0,
-                    is_generated: false,
-                    is_mirror: false,
-                    emoji_content: None,
-                    side_index: -1,
-                    reply_uname: None,
-                })
-            })?
-            .collect::<Result<Vec<_>, _>>()?;
+    /// Value in 1/1000 yuan of the records counting towards a goal, priced like
+    /// `get_time_based_stats_range`
+    pub fn get_goal_progress(&self, goal: &Goal) -> Result<u64> {
+        let conn = self.read_conn();
+        let params = params![goal.room_id as i64, goal.start_time];
+        let mut total: i64 = 0;
+
+        if goal.counts(EarningKind::Gift) {
+            total += conn.query_row(
+                "SELECT COALESCE(SUM(gift_price * num), 0) FROM gifts WHERE room_id = ?1 AND timestamp >= ?2 AND coin_type = 'gold'",
+                params,
+                |row| row.get::<_, i64>(0),
+            )?;
+        }
+        if goal.counts(EarningKind::Guard) {
+            total += conn.query_row(
+                "SELECT COALESCE(SUM(price), 0) FROM guards WHERE room_id = ?1 AND timestamp >= ?2",
+                params,
+                |row| row.get::<_, i64>(0),
+            )?;
+        }
+        if goal.counts(EarningKind::SuperChat) {
+            // Superchat prices are in yuan
+            total += 1000
+                * conn.query_row(
+                    "SELECT COALESCE(SUM(price), 0) FROM superchats WHERE room_id = ?1 AND timestamp >= ?2",
+                    params,
+                    |row| row.get::<_, i64>(0),
+                )?;
+        }
 
-        Ok(danmus)
+        Ok(total as u64)
     }
 
-    /// Get danmus by user UID with timestamp
-    pub fn get_danmus_by_user(
-        &self,
-        room_id: u64,
-        uid: u64,
-        limit: usize,
-    ) -> Result<Vec<(String, i64)>> {
+    /// Log a webhook delivery and drop old log entries
+    pub fn insert_webhook_delivery(&self, delivery: &WebhookDelivery) -> Result<()> {
         let conn = self.conn.lock();
-        let mut stmt = conn.prepare(
-            "SELECT content, timestamp
-             FROM danmus
-             WHERE room_id = ?1 AND sender_uid = ?2
-             ORDER BY timestamp DESC
-             LIMIT ?3",
+        conn.execute(
+            "INSERT INTO webhook_deliveries
+             (url, channels, event_count, status, success, attempts, error, timestamp)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                delivery.url,
+                delivery.channels,
+                delivery.event_count as i64,
+                delivery.status as i64,
+                delivery.success as i64,
+                delivery.attempts as i64,
+                delivery.error,
+                delivery.timestamp,
+            ],
+        )?;
+        conn.execute(
+            "DELETE FROM webhook_deliveries WHERE id <= ?1",
+            params![conn.last_insert_rowid() - WEBHOOK_DELIVERY_LOG_SIZE],
         )?;
+        Ok(())
+    }
 
-        let danmus = stmt
-            .query_map(params![room_id as i64, uid as i64, limit as i64], |row| {
-                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
-            })?
+    /// Get the latest webhook deliveries, newest first
+    pub fn get_webhook_deliveries(&self, limit: usize) -> Result<Vec<WebhookDelivery>> {
+        let conn = self.read_conn();
+        let mut stmt = conn.prepare(
+            "SELECT id, url, channels, event_count, status, success, attempts, error, timestamp
+             FROM webhook_deliveries ORDER BY id DESC LIMIT ?1",
+        )?;
+        let deliveries = stmt
+            .query_map(params![limit as i64], webhook_delivery_from_row)?
             .collect::<Result<Vec<_>, _>>()?;
+        Ok(deliveries)
+    }
 
-        Ok(danmus)
+    /// Insert a recording, returns its id
+    pub fn insert_recording(&self, recording: &Recording) -> Result<i64> {
+        let conn = self.conn.lock();
+        conn.execute(
+            "INSERT INTO recordings (room_id, session_start, start_time, end_time, path, size)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                recording.room_id as i64,
+                recording.session_start,
+                recording.start_time,
+                recording.end_time,
+                recording.path,
+                recording.size as i64,
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
     }
 
-    /// Clear all data for a room
-    #[allow(dead_code)]
-    pub fn clear_room_data(&self, room_id: u64) -> Result<()> {
+    /// Store the end time and final size of a recording
+    pub fn finish_recording(&self, id: i64, end_time: i64, size: u64) -> Result<()> {
         let conn = self.conn.lock();
-        conn.execute("DELETE FROM danmus WHERE room_id = ?1", params![room_id as i64])?;
-        conn.execute("DELETE FROM gifts WHERE room_id = ?1", params![room_id as i64])?;
-        conn.execute("DELETE FROM guards WHERE room_id = ?1", params![room_id as i64])?;
-        conn.execute("DELETE FROM superchats WHERE room_id = ?1", params![room_id as i64])?;
+        conn.execute(
+            "UPDATE recordings SET end_time = ?1, size = ?2 WHERE id = ?3",
+            params![end_time, size as i64, id],
+        )?;
         Ok(())
     }
 
+    /// Get the recordings of a room, oldest first
+    pub fn get_recordings(&self, room_id: u64) -> Result<Vec<Recording>> {
+        let conn = self.read_conn();
+        let mut stmt = conn.prepare(
+            "SELECT id, room_id, session_start, start_time, end_time, path, size
+             FROM recordings WHERE room_id = ?1 ORDER BY start_time ASC, id ASC",
+        )?;
+        let recordings = stmt
+            .query_map(params![room_id as i64], recording_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(recordings)
+    }
+
+    /// Earliest recording overlapping `[from, to)`, Unix timestamps in seconds.
+    /// Its start time is the 0:00 of exported danmu
+    pub fn first_recording_in(&self, room_id: u64, from: i64, to: i64) -> Result<Option<Recording>> {
+        let conn = self.read_conn();
+        let recording = conn
+            .query_row(
+                "SELECT id, room_id, session_start, start_time, end_time, path, size
+                 FROM recordings
+                 WHERE room_id = ?1 AND start_time < ?3 AND (end_time IS NULL OR end_time >= ?2)
+                 ORDER BY start_time ASC, id ASC LIMIT 1",
+                params![room_id as i64, from * 1000, to * 1000],
+                recording_from_row,
+            )
+            .optional()?;
+        Ok(recording)
+    }
+
     /// Update archived status for a gift
     pub fn set_gift_archived(&self, id: &str, archived: bool) -> Result<()> {
+        self.flush();
         let conn = self.conn.lock();
         conn.execute(
             "UPDATE gifts SET archived = ?1 WHERE id = ?2",
@@ -616,6 +1437,7 @@ impl Database {
 
     /// Update archived status for a guard
     pub fn set_guard_archived(&self, id: &str, archived: bool) -> Result<()> {
+        self.flush();
         let conn = self.conn.lock();
         conn.execute(
             "UPDATE guards SET archived = ?1 WHERE id = ?2",
@@ -626,6 +1448,7 @@ impl Database {
 
     /// Update archived status for a superchat
     pub fn set_superchat_archived(&self, id: &str, archived: bool) -> Result<()> {
+        self.flush();
         let conn = self.conn.lock();
         conn.execute(
             "UPDATE superchats SET archived = ?1 WHERE id = ?2",
@@ -636,6 +1459,7 @@ impl Database {
 
     /// Delete a gift by ID
     pub fn delete_gift(&self, id: &str) -> Result<()> {
+        self.flush();
         let conn = self.conn.lock();
         conn.execute("DELETE FROM gifts WHERE id = ?1", params![id])?;
         Ok(())
@@ -643,6 +1467,7 @@ impl Database {
 
     /// Delete a guard by ID
     pub fn delete_guard(&self, id: &str) -> Result<()> {
+        self.flush();
         let conn = self.conn.lock();
         conn.execute("DELETE FROM guards WHERE id = ?1", params![id])?;
         Ok(())
@@ -650,6 +1475,7 @@ impl Database {
 
     /// Delete a superchat by ID
     pub fn delete_superchat(&self, id: &str) -> Result<()> {
+        self.flush();
         let conn = self.conn.lock();
         conn.execute("DELETE FROM superchats WHERE id = ?1", params![id])?;
         Ok(())
@@ -657,6 +1483,7 @@ impl Database {
 
     /// Clear all gifts for a room
     pub fn clear_gifts(&self, room_id: u64) -> Result<()> {
+        self.flush();
         let conn = self.conn.lock();
         conn.execute("DELETE FROM gifts WHERE room_id = ?1", params![room_id as i64])?;
         Ok(())
@@ -664,6 +1491,7 @@ impl Database {
 
     /// Clear all guards for a room
     pub fn clear_guards(&self, room_id: u64) -> Result<()> {
+        self.flush();
         let conn = self.conn.lock();
         conn.execute("DELETE FROM guards WHERE room_id = ?1", params![room_id as i64])?;
         Ok(())
@@ -671,6 +1499,7 @@ impl Database {
 
     /// Clear all superchats for a room
     pub fn clear_superchats(&self, room_id: u64) -> Result<()> {
+        self.flush();
         let conn = self.conn.lock();
         conn.execute("DELETE FROM superchats WHERE room_id = ?1", params![room_id as i64])?;
         Ok(())
@@ -678,7 +1507,7 @@ impl Database {
 
     /// Get gift statistics for a room
     pub fn get_gift_stats(&self, room_id: u64) -> Result<GiftStats> {
-        let conn = self.conn.lock();
+        let conn = self.read_conn();
 
         // Total paid gifts value
         let total_paid: i64 = conn.query_row(
@@ -726,6 +1555,130 @@ impl GiftStats {
     }
 }
 
+/// Filter for gift, guard and superchat queries
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecordFilter {
+    /// Only records of this room, all rooms when None
+    pub room_id: Option<u64>,
+    /// Only records at or after this unix timestamp
+    pub from: Option<i64>,
+    /// Only records before this unix timestamp
+    pub to: Option<i64>,
+    /// Some(true) for archived records only, Some(false) to leave them out
+    pub archived: Option<bool>,
+}
+
+impl RecordFilter {
+    /// SQL condition and its parameters, shared by the gifts, guards and superchats tables
+    fn where_clause(&self) -> (String, Vec<i64>) {
+        let mut conditions = vec!["1 = 1".to_string()];
+        let mut values = Vec::new();
+        let mut push = |condition: &str, value: i64| {
+            values.push(value);
+            conditions.push(format!("{} ?{}", condition, values.len()));
+        };
+        if let Some(room_id) = self.room_id {
+            push("room_id =", room_id as i64);
+        }
+        if let Some(from) = self.from {
+            push("timestamp >=", from);
+        }
+        if let Some(to) = self.to {
+            push("timestamp <", to);
+        }
+        if let Some(archived) = self.archived {
+            push("COALESCE(archived, 0) =", archived as i64);
+        }
+        (conditions.join(" AND "), values)
+    }
+}
+
+/// A period of continuous danmu activity in a room
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LiveSession {
+    /// Unix timestamp of the first danmu
+    pub start: i64,
+    /// Unix timestamp of the last danmu
+    pub end: i64,
+    pub danmu_count: u64,
+}
+
+/// Kind of a `LotteryRecord` for popularity red pockets
+pub const LOTTERY_KIND_RED_POCKET: &str = "red_pocket";
+/// Kind of a `LotteryRecord` for anchor lotteries
+pub const LOTTERY_KIND_ANCHOR: &str = "anchor";
+
+/// A stored red pocket or anchor lottery
+#[derive(Debug, Clone, Default)]
+pub struct LotteryRecord {
+    /// `<kind>:<lot id>`, see `LotteryRecord::red_pocket_id` / `anchor_id`
+    pub id: String,
+    pub room: u64,
+    pub kind: String,
+    pub sender_uid: u64,
+    pub sender_uname: String,
+    pub award_name: String,
+    /// Danmu or gift required to join
+    pub requirement: String,
+    /// Total value in 1/1000 yuan (red pockets only)
+    pub total_price: u64,
+    pub winners: Vec<LotteryWinner>,
+    pub start_time: i64,
+    pub end_time: i64,
+}
+
+impl LotteryRecord {
+    pub fn red_pocket_id(lot_id: u64) -> String {
+        format!("{}:{}", LOTTERY_KIND_RED_POCKET, lot_id)
+    }
+
+    pub fn anchor_id(id: u64) -> String {
+        format!("{}:{}", LOTTERY_KIND_ANCHOR, id)
+    }
+
+    pub fn from_red_pocket(room_id: u64, pocket: &RedPocketStartMessage) -> Self {
+        let award_name = pocket
+            .awards
+            .iter()
+            .map(|a| format!("{}x{}", a.gift_name, a.num))
+            .collect::<Vec<_>>()
+            .join(", ");
+        Self {
+            id: Self::red_pocket_id(pocket.lot_id),
+            room: room_id,
+            kind: LOTTERY_KIND_RED_POCKET.to_string(),
+            sender_uid: pocket.sender.uid,
+            sender_uname: pocket.sender.uname.clone(),
+            award_name,
+            requirement: pocket.danmu.clone(),
+            total_price: pocket.total_price,
+            winners: Vec::new(),
+            start_time: stored_timestamp(pocket.start_time),
+            end_time: pocket.end_time,
+        }
+    }
+
+    pub fn from_anchor_lot(room_id: u64, lot: &AnchorLotStartMessage) -> Self {
+        let requirement = if !lot.danmu.is_empty() {
+            lot.danmu.clone()
+        } else if !lot.gift_name.is_empty() {
+            format!("{}x{}", lot.gift_name, lot.gift_num)
+        } else {
+            lot.require_text.clone()
+        };
+        Self {
+            id: Self::anchor_id(lot.id),
+            room: room_id,
+            kind: LOTTERY_KIND_ANCHOR.to_string(),
+            award_name: format!("{}x{}", lot.award_name, lot.award_num),
+            requirement,
+            start_time: stored_timestamp(lot.start_time),
+            end_time: lot.start_time + lot.duration,
+            ..Default::default()
+        }
+    }
+}
+
 /// Time-based statistics
 #[derive(Debug, Clone, Default)]
 pub struct TimeBasedStats {
@@ -752,7 +1705,7 @@ impl Database {
     /// Get statistics for a specific time period
     /// `since_timestamp` is the Unix timestamp to start counting from
     pub fn get_time_based_stats(&self, room_id: u64, since_timestamp: i64) -> Result<TimeBasedStats> {
-        let conn = self.conn.lock();
+        let conn = self.read_conn();
 
         // Danmu count
         let danmu_count: i64 = conn.query_row(
@@ -802,7 +1755,7 @@ impl Database {
     ) -> Result<Vec<TimeSeriesPoint>> {
         use std::collections::HashMap;
 
-        let conn = self.conn.lock();
+        let conn = self.read_conn();
         let now = chrono::Utc::now().timestamp();
 
         // Calculate number of buckets
@@ -940,7 +1893,7 @@ impl Database {
         start_timestamp: i64,
         end_timestamp: i64,
     ) -> Result<TimeBasedStats> {
-        let conn = self.conn.lock();
+        let conn = self.read_conn();
 
         // Danmu count
         let danmu_count: i64 = conn.query_row(
@@ -989,7 +1942,7 @@ impl Database {
     ) -> Result<Vec<TimeSeriesPoint>> {
         use std::collections::HashMap;
 
-        let conn = self.conn.lock();
+        let conn = self.read_conn();
 
         // Calculate number of buckets
         let total_seconds = end_timestamp - start_timestamp;
@@ -1094,6 +2047,319 @@ impl Database {
     }
 }
 
+/// Size of a table including its indexes
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableSize {
+    pub name: String,
+    pub rows: u64,
+    pub bytes: u64,
+}
+
+/// Size of the database file and its tables
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DatabaseStats {
+    /// Largest first
+    pub tables: Vec<TableSize>,
+    pub total_bytes: u64,
+    /// Unused pages that compaction gives back
+    pub free_bytes: u64,
+}
+
+/// Step a statement to completion, `incremental_vacuum` frees pages per step
+fn run_to_end(conn: &Connection, sql: &str) -> Result<()> {
+    let mut stmt = conn.prepare(sql)?;
+    let mut rows = stmt.query([])?;
+    while rows.next()?.is_some() {}
+    Ok(())
+}
+
+impl Database {
+    /// Delete danmus older than a unix timestamp
+    pub fn delete_danmus_before(&self, before: i64) -> Result<usize> {
+        self.flush();
+        let conn = self.conn.lock();
+        Ok(conn.execute("DELETE FROM danmus WHERE timestamp < ?1", params![before])?)
+    }
+
+    /// Delete gifts, guards and superchats older than a unix timestamp
+    pub fn delete_paid_records_before(&self, before: i64) -> Result<usize> {
+        self.flush();
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+        let mut deleted = 0;
+        for table in ["gifts", "guards", "superchats"] {
+            deleted += tx.execute(
+                &format!("DELETE FROM {} WHERE timestamp < ?1", table),
+                params![before],
+            )?;
+        }
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    /// Merge free (silver) gifts older than a unix timestamp into one record
+    /// per room, user, gift and UTC day, returns the number of records removed
+    pub fn downsample_free_gifts_before(&self, before: i64) -> Result<usize> {
+        self.flush();
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "CREATE TEMP TABLE merged_gifts AS
+             SELECT 'merged-' || room_id || '-' || sender_uid || '-' || gift_id || '-' || (timestamp / 86400) AS id,
+                room_id, sender_uid, MAX(sender_uname) AS sender_uname, MAX(sender_face) AS sender_face,
+                MAX(medal_level) AS medal_level, MAX(medal_name) AS medal_name,
+                gift_id, MAX(gift_name) AS gift_name, MAX(gift_price) AS gift_price, coin_type,
+                MAX(action) AS action, SUM(num) AS num, MIN(timestamp) AS timestamp,
+                MAX(COALESCE(archived, 0)) AS archived, timestamp / 86400 AS day
+             FROM gifts
+             WHERE coin_type = 'silver' AND timestamp < ?1
+             GROUP BY room_id, sender_uid, gift_id, timestamp / 86400
+             HAVING COUNT(*) > 1",
+            params![before],
+        )?;
+        let deleted = tx.execute(
+            "DELETE FROM gifts
+             WHERE coin_type = 'silver' AND timestamp < ?1 AND EXISTS (
+                SELECT 1 FROM merged_gifts m
+                WHERE m.room_id = gifts.room_id AND m.sender_uid = gifts.sender_uid
+                    AND m.gift_id = gifts.gift_id AND m.day = gifts.timestamp / 86400
+             )",
+            params![before],
+        )?;
+        let inserted = tx.execute(
+            "INSERT INTO gifts (
+                id, room_id, sender_uid, sender_uname, sender_face, medal_level, medal_name,
+                gift_id, gift_name, gift_price, coin_type, action, num, timestamp, archived
+            )
+            SELECT id, room_id, sender_uid, sender_uname, sender_face, medal_level, medal_name,
+                gift_id, gift_name, gift_price, coin_type, action, num, timestamp, archived
+            FROM merged_gifts",
+            [],
+        )?;
+        tx.execute("DROP TABLE merged_gifts", [])?;
+        tx.commit()?;
+        Ok(deleted - inserted)
+    }
+
+    /// Give unused pages back to the file system and refresh query statistics
+    ///
+    /// The first run switches the file to incremental auto-vacuum, which
+    /// needs a full VACUUM.
+    pub fn compact(&self) -> Result<()> {
+        self.flush();
+        let conn = self.conn.lock();
+        let auto_vacuum: i64 = conn.query_row("PRAGMA auto_vacuum", [], |row| row.get(0))?;
+        if auto_vacuum != 2 {
+            conn.execute_batch("PRAGMA auto_vacuum = INCREMENTAL; VACUUM;")?;
+        }
+        run_to_end(&conn, "PRAGMA incremental_vacuum")?;
+        conn.execute_batch("ANALYZE")?;
+        if !self.readers.is_empty() {
+            run_to_end(&conn, "PRAGMA wal_checkpoint(TRUNCATE)")?;
+        }
+        Ok(())
+    }
+
+    /// Size of the database and each table
+    pub fn stats(&self) -> Result<DatabaseStats> {
+        let conn = self.read_conn();
+        let page_size: i64 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+        let page_count: i64 = conn.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+        let free_pages: i64 = conn.query_row("PRAGMA freelist_count", [], |row| row.get(0))?;
+
+        let mut stmt = conn.prepare(
+            "SELECT m.tbl_name, SUM(s.pgsize)
+             FROM dbstat s JOIN sqlite_master m ON s.name = m.name
+             WHERE m.tbl_name NOT LIKE 'sqlite_%'
+             GROUP BY m.tbl_name",
+        )?;
+        let sizes = stmt
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut tables = Vec::with_capacity(sizes.len());
+        for (name, bytes) in sizes {
+            let rows: i64 =
+                conn.query_row(&format!("SELECT COUNT(*) FROM \"{}\"", name), [], |row| row.get(0))?;
+            tables.push(TableSize {
+                name,
+                rows: rows as u64,
+                bytes: bytes as u64,
+            });
+        }
+        tables.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name)));
+
+        Ok(DatabaseStats {
+            tables,
+            total_bytes: (page_count * page_size) as u64,
+            free_bytes: (free_pages * page_size) as u64,
+        })
+    }
+}
+
+/// Records copied by `Database::merge_from`, duplicates are not counted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MergeReport {
+    pub danmus: usize,
+    pub gifts: usize,
+    pub guards: usize,
+    pub superchats: usize,
+    pub lotteries: usize,
+}
+
+impl MergeReport {
+    /// Summary shown in the settings
+    pub fn summary(&self) -> String {
+        format!(
+            "导入 {} 条弹幕、{} 条礼物、{} 条舰长、{} 条 SC、{} 条抽奖",
+            self.danmus, self.gifts, self.guards, self.superchats, self.lotteries
+        )
+    }
+}
+
+/// Columns of `table` present in both the main and the attached `other` database
+fn shared_columns(conn: &Connection, table: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT m.name FROM pragma_table_info(?1, 'main') m
+         JOIN pragma_table_info(?1, 'other') o ON m.name = o.name
+         ORDER BY m.cid",
+    )?;
+    let columns = stmt
+        .query_map(params![table], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(columns)
+}
+
+/// Copy the rows of `table` from `other` that pass `filter`, returns the number copied
+fn merge_table(conn: &Connection, table: &str, skip: &[&str], filter: &str) -> Result<usize> {
+    let columns = shared_columns(conn, table)?
+        .into_iter()
+        .filter(|c| !skip.contains(&c.as_str()))
+        .map(|c| format!("\"{}\"", c))
+        .collect::<Vec<_>>()
+        .join(", ");
+    if columns.is_empty() {
+        return Ok(0);
+    }
+    let sql = format!(
+        "INSERT OR IGNORE INTO main.{table} ({columns}) SELECT {columns} FROM other.{table} o {filter}"
+    );
+    Ok(conn.execute(&sql, [])?)
+}
+
+impl Database {
+    /// Write a consistent copy of the database to `path` with the online
+    /// backup API, replacing any file there
+    pub fn backup_to(&self, path: &Path) -> Result<()> {
+        self.flush();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Write next to the target first so a failed backup leaves no partial file
+        let partial = path.with_extension("partial");
+        let _ = std::fs::remove_file(&partial);
+        {
+            let source = self.read_conn();
+            let mut dest = Connection::open(&partial)?;
+            rusqlite::backup::Backup::new(&source, &mut dest)?.run_to_completion(
+                256,
+                Duration::from_millis(10),
+                None,
+            )?;
+            // A single file without -wal, so it can be copied to another machine
+            dest.query_row("PRAGMA journal_mode = DELETE", [], |row| {
+                row.get::<_, String>(0)
+            })?;
+        }
+        std::fs::rename(&partial, path)?;
+        Ok(())
+    }
+
+    /// Replace the whole database with the content of a backup
+    pub fn restore_from(&self, path: &Path) -> Result<()> {
+        if !path.is_file() {
+            return Err(anyhow!("Backup not found: {}", path.display()));
+        }
+        check_database_file(path)?;
+        self.flush();
+        let mut conn = self.conn.lock();
+        conn.restore(
+            rusqlite::DatabaseName::Main,
+            path,
+            None::<fn(rusqlite::backup::Progress)>,
+        )?;
+        if !self.readers.is_empty() {
+            conn.query_row("PRAGMA journal_mode = WAL", [], |row| {
+                row.get::<_, String>(0)
+            })?;
+        }
+        drop(conn);
+        // Backups of older versions may lack newer tables and columns
+        self.init_tables()
+    }
+
+    /// Import another JLiverTool database, skipping records already stored
+    ///
+    /// Gifts, guards, superchats and lotteries are matched by id, danmus by
+    /// room, sender, content and timestamp. Goals, recordings and webhook
+    /// deliveries belong to the machine they were made on and are not copied.
+    pub fn merge_from(&self, path: &Path) -> Result<MergeReport> {
+        check_database_file(path)?;
+        self.flush();
+        let mut conn = self.conn.lock();
+        if let Some(own) = conn.path() {
+            if Path::new(own).canonicalize().ok() == path.canonicalize().ok() {
+                return Err(anyhow!("Cannot import the database in use"));
+            }
+        }
+
+        conn.execute(
+            "ATTACH DATABASE ?1 AS other",
+            params![path.to_string_lossy()],
+        )?;
+        let result = (|| {
+            let tx = conn.transaction()?;
+            let report = MergeReport {
+                danmus: merge_table(
+                    &tx,
+                    "danmus",
+                    &["id"],
+                    "WHERE NOT EXISTS (
+                        SELECT 1 FROM main.danmus d
+                        WHERE d.room_id = o.room_id AND d.timestamp = o.timestamp
+                            AND d.sender_uid = o.sender_uid AND d.content = o.content
+                    )",
+                )?,
+                gifts: merge_table(&tx, "gifts", &[], "")?,
+                guards: merge_table(&tx, "guards", &[], "")?,
+                superchats: merge_table(&tx, "superchats", &[], "")?,
+                lotteries: merge_table(&tx, "lotteries", &[], "")?,
+            };
+            tx.commit()?;
+            Ok(report)
+        })();
+        conn.execute("DETACH DATABASE other", [])?;
+        result
+    }
+}
+
+/// Fail unless `path` is a SQLite database with the tables of JLiverTool
+fn check_database_file(path: &Path) -> Result<()> {
+    let conn = Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| anyhow!("Cannot open {}: {}", path.display(), e))?;
+    let tables: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM sqlite_master
+             WHERE type = 'table' AND name IN ('danmus', 'gifts', 'guards', 'superchats')",
+            [],
+            |row| row.get(0),
+        )
+        .map_err(|_| anyhow!("{} is not a SQLite database", path.display()))?;
+    if tables < 4 {
+        return Err(anyhow!("{} is not a JLiverTool database", path.display()));
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1103,4 +2369,356 @@ mod tests {
         let db = Database::in_memory().unwrap();
         assert!(db.get_recent_gifts(12345, 10).unwrap().is_empty());
     }
+
+    #[test]
+    fn test_guards_since() {
+        let db = Database::in_memory().unwrap();
+        for (i, timestamp) in [100i64, 200, 300, 400].into_iter().enumerate() {
+            db.insert_guard(&GuardMessage {
+                id: format!("guard-{}", i),
+                room: 12345,
+                sender: Sender::default(),
+                num: 1,
+                unit: "月".to_string(),
+                guard_level: 3,
+                price: 198000,
+                timestamp,
+                archived: false,
+            })
+            .unwrap();
+        }
+
+        // Latest two at or after 200, in chronological order
+        let guards = db.get_guards_since(12345, 200, 2).unwrap();
+        let timestamps: Vec<i64> = guards.iter().map(|g| g.timestamp).collect();
+        assert_eq!(timestamps, vec![300, 400]);
+        assert!(db.get_guards_since(54321, 0, 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_store_event() {
+        let db = Database::in_memory().unwrap();
+        let danmu = |content: &str, is_generated| DanmuMessage {
+            sender: Sender::default(),
+            content: content.to_string(),
+            is_generated,
+            is_special: false,
+            is_mirror: false,
+            emoji_content: None,
+            side_index: -1,
+            reply_uname: None,
+            timestamp: 100,
+        };
+        db.store_event(12345, &Event::NewDanmu(danmu("viewer", false)));
+        db.store_event(12345, &Event::NewDanmu(danmu("generated", true)));
+        db.store_event(
+            12345,
+            &Event::AnchorLotStart(AnchorLotStartMessage {
+                id: 7,
+                award_name: "周边".to_string(),
+                award_num: 1,
+                danmu: String::new(),
+                require_text: String::new(),
+                gift_name: String::new(),
+                gift_num: 0,
+                duration: 600,
+                start_time: 100,
+            }),
+        );
+        db.store_event(
+            12345,
+            &Event::AnchorLotAward(crate::messages::AnchorLotAwardMessage {
+                id: 7,
+                award_name: "周边".to_string(),
+                winners: vec![LotteryWinner {
+                    uid: 1,
+                    uname: "winner".to_string(),
+                    award_name: "周边".to_string(),
+                    num: 1,
+                }],
+            }),
+        );
+        // Events without anything to store are ignored
+        db.store_event(12345, &Event::LiveStart);
+        db.flush();
+
+        let danmus = db.get_danmus_between(12345, 0, 1000).unwrap();
+        assert_eq!(danmus.len(), 1);
+        assert_eq!(danmus[0].content, "viewer");
+        let lotteries = db.get_recent_lotteries(12345, 10).unwrap();
+        assert_eq!(lotteries.len(), 1);
+        assert_eq!(lotteries[0].winners[0].uname, "winner");
+    }
+
+    #[test]
+    fn test_lottery_start_keeps_winners() {
+        let db = Database::in_memory().unwrap();
+        let mut lottery = LotteryRecord {
+            id: LotteryRecord::anchor_id(7),
+            room: 12345,
+            kind: LOTTERY_KIND_ANCHOR.to_string(),
+            award_name: "周边x1".to_string(),
+            start_time: 100,
+            end_time: 700,
+            ..Default::default()
+        };
+        db.insert_lottery(&lottery).unwrap();
+        db.set_lottery_winners(
+            &lottery.id,
+            &[LotteryWinner {
+                uid: 1,
+                uname: "winner".to_string(),
+                award_name: "周边".to_string(),
+                num: 1,
+            }],
+        )
+        .unwrap();
+
+        // A repeated start message must not drop the winners
+        lottery.end_time = 800;
+        db.insert_lottery(&lottery).unwrap();
+
+        let lotteries = db.get_recent_lotteries(12345, 10).unwrap();
+        assert_eq!(lotteries.len(), 1);
+        assert_eq!(lotteries[0].end_time, 800);
+        assert_eq!(lotteries[0].winners.len(), 1);
+        assert_eq!(lotteries[0].winners[0].uname, "winner");
+    }
+
+    #[test]
+    fn test_queued_writes_on_file_database() {
+        let path = std::env::temp_dir().join(format!("jlivertool-{}.db", uuid::Uuid::new_v4()));
+        let db = Database::new(&path).unwrap();
+        for i in 0..5 {
+            db.queue_danmu(
+                12345,
+                DanmuMessage {
+                    sender: Sender::default(),
+                    content: format!("danmu {}", i),
+                    is_generated: false,
+                    is_special: false,
+                    is_mirror: false,
+                    emoji_content: None,
+                    side_index: -1,
+                    reply_uname: None,
+                    timestamp: 100 + i,
+                },
+            );
+        }
+        db.flush();
+        let danmus = db.get_danmus_between(12345, 0, 1000).unwrap();
+        assert_eq!(danmus.len(), 5);
+        assert_eq!(danmus[4].content, "danmu 4");
+
+        // Writes queued before close are stored, later ones directly
+        db.queue_guard(GuardMessage {
+            id: "guard".to_string(),
+            room: 12345,
+            sender: Sender::default(),
+            num: 1,
+            unit: "月".to_string(),
+            guard_level: 3,
+            price: 198000,
+            timestamp: 100,
+            archived: false,
+        });
+        db.close();
+        db.queue_superchat(SuperChatMessage {
+            id: "sc".to_string(),
+            room: 12345,
+            sender: Sender::default(),
+            message: "SC".to_string(),
+            price: 30,
+            timestamp: 100,
+            start_time: 100,
+            end_time: 160,
+            background_color: String::new(),
+            background_bottom_color: String::new(),
+            archived: false,
+        });
+        assert_eq!(db.get_recent_guards(12345, 10).unwrap().len(), 1);
+        assert_eq!(db.get_recent_superchats(12345, 10).unwrap().len(), 1);
+
+        drop(db);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+
+    #[test]
+    fn test_danmu_round_trip() {
+        let db = Database::in_memory().unwrap();
+        let danmu = DanmuMessage {
+            sender: Sender {
+                uid: 42,
+                uname: "观众".to_string(),
+                face: "https://i0.hdslb.com/face.jpg".to_string(),
+                medal_info: MedalInfo {
+                    anchor_roomid: 21484828,
+                    anchor_uname: "主播".to_string(),
+                    guard_level: 3,
+                    medal_color: 0x6154c1,
+                    medal_color_border: 0x6154c2,
+                    medal_color_start: 0x6154c3,
+                    medal_color_end: 0x6154c4,
+                    medal_level: 21,
+                    medal_name: "粉丝".to_string(),
+                    is_lighted: true,
+                },
+            },
+            content: "[dog]".to_string(),
+            is_generated: true,
+            is_special: true,
+            is_mirror: true,
+            emoji_content: Some(crate::types::EmojiContent {
+                bulge_display: 1,
+                emoticon_unique: "room_21484828_1".to_string(),
+                height: 60,
+                in_player_area: 1,
+                is_dynamic: 0,
+                url: "https://i0.hdslb.com/emoji.png".to_string(),
+                width: 60,
+            }),
+            side_index: 2,
+            reply_uname: Some("另一位观众".to_string()),
+            timestamp: 1700000000,
+        };
+        db.insert_danmu(21484828, &danmu).unwrap();
+        let stored = db.get_recent_danmus(21484828, 10).unwrap();
+        assert_eq!(
+            serde_json::to_value(&stored).unwrap(),
+            serde_json::to_value([&danmu]).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_danmu_migration() {
+        let path = std::env::temp_dir().join(format!("jlivertool-{}.db", uuid::Uuid::new_v4()));
+        {
+            // Schema and row of a database written before the full danmu was stored
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE danmus (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    room_id INTEGER NOT NULL,
+                    sender_uid INTEGER NOT NULL,
+                    sender_uname TEXT NOT NULL,
+                    sender_face TEXT,
+                    medal_level INTEGER,
+                    medal_name TEXT,
+                    medal_anchor_uname TEXT,
+                    medal_anchor_roomid INTEGER,
+                    medal_guard_level INTEGER,
+                    content TEXT NOT NULL,
+                    is_special INTEGER DEFAULT 0,
+                    timestamp INTEGER NOT NULL,
+                    created_at INTEGER DEFAULT (strftime('%s', 'now'))
+                );
+                INSERT INTO danmus (
+                    room_id, sender_uid, sender_uname, sender_face, medal_level, medal_name,
+                    medal_anchor_uname, medal_anchor_roomid, medal_guard_level, content, timestamp
+                ) VALUES (1, 42, '观众', '', 21, '粉丝', '主播', 1, 0, '你好', 100);",
+            )
+            .unwrap();
+        }
+
+        let db = Database::new(&path).unwrap();
+        let old = &db.get_recent_danmus(1, 10).unwrap()[0];
+        assert_eq!(old.content, "你好");
+        assert_eq!(old.side_index, -1);
+        assert!(!old.is_generated && !old.is_mirror);
+        assert!(old.emoji_content.is_none() && old.reply_uname.is_none());
+        // Old rows don't know whether the medal was lighted, it stays visible
+        assert!(old.sender.medal_info.is_lighted);
+
+        let mut new = old.clone();
+        new.reply_uname = Some("观众".to_string());
+        new.sender.medal_info.is_lighted = false;
+        new.timestamp = 101;
+        db.insert_danmu(1, &new).unwrap();
+        let latest = &db.get_recent_danmus(1, 10).unwrap()[1];
+        assert_eq!(latest.reply_uname.as_deref(), Some("观众"));
+        assert!(!latest.sender.medal_info.is_lighted);
+
+        drop(db);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+
+    #[test]
+    fn test_backup_restore_and_merge() {
+        let dir = std::env::temp_dir().join(format!("jlivertool-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let danmu = |content: &str, timestamp: i64| DanmuMessage {
+            sender: Sender {
+                uid: 1,
+                ..Default::default()
+            },
+            content: content.to_string(),
+            is_generated: false,
+            is_special: false,
+            is_mirror: false,
+            emoji_content: None,
+            side_index: -1,
+            reply_uname: None,
+            timestamp,
+        };
+        let guard = |id: &str| GuardMessage {
+            id: id.to_string(),
+            room: 1,
+            sender: Sender::default(),
+            num: 1,
+            unit: "月".to_string(),
+            guard_level: 3,
+            price: 198000,
+            timestamp: 100,
+            archived: false,
+        };
+
+        let streaming = Database::new(&dir.join("streaming.db")).unwrap();
+        streaming.insert_danmus_batch(1, &[danmu("a", 100), danmu("b", 101)]).unwrap();
+        streaming.insert_guard(&guard("g1")).unwrap();
+        let backup = dir.join("backup.db");
+        streaming.backup_to(&backup).unwrap();
+
+        // The laptop saw part of the same stream
+        let laptop = Database::new(&dir.join("laptop.db")).unwrap();
+        laptop.insert_danmus_batch(1, &[danmu("b", 101), danmu("c", 102)]).unwrap();
+        laptop.insert_guard(&guard("g1")).unwrap();
+        laptop.insert_guard(&guard("g2")).unwrap();
+        let report = laptop.merge_from(&backup).unwrap();
+        assert_eq!(
+            report,
+            MergeReport {
+                danmus: 1,
+                guards: 0,
+                ..Default::default()
+            }
+        );
+        let contents: Vec<_> = laptop
+            .get_danmus_between(1, 0, 1000)
+            .unwrap()
+            .into_iter()
+            .map(|d| d.content)
+            .collect();
+        assert_eq!(contents, ["a", "b", "c"]);
+        assert_eq!(laptop.merge_from(&backup).unwrap(), MergeReport::default());
+        assert!(laptop.merge_from(&dir.join("laptop.db")).is_err());
+
+        // Restore replaces everything, and the database stays usable
+        laptop.restore_from(&backup).unwrap();
+        assert_eq!(laptop.get_danmus_between(1, 0, 1000).unwrap().len(), 2);
+        assert_eq!(laptop.get_recent_guards(1, 10).unwrap().len(), 1);
+        laptop.queue_guard(guard("g3"));
+        laptop.flush();
+        assert_eq!(laptop.get_recent_guards(1, 10).unwrap().len(), 2);
+
+        std::fs::write(dir.join("other.db"), b"not a database").unwrap();
+        assert!(laptop.restore_from(&dir.join("other.db")).is_err());
+        assert!(laptop.merge_from(&dir.join("missing.db")).is_err());
+
+        drop((streaming, laptop));
+        let _ = std::fs::remove_dir_all(dir);
+    }
 }
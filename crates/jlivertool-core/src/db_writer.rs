@@ -0,0 +1,204 @@
+//! Background writer storing messages in batched transactions
+//!
+//! Messages are queued from the WebSocket task and written on a dedicated
+//! thread, in one transaction per batch. A batch is written when it reaches
+//! `batch_size` messages, `flush_interval` after its first message, on
+//! `flush` and on `shutdown`.
+
+use crate::messages::{DanmuMessage, GiftMessage, GuardMessage, SuperChatMessage};
+use anyhow::Result;
+use parking_lot::Mutex;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Messages per transaction
+pub const DEFAULT_BATCH_SIZE: usize = 200;
+
+/// Longest time a queued message waits to be written
+pub const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A message waiting to be stored
+#[derive(Debug, Clone)]
+pub enum PendingWrite {
+    Danmu { room_id: u64, danmu: DanmuMessage },
+    Gift(GiftMessage),
+    Guard(GuardMessage),
+    SuperChat(SuperChatMessage),
+}
+
+/// Stores a batch, called on the writer thread
+pub type BatchSink = Box<dyn FnMut(&[PendingWrite]) -> Result<()> + Send>;
+
+enum Command {
+    Write(Box<PendingWrite>),
+    Flush(mpsc::Sender<()>),
+    Shutdown,
+}
+
+/// Handle of the writer thread
+///
+/// The thread exits after writing what is queued when `shutdown` is called
+/// or every handle is dropped.
+pub struct BatchWriter {
+    tx: mpsc::Sender<Command>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl BatchWriter {
+    pub fn spawn(batch_size: usize, flush_interval: Duration, sink: BatchSink) -> Result<Self> {
+        let (tx, rx) = mpsc::channel();
+        let thread = std::thread::Builder::new()
+            .name("db-writer".into())
+            .spawn(move || run(rx, batch_size.max(1), flush_interval, sink))?;
+        Ok(Self {
+            tx,
+            thread: Mutex::new(Some(thread)),
+        })
+    }
+
+    /// Queue a write, handing it back if the writer has stopped
+    pub fn push(&self, write: PendingWrite) -> Option<PendingWrite> {
+        match self.tx.send(Command::Write(Box::new(write))) {
+            Ok(()) => None,
+            Err(mpsc::SendError(Command::Write(write))) => Some(*write),
+            Err(_) => None,
+        }
+    }
+
+    /// Block until everything queued before the call is written
+    pub fn flush(&self) {
+        let (ack_tx, ack_rx) = mpsc::channel();
+        if self.tx.send(Command::Flush(ack_tx)).is_ok() {
+            let _ = ack_rx.recv();
+        }
+    }
+
+    /// Write what is queued and stop the thread
+    pub fn shutdown(&self) {
+        let _ = self.tx.send(Command::Shutdown);
+        if let Some(thread) = self.thread.lock().take() {
+            if thread.join().is_err() {
+                warn!("Database writer thread panicked");
+            }
+        }
+    }
+}
+
+fn run(
+    rx: mpsc::Receiver<Command>,
+    batch_size: usize,
+    flush_interval: Duration,
+    mut sink: BatchSink,
+) {
+    let mut batch: Vec<PendingWrite> = Vec::with_capacity(batch_size);
+    let mut deadline: Option<Instant> = None;
+    let mut write = |batch: &mut Vec<PendingWrite>| {
+        if !batch.is_empty() {
+            if let Err(e) = sink(batch) {
+                warn!("Failed to store {} messages: {}", batch.len(), e);
+            }
+            batch.clear();
+        }
+    };
+
+    loop {
+        let command = match deadline {
+            Some(deadline) => {
+                match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(command) => Some(command),
+                    Err(RecvTimeoutError::Timeout) => None,
+                    Err(RecvTimeoutError::Disconnected) => Some(Command::Shutdown),
+                }
+            }
+            None => Some(rx.recv().unwrap_or(Command::Shutdown)),
+        };
+        match command {
+            Some(Command::Write(pending)) => {
+                batch.push(*pending);
+                deadline.get_or_insert_with(|| Instant::now() + flush_interval);
+                if batch.len() < batch_size {
+                    continue;
+                }
+            }
+            Some(Command::Flush(ack)) => {
+                write(&mut batch);
+                deadline = None;
+                let _ = ack.send(());
+                continue;
+            }
+            Some(Command::Shutdown) => {
+                write(&mut batch);
+                break;
+            }
+            None => {}
+        }
+        write(&mut batch);
+        deadline = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn guard(id: &str) -> PendingWrite {
+        PendingWrite::Guard(GuardMessage {
+            id: id.to_string(),
+            room: 1,
+            sender: Default::default(),
+            num: 1,
+            unit: "月".to_string(),
+            guard_level: 3,
+            price: 198000,
+            timestamp: 1,
+            archived: false,
+        })
+    }
+
+    fn recording_writer(
+        batch_size: usize,
+        interval: Duration,
+    ) -> (BatchWriter, Arc<Mutex<Vec<usize>>>) {
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let sink_batches = batches.clone();
+        let writer = BatchWriter::spawn(
+            batch_size,
+            interval,
+            Box::new(move |batch| {
+                sink_batches.lock().push(batch.len());
+                Ok(())
+            }),
+        )
+        .unwrap();
+        (writer, batches)
+    }
+
+    #[test]
+    fn test_batches_by_size_and_flush() {
+        let (writer, batches) = recording_writer(3, Duration::from_secs(60));
+        for i in 0..7 {
+            assert!(writer.push(guard(&i.to_string())).is_none());
+        }
+        writer.flush();
+        assert_eq!(*batches.lock(), vec![3, 3, 1]);
+
+        assert!(writer.push(guard("last")).is_none());
+        writer.shutdown();
+        assert_eq!(*batches.lock(), vec![3, 3, 1, 1]);
+        assert!(writer.push(guard("late")).is_some());
+        writer.flush();
+    }
+
+    #[test]
+    fn test_batches_by_time() {
+        let (writer, batches) = recording_writer(100, Duration::from_millis(20));
+        assert!(writer.push(guard("a")).is_none());
+        assert!(writer.push(guard("b")).is_none());
+        std::thread::sleep(Duration::from_millis(200));
+        assert_eq!(*batches.lock(), vec![2]);
+        writer.shutdown();
+    }
+}
@@ -0,0 +1,701 @@
+//! Earnings export of gifts, guards and superchats
+//!
+//! Records are read from the database with room, time range, type and archive
+//! filters and converted to CNY: gift and guard prices are stored in 1/1000 yuan,
+//! superchat prices in yuan, silver gifts are worth nothing. Amounts are summed
+//! in 1/1000 yuan so totals don't drift. Records and the per-day and per-user
+//! summaries can be written as CSV (with a UTF-8 BOM so Excel detects the
+//! encoding) or JSON Lines.
+
+use crate::database::{Database, RecordFilter};
+use crate::types::guard_level_name;
+use anyhow::Result;
+use chrono::{Local, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// Output format of an earnings export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EarningsFormat {
+    Csv,
+    Jsonl,
+}
+
+impl EarningsFormat {
+    /// Guess the format from a file extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?;
+        Self::parse(ext)
+    }
+
+    /// Parse "csv" or "jsonl"
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "csv" => Some(Self::Csv),
+            "jsonl" | "ndjson" => Some(Self::Jsonl),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Jsonl => "jsonl",
+        }
+    }
+}
+
+/// Type of a paid record
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EarningKind {
+    Gift,
+    Guard,
+    SuperChat,
+}
+
+impl EarningKind {
+    pub const ALL: [EarningKind; 3] = [Self::Gift, Self::Guard, Self::SuperChat];
+
+    /// Parse "gift", "guard" or "superchat"
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "gift" | "gifts" => Some(Self::Gift),
+            "guard" | "guards" => Some(Self::Guard),
+            "superchat" | "superchats" | "sc" => Some(Self::SuperChat),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Gift => "gift",
+            Self::Guard => "guard",
+            Self::SuperChat => "superchat",
+        }
+    }
+}
+
+/// Which report to write
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EarningsReport {
+    /// One row per gift, guard or superchat
+    Records,
+    /// Totals per local calendar day
+    Daily,
+    /// Totals per sender, highest first
+    Users,
+}
+
+impl EarningsReport {
+    /// Parse "records", "daily" or "users"
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "records" => Some(Self::Records),
+            "daily" => Some(Self::Daily),
+            "users" => Some(Self::Users),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Records => "records",
+            Self::Daily => "daily",
+            Self::Users => "users",
+        }
+    }
+}
+
+/// Filter of an earnings export
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EarningsFilter {
+    /// Room, time range and archive filter
+    pub records: RecordFilter,
+    /// Record types to include
+    pub kinds: Vec<EarningKind>,
+}
+
+impl Default for EarningsFilter {
+    fn default() -> Self {
+        Self {
+            records: RecordFilter::default(),
+            kinds: EarningKind::ALL.to_vec(),
+        }
+    }
+}
+
+/// A gift, guard or superchat with its value in CNY
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EarningRecord {
+    pub kind: EarningKind,
+    pub id: String,
+    pub room_id: u64,
+    pub timestamp: i64,
+    pub uid: u64,
+    pub uname: String,
+    /// Gift name, guard level or superchat message
+    pub item: String,
+    pub num: u32,
+    /// "gold" or "silver" for gifts, "gold" otherwise
+    pub coin_type: String,
+    /// Value in 1/1000 yuan
+    pub value_milli: u64,
+    pub archived: bool,
+}
+
+/// Counts and values of one summary row, values in 1/1000 yuan
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EarningsTotals {
+    pub gift_count: u64,
+    pub gift_value_milli: u64,
+    pub guard_count: u64,
+    pub guard_value_milli: u64,
+    pub superchat_count: u64,
+    pub superchat_value_milli: u64,
+}
+
+impl EarningsTotals {
+    fn add(&mut self, record: &EarningRecord) {
+        match record.kind {
+            EarningKind::Gift => {
+                self.gift_count += 1;
+                self.gift_value_milli += record.value_milli;
+            }
+            EarningKind::Guard => {
+                self.guard_count += 1;
+                self.guard_value_milli += record.value_milli;
+            }
+            EarningKind::SuperChat => {
+                self.superchat_count += 1;
+                self.superchat_value_milli += record.value_milli;
+            }
+        }
+    }
+
+    pub fn total_value_milli(&self) -> u64 {
+        self.gift_value_milli + self.guard_value_milli + self.superchat_value_milli
+    }
+}
+
+/// Totals of one local calendar day
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DailySummary {
+    /// "YYYY-MM-DD"
+    pub date: String,
+    pub totals: EarningsTotals,
+}
+
+/// Totals of one sender
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserSummary {
+    pub uid: u64,
+    /// Latest name seen for the sender
+    pub uname: String,
+    pub totals: EarningsTotals,
+}
+
+/// Read the records matching `filter`, in chronological order
+pub fn collect_records(db: &Database, filter: &EarningsFilter) -> Result<Vec<EarningRecord>> {
+    let mut records = Vec::new();
+    // Include records still waiting for the writer
+    db.flush();
+
+    if filter.kinds.contains(&EarningKind::Gift) {
+        for gift in db.query_gifts(&filter.records)? {
+            let value_milli = if gift.gift_info.coin_type == "silver" {
+                0
+            } else {
+                gift.gift_info.price * gift.num as u64
+            };
+            records.push(EarningRecord {
+                kind: EarningKind::Gift,
+                id: gift.id,
+                room_id: gift.room,
+                timestamp: gift.timestamp,
+                uid: gift.sender.uid,
+                uname: gift.sender.uname,
+                item: gift.gift_info.name,
+                num: gift.num,
+                coin_type: gift.gift_info.coin_type,
+                value_milli,
+                archived: gift.archived,
+            });
+        }
+    }
+
+    if filter.kinds.contains(&EarningKind::Guard) {
+        for guard in db.query_guards(&filter.records)? {
+            records.push(EarningRecord {
+                kind: EarningKind::Guard,
+                id: guard.id,
+                room_id: guard.room,
+                timestamp: guard.timestamp,
+                uid: guard.sender.uid,
+                uname: guard.sender.uname,
+                item: guard_level_name(guard.guard_level).to_string(),
+                num: guard.num,
+                coin_type: "gold".to_string(),
+                value_milli: guard.price,
+                archived: guard.archived,
+            });
+        }
+    }
+
+    if filter.kinds.contains(&EarningKind::SuperChat) {
+        for sc in db.query_superchats(&filter.records)? {
+            records.push(EarningRecord {
+                kind: EarningKind::SuperChat,
+                id: sc.id,
+                room_id: sc.room,
+                timestamp: sc.timestamp,
+                uid: sc.sender.uid,
+                uname: sc.sender.uname,
+                item: sc.message,
+                num: 1,
+                coin_type: "gold".to_string(),
+                value_milli: sc.price * 1000,
+                archived: sc.archived,
+            });
+        }
+    }
+
+    // Stable, so records of the same second keep their type order
+    records.sort_by_key(|r| r.timestamp);
+    Ok(records)
+}
+
+/// Sum records per local calendar day, oldest first
+pub fn daily_summary(records: &[EarningRecord]) -> Vec<DailySummary> {
+    let mut days: BTreeMap<String, EarningsTotals> = BTreeMap::new();
+    for record in records {
+        days.entry(local_date(record.timestamp))
+            .or_default()
+            .add(record);
+    }
+    days.into_iter()
+        .map(|(date, totals)| DailySummary { date, totals })
+        .collect()
+}
+
+/// Sum records per sender, highest total first
+pub fn user_summary(records: &[EarningRecord]) -> Vec<UserSummary> {
+    let mut users: HashMap<u64, UserSummary> = HashMap::new();
+    for record in records {
+        let user = users.entry(record.uid).or_insert_with(|| UserSummary {
+            uid: record.uid,
+            uname: String::new(),
+            totals: EarningsTotals::default(),
+        });
+        // Records are chronological, keep the latest name
+        user.uname.clone_from(&record.uname);
+        user.totals.add(record);
+    }
+    let mut users: Vec<UserSummary> = users.into_values().collect();
+    users.sort_by(|a, b| {
+        b.totals
+            .total_value_milli()
+            .cmp(&a.totals.total_value_milli())
+            .then(a.uid.cmp(&b.uid))
+    });
+    users
+}
+
+/// Render a report of `records`
+pub fn render_report(
+    records: &[EarningRecord],
+    report: EarningsReport,
+    format: EarningsFormat,
+) -> String {
+    let table = match report {
+        EarningsReport::Records => records_table(records),
+        EarningsReport::Daily => daily_table(&daily_summary(records)),
+        EarningsReport::Users => users_table(&user_summary(records)),
+    };
+    match format {
+        EarningsFormat::Csv => table.to_csv(),
+        EarningsFormat::Jsonl => table.to_jsonl(),
+    }
+}
+
+/// Export a report of the records matching `filter` to `path`
+/// Returns the number of rows written
+pub fn export_earnings(
+    db: &Database,
+    filter: &EarningsFilter,
+    report: EarningsReport,
+    format: EarningsFormat,
+    path: &Path,
+) -> Result<usize> {
+    let records = collect_records(db, filter)?;
+    let rows = match report {
+        EarningsReport::Records => records.len(),
+        EarningsReport::Daily => daily_summary(&records).len(),
+        EarningsReport::Users => user_summary(&records).len(),
+    };
+    let content = render_report(&records, report, format);
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, content)?;
+    Ok(rows)
+}
+
+/// Format 1/1000 yuan as a decimal CNY amount with at least two decimals
+pub fn format_cny(milli: u64) -> String {
+    let mut text = format!("{}.{:03}", milli / 1000, milli % 1000);
+    if text.ends_with('0') {
+        text.pop();
+    }
+    text
+}
+
+fn local_date(timestamp: i64) -> String {
+    Local
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .map(|t| t.format("%Y-%m-%d").to_string())
+        .unwrap_or_default()
+}
+
+fn local_time(timestamp: i64) -> String {
+    Local
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
+/// A cell of a report table
+enum Cell {
+    Text(String),
+    Int(i64),
+    Bool(bool),
+    /// Amount in 1/1000 yuan
+    Cny(u64),
+}
+
+impl From<&str> for Cell {
+    fn from(value: &str) -> Self {
+        Cell::Text(value.to_string())
+    }
+}
+
+impl From<String> for Cell {
+    fn from(value: String) -> Self {
+        Cell::Text(value)
+    }
+}
+
+/// Column names and rows shared by the CSV and JSONL writers
+struct Table {
+    header: &'static [&'static str],
+    rows: Vec<Vec<Cell>>,
+}
+
+impl Table {
+    fn to_csv(&self) -> String {
+        let mut out = String::from("\u{feff}");
+        out.push_str(&self.header.join(","));
+        out.push_str("\r\n");
+        for row in &self.rows {
+            let cells: Vec<String> = row
+                .iter()
+                .map(|cell| match cell {
+                    Cell::Text(text) => escape_csv(text),
+                    Cell::Int(value) => value.to_string(),
+                    Cell::Bool(value) => value.to_string(),
+                    Cell::Cny(milli) => format_cny(*milli),
+                })
+                .collect();
+            out.push_str(&cells.join(","));
+            out.push_str("\r\n");
+        }
+        out
+    }
+
+    fn to_jsonl(&self) -> String {
+        let mut out = String::new();
+        for row in &self.rows {
+            out.push('{');
+            for (i, (name, cell)) in self.header.iter().zip(row).enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                let value = match cell {
+                    Cell::Text(text) => serde_json::Value::from(text.as_str()),
+                    Cell::Int(value) => serde_json::Value::from(*value),
+                    Cell::Bool(value) => serde_json::Value::from(*value),
+                    Cell::Cny(milli) => serde_json::Value::from(*milli as f64 / 1000.0),
+                };
+                let _ = write!(out, "\"{}\":{}", name, value);
+            }
+            out.push_str("}\n");
+        }
+        out
+    }
+}
+
+const TOTALS_HEADER: [&str; 7] = [
+    "gift_count",
+    "gift_cny",
+    "guard_count",
+    "guard_cny",
+    "superchat_count",
+    "superchat_cny",
+    "total_cny",
+];
+
+fn totals_cells(totals: &EarningsTotals) -> [Cell; 7] {
+    [
+        Cell::Int(totals.gift_count as i64),
+        Cell::Cny(totals.gift_value_milli),
+        Cell::Int(totals.guard_count as i64),
+        Cell::Cny(totals.guard_value_milli),
+        Cell::Int(totals.superchat_count as i64),
+        Cell::Cny(totals.superchat_value_milli),
+        Cell::Cny(totals.total_value_milli()),
+    ]
+}
+
+fn records_table(records: &[EarningRecord]) -> Table {
+    Table {
+        header: &[
+            "time",
+            "timestamp",
+            "room_id",
+            "type",
+            "uid",
+            "uname",
+            "item",
+            "num",
+            "coin_type",
+            "value_cny",
+            "archived",
+            "id",
+        ],
+        rows: records
+            .iter()
+            .map(|r| {
+                vec![
+                    Cell::Text(local_time(r.timestamp)),
+                    Cell::Int(r.timestamp),
+                    Cell::Int(r.room_id as i64),
+                    r.kind.as_str().into(),
+                    Cell::Int(r.uid as i64),
+                    r.uname.clone().into(),
+                    r.item.clone().into(),
+                    Cell::Int(r.num as i64),
+                    r.coin_type.clone().into(),
+                    Cell::Cny(r.value_milli),
+                    Cell::Bool(r.archived),
+                    r.id.clone().into(),
+                ]
+            })
+            .collect(),
+    }
+}
+
+fn daily_table(days: &[DailySummary]) -> Table {
+    const HEADER: [&str; 8] = [
+        "date",
+        TOTALS_HEADER[0],
+        TOTALS_HEADER[1],
+        TOTALS_HEADER[2],
+        TOTALS_HEADER[3],
+        TOTALS_HEADER[4],
+        TOTALS_HEADER[5],
+        TOTALS_HEADER[6],
+    ];
+    Table {
+        header: &HEADER,
+        rows: days
+            .iter()
+            .map(|day| {
+                let mut row = vec![day.date.as_str().into()];
+                row.extend(totals_cells(&day.totals));
+                row
+            })
+            .collect(),
+    }
+}
+
+fn users_table(users: &[UserSummary]) -> Table {
+    const HEADER: [&str; 9] = [
+        "uid",
+        "uname",
+        TOTALS_HEADER[0],
+        TOTALS_HEADER[1],
+        TOTALS_HEADER[2],
+        TOTALS_HEADER[3],
+        TOTALS_HEADER[4],
+        TOTALS_HEADER[5],
+        TOTALS_HEADER[6],
+    ];
+    Table {
+        header: &HEADER,
+        rows: users
+            .iter()
+            .map(|user| {
+                let mut row = vec![Cell::Int(user.uid as i64), user.uname.as_str().into()];
+                row.extend(totals_cells(&user.totals));
+                row
+            })
+            .collect(),
+    }
+}
+
+/// Quote a CSV field when needed
+/// Text starting with a formula character is prefixed with `'` so spreadsheets
+/// don't evaluate user supplied names and messages.
+fn escape_csv(text: &str) -> String {
+    let text = if text.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", text)
+    } else {
+        text.to_string()
+    };
+    if text.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{GiftInfo, GiftMessage, GuardMessage, SuperChatMessage};
+    use crate::types::Sender;
+
+    fn sender(uid: u64, uname: &str) -> Sender {
+        Sender {
+            uid,
+            uname: uname.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn gift(id: &str, uid: u64, price: u64, num: u32, coin_type: &str, timestamp: i64) -> GiftMessage {
+        GiftMessage {
+            id: id.to_string(),
+            room: 1,
+            gift_info: GiftInfo {
+                id: 31036,
+                name: "小花花".to_string(),
+                price,
+                coin_type: coin_type.to_string(),
+                img_basic: String::new(),
+                img_dynamic: String::new(),
+                gif: String::new(),
+                webp: String::new(),
+            },
+            sender: sender(uid, "a"),
+            action: "投喂".to_string(),
+            num,
+            timestamp,
+            archived: false,
+        }
+    }
+
+    fn sample_db() -> Database {
+        let db = Database::in_memory().unwrap();
+        db.insert_gift(&gift("g1", 1, 100, 3, "gold", 1000)).unwrap();
+        db.insert_gift(&gift("g2", 2, 100, 5, "silver", 1001)).unwrap();
+        db.insert_guard(&GuardMessage {
+            id: "guard1".to_string(),
+            room: 1,
+            sender: sender(2, "b"),
+            num: 1,
+            unit: "月".to_string(),
+            guard_level: 3,
+            price: 198000,
+            timestamp: 1002,
+            archived: false,
+        })
+        .unwrap();
+        db.set_guard_archived("guard1", true).unwrap();
+        db.insert_superchat(&SuperChatMessage {
+            id: "sc1".to_string(),
+            room: 1,
+            sender: sender(1, "=a"),
+            message: "hello, \"world\"".to_string(),
+            price: 30,
+            timestamp: 1003,
+            start_time: 1003,
+            end_time: 1063,
+            background_color: String::new(),
+            background_bottom_color: String::new(),
+            archived: false,
+        })
+        .unwrap();
+        db
+    }
+
+    #[test]
+    fn test_cny_conversion_and_summaries() {
+        let db = sample_db();
+        let records = collect_records(&db, &EarningsFilter::default()).unwrap();
+        let values: Vec<u64> = records.iter().map(|r| r.value_milli).collect();
+        // Gold gift 0.1 x 3, silver gift, guard 198, SC 30 yuan
+        assert_eq!(values, vec![300, 0, 198000, 30000]);
+
+        let days = daily_summary(&records);
+        assert_eq!(days.len(), 1);
+        assert_eq!(format_cny(days[0].totals.total_value_milli()), "228.30");
+
+        let users = user_summary(&records);
+        assert_eq!(users.iter().map(|u| u.uid).collect::<Vec<_>>(), vec![2, 1]);
+        assert_eq!(users[1].uname, "=a");
+        assert_eq!(users[1].totals.superchat_count, 1);
+
+        let filter = EarningsFilter {
+            records: RecordFilter {
+                archived: Some(false),
+                ..Default::default()
+            },
+            kinds: vec![EarningKind::Guard, EarningKind::SuperChat],
+        };
+        let records = collect_records(&db, &filter).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].kind, EarningKind::SuperChat);
+    }
+
+    #[test]
+    fn test_collect_includes_queued_records() {
+        let db = Database::in_memory().unwrap();
+        db.queue_gift(gift("g1", 1, 100, 3, "gold", 1000));
+        let records = collect_records(&db, &EarningsFilter::default()).unwrap();
+        assert_eq!(records.len(), 1);
+    }
+
+    #[test]
+    fn test_csv_and_jsonl_output() {
+        let db = sample_db();
+        let filter = EarningsFilter {
+            kinds: vec![EarningKind::SuperChat],
+            ..Default::default()
+        };
+        let records = collect_records(&db, &filter).unwrap();
+
+        let csv = render_report(&records, EarningsReport::Records, EarningsFormat::Csv);
+        assert!(csv.starts_with("\u{feff}time,timestamp,"));
+        let row = csv.lines().nth(1).unwrap();
+        assert!(row.contains(",superchat,1,'=a,\"hello, \"\"world\"\"\",1,gold,30.00,false,sc1"));
+
+        let jsonl = render_report(&records, EarningsReport::Users, EarningsFormat::Jsonl);
+        let value: serde_json::Value = serde_json::from_str(jsonl.trim()).unwrap();
+        assert_eq!(value["uname"], "=a");
+        assert_eq!(value["total_cny"], 30.0);
+    }
+
+    #[test]
+    fn test_format_cny() {
+        assert_eq!(format_cny(0), "0.00");
+        assert_eq!(format_cny(100), "0.10");
+        assert_eq!(format_cny(1), "0.001");
+        assert_eq!(format_cny(198000), "198.00");
+    }
+}
@@ -2,19 +2,44 @@
 //!
 //! Provides a type-safe event bus for communication between components.
 
-use crate::bilibili::api::{GuardListItem, OnlineGoldRankItem, UserInfoData};
+use crate::bilibili::api::{
+    AreaList, GuardListItem, OnlineGoldRankItem, SilentUserItem, UserInfoData,
+};
+use crate::cmd_stats::CmdStat;
+use crate::goals::Goal;
 use crate::messages::{
-    CutOffMessage, DanmuMessage, EntryEffectMessage, GiftMessage, GuardMessage, InteractMessage,
-    RoomChangeMessage, SuperChatMessage, WarningMessage,
+    AnchorLotAwardMessage, AnchorLotStartMessage, CutOffMessage, DanmuMessage, EntryEffectMessage,
+    GiftMessage, GuardMessage, InteractMessage, LikeClickMessage, RedPocketStartMessage,
+    RedPocketWinnerMessage, RoomBlockMessage, RoomChangeMessage, RoomSilentMessage,
+    SuperChatMessage, WarningMessage,
 };
+use crate::backup::{BackupConfig, BackupFile};
+use crate::database::DatabaseStats;
+use crate::network::NetworkConfig;
+use crate::obs::ObsConfig;
+use crate::recorder::RecorderConfig;
+use crate::retention::RetentionConfig;
 use crate::types::{DetailInfo, RoomId};
-use parking_lot::RwLock;
-use std::collections::HashMap;
+use crate::webhook::{WebhookDelivery, WebhookEndpoint};
+use anyhow::{anyhow, Result};
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::broadcast;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+/// Version of the JSON wire format of `Event`, bumped on breaking changes
+pub const EVENT_SCHEMA_VERSION: u32 = 1;
 
 /// Event types
-#[derive(Debug, Clone)]
+///
+/// Serialized as `{"version": 1, "type": "<event type>", "data": <payload>}`
+/// through `to_json`/`from_json`, `type` is the name from `EventType::as_str`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
 pub enum Event {
     /// Room info updated
     UpdateRoom {
@@ -37,6 +62,7 @@ pub enum Event {
     NewGuard(GuardMessage),
 
     /// New superchat
+    #[serde(rename = "new_superchat")]
     NewSuperChat(SuperChatMessage),
 
     /// User interaction (enter/follow)
@@ -69,6 +95,12 @@ pub enum Event {
         max_danmu_count: usize,
         log_level: String,
         auto_update_check: bool,
+        obs: ObsConfig,
+        webhooks: Vec<WebhookEndpoint>,
+        recorder: RecorderConfig,
+        network: NetworkConfig,
+        retention: RetentionConfig,
+        backup: BackupConfig,
     },
 
     /// Detail window data updated
@@ -163,10 +195,111 @@ pub enum Event {
 
     /// Live stream cut off
     CutOff(CutOffMessage),
+
+    /// Snapshot of received WebSocket cmds for the developer panel
+    CmdStatsUpdated(Vec<CmdStat>),
+
+    /// Result of a moderation action (mute, room silence, admin change)
+    ModerationResult { success: bool, message: String },
+
+    /// Blocked users of the current room fetched
+    BlockedUsersFetched {
+        users: Vec<SilentUserItem>,
+        total: u64,
+    },
+
+    /// A viewer liked the stream
+    NewLikeClick(LikeClickMessage),
+
+    /// Total like count updated
+    UpdateLikeCount { count: u64 },
+
+    /// Watched count updated
+    UpdateWatched { count: u64, text: String },
+
+    /// Popularity red pocket started
+    RedPocketStart(RedPocketStartMessage),
+
+    /// Popularity red pocket drawn
+    RedPocketWinners(RedPocketWinnerMessage),
+
+    /// Anchor lottery started
+    AnchorLotStart(AnchorLotStartMessage),
+
+    /// Anchor lottery drawn
+    AnchorLotAward(AnchorLotAwardMessage),
+
+    /// Superchats deleted by Bilibili or the streamer
+    #[serde(rename = "superchat_deleted")]
+    SuperChatDeleted { ids: Vec<String> },
+
+    /// Room-wide silence turned on or off
+    RoomSilent(RoomSilentMessage),
+
+    /// A user was blocked in the room
+    UserBlocked(RoomBlockMessage),
+
+    /// Danmaku or earnings export finished
+    ExportFinished { success: bool, message: String },
+
+    /// A goal was created, reset, completed or made progress
+    GoalProgress(Goal),
+
+    /// Result of creating, resetting or completing a goal
+    GoalCommandResult { success: bool, message: String },
+
+    /// OBS connection test result or failed OBS action
+    ObsStatus { connected: bool, message: String },
+
+    /// Result of saving or testing the network settings
+    NetworkStatus { success: bool, message: String },
+
+    /// Latest webhook deliveries, newest first
+    WebhookDeliveries(Vec<WebhookDelivery>),
+
+    /// Recording started, stopped or was interrupted
+    RecorderStatus { recording: bool, message: String },
+
+    /// All live areas, for the area picker
+    AreaList(AreaList),
+
+    /// Editable room details for the room editor
+    RoomDetails {
+        description: String,
+        tags: Vec<String>,
+        news: String,
+        cover: String,
+    },
+
+    /// Result of changing the area, details or cover of the room
+    RoomEditResult { success: bool, message: String },
+
+    /// Raw session recording or replay started or stopped
+    /// `files` lists recorded sessions, newest first
+    SessionStatus {
+        recording: Option<String>,
+        replaying: Option<String>,
+        files: Vec<String>,
+        message: String,
+    },
+
+    /// Database size per table, with the result of saving the retention
+    /// settings or of a retention run
+    DatabaseStatus {
+        stats: DatabaseStats,
+        message: String,
+    },
+
+    /// Backups on disk, newest first, with the result of the last backup,
+    /// restore or import
+    BackupStatus {
+        backups: Vec<BackupFile>,
+        message: String,
+    },
 }
 
 /// Plugin info for events (simplified version)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PluginInfoEvent {
     pub id: String,
     pub name: String,
@@ -174,125 +307,106 @@ pub struct PluginInfoEvent {
     pub desc: String,
     pub version: String,
     pub path: std::path::PathBuf,
+    pub settings: Vec<crate::types::PluginSettingField>,
+    pub setting_values: serde_json::Map<String, serde_json::Value>,
 }
 
-/// Event handler callback type
-pub type EventHandler = Box<dyn Fn(&Event) + Send + Sync>;
-
-/// Event bus for distributing events to subscribers
-#[derive(Clone)]
-pub struct EventBus {
-    inner: Arc<EventBusInner>,
-}
-
-struct EventBusInner {
-    sender: broadcast::Sender<Event>,
-    handlers: RwLock<HashMap<String, Vec<EventHandler>>>,
+#[derive(Serialize)]
+struct EventEnvelopeRef<'a> {
+    version: u32,
+    #[serde(flatten)]
+    event: &'a Event,
 }
 
-impl Default for EventBus {
-    fn default() -> Self {
-        Self::new()
-    }
+#[derive(Deserialize)]
+struct EventEnvelope {
+    version: u32,
+    #[serde(flatten)]
+    event: Event,
 }
 
-impl EventBus {
-    /// Create a new event bus
-    pub fn new() -> Self {
-        let (sender, _) = broadcast::channel(1000);
-
-        Self {
-            inner: Arc::new(EventBusInner {
-                sender,
-                handlers: RwLock::new(HashMap::new()),
-            }),
-        }
-    }
-
-    /// Subscribe to all events
-    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
-        self.inner.sender.subscribe()
+impl Event {
+    /// Encode with the versioned wire format
+    pub fn to_json(&self) -> Result<serde_json::Value> {
+        Ok(serde_json::to_value(EventEnvelopeRef {
+            version: EVENT_SCHEMA_VERSION,
+            event: self,
+        })?)
     }
 
-    /// Emit an event
-    pub fn emit(&self, event: Event) {
-        // Send to broadcast subscribers
-        let _ = self.inner.sender.send(event.clone());
-
-        // Call registered handlers
-        let event_type = Self::event_type_name(&event);
-        let handlers = self.inner.handlers.read();
-
-        // Call type-specific handlers
-        if let Some(type_handlers) = handlers.get(event_type) {
-            for handler in type_handlers {
-                handler(&event);
-            }
+    /// Decode the versioned wire format, rejecting newer schema versions
+    pub fn from_json(value: serde_json::Value) -> Result<Self> {
+        let envelope: EventEnvelope = serde_json::from_value(value)?;
+        if envelope.version == 0 || envelope.version > EVENT_SCHEMA_VERSION {
+            return Err(anyhow!(
+                "Unsupported event schema version {}",
+                envelope.version
+            ));
         }
-
-        // Call wildcard handlers
-        if let Some(all_handlers) = handlers.get("*") {
-            for handler in all_handlers {
-                handler(&event);
-            }
-        }
-    }
-
-    /// Register a handler for a specific event type
-    pub fn on<F>(&self, event_type: &str, handler: F)
-    where
-        F: Fn(&Event) + Send + Sync + 'static,
-    {
-        let mut handlers = self.inner.handlers.write();
-        handlers
-            .entry(event_type.to_string())
-            .or_default()
-            .push(Box::new(handler));
+        Ok(envelope.event)
     }
 
-    /// Register a handler for all events
-    pub fn on_all<F>(&self, handler: F)
-    where
-        F: Fn(&Event) + Send + Sync + 'static,
-    {
-        self.on("*", handler);
-    }
-
-    /// Get the type name for an event
-    fn event_type_name(event: &Event) -> &'static str {
-        match event {
-            Event::UpdateRoom { .. } => "update_room",
-            Event::UpdateOnline { .. } => "update_online",
-            Event::NewDanmu(_) => "new_danmu",
-            Event::NewGift(_) => "new_gift",
-            Event::NewGuard(_) => "new_guard",
-            Event::NewSuperChat(_) => "new_superchat",
-            Event::NewInteract(_) => "new_interact",
-            Event::NewEntryEffect(_) => "new_entry_effect",
-            Event::RoomChange(_) => "room_change",
-            Event::ConfigChanged { .. } => "config_changed",
-            Event::ConfigLoaded { .. } => "config_loaded",
-            Event::DetailUpdate(_) => "detail_update",
-            Event::LiveStart => "live_start",
-            Event::LiveEnd => "live_end",
-            Event::ConnectionStatus { .. } => "connection_status",
-            Event::LoginStatusChanged { .. } => "login_status_changed",
-            Event::RequestQrLogin => "request_qr_login",
-            Event::QrCodeGenerated { .. } => "qr_code_generated",
-            Event::QrLoginStatus { .. } => "qr_login_status",
-            Event::RequestLogout => "request_logout",
-            Event::RtmpInfo { .. } => "rtmp_info",
-            Event::FaceAuthRequired { .. } => "face_auth_required",
-            Event::ClearDanmuList => "clear_danmu_list",
-            Event::UserInfoFetched { .. } => "user_info_fetched",
-            Event::AudienceListFetched { .. } => "audience_list_fetched",
-            Event::GuardListFetched { .. } => "guard_list_fetched",
-            Event::PluginsRefreshed { .. } => "plugins_refreshed",
-            Event::PluginImportResult { .. } => "plugin_import_result",
-            Event::DataCleared => "data_cleared",
-            Event::UpdateCheckResult { .. } => "update_check_result",
-            Event::Warning(_) => "warning",
-            Event::CutOff(_) => "cut_off",
+    /// Type of the event, used to filter subscriptions
+    pub fn event_type(&self) -> EventType {
+        match self {
+            Event::UpdateRoom { .. } => EventType::UpdateRoom,
+            Event::UpdateOnline { .. } => EventType::UpdateOnline,
+            Event::NewDanmu(_) => EventType::NewDanmu,
+            Event::NewGift(_) => EventType::NewGift,
+            Event::NewGuard(_) => EventType::NewGuard,
+            Event::NewSuperChat(_) => EventType::NewSuperChat,
+            Event::NewInteract(_) => EventType::NewInteract,
+            Event::NewEntryEffect(_) => EventType::NewEntryEffect,
+            Event::RoomChange(_) => EventType::RoomChange,
+            Event::ConfigChanged { .. } => EventType::ConfigChanged,
+            Event::ConfigLoaded { .. } => EventType::ConfigLoaded,
+            Event::DetailUpdate(_) => EventType::DetailUpdate,
+            Event::LiveStart => EventType::LiveStart,
+            Event::LiveEnd => EventType::LiveEnd,
+            Event::ConnectionStatus { .. } => EventType::ConnectionStatus,
+            Event::LoginStatusChanged { .. } => EventType::LoginStatusChanged,
+            Event::RequestQrLogin => EventType::RequestQrLogin,
+            Event::QrCodeGenerated { .. } => EventType::QrCodeGenerated,
+            Event::QrLoginStatus { .. } => EventType::QrLoginStatus,
+            Event::RequestLogout => EventType::RequestLogout,
+            Event::RtmpInfo { .. } => EventType::RtmpInfo,
+            Event::FaceAuthRequired { .. } => EventType::FaceAuthRequired,
+            Event::ClearDanmuList => EventType::ClearDanmuList,
+            Event::UserInfoFetched { .. } => EventType::UserInfoFetched,
+            Event::AudienceListFetched { .. } => EventType::AudienceListFetched,
+            Event::GuardListFetched { .. } => EventType::GuardListFetched,
+            Event::PluginsRefreshed { .. } => EventType::PluginsRefreshed,
+            Event::PluginImportResult { .. } => EventType::PluginImportResult,
+            Event::DataCleared => EventType::DataCleared,
+            Event::UpdateCheckResult { .. } => EventType::UpdateCheckResult,
+            Event::Warning(_) => EventType::Warning,
+            Event::CutOff(_) => EventType::CutOff,
+            Event::CmdStatsUpdated(_) => EventType::CmdStatsUpdated,
+            Event::ModerationResult { .. } => EventType::ModerationResult,
+            Event::BlockedUsersFetched { .. } => EventType::BlockedUsersFetched,
+            Event::NewLikeClick(_) => EventType::NewLikeClick,
+            Event::UpdateLikeCount { .. } => EventType::UpdateLikeCount,
+            Event::UpdateWatched { .. } => EventType::UpdateWatched,
+            Event::RedPocketStart(_) => EventType::RedPocketStart,
+            Event::RedPocketWinners(_) => EventType::RedPocketWinners,
+            Event::AnchorLotStart(_) => EventType::AnchorLotStart,
+            Event::AnchorLotAward(_) => EventType::AnchorLotAward,
+            Event::SuperChatDeleted { .. } => EventType::SuperChatDeleted,
+            Event::RoomSilent(_) => EventType::RoomSilent,
+            Event::UserBlocked(_) => EventType::UserBlocked,
+            Event::ExportFinished { .. } => EventType::ExportFinished,
+            Event::GoalProgress(_) => EventType::GoalProgress,
+            Event::GoalCommandResult { .. } => EventType::GoalCommandResult,
+            Event::ObsStatus { .. } => EventType::ObsStatus,
+            Event::NetworkStatus { .. } => EventType::NetworkStatus,
+            Event::WebhookDeliveries(_) => EventType::WebhookDeliveries,
+            Event::RecorderStatus { .. } => EventType::RecorderStatus,
+            Event::AreaList(_) => EventType::AreaList,
+            Event::RoomDetails { .. } => EventType::RoomDetails,
+            Event::RoomEditResult { .. } => EventType::RoomEditResult,
+            Event::SessionStatus { .. } => EventType::SessionStatus,
+            Event::DatabaseStatus { .. } => EventType::DatabaseStatus,
+            Event::BackupStatus { .. } => EventType::BackupStatus,
         }
     }
 }
@@ -310,6 +424,7 @@ pub enum EventType {
     NewEntryEffect,
     RoomChange,
     ConfigChanged,
+    ConfigLoaded,
     DetailUpdate,
     LiveStart,
     LiveEnd,
@@ -319,13 +434,109 @@ pub enum EventType {
     QrCodeGenerated,
     QrLoginStatus,
     RequestLogout,
+    RtmpInfo,
+    FaceAuthRequired,
     ClearDanmuList,
     UserInfoFetched,
     AudienceListFetched,
     GuardListFetched,
+    PluginsRefreshed,
+    PluginImportResult,
+    DataCleared,
+    UpdateCheckResult,
+    Warning,
+    CutOff,
+    CmdStatsUpdated,
+    ModerationResult,
+    BlockedUsersFetched,
+    NewLikeClick,
+    UpdateLikeCount,
+    UpdateWatched,
+    RedPocketStart,
+    RedPocketWinners,
+    AnchorLotStart,
+    AnchorLotAward,
+    SuperChatDeleted,
+    RoomSilent,
+    UserBlocked,
+    ExportFinished,
+    GoalProgress,
+    GoalCommandResult,
+    ObsStatus,
+    NetworkStatus,
+    WebhookDeliveries,
+    RecorderStatus,
+    AreaList,
+    RoomDetails,
+    RoomEditResult,
+    SessionStatus,
+    DatabaseStatus,
+    BackupStatus,
 }
 
 impl EventType {
+    /// Every event type
+    pub const ALL: &'static [EventType] = &[
+        Self::UpdateRoom,
+        Self::UpdateOnline,
+        Self::NewDanmu,
+        Self::NewGift,
+        Self::NewGuard,
+        Self::NewSuperChat,
+        Self::NewInteract,
+        Self::NewEntryEffect,
+        Self::RoomChange,
+        Self::ConfigChanged,
+        Self::ConfigLoaded,
+        Self::DetailUpdate,
+        Self::LiveStart,
+        Self::LiveEnd,
+        Self::ConnectionStatus,
+        Self::LoginStatusChanged,
+        Self::RequestQrLogin,
+        Self::QrCodeGenerated,
+        Self::QrLoginStatus,
+        Self::RequestLogout,
+        Self::RtmpInfo,
+        Self::FaceAuthRequired,
+        Self::ClearDanmuList,
+        Self::UserInfoFetched,
+        Self::AudienceListFetched,
+        Self::GuardListFetched,
+        Self::PluginsRefreshed,
+        Self::PluginImportResult,
+        Self::DataCleared,
+        Self::UpdateCheckResult,
+        Self::Warning,
+        Self::CutOff,
+        Self::CmdStatsUpdated,
+        Self::ModerationResult,
+        Self::BlockedUsersFetched,
+        Self::NewLikeClick,
+        Self::UpdateLikeCount,
+        Self::UpdateWatched,
+        Self::RedPocketStart,
+        Self::RedPocketWinners,
+        Self::AnchorLotStart,
+        Self::AnchorLotAward,
+        Self::SuperChatDeleted,
+        Self::RoomSilent,
+        Self::UserBlocked,
+        Self::ExportFinished,
+        Self::GoalProgress,
+        Self::GoalCommandResult,
+        Self::ObsStatus,
+        Self::NetworkStatus,
+        Self::WebhookDeliveries,
+        Self::RecorderStatus,
+        Self::AreaList,
+        Self::RoomDetails,
+        Self::RoomEditResult,
+        Self::SessionStatus,
+        Self::DatabaseStatus,
+        Self::BackupStatus,
+    ];
+
     pub fn as_str(&self) -> &'static str {
         match self {
             Self::UpdateRoom => "update_room",
@@ -338,6 +549,7 @@ impl EventType {
             Self::NewEntryEffect => "new_entry_effect",
             Self::RoomChange => "room_change",
             Self::ConfigChanged => "config_changed",
+            Self::ConfigLoaded => "config_loaded",
             Self::DetailUpdate => "detail_update",
             Self::LiveStart => "live_start",
             Self::LiveEnd => "live_end",
@@ -347,12 +559,366 @@ impl EventType {
             Self::QrCodeGenerated => "qr_code_generated",
             Self::QrLoginStatus => "qr_login_status",
             Self::RequestLogout => "request_logout",
+            Self::RtmpInfo => "rtmp_info",
+            Self::FaceAuthRequired => "face_auth_required",
             Self::ClearDanmuList => "clear_danmu_list",
             Self::UserInfoFetched => "user_info_fetched",
             Self::AudienceListFetched => "audience_list_fetched",
             Self::GuardListFetched => "guard_list_fetched",
+            Self::PluginsRefreshed => "plugins_refreshed",
+            Self::PluginImportResult => "plugin_import_result",
+            Self::DataCleared => "data_cleared",
+            Self::UpdateCheckResult => "update_check_result",
+            Self::Warning => "warning",
+            Self::CutOff => "cut_off",
+            Self::CmdStatsUpdated => "cmd_stats_updated",
+            Self::ModerationResult => "moderation_result",
+            Self::BlockedUsersFetched => "blocked_users_fetched",
+            Self::NewLikeClick => "new_like_click",
+            Self::UpdateLikeCount => "update_like_count",
+            Self::UpdateWatched => "update_watched",
+            Self::RedPocketStart => "red_pocket_start",
+            Self::RedPocketWinners => "red_pocket_winners",
+            Self::AnchorLotStart => "anchor_lot_start",
+            Self::AnchorLotAward => "anchor_lot_award",
+            Self::SuperChatDeleted => "superchat_deleted",
+            Self::RoomSilent => "room_silent",
+            Self::UserBlocked => "user_blocked",
+            Self::ExportFinished => "export_finished",
+            Self::GoalProgress => "goal_progress",
+            Self::GoalCommandResult => "goal_command_result",
+            Self::ObsStatus => "obs_status",
+            Self::NetworkStatus => "network_status",
+            Self::WebhookDeliveries => "webhook_deliveries",
+            Self::RecorderStatus => "recorder_status",
+            Self::AreaList => "area_list",
+            Self::RoomDetails => "room_details",
+            Self::RoomEditResult => "room_edit_result",
+            Self::SessionStatus => "session_status",
+            Self::DatabaseStatus => "database_status",
+            Self::BackupStatus => "backup_status",
+        }
+    }
+
+    /// Type with the given channel or type name, see `channel_matches`
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|t| name != "*" && channel_matches(name, t.as_str()))
+    }
+
+    /// State updates where only the latest value matters
+    pub fn is_state_update(&self) -> bool {
+        matches!(
+            self,
+            Self::UpdateOnline | Self::UpdateLikeCount | Self::UpdateWatched
+        )
+    }
+}
+
+/// Queue capacity of subscribers that don't set one
+pub const DEFAULT_QUEUE_CAPACITY: usize = 1000;
+
+/// What a subscription does with events it can't keep up with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LagPolicy {
+    /// Drop the oldest queued event when the queue is full
+    #[default]
+    DropOldest,
+    /// Like `DropOldest`, but a state update (online, like and watched
+    /// counts) replaces the queued update of the same type
+    Coalesce,
+}
+
+/// Which events a subscription receives and how it is queued
+#[derive(Debug, Clone)]
+pub struct SubscribeOptions {
+    name: String,
+    types: Option<HashSet<EventType>>,
+    capacity: usize,
+    lag: LagPolicy,
+}
+
+impl SubscribeOptions {
+    /// All events, `name` labels the subscriber in the stats
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            types: None,
+            capacity: DEFAULT_QUEUE_CAPACITY,
+            lag: LagPolicy::default(),
+        }
+    }
+
+    /// Only receive events of these types
+    pub fn types(mut self, types: impl IntoIterator<Item = EventType>) -> Self {
+        self.types = Some(types.into_iter().collect());
+        self
+    }
+
+    /// Maximum number of queued events
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    pub fn lag(mut self, lag: LagPolicy) -> Self {
+        self.lag = lag;
+        self
+    }
+
+    fn accepts(&self, event_type: EventType) -> bool {
+        self.types
+            .as_ref()
+            .map(|types| types.contains(&event_type))
+            .unwrap_or(true)
+    }
+}
+
+/// Counters of one subscription
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SubscriberStats {
+    pub name: String,
+    /// Events taken from the queue
+    pub delivered: u64,
+    /// Events dropped because the queue was full
+    pub dropped: u64,
+    /// State updates replaced by a newer one
+    pub coalesced: u64,
+    pub queued: usize,
+}
+
+struct Subscriber {
+    options: SubscribeOptions,
+    queue: Mutex<VecDeque<Event>>,
+    notify: Notify,
+    /// Set when the subscription or the bus is dropped
+    closed: AtomicBool,
+    delivered: AtomicU64,
+    dropped: AtomicU64,
+    coalesced: AtomicU64,
+}
+
+impl Subscriber {
+    fn push(&self, event: Event, event_type: EventType) {
+        let mut queue = self.queue.lock();
+        if self.options.lag == LagPolicy::Coalesce && event_type.is_state_update() {
+            if let Some(queued) = queue.iter_mut().find(|e| e.event_type() == event_type) {
+                *queued = event;
+                self.coalesced.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        }
+        if queue.len() >= self.options.capacity {
+            queue.pop_front();
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        queue.push_back(event);
+        drop(queue);
+        self.notify.notify_one();
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.notify.notify_one();
+    }
+
+    fn stats(&self) -> SubscriberStats {
+        SubscriberStats {
+            name: self.options.name.clone(),
+            delivered: self.delivered.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            coalesced: self.coalesced.load(Ordering::Relaxed),
+            queued: self.queue.lock().len(),
+        }
+    }
+}
+
+/// Receiving end of a bus subscription with its own bounded queue
+pub struct Subscription {
+    subscriber: Arc<Subscriber>,
+}
+
+impl Subscription {
+    /// Next event, `None` once the bus is dropped and the queue is empty
+    pub async fn recv(&mut self) -> Option<Event> {
+        loop {
+            if let Some(event) = self.try_recv() {
+                return Some(event);
+            }
+            if self.subscriber.closed.load(Ordering::SeqCst) {
+                return None;
+            }
+            self.subscriber.notify.notified().await;
+        }
+    }
+
+    /// Next queued event without waiting
+    pub fn try_recv(&mut self) -> Option<Event> {
+        let event = self.subscriber.queue.lock().pop_front()?;
+        self.subscriber.delivered.fetch_add(1, Ordering::Relaxed);
+        Some(event)
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.subscriber.close();
+    }
+}
+
+/// Event handler callback type
+pub type EventHandler = Box<dyn Fn(&Event) + Send + Sync>;
+
+/// Event bus for distributing events to subscribers
+///
+/// Sync handlers registered with `on` run inside `emit` and must be cheap.
+/// Subscriptions get their own bounded queue, so a slow subscriber only
+/// loses its own events.
+#[derive(Clone)]
+pub struct EventBus {
+    inner: Arc<EventBusInner>,
+}
+
+struct EventBusInner {
+    subscribers: RwLock<Vec<Arc<Subscriber>>>,
+    handlers: RwLock<Vec<(Option<HashSet<EventType>>, EventHandler)>>,
+}
+
+impl Drop for EventBusInner {
+    fn drop(&mut self) {
+        for subscriber in self.subscribers.read().iter() {
+            subscriber.close();
+        }
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    /// Create a new event bus
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(EventBusInner {
+                subscribers: RwLock::new(Vec::new()),
+                handlers: RwLock::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// Subscribe to all events
+    pub fn subscribe(&self) -> Subscription {
+        self.subscribe_with(SubscribeOptions::new("default"))
+    }
+
+    /// Subscribe with a type filter, queue capacity and lag policy
+    pub fn subscribe_with(&self, options: SubscribeOptions) -> Subscription {
+        let subscriber = Arc::new(Subscriber {
+            options,
+            queue: Mutex::new(VecDeque::new()),
+            notify: Notify::new(),
+            closed: AtomicBool::new(false),
+            delivered: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            coalesced: AtomicU64::new(0),
+        });
+        self.inner.subscribers.write().push(subscriber.clone());
+        Subscription { subscriber }
+    }
+
+    /// Run an async handler for every event of a new subscription
+    ///
+    /// Events are handled one at a time on a task of the current tokio
+    /// runtime, which ends when the bus is dropped.
+    pub fn spawn_handler<F, Fut>(&self, options: SubscribeOptions, mut handler: F) -> JoinHandle<()>
+    where
+        F: FnMut(Event) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let mut subscription = self.subscribe_with(options);
+        tokio::spawn(async move {
+            while let Some(event) = subscription.recv().await {
+                handler(event).await;
+            }
+        })
+    }
+
+    /// Emit an event
+    pub fn emit(&self, event: Event) {
+        let event_type = event.event_type();
+
+        // Call sync handlers
+        for (types, handler) in self.inner.handlers.read().iter() {
+            if types
+                .as_ref()
+                .map(|t| t.contains(&event_type))
+                .unwrap_or(true)
+            {
+                handler(&event);
+            }
         }
+
+        // Queue for subscribers, forgetting dropped ones
+        let mut closed = false;
+        for subscriber in self.inner.subscribers.read().iter() {
+            if subscriber.closed.load(Ordering::SeqCst) {
+                closed = true;
+            } else if subscriber.options.accepts(event_type) {
+                subscriber.push(event.clone(), event_type);
+            }
+        }
+        if closed {
+            self.inner
+                .subscribers
+                .write()
+                .retain(|s| !s.closed.load(Ordering::SeqCst));
+        }
+    }
+
+    /// Register a sync handler for events of the given types
+    pub fn on<F>(&self, types: impl IntoIterator<Item = EventType>, handler: F)
+    where
+        F: Fn(&Event) + Send + Sync + 'static,
+    {
+        self.inner
+            .handlers
+            .write()
+            .push((Some(types.into_iter().collect()), Box::new(handler)));
+    }
+
+    /// Register a sync handler for all events
+    pub fn on_all<F>(&self, handler: F)
+    where
+        F: Fn(&Event) + Send + Sync + 'static,
+    {
+        self.inner.handlers.write().push((None, Box::new(handler)));
     }
+
+    /// Counters of the live subscriptions
+    pub fn stats(&self) -> Vec<SubscriberStats> {
+        self.inner
+            .subscribers
+            .read()
+            .iter()
+            .filter(|s| !s.closed.load(Ordering::SeqCst))
+            .map(|s| s.stats())
+            .collect()
+    }
+}
+
+/// Check whether a subscription name refers to a channel
+/// Accepts "*", the channel ("new_danmu") and the type name ("NewDanmu"), ignoring case
+pub fn channel_matches(name: &str, channel: &str) -> bool {
+    if name == "*" {
+        return true;
+    }
+    let normalize = |s: &str| s.replace('_', "").to_ascii_lowercase();
+    normalize(name) == normalize(channel)
 }
 
 /// Helper macro for matching events
@@ -365,3 +931,435 @@ macro_rules! match_event {
         }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_type_names() {
+        let mut names = HashSet::new();
+        for event_type in EventType::ALL {
+            assert!(names.insert(event_type.as_str()));
+            assert_eq!(EventType::from_name(event_type.as_str()), Some(*event_type));
+        }
+        assert_eq!(EventType::from_name("NewDanmu"), Some(EventType::NewDanmu));
+        assert_eq!(EventType::from_name("*"), None);
+        assert_eq!(Event::LiveStart.event_type().as_str(), "live_start");
+    }
+
+    #[test]
+    fn test_filtered_subscription() {
+        let bus = EventBus::new();
+        let mut lives =
+            bus.subscribe_with(SubscribeOptions::new("lives").types([EventType::LiveStart]));
+        let mut all = bus.subscribe();
+        let handled = Arc::new(AtomicU64::new(0));
+        bus.on([EventType::LiveEnd], {
+            let handled = handled.clone();
+            move |_| {
+                handled.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+
+        bus.emit(Event::LiveStart);
+        bus.emit(Event::LiveEnd);
+
+        assert!(matches!(lives.try_recv(), Some(Event::LiveStart)));
+        assert!(lives.try_recv().is_none());
+        assert!(matches!(all.try_recv(), Some(Event::LiveStart)));
+        assert!(matches!(all.try_recv(), Some(Event::LiveEnd)));
+        assert_eq!(handled.load(Ordering::Relaxed), 1);
+
+        drop(all);
+        bus.emit(Event::LiveStart);
+        let stats = bus.stats();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].name, "lives");
+        assert_eq!((stats[0].delivered, stats[0].queued), (1, 1));
+    }
+
+    #[test]
+    fn test_lag_policies() {
+        let bus = EventBus::new();
+        let mut oldest = bus.subscribe_with(SubscribeOptions::new("oldest").capacity(2));
+        let mut coalesce = bus.subscribe_with(
+            SubscribeOptions::new("coalesce")
+                .capacity(2)
+                .lag(LagPolicy::Coalesce),
+        );
+
+        for count in 1..=3 {
+            bus.emit(Event::UpdateOnline { count });
+        }
+        bus.emit(Event::LiveStart);
+
+        // Drop oldest keeps the last two events
+        assert!(matches!(
+            oldest.try_recv(),
+            Some(Event::UpdateOnline { count: 3 })
+        ));
+        assert!(matches!(oldest.try_recv(), Some(Event::LiveStart)));
+        // Coalesce keeps one online update with the latest count
+        assert!(matches!(
+            coalesce.try_recv(),
+            Some(Event::UpdateOnline { count: 3 })
+        ));
+        assert!(matches!(coalesce.try_recv(), Some(Event::LiveStart)));
+
+        let stats = bus.stats();
+        assert_eq!((stats[0].dropped, stats[0].coalesced), (2, 0));
+        assert_eq!((stats[1].dropped, stats[1].coalesced), (0, 2));
+    }
+
+    #[tokio::test]
+    async fn test_async_handler() {
+        let bus = EventBus::new();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let handler = bus.spawn_handler(
+            SubscribeOptions::new("async").types([EventType::UpdateOnline]),
+            move |event| {
+                let tx = tx.clone();
+                async move {
+                    tokio::task::yield_now().await;
+                    let _ = tx.send(event);
+                }
+            },
+        );
+
+        bus.emit(Event::LiveStart);
+        bus.emit(Event::UpdateOnline { count: 7 });
+        assert!(matches!(
+            rx.recv().await,
+            Some(Event::UpdateOnline { count: 7 })
+        ));
+
+        // The handler ends with the bus
+        drop(bus);
+        handler.await.unwrap();
+        assert!(rx.recv().await.is_none());
+    }
+
+    fn from<T: serde::de::DeserializeOwned>(value: serde_json::Value) -> T {
+        serde_json::from_value(value).unwrap()
+    }
+
+    /// One event of every type
+    fn sample_events() -> Vec<Event> {
+        use serde_json::json;
+        let sender = json!({"uid": 1, "uname": "测试", "face": "https://example.com/face.jpg"});
+        let message = || json!({"msg": "请注意"});
+        let winners = json!([{"uid": 2, "uname": "中奖者", "award_name": "小花花", "num": 1}]);
+        let status = |success| (success, "完成".to_string());
+        vec![
+            Event::UpdateRoom {
+                room_id: RoomId::new(123, 21484828, 1),
+                title: "标题".into(),
+                live_status: 1,
+                area_id: 745,
+            },
+            Event::UpdateOnline { count: 10 },
+            Event::NewDanmu(from(json!({
+                "sender": sender,
+                "content": "你好[dog]",
+                "emoji_content": null,
+                "reply_uname": "主播",
+                "timestamp": 1700000000,
+            }))),
+            Event::NewGift(from(json!({
+                "id": "g1",
+                "room": 21484828,
+                "gift_info": {
+                    "id": 31036, "name": "小花花", "price": 100, "coin_type": "gold",
+                    "img_basic": "", "img_dynamic": "", "gif": "", "webp": "",
+                },
+                "sender": sender,
+                "action": "投喂",
+                "num": 2,
+                "timestamp": 1700000000,
+            }))),
+            Event::NewGuard(from(json!({
+                "id": "d1", "room": 21484828, "sender": sender, "num": 1, "unit": "月",
+                "guard_level": 3, "price": 198000, "timestamp": 1700000000, "archived": true,
+            }))),
+            Event::NewSuperChat(from(json!({
+                "id": "s1", "room": 21484828, "sender": sender, "message": "SC",
+                "price": 30, "timestamp": 1700000000, "start_time": 1700000000,
+                "end_time": 1700000060, "background_color": "#EDF5FF",
+                "background_bottom_color": "#2A60B2",
+            }))),
+            Event::NewInteract(from(json!({"sender": sender, "action": 2}))),
+            Event::NewEntryEffect(from(json!({"sender": sender, "privilege_type": 3}))),
+            Event::RoomChange(from(json!({
+                "title": "新标题", "area_name": "虚拟主播", "parent_area_name": "虚拟主播",
+            }))),
+            Event::ConfigChanged {
+                key: "theme".into(),
+                value: json!("dark"),
+            },
+            Event::ConfigLoaded {
+                always_on_top: true,
+                guard_effect: true,
+                level_effect: false,
+                opacity: 0.5,
+                lite_mode: false,
+                medal_display: true,
+                interact_display: false,
+                theme: "dark".into(),
+                font_size: 14.0,
+                tts_enabled: false,
+                tts_gift_enabled: false,
+                tts_sc_enabled: true,
+                tts_volume: 0.25,
+                max_danmu_count: 500,
+                log_level: "info".into(),
+                auto_update_check: true,
+                obs: ObsConfig::default(),
+                webhooks: vec![from(
+                    json!({"url": "https://example.com/hook", "events": ["new_danmu"]}),
+                )],
+                recorder: RecorderConfig::default(),
+                network: NetworkConfig::default(),
+                retention: RetentionConfig::default(),
+                backup: BackupConfig::default(),
+            },
+            Event::DetailUpdate(from(json!({
+                "sender": sender,
+                "danmus": [{"record_type": "Danmu", "content": "你好", "timestamp": 1700000000}],
+            }))),
+            Event::LiveStart,
+            Event::LiveEnd,
+            Event::ConnectionStatus { connected: true },
+            Event::LoginStatusChanged {
+                logged_in: true,
+                user_info: Some(from(json!({
+                    "mid": 1, "name": "测试", "face": "", "sign": "", "level": 6,
+                    "live_room": {"roomid": 21484828, "liveStatus": 1},
+                }))),
+            },
+            Event::RequestQrLogin,
+            Event::QrCodeGenerated {
+                url: "https://example.com/qr".into(),
+                qrcode_key: "key".into(),
+            },
+            Event::QrLoginStatus {
+                status: crate::bilibili::api::QrCodeStatus::NeedConfirm,
+            },
+            Event::RequestLogout,
+            Event::RtmpInfo {
+                addr: "rtmp://example.com/live".into(),
+                code: "code".into(),
+            },
+            Event::FaceAuthRequired {
+                qr_url: "https://example.com/face".into(),
+            },
+            Event::ClearDanmuList,
+            Event::UserInfoFetched {
+                uid: 1,
+                user_info: from(
+                    json!({"mid": 1, "name": "测试", "face": "", "sign": "签名", "level": 5}),
+                ),
+            },
+            Event::AudienceListFetched {
+                list: vec![from(json!({
+                    "uid": 1, "name": "测试", "rank": 1, "score": 100,
+                    "medal_info": {"guard_level": 3, "medal_name": "牌子", "level": 21},
+                }))],
+            },
+            Event::GuardListFetched {
+                list: vec![from(json!({
+                    "uid": 1, "username": "测试", "guard_level": 3,
+                    "medal_info": {"medal_name": "牌子", "medal_level": 21},
+                }))],
+                total: 1,
+                page: 1,
+            },
+            Event::PluginsRefreshed {
+                plugins: vec![PluginInfoEvent {
+                    id: "demo".into(),
+                    name: "示例".into(),
+                    author: "作者".into(),
+                    desc: "".into(),
+                    version: "1.0.0".into(),
+                    path: "plugins/demo".into(),
+                    settings: vec![],
+                    setting_values: from(json!({"enabled": true})),
+                }],
+            },
+            Event::PluginImportResult {
+                success: true,
+                message: "导入成功".into(),
+            },
+            Event::DataCleared,
+            Event::UpdateCheckResult {
+                has_update: false,
+                current_version: "3.0.0".into(),
+                latest_version: "3.0.0".into(),
+                release_url: "".into(),
+                error: Some("timeout".into()),
+            },
+            Event::Warning(from(message())),
+            Event::CutOff(from(message())),
+            Event::CmdStatsUpdated(vec![from(json!({
+                "cmd": "DANMU_MSG", "count": 3, "decoded": true, "last_seen": 1700000000,
+                "sample": "{}",
+            }))]),
+            Event::ModerationResult {
+                success: false,
+                message: "失败".into(),
+            },
+            Event::BlockedUsersFetched {
+                users: vec![from(
+                    json!({"tuid": 2, "tname": "路人", "ctime": "2024-01-01 20:00:00"}),
+                )],
+                total: 1,
+            },
+            Event::NewLikeClick(from(json!({"sender": sender, "like_text": "为主播点赞了"}))),
+            Event::UpdateLikeCount { count: 99 },
+            Event::UpdateWatched {
+                count: 1000,
+                text: "1000人看过".into(),
+            },
+            Event::RedPocketStart(from(json!({
+                "lot_id": 1, "sender": sender, "danmu": "老板大气", "total_price": 1600,
+                "awards": [{"gift_id": 31212, "gift_name": "打call", "num": 2}],
+                "start_time": 1700000000, "end_time": 1700000180,
+            }))),
+            Event::RedPocketWinners(from(json!({"lot_id": 1, "winners": winners}))),
+            Event::AnchorLotStart(from(json!({
+                "id": 1, "award_name": "周边", "award_num": 1, "danmu": "抽奖", "require_text": "",
+                "gift_name": "", "gift_num": 0, "duration": 600, "start_time": 1700000000,
+            }))),
+            Event::AnchorLotAward(from(
+                json!({"id": 1, "award_name": "周边", "winners": winners}),
+            )),
+            Event::SuperChatDeleted {
+                ids: vec!["s1".into()],
+            },
+            Event::RoomSilent(from(json!({
+                "enabled": true, "silence_type": "medal", "level": 5, "end_time": 0,
+            }))),
+            Event::UserBlocked(from(json!({"uid": 2, "uname": "路人", "operator": 1}))),
+            {
+                let (success, message) = status(true);
+                Event::ExportFinished { success, message }
+            },
+            Event::GoalProgress(from(json!({
+                "id": 1, "room_id": 21484828, "title": "目标", "target": 100000,
+                "start_time": 1700000000, "sources": ["gift", "superchat"], "progress": 500,
+                "completed_at": null,
+            }))),
+            {
+                let (success, message) = status(false);
+                Event::GoalCommandResult { success, message }
+            },
+            Event::ObsStatus {
+                connected: true,
+                message: "已连接".into(),
+            },
+            {
+                let (success, message) = status(true);
+                Event::NetworkStatus { success, message }
+            },
+            Event::WebhookDeliveries(vec![WebhookDelivery {
+                id: 1,
+                url: "https://example.com/hook".into(),
+                channels: "danmu,gift".into(),
+                event_count: 2,
+                status: 200,
+                success: true,
+                attempts: 1,
+                error: "".into(),
+                timestamp: 1700000000,
+            }]),
+            Event::RecorderStatus {
+                recording: true,
+                message: "录制中".into(),
+            },
+            Event::AreaList(from(json!({"groups": [{
+                "id": 9, "name": "虚拟主播",
+                "list": [{"id": "745", "parent_id": "9", "name": "虚拟主播", "pinyin": "xunizhubo"}],
+            }]}))),
+            Event::RoomDetails {
+                description: "简介".into(),
+                tags: vec!["唱歌".into()],
+                news: "公告".into(),
+                cover: "https://example.com/cover.jpg".into(),
+            },
+            {
+                let (success, message) = status(true);
+                Event::RoomEditResult { success, message }
+            },
+            Event::SessionStatus {
+                recording: Some("a.jlrec".into()),
+                replaying: None,
+                files: vec!["a.jlrec".into()],
+                message: "".into(),
+            },
+            Event::DatabaseStatus {
+                stats: from(json!({
+                    "tables": [{"name": "danmus", "rows": 10, "bytes": 8192}],
+                    "total_bytes": 16384,
+                    "free_bytes": 4096,
+                })),
+                message: "已压缩".into(),
+            },
+            Event::BackupStatus {
+                backups: vec![from(json!({
+                    "path": "backups/jlivertool-20240501-200000.db",
+                    "size": 16384,
+                    "created": 1714564800,
+                }))],
+                message: "".into(),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_event_json_round_trip() {
+        let events = sample_events();
+        let mut types = HashSet::new();
+        for event in &events {
+            types.insert(event.event_type());
+            let json = event.to_json().unwrap();
+            assert_eq!(json["version"], EVENT_SCHEMA_VERSION);
+            assert_eq!(json["type"], event.event_type().as_str());
+
+            // Decoding and encoding again must give the same document
+            let decoded = Event::from_json(json.clone()).unwrap();
+            assert_eq!(decoded.event_type(), event.event_type());
+            assert_eq!(decoded.to_json().unwrap(), json);
+
+            let text = serde_json::to_string(&json).unwrap();
+            let decoded = Event::from_json(serde_json::from_str(&text).unwrap()).unwrap();
+            assert_eq!(decoded.to_json().unwrap(), json);
+        }
+        assert_eq!(types.len(), EventType::ALL.len(), "missing sample events");
+    }
+
+    #[test]
+    fn test_event_wire_format() {
+        let event = Event::UpdateWatched {
+            count: 1000,
+            text: "1000人看过".into(),
+        };
+        assert_eq!(
+            event.to_json().unwrap(),
+            serde_json::json!({
+                "version": 1,
+                "type": "update_watched",
+                "data": {"count": 1000, "text": "1000人看过"},
+            })
+        );
+        assert_eq!(
+            Event::LiveStart.to_json().unwrap(),
+            serde_json::json!({"version": 1, "type": "live_start"})
+        );
+
+        let future = serde_json::json!({"version": 2, "type": "live_start"});
+        assert!(Event::from_json(future).is_err());
+        assert!(Event::from_json(serde_json::json!({"type": "live_start"})).is_err());
+        let unknown = serde_json::json!({"version": 1, "type": "not_an_event"});
+        assert!(Event::from_json(unknown).is_err());
+    }
+}
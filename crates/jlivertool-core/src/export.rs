@@ -0,0 +1,508 @@
+//! Danmaku export to Bilibili XML and ASS subtitles
+//!
+//! Danmus, gifts and superchats stored in the database are mapped onto a video
+//! timeline starting at `ExportOptions::start_time`. Danmus and gifts scroll from
+//! right to left, superchats are pinned to the top. ASS output lays scrolling
+//! messages out in lanes so they don't overlap.
+
+use crate::database::Database;
+use crate::text::estimate_text_width;
+use anyhow::{bail, Result};
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// Output format of an export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Bilibili danmaku XML, loadable by most players and danmaku tools
+    Xml,
+    /// ASS subtitles with pre-computed scrolling lanes
+    Ass,
+}
+
+impl ExportFormat {
+    /// Guess the format from a file extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        Self::parse(&ext)
+    }
+
+    /// Parse "xml" or "ass"
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "xml" => Some(Self::Xml),
+            "ass" => Some(Self::Ass),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Xml => "xml",
+            Self::Ass => "ass",
+        }
+    }
+}
+
+/// Options of an export
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    /// Unix timestamp mapped to 0:00 of the video
+    pub start_time: i64,
+    /// Seconds added to every message time, negative to show messages earlier
+    pub offset: f64,
+    pub include_gifts: bool,
+    pub include_superchats: bool,
+    /// Video resolution used for the ASS layout
+    pub width: u32,
+    pub height: u32,
+    pub font_name: String,
+    pub font_size: f32,
+    /// Seconds a scrolling message takes to cross the screen
+    pub scroll_duration: f64,
+    /// Seconds a pinned superchat stays on screen
+    pub fixed_duration: f64,
+    /// Fraction of the screen height used by scrolling lanes
+    pub scroll_area: f32,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            start_time: 0,
+            offset: 0.0,
+            include_gifts: true,
+            include_superchats: true,
+            width: 1920,
+            height: 1080,
+            font_name: "Microsoft YaHei".to_string(),
+            font_size: 48.0,
+            scroll_duration: 10.0,
+            fixed_duration: 5.0,
+            scroll_area: 0.8,
+        }
+    }
+}
+
+/// Display mode of an exported message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportMode {
+    Scroll,
+    Top,
+}
+
+/// A message placed on the video timeline
+#[derive(Debug, Clone)]
+pub struct ExportItem {
+    /// Seconds since the start of the video
+    pub time: f64,
+    /// Unix timestamp the message was sent at
+    pub timestamp: i64,
+    pub mode: ExportMode,
+    /// 0xRRGGBB
+    pub color: u32,
+    pub uid: u64,
+    pub text: String,
+}
+
+const COLOR_WHITE: u32 = 0xffffff;
+const COLOR_GIFT: u32 = 0xedb83f;
+const COLOR_SUPERCHAT: u32 = 0xe5534b;
+
+/// Load the messages of `[from, to)` and place them on the timeline
+pub fn collect_items(
+    db: &Database,
+    room_id: u64,
+    from: i64,
+    to: i64,
+    options: &ExportOptions,
+) -> Result<Vec<ExportItem>> {
+    let mut items = Vec::new();
+    // Include messages still waiting for the writer
+    db.flush();
+
+    for danmu in db.get_danmus_between(room_id, from, to)? {
+        let text = match &danmu.emoji_content {
+            Some(emoji) if danmu.content.is_empty() => emoji.emoticon_unique.clone(),
+            _ => danmu.content.clone(),
+        };
+        items.push(ExportItem {
+            time: 0.0,
+            timestamp: danmu.timestamp,
+            mode: ExportMode::Scroll,
+            color: COLOR_WHITE,
+            uid: danmu.sender.uid,
+            text,
+        });
+    }
+
+    if options.include_gifts {
+        for gift in db.get_gifts_between(room_id, from, to)? {
+            items.push(ExportItem {
+                time: 0.0,
+                timestamp: gift.timestamp,
+                mode: ExportMode::Scroll,
+                color: COLOR_GIFT,
+                uid: gift.sender.uid,
+                text: format!(
+                    "{} {} {}x{}",
+                    gift.sender.uname, gift.action, gift.gift_info.name, gift.num
+                ),
+            });
+        }
+    }
+
+    if options.include_superchats {
+        for sc in db.get_superchats_between(room_id, from, to)? {
+            items.push(ExportItem {
+                time: 0.0,
+                timestamp: sc.timestamp,
+                mode: ExportMode::Top,
+                color: parse_hex_color(&sc.background_bottom_color).unwrap_or(COLOR_SUPERCHAT),
+                uid: sc.sender.uid,
+                text: format!("{} ¥{}：{}", sc.sender.uname, sc.price, sc.message),
+            });
+        }
+    }
+
+    // Stable sort keeps the database order of messages sent in the same second
+    items.sort_by_key(|item| item.timestamp);
+    place_on_timeline(&mut items, options);
+    Ok(items)
+}
+
+/// Compute `time` of sorted items. Timestamps only have second precision, so
+/// messages sent in the same second are spread evenly across it.
+fn place_on_timeline(items: &mut Vec<ExportItem>, options: &ExportOptions) {
+    let mut i = 0;
+    while i < items.len() {
+        let second = items[i].timestamp;
+        let count = items[i..]
+            .iter()
+            .take_while(|item| item.timestamp == second)
+            .count();
+        for (n, item) in items[i..i + count].iter_mut().enumerate() {
+            item.time = (second - options.start_time) as f64
+                + n as f64 / count as f64
+                + options.offset;
+        }
+        i += count;
+    }
+    items.retain(|item| item.time >= 0.0);
+}
+
+/// Render items as Bilibili danmaku XML
+pub fn to_xml(items: &[ExportItem]) -> String {
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<i>\n");
+    out.push_str("  <chatserver>chat.bilibili.com</chatserver>\n");
+    out.push_str("  <chatid>0</chatid>\n");
+    out.push_str("  <mission>0</mission>\n");
+    let _ = writeln!(out, "  <maxlimit>{}</maxlimit>", items.len().max(1000));
+    out.push_str("  <state>0</state>\n");
+    out.push_str("  <real_name>0</real_name>\n");
+    out.push_str("  <source>k-v</source>\n");
+    for (id, item) in items.iter().enumerate() {
+        let mode = match item.mode {
+            ExportMode::Scroll => 1,
+            ExportMode::Top => 5,
+        };
+        // p = time, mode, font size, color, send time, pool, sender hash, id
+        let _ = writeln!(
+            out,
+            "  <d p=\"{:.3},{},25,{},{},0,{:08x},{}\">{}</d>",
+            item.time,
+            mode,
+            item.color,
+            item.timestamp,
+            item.uid,
+            id,
+            escape_xml(&item.text)
+        );
+    }
+    out.push_str("</i>\n");
+    out
+}
+
+/// Render items as ASS subtitles
+pub fn to_ass(items: &[ExportItem], options: &ExportOptions) -> String {
+    let width = options.width as f32;
+    let line_height = options.font_size * 1.2;
+    let scroll_lanes =
+        ((options.height as f32 * options.scroll_area / line_height).floor() as usize).max(1);
+    let top_lanes = ((options.height as f32 / line_height).floor() as usize).max(1);
+
+    let mut out = String::new();
+    out.push_str("[Script Info]\n");
+    out.push_str("; Generated by JLiverTool\n");
+    out.push_str("ScriptType: v4.00+\n");
+    let _ = writeln!(out, "PlayResX: {}", options.width);
+    let _ = writeln!(out, "PlayResY: {}", options.height);
+    out.push_str("WrapStyle: 2\n");
+    out.push_str("ScaledBorderAndShadow: yes\n\n");
+
+    out.push_str("[V4+ Styles]\n");
+    out.push_str(
+        "Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, \
+         BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, \
+         BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\n",
+    );
+    let _ = writeln!(
+        out,
+        "Style: Danmaku,{},{},&H00FFFFFF,&H00FFFFFF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,1.5,0,7,0,0,0,1\n",
+        options.font_name, options.font_size
+    );
+
+    out.push_str("[Events]\n");
+    out.push_str("Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n");
+
+    let mut scroll = ScrollLanes::new(scroll_lanes, width, options.scroll_duration);
+    // Time each top lane becomes free
+    let mut top_free = vec![f64::MIN; top_lanes];
+
+    for item in items {
+        let text_width = estimate_text_width(&item.text, options.font_size);
+        let color = if item.color == COLOR_WHITE {
+            String::new()
+        } else {
+            format!("\\c{}", ass_color(item.color))
+        };
+
+        let (end, position) = match item.mode {
+            ExportMode::Scroll => {
+                let lane = scroll.assign(item.time, text_width);
+                let y = lane as f32 * line_height;
+                (
+                    item.time + options.scroll_duration,
+                    format!(
+                        "\\move({:.0},{:.0},{:.0},{:.0})",
+                        width,
+                        y,
+                        -text_width,
+                        y
+                    ),
+                )
+            }
+            ExportMode::Top => {
+                let lane = top_free
+                    .iter()
+                    .position(|free| *free <= item.time)
+                    .unwrap_or_else(|| earliest(&top_free));
+                top_free[lane] = item.time + options.fixed_duration;
+                let y = lane as f32 * line_height;
+                (
+                    item.time + options.fixed_duration,
+                    format!("\\an8\\pos({:.0},{:.0})", width / 2.0, y),
+                )
+            }
+        };
+
+        let _ = writeln!(
+            out,
+            "Dialogue: 0,{},{},Danmaku,,0,0,0,,{{{}{}}}{}",
+            ass_time(item.time),
+            ass_time(end),
+            position,
+            color,
+            escape_ass(&item.text)
+        );
+    }
+
+    out
+}
+
+/// Export `[from, to)` of a room to a file, returns the number of exported messages
+pub fn export_to_file(
+    db: &Database,
+    room_id: u64,
+    from: i64,
+    to: i64,
+    format: ExportFormat,
+    options: &ExportOptions,
+    path: &Path,
+) -> Result<usize> {
+    if to <= from {
+        bail!("Export range is empty");
+    }
+    let items = collect_items(db, room_id, from, to, options)?;
+    let content = match format {
+        ExportFormat::Xml => to_xml(&items),
+        ExportFormat::Ass => to_ass(&items, options),
+    };
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, content)?;
+    Ok(items.len())
+}
+
+/// Assigns scrolling messages to lanes so that a message neither overlaps the
+/// previous one in its lane nor catches up with it before it leaves the screen
+struct ScrollLanes {
+    /// (start time, text width) of the last message in each lane
+    last: Vec<Option<(f64, f32)>>,
+    screen_width: f32,
+    duration: f64,
+}
+
+impl ScrollLanes {
+    fn new(lanes: usize, screen_width: f32, duration: f64) -> Self {
+        Self {
+            last: vec![None; lanes],
+            screen_width,
+            duration,
+        }
+    }
+
+    fn speed(&self, text_width: f32) -> f64 {
+        (self.screen_width + text_width) as f64 / self.duration
+    }
+
+    fn assign(&mut self, time: f64, text_width: f32) -> usize {
+        let speed = self.speed(text_width);
+        let fits = |last: &Option<(f64, f32)>| match *last {
+            None => true,
+            Some((prev_time, prev_width)) => {
+                let prev_speed = self.speed(prev_width);
+                // The previous message has fully entered the screen
+                let entered = time >= prev_time + prev_width as f64 / prev_speed;
+                // The new message reaches the left edge after the previous one left
+                let no_catch_up =
+                    time + self.screen_width as f64 / speed >= prev_time + self.duration;
+                entered && no_catch_up
+            }
+        };
+
+        let lane = self.last.iter().position(fits).unwrap_or_else(|| {
+            // All lanes busy: reuse the lane whose message started earliest
+            let starts: Vec<f64> = self
+                .last
+                .iter()
+                .map(|l| l.map(|(t, _)| t).unwrap_or(f64::MIN))
+                .collect();
+            earliest(&starts)
+        });
+        self.last[lane] = Some((time, text_width));
+        lane
+    }
+}
+
+/// Index of the smallest value
+fn earliest(values: &[f64]) -> usize {
+    values
+        .iter()
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+/// Parse "#RRGGBB" into 0xRRGGBB
+fn parse_hex_color(color: &str) -> Option<u32> {
+    let hex = color.trim().trim_start_matches('#');
+    if hex.len() != 6 {
+        return None;
+    }
+    u32::from_str_radix(hex, 16).ok()
+}
+
+/// ASS colors are written as &HBBGGRR&
+fn ass_color(color: u32) -> String {
+    let r = (color >> 16) & 0xff;
+    let g = (color >> 8) & 0xff;
+    let b = color & 0xff;
+    format!("&H{:02X}{:02X}{:02X}&", b, g, r)
+}
+
+/// Format seconds as H:MM:SS.cc
+fn ass_time(seconds: f64) -> String {
+    let centis = (seconds.max(0.0) * 100.0).round() as u64;
+    format!(
+        "{}:{:02}:{:02}.{:02}",
+        centis / 360_000,
+        centis / 6000 % 60,
+        centis / 100 % 60,
+        centis % 100
+    )
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            // Control characters are not allowed in XML 1.0
+            c if c.is_control() => out.push(' '),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Braces and backslashes start override tags in ASS, replace them with fullwidth forms
+fn escape_ass(text: &str) -> String {
+    text.chars()
+        .map(|ch| match ch {
+            '{' => '｛',
+            '}' => '｝',
+            '\\' => '＼',
+            c if c.is_control() => ' ',
+            c => c,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(time: f64, text: &str) -> ExportItem {
+        ExportItem {
+            time,
+            timestamp: 0,
+            mode: ExportMode::Scroll,
+            color: COLOR_WHITE,
+            uid: 1,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_xml_escapes_content() {
+        let xml = to_xml(&[item(1.5, "<a & b>")]);
+        assert!(xml.contains("<d p=\"1.500,1,25,16777215,0,0,00000001,0\">&lt;a &amp; b&gt;</d>"));
+    }
+
+    #[test]
+    fn test_scroll_lanes_avoid_overlap() {
+        let mut lanes = ScrollLanes::new(3, 1920.0, 10.0);
+        // Two messages at the same time can't share a lane
+        assert_eq!(lanes.assign(0.0, 200.0), 0);
+        assert_eq!(lanes.assign(0.0, 200.0), 1);
+        // Long after, the first lane is free again
+        assert_eq!(lanes.assign(9.0, 200.0), 0);
+    }
+
+    #[test]
+    fn test_timeline_spreads_same_second_and_applies_offset() {
+        let mut items = vec![item(0.0, "a"), item(0.0, "b"), item(0.0, "c")];
+        for (i, it) in items.iter_mut().enumerate() {
+            it.timestamp = if i < 2 { 100 } else { 90 };
+        }
+        items.sort_by_key(|item| item.timestamp);
+        let options = ExportOptions {
+            start_time: 95,
+            offset: 1.0,
+            ..Default::default()
+        };
+        place_on_timeline(&mut items, &options);
+        // The message before the start is dropped
+        let times: Vec<f64> = items.iter().map(|i| i.time).collect();
+        assert_eq!(times, vec![6.0, 6.5]);
+        assert_eq!(ass_time(3725.5), "1:02:05.50");
+    }
+}
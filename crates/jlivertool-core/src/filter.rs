@@ -0,0 +1,68 @@
+//! Display rules shared by the main view and the browser overlays
+
+use crate::config::ConfigStore;
+use crate::messages::{DanmuMessage, EntryEffectMessage};
+use crate::types::MedalInfo;
+use serde::{Deserialize, Serialize};
+
+/// Which messages are shown in the danmu list
+///
+/// The browser overlays receive these values as JSON and apply the same rules
+/// in `overlay.js`, keep both in sync.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DisplayFilter {
+    /// Show fan medals in front of user names
+    pub medal_display: bool,
+    /// Show follow / share / like messages
+    pub interact_display: bool,
+    /// Show entry effects of guards
+    pub guard_effect: bool,
+    /// Show entry effects of high level users
+    pub level_effect: bool,
+}
+
+impl Default for DisplayFilter {
+    fn default() -> Self {
+        Self {
+            medal_display: true,
+            interact_display: false,
+            guard_effect: true,
+            level_effect: false,
+        }
+    }
+}
+
+impl DisplayFilter {
+    /// Read the rules from the configuration
+    pub fn from_config(config: &ConfigStore) -> Self {
+        Self {
+            medal_display: config.get_medal_display(),
+            interact_display: config.get_interact_display(),
+            guard_effect: config.get_guard_effect(),
+            level_effect: config.get_level_effect(),
+        }
+    }
+
+    /// Generated danmus (lottery and red pocket entries) are never shown
+    pub fn shows_danmu(&self, danmu: &DanmuMessage) -> bool {
+        !danmu.is_generated
+    }
+
+    /// Interact and like messages
+    pub fn shows_interact(&self) -> bool {
+        self.interact_display
+    }
+
+    pub fn shows_entry_effect(&self, entry: &EntryEffectMessage) -> bool {
+        if (1..=3).contains(&entry.privilege_type) {
+            self.guard_effect
+        } else {
+            self.level_effect
+        }
+    }
+
+    /// Only lit medals are shown
+    pub fn shows_medal(&self, medal: &MedalInfo) -> bool {
+        self.medal_display && !medal.medal_name.is_empty() && medal.is_lighted
+    }
+}
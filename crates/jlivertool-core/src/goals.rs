@@ -0,0 +1,291 @@
+//! Gift goals ("reach ¥X tonight")
+//!
+//! A goal has a target value, a start time and the record types that count
+//! towards it. Progress is loaded from the database with the same pricing as
+//! `Database::get_time_based_stats_range` (paid gifts, guards and superchats)
+//! and then updated incrementally from new gifts, guards and superchats.
+
+use crate::database::Database;
+use crate::earnings::EarningKind;
+use crate::messages::{GiftMessage, GuardMessage, SuperChatMessage};
+use anyhow::{bail, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// A donation goal of a room
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Goal {
+    pub id: i64,
+    pub room_id: u64,
+    pub title: String,
+    /// Target value in 1/1000 yuan
+    pub target: u64,
+    /// Unix timestamp from which records count
+    pub start_time: i64,
+    /// Record types that count towards the goal
+    pub sources: Vec<EarningKind>,
+    /// Current value in 1/1000 yuan
+    pub progress: u64,
+    /// Unix timestamp the goal was completed, None while active
+    pub completed_at: Option<i64>,
+}
+
+impl Goal {
+    pub fn counts(&self, kind: EarningKind) -> bool {
+        self.sources.contains(&kind)
+    }
+
+    pub fn is_reached(&self) -> bool {
+        self.progress >= self.target
+    }
+
+    /// Progress between 0.0 and 1.0
+    pub fn ratio(&self) -> f64 {
+        if self.target == 0 {
+            return 1.0;
+        }
+        (self.progress as f64 / self.target as f64).min(1.0)
+    }
+
+    /// Sources as stored in the database, e.g. "gift,guard"
+    pub fn sources_str(&self) -> String {
+        self.sources
+            .iter()
+            .map(|kind| kind.as_str())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// Parse comma separated sources, unknown names are ignored
+    pub fn parse_sources(value: &str) -> Vec<EarningKind> {
+        EarningKind::ALL
+            .into_iter()
+            .filter(|kind| {
+                value
+                    .split(',')
+                    .any(|name| EarningKind::parse(name.trim()) == Some(*kind))
+            })
+            .collect()
+    }
+
+    /// Add a record value, returns false if the record doesn't count
+    fn add(&mut self, kind: EarningKind, timestamp: i64, value: u64) -> bool {
+        if !self.counts(kind) || (timestamp > 0 && timestamp < self.start_time) || value == 0 {
+            return false;
+        }
+        self.progress += value;
+        true
+    }
+}
+
+/// Value of a gift in 1/1000 yuan, silver gifts are worth nothing
+pub fn gift_value(gift: &GiftMessage) -> u64 {
+    if gift.gift_info.coin_type == "gold" {
+        gift.gift_info.price * gift.num as u64
+    } else {
+        0
+    }
+}
+
+/// Value of a guard purchase in 1/1000 yuan
+pub fn guard_value(guard: &GuardMessage) -> u64 {
+    guard.price
+}
+
+/// Value of a superchat in 1/1000 yuan
+pub fn superchat_value(sc: &SuperChatMessage) -> u64 {
+    sc.price * 1000
+}
+
+/// Active goals of the connected room
+///
+/// Shared between the WebSocket handler, which feeds new records, and the UI
+/// commands that create, reset and complete goals. Every method returns the
+/// goals that changed so the caller can publish them.
+pub struct GoalTracker {
+    database: Database,
+    state: Mutex<TrackerState>,
+}
+
+#[derive(Default)]
+struct TrackerState {
+    room_id: u64,
+    goals: Vec<Goal>,
+    /// Superchats already counted, the same superchat may be pushed twice
+    superchat_ids: HashSet<String>,
+}
+
+impl GoalTracker {
+    pub fn new(database: Database) -> Self {
+        Self {
+            database,
+            state: Mutex::new(TrackerState::default()),
+        }
+    }
+
+    /// Load the active goals of a room with their progress
+    pub fn set_room(&self, room_id: u64) -> Result<Vec<Goal>> {
+        // Progress counts queued messages as well
+        self.database.flush();
+        let mut goals = self.database.get_active_goals(room_id)?;
+        for goal in &mut goals {
+            goal.progress = self.database.get_goal_progress(goal)?;
+        }
+        let mut state = self.state.lock();
+        *state = TrackerState {
+            room_id,
+            goals: goals.clone(),
+            superchat_ids: HashSet::new(),
+        };
+        Ok(goals)
+    }
+
+    /// Active goals of the current room
+    pub fn goals(&self) -> Vec<Goal> {
+        self.state.lock().goals.clone()
+    }
+
+    /// Create a goal for the current room starting now
+    pub fn create(&self, title: &str, target: u64, sources: Vec<EarningKind>) -> Result<Goal> {
+        if target == 0 {
+            bail!("Goal target must be positive");
+        }
+        if sources.is_empty() {
+            bail!("Goal needs at least one source");
+        }
+        let mut state = self.state.lock();
+        if state.room_id == 0 {
+            bail!("No room is connected");
+        }
+        let mut goal = Goal {
+            id: 0,
+            room_id: state.room_id,
+            title: title.trim().to_string(),
+            target,
+            start_time: chrono::Utc::now().timestamp(),
+            sources,
+            progress: 0,
+            completed_at: None,
+        };
+        goal.id = self.database.insert_goal(&goal)?;
+        state.goals.push(goal.clone());
+        Ok(goal)
+    }
+
+    /// Restart a goal from zero at the current time
+    pub fn reset(&self, id: i64) -> Result<Goal> {
+        let now = chrono::Utc::now().timestamp();
+        let mut state = self.state.lock();
+        let goal = find_goal(&mut state.goals, id)?;
+        self.database.set_goal_start_time(id, now)?;
+        goal.start_time = now;
+        goal.progress = 0;
+        Ok(goal.clone())
+    }
+
+    /// Mark a goal as completed, it is no longer tracked
+    pub fn complete(&self, id: i64) -> Result<Goal> {
+        let now = chrono::Utc::now().timestamp();
+        let mut state = self.state.lock();
+        let goal = find_goal(&mut state.goals, id)?;
+        self.database.set_goal_completed(id, now)?;
+        goal.completed_at = Some(now);
+        let goal = goal.clone();
+        state.goals.retain(|g| g.id != id);
+        Ok(goal)
+    }
+
+    pub fn on_gift(&self, gift: &GiftMessage) -> Vec<Goal> {
+        self.add(gift.room, EarningKind::Gift, gift.timestamp, gift_value(gift))
+    }
+
+    pub fn on_guard(&self, guard: &GuardMessage) -> Vec<Goal> {
+        self.add(guard.room, EarningKind::Guard, guard.timestamp, guard_value(guard))
+    }
+
+    pub fn on_superchat(&self, sc: &SuperChatMessage) -> Vec<Goal> {
+        if !self.state.lock().superchat_ids.insert(sc.id.clone()) {
+            return Vec::new();
+        }
+        self.add(sc.room, EarningKind::SuperChat, sc.timestamp, superchat_value(sc))
+    }
+
+    fn add(&self, room_id: u64, kind: EarningKind, timestamp: i64, value: u64) -> Vec<Goal> {
+        let mut state = self.state.lock();
+        if room_id != state.room_id {
+            return Vec::new();
+        }
+        state
+            .goals
+            .iter_mut()
+            .filter_map(|goal| goal.add(kind, timestamp, value).then(|| goal.clone()))
+            .collect()
+    }
+}
+
+fn find_goal(goals: &mut [Goal], id: i64) -> Result<&mut Goal> {
+    match goals.iter_mut().find(|g| g.id == id) {
+        Some(goal) => Ok(goal),
+        None => bail!("Goal {} not found", id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::GiftInfo;
+    use crate::types::Sender;
+
+    fn gift(room: u64, coin_type: &str, price: u64, num: u32, timestamp: i64) -> GiftMessage {
+        GiftMessage {
+            id: uuid::Uuid::new_v4().to_string(),
+            room,
+            gift_info: GiftInfo {
+                id: 1,
+                name: "gift".to_string(),
+                price,
+                coin_type: coin_type.to_string(),
+                img_basic: String::new(),
+                img_dynamic: String::new(),
+                gif: String::new(),
+                webp: String::new(),
+            },
+            sender: Sender::default(),
+            action: "投喂".to_string(),
+            num,
+            timestamp,
+            archived: false,
+        }
+    }
+
+    #[test]
+    fn test_goal_progress() {
+        let db = Database::in_memory().unwrap();
+        let tracker = GoalTracker::new(db.clone());
+        tracker.set_room(1).unwrap();
+        let goal = tracker
+            .create("今晚目标", 10_000, vec![EarningKind::Gift, EarningKind::SuperChat])
+            .unwrap();
+
+        let now = chrono::Utc::now().timestamp();
+        let paid = gift(1, "gold", 1000, 3, now);
+        assert_eq!(tracker.on_gift(&paid)[0].progress, 3000);
+        // Silver gifts, other rooms and records before the start don't count
+        assert!(tracker.on_gift(&gift(1, "silver", 1000, 1, now)).is_empty());
+        assert!(tracker.on_gift(&gift(2, "gold", 1000, 1, now)).is_empty());
+        assert!(tracker.on_gift(&gift(1, "gold", 1000, 1, now - 3600)).is_empty());
+
+        // Progress is restored from the database
+        db.insert_gift(&paid).unwrap();
+        db.insert_gift(&gift(1, "gold", 1000, 1, now - 3600)).unwrap();
+        let goals = tracker.set_room(1).unwrap();
+        assert_eq!(goals[0].progress, 3000);
+        assert_eq!(goals[0].sources, vec![EarningKind::Gift, EarningKind::SuperChat]);
+
+        assert_eq!(tracker.reset(goal.id).unwrap().progress, 0);
+        assert!(tracker.complete(goal.id).unwrap().completed_at.is_some());
+        assert!(tracker.goals().is_empty());
+        assert!(db.get_active_goals(1).unwrap().is_empty());
+    }
+}
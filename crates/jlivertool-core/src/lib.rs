@@ -4,19 +4,45 @@
 //! - Bilibili API client
 //! - WebSocket danmaku connection
 //! - Event system
+//! - Display filter rules
 //! - Configuration storage
 //! - Data models
-//! - SQLite database
+//! - SQLite database with a batched background writer
+//! - Data retention and database compaction
+//! - Database backups
+//! - Danmaku export (XML / ASS)
+//! - Text width estimates shared by the UI and export
+//! - Earnings export (CSV / JSONL)
+//! - Gift goals
+//! - Prometheus metrics
+//! - OBS integration (obs-websocket)
+//! - Outbound webhooks
+//! - Proxy and network settings
+//! - Live stream recording
 //! - TTS (Text-to-Speech) support
 
+pub mod backup;
 pub mod bilibili;
+pub mod cmd_stats;
 pub mod config;
 pub mod database;
+pub mod db_writer;
+pub mod earnings;
 pub mod events;
+pub mod export;
+pub mod filter;
+pub mod goals;
 pub mod messages;
+pub mod metrics;
+pub mod network;
+pub mod obs;
+pub mod recorder;
+pub mod retention;
+pub mod text;
 pub mod tts;
 pub mod types;
 pub mod update;
+pub mod webhook;
 
 pub use bilibili::api::BiliApi;
 pub use bilibili::ws::BiliWebSocket;
@@ -48,6 +48,9 @@ pub struct DanmuMessage {
     #[serde(default = "default_side_index")]
     pub side_index: i32,
     pub reply_uname: Option<String>,
+    /// Unix timestamp (seconds) the danmaku was sent at
+    #[serde(default)]
+    pub timestamp: i64,
 }
 
 fn default_side_index() -> i32 {
@@ -153,6 +156,16 @@ impl DanmuMessage {
             .map(|v| v > 0)
             .unwrap_or(false);
 
+        // Send time in milliseconds: info[0][4]
+        let timestamp = info
+            .first()
+            .and_then(|v| v.as_array())
+            .and_then(|arr| arr.get(4))
+            .and_then(|v| v.as_i64())
+            .filter(|ms| *ms > 0)
+            .map(|ms| ms / 1000)
+            .unwrap_or_else(|| chrono::Utc::now().timestamp());
+
         // Special user: info[2][2] > 0
         let is_special = info
             .get(2)
@@ -171,6 +184,7 @@ impl DanmuMessage {
             emoji_content,
             side_index,
             reply_uname,
+            timestamp,
         })
     }
 }
@@ -609,3 +623,689 @@ impl CutOffMessage {
         Some(Self { msg })
     }
 }
+
+/// Read a string field, defaulting to empty
+fn str_field(data: &Value, key: &str) -> String {
+    data.get(key)
+        .and_then(|v| v.as_str())
+        .unwrap_or("")
+        .to_string()
+}
+
+/// Read an unsigned integer field that may be sent as a number or a string
+fn u64_field(data: &Value, key: &str) -> u64 {
+    match data.get(key) {
+        Some(Value::String(s)) => s.parse().unwrap_or(0),
+        Some(v) => v.as_u64().unwrap_or(0),
+        None => 0,
+    }
+}
+
+/// Parse a `fans_medal` / `medal_info` object
+fn parse_medal(medal: &Value) -> MedalInfo {
+    MedalInfo {
+        medal_level: u64_field(medal, "medal_level") as u8,
+        medal_name: str_field(medal, "medal_name"),
+        anchor_roomid: u64_field(medal, "anchor_roomid"),
+        guard_level: u64_field(medal, "guard_level") as u8,
+        medal_color: u64_field(medal, "medal_color") as u32,
+        is_lighted: u64_field(medal, "is_lighted") == 1,
+        ..Default::default()
+    }
+}
+
+/// Total like count update (LIKE_INFO_V3_UPDATE)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LikeCountMessage {
+    pub click_count: u64,
+}
+
+impl LikeCountMessage {
+    pub fn from_raw(body: &Value) -> Option<Self> {
+        let data = body.get("data")?;
+        let click_count = data.get("click_count")?.as_u64()?;
+        Some(Self { click_count })
+    }
+}
+
+/// A user liked the stream (LIKE_INFO_V3_CLICK)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LikeClickMessage {
+    pub sender: Sender,
+    /// Text shown by Bilibili, e.g. "为主播点赞了"
+    pub like_text: String,
+}
+
+impl LikeClickMessage {
+    pub fn from_raw(body: &Value) -> Option<Self> {
+        let data = body.get("data")?;
+
+        let mut sender = Sender {
+            uid: data.get("uid")?.as_u64()?,
+            uname: data.get("uname")?.as_str()?.to_string(),
+            ..Default::default()
+        };
+        if let Some(face) = data.pointer("/uinfo/base/face").and_then(|v| v.as_str()) {
+            sender.face = face.to_string();
+        }
+        if let Some(medal) = data.get("fans_medal") {
+            sender.medal_info = parse_medal(medal);
+        }
+
+        Some(Self {
+            sender,
+            like_text: str_field(data, "like_text"),
+        })
+    }
+}
+
+/// Award of a popularity red pocket
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedPocketAward {
+    pub gift_id: u64,
+    pub gift_name: String,
+    pub num: u32,
+}
+
+/// Popularity red pocket started (POPULARITY_RED_POCKET_START)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedPocketStartMessage {
+    pub lot_id: u64,
+    pub sender: Sender,
+    /// Danmu viewers must send to join
+    pub danmu: String,
+    /// Total value in 1/1000 yuan, same unit as gift prices
+    pub total_price: u64,
+    pub awards: Vec<RedPocketAward>,
+    pub start_time: i64,
+    pub end_time: i64,
+}
+
+impl RedPocketStartMessage {
+    pub fn from_raw(body: &Value) -> Option<Self> {
+        let data = body.get("data")?;
+
+        let mut sender = Sender {
+            uid: u64_field(data, "sender_uid"),
+            uname: str_field(data, "sender_name"),
+            face: str_field(data, "sender_face"),
+            ..Default::default()
+        };
+        if let Some(medal) = data.get("medal_info") {
+            sender.medal_info = parse_medal(medal);
+        }
+
+        let awards = data
+            .get("awards")
+            .and_then(|v| v.as_array())
+            .map(|awards| {
+                awards
+                    .iter()
+                    .map(|a| RedPocketAward {
+                        gift_id: u64_field(a, "gift_id"),
+                        gift_name: str_field(a, "gift_name"),
+                        num: u64_field(a, "num") as u32,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Some(Self {
+            lot_id: data.get("lot_id")?.as_u64()?,
+            sender,
+            danmu: str_field(data, "danmu"),
+            total_price: u64_field(data, "total_price"),
+            awards,
+            start_time: data.get("start_time").and_then(|v| v.as_i64()).unwrap_or(0),
+            end_time: data.get("end_time").and_then(|v| v.as_i64()).unwrap_or(0),
+        })
+    }
+}
+
+/// Winner of a lottery (red pocket or anchor lottery)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LotteryWinner {
+    pub uid: u64,
+    pub uname: String,
+    /// Name of the won award
+    pub award_name: String,
+    pub num: u32,
+}
+
+/// Winners of a popularity red pocket (POPULARITY_RED_POCKET_WINNER_LIST)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedPocketWinnerMessage {
+    pub lot_id: u64,
+    pub winners: Vec<LotteryWinner>,
+}
+
+impl RedPocketWinnerMessage {
+    pub fn from_raw(body: &Value) -> Option<Self> {
+        let data = body.get("data")?;
+        let awards = data.get("awards");
+
+        // winner_info entries are arrays: [uid, uname, ?, award gift id, ...]
+        let winners = data
+            .get("winner_info")
+            .and_then(|v| v.as_array())
+            .map(|list| {
+                list.iter()
+                    .filter_map(|w| {
+                        let w = w.as_array()?;
+                        let gift_id = w.get(3).and_then(|v| v.as_u64()).unwrap_or(0);
+                        let award_name = awards
+                            .and_then(|a| a.get(gift_id.to_string()))
+                            .map(|a| str_field(a, "award_name"))
+                            .unwrap_or_default();
+                        Some(LotteryWinner {
+                            uid: w.first()?.as_u64()?,
+                            uname: w.get(1)?.as_str()?.to_string(),
+                            award_name,
+                            num: 1,
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Some(Self {
+            lot_id: data.get("lot_id")?.as_u64()?,
+            winners,
+        })
+    }
+}
+
+/// Anchor lottery started (ANCHOR_LOT_START)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnchorLotStartMessage {
+    pub id: u64,
+    pub award_name: String,
+    pub award_num: u32,
+    /// Danmu viewers must send to join, may be empty
+    pub danmu: String,
+    /// Join requirement shown by Bilibili, e.g. "当前主播粉丝勋章至少1级"
+    pub require_text: String,
+    /// Gift viewers must send to join, may be empty
+    pub gift_name: String,
+    pub gift_num: u32,
+    /// Duration of the lottery in seconds
+    pub duration: i64,
+    pub start_time: i64,
+}
+
+impl AnchorLotStartMessage {
+    pub fn from_raw(body: &Value) -> Option<Self> {
+        let data = body.get("data")?;
+
+        Some(Self {
+            id: data.get("id")?.as_u64()?,
+            award_name: str_field(data, "award_name"),
+            award_num: u64_field(data, "award_num") as u32,
+            danmu: str_field(data, "danmu"),
+            require_text: str_field(data, "require_text"),
+            gift_name: str_field(data, "gift_name"),
+            gift_num: u64_field(data, "gift_num") as u32,
+            duration: data.get("time").and_then(|v| v.as_i64()).unwrap_or(0),
+            start_time: data
+                .get("current_time")
+                .and_then(|v| v.as_i64())
+                .unwrap_or_else(|| chrono::Utc::now().timestamp()),
+        })
+    }
+}
+
+/// Anchor lottery drawn (ANCHOR_LOT_AWARD)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnchorLotAwardMessage {
+    pub id: u64,
+    pub award_name: String,
+    pub winners: Vec<LotteryWinner>,
+}
+
+impl AnchorLotAwardMessage {
+    pub fn from_raw(body: &Value) -> Option<Self> {
+        let data = body.get("data")?;
+        let award_name = str_field(data, "award_name");
+
+        let winners = data
+            .get("award_users")
+            .and_then(|v| v.as_array())
+            .map(|users| {
+                users
+                    .iter()
+                    .filter_map(|u| {
+                        Some(LotteryWinner {
+                            uid: u.get("uid")?.as_u64()?,
+                            uname: str_field(u, "uname"),
+                            award_name: award_name.clone(),
+                            num: u64_field(u, "num").max(1) as u32,
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Some(Self {
+            id: data.get("id")?.as_u64()?,
+            award_name,
+            winners,
+        })
+    }
+}
+
+/// Watched count update (WATCHED_CHANGE)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchedChangeMessage {
+    pub num: u64,
+    /// Display text, e.g. "1.2万人看过"
+    pub text: String,
+}
+
+impl WatchedChangeMessage {
+    pub fn from_raw(body: &Value) -> Option<Self> {
+        let data = body.get("data")?;
+        Some(Self {
+            num: data.get("num")?.as_u64()?,
+            text: str_field(data, "text_large"),
+        })
+    }
+}
+
+/// Superchats removed by Bilibili or the streamer (SUPER_CHAT_MESSAGE_DELETE)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SuperChatDeleteMessage {
+    /// IDs matching `SuperChatMessage::id`
+    pub ids: Vec<String>,
+}
+
+impl SuperChatDeleteMessage {
+    pub fn from_raw(body: &Value) -> Option<Self> {
+        let ids = body
+            .pointer("/data/ids")?
+            .as_array()?
+            .iter()
+            .filter_map(|id| match id {
+                Value::String(s) => Some(s.clone()),
+                v => v.as_u64().map(|id| id.to_string()),
+            })
+            .collect();
+        Some(Self { ids })
+    }
+}
+
+/// Room-wide silence changed (ROOM_SILENT_ON / ROOM_SILENT_OFF)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomSilentMessage {
+    pub enabled: bool,
+    /// "level", "medal" or "member"
+    pub silence_type: String,
+    /// Minimum level allowed to send danmu
+    pub level: u32,
+    /// Unix timestamp the silence ends at, -1 until lifted
+    pub end_time: i64,
+}
+
+impl RoomSilentMessage {
+    pub fn from_raw(body: &Value, enabled: bool) -> Option<Self> {
+        let data = body.get("data");
+        Some(Self {
+            enabled,
+            silence_type: data.map(|d| str_field(d, "type")).unwrap_or_default(),
+            level: data.map(|d| u64_field(d, "level") as u32).unwrap_or(0),
+            end_time: data
+                .and_then(|d| d.get("second"))
+                .and_then(|v| v.as_i64())
+                .unwrap_or(-1),
+        })
+    }
+}
+
+/// A user was blocked in the room (ROOM_BLOCK_MSG)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomBlockMessage {
+    pub uid: u64,
+    pub uname: String,
+    /// 1 room admin, 2 streamer
+    pub operator: u8,
+}
+
+impl RoomBlockMessage {
+    pub fn from_raw(body: &Value) -> Option<Self> {
+        let data = body.get("data")?;
+        Some(Self {
+            uid: u64_field(data, "uid"),
+            uname: str_field(data, "uname"),
+            operator: u64_field(data, "operator") as u8,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_like_click() {
+        let body = json!({
+            "cmd": "LIKE_INFO_V3_CLICK",
+            "data": {
+                "show_area": 0,
+                "msg_type": 6,
+                "like_icon": "https://i0.hdslb.com/bfs/live/23678e3d90402bea6a65251b3e728044c21b1f0f.png",
+                "uid": 475210,
+                "like_text": "为主播点赞了",
+                "uname": "Xinrea",
+                "uname_color": "",
+                "identities": [1],
+                "fans_medal": {
+                    "target_id": 61639371,
+                    "medal_level": 21,
+                    "medal_name": "轴芯",
+                    "medal_color": 1725515,
+                    "medal_color_start": 1725515,
+                    "medal_color_end": 5414290,
+                    "medal_color_border": 6809855,
+                    "is_lighted": 1,
+                    "guard_level": 3,
+                    "special": "",
+                    "icon_id": 0,
+                    "anchor_roomid": 21484828,
+                    "score": 50001980
+                },
+                "contribution_info": { "grade": 0 },
+                "dmscore": 20,
+                "is_mystery": false,
+                "uinfo": {
+                    "uid": 475210,
+                    "base": {
+                        "name": "Xinrea",
+                        "face": "https://i1.hdslb.com/bfs/face/noface.jpg"
+                    }
+                }
+            }
+        });
+
+        let like = LikeClickMessage::from_raw(&body).unwrap();
+        assert_eq!(like.sender.uid, 475210);
+        assert_eq!(like.sender.uname, "Xinrea");
+        assert_eq!(like.sender.face, "https://i1.hdslb.com/bfs/face/noface.jpg");
+        assert_eq!(like.sender.medal_info.medal_name, "轴芯");
+        assert_eq!(like.sender.medal_info.medal_level, 21);
+        assert_eq!(like.sender.medal_info.guard_level, 3);
+        assert!(like.sender.medal_info.is_lighted);
+        assert_eq!(like.like_text, "为主播点赞了");
+    }
+
+    #[test]
+    fn test_red_pocket_start() {
+        let body = json!({
+            "cmd": "POPULARITY_RED_POCKET_START",
+            "data": {
+                "lot_id": 16224437,
+                "sender_uid": 1405589619,
+                "sender_name": "红包老板",
+                "sender_face": "http://i0.hdslb.com/bfs/face/member/noface.jpg",
+                "join_requirement": 1,
+                "danmu": "老板大气！点点红包抽礼物！",
+                "current_time": 1700000000,
+                "start_time": 1700000000,
+                "end_time": 1700000180,
+                "last_time": 180,
+                "remove_time": 1700000195,
+                "replace_time": 1700000190,
+                "lot_status": 1,
+                "user_status": 2,
+                "awards": [
+                    { "gift_id": 31212, "gift_name": "打call", "gift_pic": "", "num": 2 },
+                    { "gift_id": 31214, "gift_name": "牛哇", "gift_pic": "", "num": 3 }
+                ],
+                "lot_config_id": 3,
+                "total_price": 16000,
+                "wait_num": 0,
+                "is_mystery": false,
+                "rp_type": 0,
+                "medal_info": {
+                    "target_id": 61639371,
+                    "special": "",
+                    "icon_id": 0,
+                    "anchor_uname": "",
+                    "anchor_roomid": 21484828,
+                    "medal_level": 12,
+                    "medal_name": "轴芯",
+                    "medal_color": 12478086,
+                    "is_lighted": 1,
+                    "guard_level": 0
+                }
+            }
+        });
+
+        let pocket = RedPocketStartMessage::from_raw(&body).unwrap();
+        assert_eq!(pocket.lot_id, 16224437);
+        assert_eq!(pocket.sender.uid, 1405589619);
+        assert_eq!(pocket.sender.uname, "红包老板");
+        assert_eq!(pocket.sender.medal_info.medal_level, 12);
+        assert_eq!(pocket.danmu, "老板大气！点点红包抽礼物！");
+        assert_eq!(pocket.total_price, 16000);
+        assert_eq!(pocket.awards.len(), 2);
+        assert_eq!(pocket.awards[1].gift_id, 31214);
+        assert_eq!(pocket.awards[1].gift_name, "牛哇");
+        assert_eq!(pocket.awards[1].num, 3);
+        assert_eq!(pocket.start_time, 1700000000);
+        assert_eq!(pocket.end_time, 1700000180);
+    }
+
+    #[test]
+    fn test_red_pocket_winners() {
+        // winner_info entries are positional: [uid, uname, bag id, gift id, ...]
+        let body = json!({
+            "cmd": "POPULARITY_RED_POCKET_WINNER_LIST",
+            "data": {
+                "lot_id": 16224437,
+                "total_num": 3,
+                "award_num": 5,
+                "winner_info": [
+                    [3493139045795863u64, "用户A", 10567413, 31212, false, 0],
+                    [38626213, "用户B", 10567414, 31214, false, 0],
+                    [38626214, "用户C", 10567415, 99999, false, 0]
+                ],
+                "awards": {
+                    "31212": {
+                        "award_type": 1,
+                        "award_name": "打call",
+                        "award_pic": "",
+                        "award_big_pic": "",
+                        "award_price": 500
+                    },
+                    "31214": {
+                        "award_type": 1,
+                        "award_name": "牛哇",
+                        "award_pic": "",
+                        "award_big_pic": "",
+                        "award_price": 100
+                    }
+                },
+                "version": 1,
+                "rp_type": 0,
+                "timestamp": 1700000181
+            }
+        });
+
+        let result = RedPocketWinnerMessage::from_raw(&body).unwrap();
+        assert_eq!(result.lot_id, 16224437);
+        assert_eq!(result.winners.len(), 3);
+        assert_eq!(result.winners[0].uid, 3493139045795863);
+        assert_eq!(result.winners[0].uname, "用户A");
+        assert_eq!(result.winners[0].award_name, "打call");
+        assert_eq!(result.winners[1].award_name, "牛哇");
+        assert_eq!(result.winners[1].num, 1);
+        // Unknown award ids keep the winner without an award name
+        assert_eq!(result.winners[2].uname, "用户C");
+        assert_eq!(result.winners[2].award_name, "");
+    }
+
+    #[test]
+    fn test_anchor_lot_start() {
+        let body = json!({
+            "cmd": "ANCHOR_LOT_START",
+            "data": {
+                "asset_icon": "https://i0.hdslb.com/bfs/live/627ee2d9e71c682810e7dc4400d5ae2713442c02.png",
+                "award_id": 0,
+                "award_image": "",
+                "award_name": "舰长",
+                "award_num": 1,
+                "award_type": 0,
+                "cur_gift_num": 0,
+                "current_time": 1700000000,
+                "danmu": "冲冲冲",
+                "danmu_type": 0,
+                "gift_id": 0,
+                "gift_name": "",
+                "gift_num": 1,
+                "gift_price": 0,
+                "goaway_time": 180,
+                "goods_id": -99998,
+                "id": 5433217,
+                "is_broadcast": 1,
+                "join_type": 0,
+                "lot_status": 0,
+                "max_time": 600,
+                "require_text": "当前主播粉丝勋章至少1级",
+                "require_type": 2,
+                "require_value": 1,
+                "room_id": 21484828,
+                "send_gift_ensure": 0,
+                "show_panel": 1,
+                "status": 1,
+                "time": 599,
+                "url": "https://live.bilibili.com/p/html/live-lottery/anchor-join.html",
+                "web_url": "https://live.bilibili.com/p/html/live-lottery/anchor-join.html"
+            }
+        });
+
+        let lot = AnchorLotStartMessage::from_raw(&body).unwrap();
+        assert_eq!(lot.id, 5433217);
+        assert_eq!(lot.award_name, "舰长");
+        assert_eq!(lot.award_num, 1);
+        assert_eq!(lot.danmu, "冲冲冲");
+        assert_eq!(lot.require_text, "当前主播粉丝勋章至少1级");
+        assert_eq!(lot.gift_name, "");
+        assert_eq!(lot.gift_num, 1);
+        assert_eq!(lot.duration, 599);
+        assert_eq!(lot.start_time, 1700000000);
+    }
+
+    #[test]
+    fn test_anchor_lot_award() {
+        let body = json!({
+            "cmd": "ANCHOR_LOT_AWARD",
+            "data": {
+                "award_dont_popup": 1,
+                "award_image": "",
+                "award_name": "舰长",
+                "award_num": 2,
+                "award_price_text": "价值138电池",
+                "award_type": 0,
+                "award_users": [
+                    {
+                        "uid": 475210,
+                        "uname": "Xinrea",
+                        "face": "https://i1.hdslb.com/bfs/face/noface.jpg",
+                        "level": 21,
+                        "color": 5805790,
+                        "num": 1
+                    },
+                    {
+                        "uid": 38626213,
+                        "uname": "用户B",
+                        "face": "",
+                        "level": 3,
+                        "color": 9868950
+                    }
+                ],
+                "id": 5433217,
+                "lot_status": 2,
+                "url": "https://live.bilibili.com/p/html/live-lottery/anchor-join.html",
+                "web_url": "https://live.bilibili.com/p/html/live-lottery/anchor-join.html"
+            }
+        });
+
+        let award = AnchorLotAwardMessage::from_raw(&body).unwrap();
+        assert_eq!(award.id, 5433217);
+        assert_eq!(award.award_name, "舰长");
+        assert_eq!(award.winners.len(), 2);
+        assert_eq!(award.winners[0].uid, 475210);
+        assert_eq!(award.winners[0].uname, "Xinrea");
+        assert_eq!(award.winners[0].award_name, "舰长");
+        // Missing num counts as one award
+        assert_eq!(award.winners[1].num, 1);
+    }
+
+    #[test]
+    fn test_watched_change() {
+        let body = json!({
+            "cmd": "WATCHED_CHANGE",
+            "data": { "num": 12345, "text_small": "1.2万", "text_large": "1.2万人看过" }
+        });
+
+        let watched = WatchedChangeMessage::from_raw(&body).unwrap();
+        assert_eq!(watched.num, 12345);
+        assert_eq!(watched.text, "1.2万人看过");
+    }
+
+    #[test]
+    fn test_super_chat_delete() {
+        let body = json!({
+            "cmd": "SUPER_CHAT_MESSAGE_DELETE",
+            "data": { "ids": [8795431, "8795432"] },
+            "roomid": 21484828
+        });
+
+        let delete = SuperChatDeleteMessage::from_raw(&body).unwrap();
+        assert_eq!(delete.ids, vec!["8795431", "8795432"]);
+    }
+
+    #[test]
+    fn test_room_silent() {
+        let on = json!({
+            "cmd": "ROOM_SILENT_ON",
+            "data": { "type": "level", "level": 10, "second": 1700003600 }
+        });
+        let silent = RoomSilentMessage::from_raw(&on, true).unwrap();
+        assert!(silent.enabled);
+        assert_eq!(silent.silence_type, "level");
+        assert_eq!(silent.level, 10);
+        assert_eq!(silent.end_time, 1700003600);
+
+        let until_lifted = json!({
+            "cmd": "ROOM_SILENT_ON",
+            "data": { "type": "medal", "level": 1, "second": -1 }
+        });
+        let silent = RoomSilentMessage::from_raw(&until_lifted, true).unwrap();
+        assert_eq!(silent.silence_type, "medal");
+        assert_eq!(silent.end_time, -1);
+
+        let off = json!({
+            "cmd": "ROOM_SILENT_OFF",
+            "data": { "type": "", "level": 0, "second": 0 }
+        });
+        let silent = RoomSilentMessage::from_raw(&off, false).unwrap();
+        assert!(!silent.enabled);
+        assert_eq!(silent.level, 0);
+    }
+
+    #[test]
+    fn test_room_block() {
+        let body = json!({
+            "cmd": "ROOM_BLOCK_MSG",
+            "data": { "dmscore": 30, "operator": 1, "uid": 38626213, "uname": "用户B" },
+            "uid": "38626213",
+            "uname": "用户B"
+        });
+
+        let block = RoomBlockMessage::from_raw(&body).unwrap();
+        assert_eq!(block.uid, 38626213);
+        assert_eq!(block.uname, "用户B");
+        assert_eq!(block.operator, 1);
+    }
+}
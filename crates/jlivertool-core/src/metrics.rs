@@ -0,0 +1,359 @@
+//! Live stream metrics in the Prometheus text format
+//!
+//! Counters and gauges are fed from the event stream and labelled by room.
+//! Values that live elsewhere (TTS queue, plugin clients) are registered as
+//! sampled gauges and read when the metrics are rendered.
+
+use crate::events::{Event, EventBus, SubscriberStats};
+use crate::goals::{gift_value, guard_value, superchat_value};
+use parking_lot::{Mutex, RwLock};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Content type of `Metrics::render`
+pub const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Metrics of one room
+#[derive(Debug, Clone, Default)]
+struct RoomMetrics {
+    danmu: u64,
+    gifts: u64,
+    /// Paid gift value in 1/1000 yuan
+    gift_value: u64,
+    guards: u64,
+    guard_value: u64,
+    superchats: u64,
+    superchat_value: u64,
+    online: u64,
+    live: bool,
+    interactions: BTreeMap<&'static str, u64>,
+    reconnects: u64,
+}
+
+#[derive(Default)]
+struct MetricsState {
+    current_room: u64,
+    rooms: BTreeMap<u64, RoomMetrics>,
+    /// Room of the last established connection
+    connected_room: Option<u64>,
+}
+
+impl MetricsState {
+    fn room(&mut self, room_id: u64) -> &mut RoomMetrics {
+        self.rooms.entry(room_id).or_default()
+    }
+
+    fn current(&mut self) -> &mut RoomMetrics {
+        let room_id = self.current_room;
+        self.room(room_id)
+    }
+}
+
+struct SampledGauge {
+    name: &'static str,
+    help: &'static str,
+    sample: Box<dyn Fn() -> f64 + Send + Sync>,
+}
+
+/// Metrics registry shared by the event pipeline and the HTTP server
+#[derive(Default)]
+pub struct Metrics {
+    state: Mutex<MetricsState>,
+    gauges: RwLock<Vec<SampledGauge>>,
+    buses: RwLock<Vec<EventBus>>,
+}
+
+/// Interaction type label of an interact action
+fn interaction_type(action: i32) -> &'static str {
+    match action {
+        1 => "enter",
+        2 => "follow",
+        3 => "share",
+        4 => "special_follow",
+        5 => "mutual_follow",
+        _ => "other",
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a gauge whose value is read on every render, labelled with
+    /// the current room
+    pub fn register_gauge<F>(&self, name: &'static str, help: &'static str, sample: F)
+    where
+        F: Fn() -> f64 + Send + Sync + 'static,
+    {
+        let mut gauges = self.gauges.write();
+        gauges.retain(|g| g.name != name);
+        gauges.push(SampledGauge {
+            name,
+            help,
+            sample: Box::new(sample),
+        });
+    }
+
+    /// Report the queues of the subscribers of `bus` too
+    pub fn add_event_bus(&self, bus: EventBus) {
+        self.buses.write().push(bus);
+    }
+
+    /// Update the metrics from an event
+    pub fn observe(&self, event: &Event) {
+        let mut state = self.state.lock();
+        match event {
+            Event::UpdateRoom {
+                room_id,
+                live_status,
+                ..
+            } => {
+                state.current_room = room_id.real_id();
+                state.current().live = *live_status == 1;
+            }
+            Event::ConnectionStatus { connected: true } => {
+                // Connecting again to the same room is a reconnect
+                if state.connected_room == Some(state.current_room) {
+                    state.current().reconnects += 1;
+                }
+                state.connected_room = Some(state.current_room);
+            }
+            Event::LiveStart => state.current().live = true,
+            Event::LiveEnd => state.current().live = false,
+            Event::UpdateOnline { count } => state.current().online = *count,
+            Event::NewDanmu(_) => state.current().danmu += 1,
+            Event::NewGift(gift) => {
+                let room = state.room(gift.room);
+                room.gifts += 1;
+                room.gift_value += gift_value(gift);
+            }
+            Event::NewGuard(guard) => {
+                let room = state.room(guard.room);
+                room.guards += 1;
+                room.guard_value += guard_value(guard);
+            }
+            Event::NewSuperChat(sc) => {
+                let room = state.room(sc.room);
+                room.superchats += 1;
+                room.superchat_value += superchat_value(sc);
+            }
+            Event::NewInteract(interact) => {
+                *state
+                    .current()
+                    .interactions
+                    .entry(interaction_type(interact.action))
+                    .or_default() += 1;
+            }
+            Event::NewLikeClick(_) => {
+                *state.current().interactions.entry("like").or_default() += 1;
+            }
+            _ => {}
+        }
+    }
+
+    /// Render all metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let state = self.state.lock();
+        let rooms = &state.rooms;
+        let mut out = String::new();
+
+        let mut family =
+            |name: &str, kind: &str, help: &str, value: &dyn Fn(&RoomMetrics) -> f64| {
+                let _ = writeln!(out, "# HELP {} {}", name, help);
+                let _ = writeln!(out, "# TYPE {} {}", name, kind);
+                for (room_id, room) in rooms {
+                    let _ = writeln!(out, "{}{{room=\"{}\"}} {}", name, room_id, value(room));
+                }
+            };
+        family(
+            "jlivertool_danmu_total",
+            "counter",
+            "Danmu messages received",
+            &|r| r.danmu as f64,
+        );
+        family(
+            "jlivertool_gifts_total",
+            "counter",
+            "Gift messages received",
+            &|r| r.gifts as f64,
+        );
+        family(
+            "jlivertool_gift_value_yuan_total",
+            "counter",
+            "Value of paid gifts in yuan",
+            &|r| r.gift_value as f64 / 1000.0,
+        );
+        family(
+            "jlivertool_guards_total",
+            "counter",
+            "Guard purchases received",
+            &|r| r.guards as f64,
+        );
+        family(
+            "jlivertool_guard_value_yuan_total",
+            "counter",
+            "Value of guard purchases in yuan",
+            &|r| r.guard_value as f64 / 1000.0,
+        );
+        family(
+            "jlivertool_superchats_total",
+            "counter",
+            "Superchats received",
+            &|r| r.superchats as f64,
+        );
+        family(
+            "jlivertool_superchat_value_yuan_total",
+            "counter",
+            "Value of superchats in yuan",
+            &|r| r.superchat_value as f64 / 1000.0,
+        );
+        family(
+            "jlivertool_online",
+            "gauge",
+            "Online count of the room",
+            &|r| r.online as f64,
+        );
+        family(
+            "jlivertool_live",
+            "gauge",
+            "1 while the room is live",
+            &|r| {
+                if r.live {
+                    1.0
+                } else {
+                    0.0
+                }
+            },
+        );
+        family(
+            "jlivertool_websocket_reconnects_total",
+            "counter",
+            "Reconnects of the danmu WebSocket",
+            &|r| r.reconnects as f64,
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP jlivertool_interactions_total Interactions by type"
+        );
+        let _ = writeln!(out, "# TYPE jlivertool_interactions_total counter");
+        for (room_id, room) in rooms {
+            for (kind, count) in &room.interactions {
+                let _ = writeln!(
+                    out,
+                    "jlivertool_interactions_total{{room=\"{}\",type=\"{}\"}} {}",
+                    room_id, kind, count
+                );
+            }
+        }
+
+        let stats: Vec<SubscriberStats> =
+            self.buses.read().iter().flat_map(|bus| bus.stats()).collect();
+        if !stats.is_empty() {
+            let mut family =
+                |name: &str, kind: &str, help: &str, value: &dyn Fn(&SubscriberStats) -> f64| {
+                    let _ = writeln!(out, "# HELP {} {}", name, help);
+                    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+                    for subscriber in &stats {
+                        let _ = writeln!(
+                            out,
+                            "{}{{subscriber=\"{}\"}} {}",
+                            name,
+                            subscriber.name,
+                            value(subscriber)
+                        );
+                    }
+                };
+            family(
+                "jlivertool_event_bus_delivered_total",
+                "counter",
+                "Events received by a bus subscriber",
+                &|s| s.delivered as f64,
+            );
+            family(
+                "jlivertool_event_bus_dropped_total",
+                "counter",
+                "Events dropped because the subscriber queue was full",
+                &|s| s.dropped as f64,
+            );
+            family(
+                "jlivertool_event_bus_coalesced_total",
+                "counter",
+                "State updates replaced by a newer one",
+                &|s| s.coalesced as f64,
+            );
+            family(
+                "jlivertool_event_bus_queued",
+                "gauge",
+                "Events waiting in the subscriber queue",
+                &|s| s.queued as f64,
+            );
+        }
+
+        for gauge in self.gauges.read().iter() {
+            let _ = writeln!(out, "# HELP {} {}", gauge.name, gauge.help);
+            let _ = writeln!(out, "# TYPE {} gauge", gauge.name);
+            let _ = writeln!(
+                out,
+                "{}{{room=\"{}\"}} {}",
+                gauge.name,
+                state.current_room,
+                (gauge.sample)()
+            );
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::SubscribeOptions;
+    use crate::messages::InteractMessage;
+    use crate::types::{RoomId, Sender};
+
+    #[test]
+    fn test_metrics_from_events() {
+        let metrics = Metrics::new();
+        let update_room = Event::UpdateRoom {
+            room_id: RoomId::new(0, 1, 0),
+            title: String::new(),
+            live_status: 1,
+            area_id: 0,
+        };
+        metrics.observe(&update_room);
+        metrics.observe(&Event::ConnectionStatus { connected: true });
+        metrics.observe(&Event::UpdateOnline { count: 42 });
+        metrics.observe(&Event::NewInteract(InteractMessage {
+            sender: Sender::default(),
+            action: 2,
+        }));
+        // The same room connects again after a disconnect
+        metrics.observe(&Event::ConnectionStatus { connected: false });
+        metrics.observe(&update_room);
+        metrics.observe(&Event::ConnectionStatus { connected: true });
+        metrics.register_gauge(
+            "jlivertool_plugin_clients",
+            "Connected plugin clients",
+            || 3.0,
+        );
+
+        let bus = EventBus::new();
+        let _subscription = bus.subscribe_with(SubscribeOptions::new("webhooks").capacity(1));
+        bus.emit(Event::LiveStart);
+        bus.emit(Event::LiveEnd);
+        metrics.add_event_bus(bus);
+
+        let text = metrics.render();
+        assert!(text.contains("# TYPE jlivertool_danmu_total counter"));
+        assert!(text.contains("jlivertool_event_bus_dropped_total{subscriber=\"webhooks\"} 1\n"));
+        assert!(text.contains("jlivertool_event_bus_queued{subscriber=\"webhooks\"} 1\n"));
+        assert!(text.contains("jlivertool_online{room=\"1\"} 42\n"));
+        assert!(text.contains("jlivertool_live{room=\"1\"} 1\n"));
+        assert!(text.contains("jlivertool_websocket_reconnects_total{room=\"1\"} 1\n"));
+        assert!(text.contains("jlivertool_interactions_total{room=\"1\",type=\"follow\"} 1\n"));
+        assert!(text.contains("jlivertool_plugin_clients{room=\"1\"} 3\n"));
+    }
+}
@@ -0,0 +1,689 @@
+//! OBS integration through obs-websocket v5
+//!
+//! Rules map live events to OBS requests, e.g. switch scene when the live
+//! starts or show a source for a few seconds on a new guard. Requests are run
+//! one by one by a worker that owns the connection and reconnects on demand.
+
+use crate::events::{Event, EventType};
+use crate::goals::{gift_value, superchat_value};
+use anyhow::{anyhow, bail, Result};
+use base64::Engine;
+use futures::{SinkExt, StreamExt};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::protocol::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+use tracing::{debug, info, warn};
+
+/// obs-websocket RPC version
+const RPC_VERSION: u32 = 1;
+/// Timeout of the handshake and of a single request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// OBS connection settings and rules
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    #[serde(default = "default_address")]
+    pub address: String,
+
+    #[serde(default)]
+    pub password: String,
+
+    /// Write the RTMP address and key into the OBS stream settings when the
+    /// live is started from JLiverTool
+    #[serde(default)]
+    pub push_stream_settings: bool,
+
+    #[serde(default)]
+    pub rules: Vec<ObsRule>,
+}
+
+fn default_address() -> String {
+    "ws://127.0.0.1:4455".to_string()
+}
+
+impl Default for ObsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: default_address(),
+            password: String::new(),
+            push_stream_settings: false,
+            rules: Vec::new(),
+        }
+    }
+}
+
+/// Run an action when a trigger matches
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObsRule {
+    pub trigger: ObsTrigger,
+    pub action: ObsAction,
+}
+
+/// Events that can trigger an OBS action
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ObsTrigger {
+    LiveStart,
+    LiveEnd,
+    NewGuard,
+    /// Superchat of at least `min_price` yuan
+    NewSuperChat {
+        #[serde(default)]
+        min_price: f64,
+    },
+    /// Paid gift worth at least `min_price` yuan
+    NewGift {
+        #[serde(default)]
+        min_price: f64,
+    },
+    /// Live started from JLiverTool and the RTMP info is available
+    RtmpInfo,
+}
+
+impl ObsTrigger {
+    pub fn matches(&self, event: &Event) -> bool {
+        match (self, event) {
+            (Self::LiveStart, Event::LiveStart)
+            | (Self::LiveEnd, Event::LiveEnd)
+            | (Self::NewGuard, Event::NewGuard(_))
+            | (Self::RtmpInfo, Event::RtmpInfo { .. }) => true,
+            (Self::NewSuperChat { min_price }, Event::NewSuperChat(sc)) => {
+                superchat_value(sc) as f64 >= min_price * 1000.0
+            }
+            (Self::NewGift { min_price }, Event::NewGift(gift)) => {
+                let value = gift_value(gift);
+                value > 0 && value as f64 >= min_price * 1000.0
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Request sent to OBS when a rule matches
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ObsAction {
+    SwitchScene {
+        scene: String,
+    },
+    /// Show a source of a scene and hide it again after `seconds`
+    ShowSource {
+        scene: String,
+        source: String,
+        #[serde(default = "default_show_seconds")]
+        seconds: u64,
+    },
+    StartRecord,
+    StopRecord,
+    StartStream,
+    StopStream,
+}
+
+fn default_show_seconds() -> u64 {
+    10
+}
+
+/// Authentication string of the obs-websocket v5 handshake
+fn auth_string(password: &str, salt: &str, challenge: &str) -> String {
+    let engine = base64::engine::general_purpose::STANDARD;
+    let secret = engine.encode(Sha256::digest(format!("{}{}", password, salt)));
+    engine.encode(Sha256::digest(format!("{}{}", secret, challenge)))
+}
+
+/// OBS answered a request with a failure, e.g. an unknown scene name
+/// The connection itself is still usable
+#[derive(Debug)]
+pub struct ObsRequestError(String);
+
+impl std::fmt::Display for ObsRequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ObsRequestError {}
+
+/// A connected and identified obs-websocket session
+pub struct ObsClient {
+    ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    next_id: u64,
+}
+
+impl ObsClient {
+    /// Connect and identify, `password` is ignored if OBS doesn't require one
+    pub async fn connect(address: &str, password: &str) -> Result<Self> {
+        let (ws, _) = tokio::time::timeout(REQUEST_TIMEOUT, connect_async(address))
+            .await
+            .map_err(|_| anyhow!("Connecting to {} timed out", address))??;
+        let mut client = Self { ws, next_id: 0 };
+
+        let hello = client.recv_op(0).await?;
+        let mut identify = json!({
+            "rpcVersion": RPC_VERSION,
+            // No events are needed, only requests
+            "eventSubscriptions": 0,
+        });
+        if let Some(auth) = hello.get("authentication") {
+            if password.is_empty() {
+                bail!("OBS requires a password");
+            }
+            let salt = auth["salt"].as_str().unwrap_or_default();
+            let challenge = auth["challenge"].as_str().unwrap_or_default();
+            identify["authentication"] = json!(auth_string(password, salt, challenge));
+        }
+        client.send_op(1, identify).await?;
+        client.recv_op(2).await?;
+        Ok(client)
+    }
+
+    /// Send a request and wait for its response data
+    pub async fn request(&mut self, request_type: &str, data: Value) -> Result<Value> {
+        self.next_id += 1;
+        let request_id = self.next_id.to_string();
+        debug!("OBS request {} {}", request_type, data);
+        self.send_op(
+            6,
+            json!({
+                "requestType": request_type,
+                "requestId": request_id,
+                "requestData": data,
+            }),
+        )
+        .await?;
+
+        loop {
+            let response = self.recv_op(7).await?;
+            if response["requestId"].as_str() != Some(request_id.as_str()) {
+                continue;
+            }
+            let status = &response["requestStatus"];
+            if status["result"].as_bool() != Some(true) {
+                return Err(ObsRequestError(format!(
+                    "{} failed ({}): {}",
+                    request_type,
+                    status["code"],
+                    status["comment"].as_str().unwrap_or_default()
+                ))
+                .into());
+            }
+            return Ok(response.get("responseData").cloned().unwrap_or(Value::Null));
+        }
+    }
+
+    /// Run an action, a shown source is not hidden again by this call
+    pub async fn run(&mut self, action: &ObsAction) -> Result<()> {
+        match action {
+            ObsAction::SwitchScene { scene } => {
+                self.request("SetCurrentProgramScene", json!({ "sceneName": scene }))
+                    .await?;
+            }
+            ObsAction::ShowSource { scene, source, .. } => {
+                self.set_source_visible(scene, source, true).await?;
+            }
+            ObsAction::StartRecord => {
+                self.request("StartRecord", Value::Null).await?;
+            }
+            ObsAction::StopRecord => {
+                self.request("StopRecord", Value::Null).await?;
+            }
+            ObsAction::StartStream => {
+                self.request("StartStream", Value::Null).await?;
+            }
+            ObsAction::StopStream => {
+                self.request("StopStream", Value::Null).await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn set_source_visible(
+        &mut self,
+        scene: &str,
+        source: &str,
+        visible: bool,
+    ) -> Result<()> {
+        let item = self
+            .request(
+                "GetSceneItemId",
+                json!({ "sceneName": scene, "sourceName": source }),
+            )
+            .await?;
+        let item_id = item["sceneItemId"].as_i64().ok_or_else(|| {
+            ObsRequestError(format!("Source {} not found in scene {}", source, scene))
+        })?;
+        self.request(
+            "SetSceneItemEnabled",
+            json!({
+                "sceneName": scene,
+                "sceneItemId": item_id,
+                "sceneItemEnabled": visible,
+            }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Use a custom RTMP server and stream key
+    pub async fn set_stream_target(&mut self, server: &str, key: &str) -> Result<()> {
+        self.request(
+            "SetStreamServiceSettings",
+            json!({
+                "streamServiceType": "rtmp_custom",
+                "streamServiceSettings": { "server": server, "key": key },
+            }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn send_op(&mut self, op: u8, d: Value) -> Result<()> {
+        let message = json!({ "op": op, "d": d }).to_string();
+        self.ws.send(Message::Text(message)).await?;
+        Ok(())
+    }
+
+    /// Wait for a message with the given op code, others are skipped
+    async fn recv_op(&mut self, op: u64) -> Result<Value> {
+        loop {
+            let message = tokio::time::timeout(REQUEST_TIMEOUT, self.ws.next())
+                .await
+                .map_err(|_| anyhow!("OBS did not respond"))?;
+            let text = match message {
+                Some(Ok(Message::Text(text))) => text,
+                Some(Ok(Message::Close(frame))) => {
+                    let reason = frame.map(|f| f.reason.to_string()).unwrap_or_default();
+                    bail!("OBS closed the connection: {}", reason);
+                }
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => return Err(e.into()),
+                None => bail!("OBS closed the connection"),
+            };
+            let mut value: Value = serde_json::from_str(&text)?;
+            if value["op"].as_u64() == Some(op) {
+                return Ok(value["d"].take());
+            }
+        }
+    }
+}
+
+enum ObsCommand {
+    Run(ObsAction),
+    HideSource { scene: String, source: String },
+    PushStream { server: String, key: String },
+    Reconfigure(ObsConfig),
+    Test,
+}
+
+/// Connection status reported by the worker: (connected, message)
+pub type ObsStatusCallback = Box<dyn Fn(bool, String) + Send>;
+
+/// Matches events against the rules and queues actions for the worker
+#[derive(Clone)]
+pub struct ObsHandle {
+    config: Arc<RwLock<ObsConfig>>,
+    tx: mpsc::UnboundedSender<ObsCommand>,
+}
+
+impl ObsHandle {
+    /// Events that can trigger a rule or carry stream settings
+    pub const EVENT_TYPES: &'static [EventType] = &[
+        EventType::LiveStart,
+        EventType::LiveEnd,
+        EventType::NewGuard,
+        EventType::NewSuperChat,
+        EventType::NewGift,
+        EventType::RtmpInfo,
+    ];
+
+    /// Queue the actions of all rules matching an event
+    pub fn notify(&self, event: &Event) {
+        let config = self.config.read();
+        if !config.enabled {
+            return;
+        }
+        if config.push_stream_settings {
+            if let Event::RtmpInfo { addr, code } = event {
+                let _ = self.tx.send(ObsCommand::PushStream {
+                    server: addr.clone(),
+                    key: code.clone(),
+                });
+            }
+        }
+        for rule in config.rules.iter().filter(|r| r.trigger.matches(event)) {
+            let _ = self.tx.send(ObsCommand::Run(rule.action.clone()));
+        }
+    }
+
+    /// Replace the settings, the connection is reopened on the next action
+    pub fn reconfigure(&self, config: ObsConfig) {
+        *self.config.write() = config.clone();
+        let _ = self.tx.send(ObsCommand::Reconfigure(config));
+    }
+
+    /// Connect with the current settings and report the result
+    pub fn test(&self) {
+        let _ = self.tx.send(ObsCommand::Test);
+    }
+}
+
+/// Owns the OBS connection and runs queued actions in order
+pub struct ObsWorker {
+    config: ObsConfig,
+    client: Option<ObsClient>,
+    /// Queues delayed commands, weak so dropping the last handle stops the worker
+    tx: mpsc::WeakUnboundedSender<ObsCommand>,
+    rx: mpsc::UnboundedReceiver<ObsCommand>,
+    on_status: ObsStatusCallback,
+}
+
+/// Create a handle and the worker it feeds
+pub fn channel(config: ObsConfig, on_status: ObsStatusCallback) -> (ObsHandle, ObsWorker) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let worker = ObsWorker {
+        config: config.clone(),
+        client: None,
+        tx: tx.downgrade(),
+        rx,
+        on_status,
+    };
+    let handle = ObsHandle {
+        config: Arc::new(RwLock::new(config)),
+        tx,
+    };
+    (handle, worker)
+}
+
+impl ObsWorker {
+    /// Process commands until all handles are dropped and pending sources
+    /// are hidden again
+    pub async fn run(mut self) {
+        while let Some(command) = self.rx.recv().await {
+            match command {
+                ObsCommand::Run(action) => {
+                    let result = self.run_action(&action).await;
+                    self.check(result, &format!("{:?}", action));
+                }
+                ObsCommand::HideSource { scene, source } => {
+                    let result = match self.client().await {
+                        Ok(client) => client.set_source_visible(&scene, &source, false).await,
+                        Err(e) => Err(e),
+                    };
+                    self.check(result, "HideSource");
+                }
+                ObsCommand::PushStream { server, key } => {
+                    let result = match self.client().await {
+                        Ok(client) => client.set_stream_target(&server, &key).await,
+                        Err(e) => Err(e),
+                    };
+                    self.check(result, "SetStreamServiceSettings");
+                }
+                ObsCommand::Reconfigure(config) => {
+                    self.config = config;
+                    self.client = None;
+                }
+                ObsCommand::Test => {
+                    self.client = None;
+                    match self.client().await {
+                        Ok(_) => (self.on_status)(true, "已连接到 OBS".to_string()),
+                        Err(e) => (self.on_status)(false, format!("连接 OBS 失败: {}", e)),
+                    }
+                }
+            }
+        }
+    }
+
+    async fn run_action(&mut self, action: &ObsAction) -> Result<()> {
+        self.client().await?.run(action).await?;
+        if let ObsAction::ShowSource {
+            scene,
+            source,
+            seconds,
+        } = action
+        {
+            // Without handles the worker is stopping, the source stays shown
+            let Some(tx) = self.tx.upgrade() else {
+                return Ok(());
+            };
+            let hide = ObsCommand::HideSource {
+                scene: scene.clone(),
+                source: source.clone(),
+            };
+            let delay = Duration::from_secs(*seconds);
+            tokio::spawn(async move {
+                tokio::time::sleep(delay).await;
+                let _ = tx.send(hide);
+            });
+        }
+        Ok(())
+    }
+
+    /// Current connection, connecting first if needed
+    async fn client(&mut self) -> Result<&mut ObsClient> {
+        if self.client.is_none() {
+            let client = ObsClient::connect(&self.config.address, &self.config.password).await?;
+            info!("Connected to OBS at {}", self.config.address);
+            self.client = Some(client);
+        }
+        Ok(self.client.as_mut().unwrap())
+    }
+
+    /// Drop the connection on transport failures so the next action
+    /// reconnects, failed requests keep it
+    fn check(&mut self, result: Result<()>, what: &str) {
+        if let Err(e) = result {
+            warn!("OBS action {} failed: {}", what, e);
+            if e.downcast_ref::<ObsRequestError>().is_none() {
+                self.client = None;
+            }
+            (self.on_status)(false, format!("OBS 操作失败: {}", e));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::net::TcpListener;
+
+    /// Minimal obs-websocket server that requires a password, answers
+    /// requests with success unless they name the scene "Missing" and reports
+    /// the requests it received and the number of accepted connections
+    async fn mock_obs(
+        password: &'static str,
+    ) -> (String, mpsc::UnboundedReceiver<Value>, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("ws://{}", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::unbounded_channel();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let accepted = connections.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                accepted.fetch_add(1, Ordering::Relaxed);
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+                    let (salt, challenge) = ("salt", "challenge");
+                    let hello = json!({ "op": 0, "d": {
+                        "obsWebSocketVersion": "5.0.0",
+                        "rpcVersion": 1,
+                        "authentication": { "salt": salt, "challenge": challenge },
+                    }});
+                    ws.send(Message::Text(hello.to_string())).await.unwrap();
+                    while let Some(Ok(Message::Text(text))) = ws.next().await {
+                        let message: Value = serde_json::from_str(&text).unwrap();
+                        let d = &message["d"];
+                        let reply = match message["op"].as_u64() {
+                            Some(1) => {
+                                if d["authentication"]
+                                    != json!(auth_string(password, salt, challenge))
+                                {
+                                    let _ = ws.close(None).await;
+                                    return;
+                                }
+                                json!({ "op": 2, "d": { "negotiatedRpcVersion": 1 } })
+                            }
+                            Some(6) => {
+                                let _ = tx.send(d.clone());
+                                let status = if d["requestData"]["sceneName"] == "Missing" {
+                                    json!({ "result": false, "code": 600, "comment": "No scene" })
+                                } else {
+                                    json!({ "result": true, "code": 100 })
+                                };
+                                json!({ "op": 7, "d": {
+                                    "requestType": d["requestType"],
+                                    "requestId": d["requestId"],
+                                    "requestStatus": status,
+                                    "responseData": { "sceneItemId": 7 },
+                                }})
+                            }
+                            _ => continue,
+                        };
+                        ws.send(Message::Text(reply.to_string())).await.unwrap();
+                    }
+                });
+            }
+        });
+        (address, rx, connections)
+    }
+
+    #[test]
+    fn test_rule_matching() {
+        let rules: Vec<ObsRule> = serde_json::from_str(
+            r#"[
+                {"trigger": {"type": "live_start"}, "action": {"type": "switch_scene", "scene": "Live"}},
+                {"trigger": {"type": "new_super_chat", "min_price": 50}, "action": {"type": "start_record"}}
+            ]"#,
+        )
+        .unwrap();
+        assert!(rules[0].trigger.matches(&Event::LiveStart));
+        assert!(!rules[0].trigger.matches(&Event::LiveEnd));
+
+        let mut sc = crate::messages::SuperChatMessage {
+            id: "sc".to_string(),
+            room: 1,
+            sender: crate::types::Sender::default(),
+            message: String::new(),
+            price: 30,
+            timestamp: 0,
+            start_time: 0,
+            end_time: 0,
+            background_color: String::new(),
+            background_bottom_color: String::new(),
+            archived: false,
+        };
+        assert!(!rules[1].trigger.matches(&Event::NewSuperChat(sc.clone())));
+        sc.price = 50;
+        assert!(rules[1].trigger.matches(&Event::NewSuperChat(sc)));
+    }
+
+    #[tokio::test]
+    async fn test_client_against_mock_server() {
+        let (address, mut requests, _) = mock_obs("secret").await;
+
+        assert!(ObsClient::connect(&address, "wrong").await.is_err());
+
+        let mut client = ObsClient::connect(&address, "secret").await.unwrap();
+        client
+            .run(&ObsAction::SwitchScene {
+                scene: "Live".to_string(),
+            })
+            .await
+            .unwrap();
+        let request = requests.recv().await.unwrap();
+        assert_eq!(request["requestType"], "SetCurrentProgramScene");
+        assert_eq!(request["requestData"]["sceneName"], "Live");
+
+        // Rules are run by the worker, a shown source is hidden again
+        let config = ObsConfig {
+            enabled: true,
+            address,
+            password: "secret".to_string(),
+            push_stream_settings: true,
+            rules: vec![ObsRule {
+                trigger: ObsTrigger::RtmpInfo,
+                action: ObsAction::ShowSource {
+                    scene: "Live".to_string(),
+                    source: "Alert".to_string(),
+                    seconds: 0,
+                },
+            }],
+        };
+        let (handle, worker) = channel(config, Box::new(|_, _| {}));
+        tokio::spawn(worker.run());
+        handle.notify(&Event::RtmpInfo {
+            addr: "rtmp://live/".to_string(),
+            code: "key".to_string(),
+        });
+
+        let mut received = Vec::new();
+        for _ in 0..5 {
+            received.push(requests.recv().await.unwrap());
+        }
+        let types: Vec<_> = received
+            .iter()
+            .map(|r| r["requestType"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            types,
+            [
+                "SetStreamServiceSettings",
+                "GetSceneItemId",
+                "SetSceneItemEnabled",
+                "GetSceneItemId",
+                "SetSceneItemEnabled",
+            ]
+        );
+        assert_eq!(
+            received[0]["requestData"]["streamServiceSettings"]["key"],
+            "key"
+        );
+        assert_eq!(received[2]["requestData"]["sceneItemEnabled"], true);
+        assert_eq!(received[4]["requestData"]["sceneItemEnabled"], false);
+    }
+
+    #[tokio::test]
+    async fn test_worker_keeps_connection_and_stops_with_handles() {
+        let (address, mut requests, connections) = mock_obs("secret").await;
+        let switch_to = |scene: &str| ObsRule {
+            trigger: ObsTrigger::LiveStart,
+            action: ObsAction::SwitchScene {
+                scene: scene.to_string(),
+            },
+        };
+        let config = ObsConfig {
+            enabled: true,
+            address,
+            password: "secret".to_string(),
+            push_stream_settings: false,
+            rules: vec![switch_to("Missing"), switch_to("Live")],
+        };
+        let (handle, worker) = channel(config, Box::new(|_, _| {}));
+        let worker = tokio::spawn(worker.run());
+
+        // A failed request doesn't cost the connection
+        handle.notify(&Event::LiveStart);
+        for scene in ["Missing", "Live"] {
+            let request = requests.recv().await.unwrap();
+            assert_eq!(request["requestData"]["sceneName"], scene);
+        }
+        assert_eq!(connections.load(Ordering::Relaxed), 1);
+
+        drop(handle);
+        tokio::time::timeout(Duration::from_secs(5), worker)
+            .await
+            .expect("worker should stop once all handles are dropped")
+            .unwrap();
+    }
+}
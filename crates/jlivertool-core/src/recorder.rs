@@ -0,0 +1,1001 @@
+//! Live stream recorder
+//!
+//! Downloads the FLV or HLS stream of the connected room while it is live.
+//! A recording starts on `LiveStart` (or when the room is found live), is
+//! split into files by size or duration and stops on `LiveEnd`. Dropped
+//! streams are reconnected with fresh play URLs until the room goes offline.
+//! Every file is stored in the database with the time its first byte was
+//! written, so danmu exports can be aligned with the video.
+
+use crate::bilibili::api::BiliApi;
+use crate::database::Database;
+use crate::events::{Event, EventType};
+use crate::network::SharedClient;
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use chrono::{Local, TimeZone};
+use parking_lot::RwLock;
+use reqwest::{header, Client, Url};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::fs::File;
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use tracing::{info, warn};
+
+/// Longest wait for stream data before the stream counts as dropped
+const STALL_TIMEOUT: Duration = Duration::from_secs(30);
+/// Longest wait between two reconnects
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+const FLV_TAG_HEADER_SIZE: usize = 11;
+const TAG_AUDIO: u8 = 8;
+const TAG_VIDEO: u8 = 9;
+const TAG_SCRIPT: u8 = 18;
+
+/// Stream protocol to record
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamFormat {
+    #[default]
+    Flv,
+    Hls,
+}
+
+/// Recorder settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecorderConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Directory of the recordings, empty for `recordings` in the data directory
+    #[serde(default)]
+    pub output_dir: String,
+
+    /// Preferred protocol, the other one is used if it is not offered
+    #[serde(default)]
+    pub format: StreamFormat,
+
+    /// Requested quality, 10000 is the original quality
+    #[serde(default = "default_quality")]
+    pub quality: u32,
+
+    /// Start a new file after this many MiB, 0 to disable
+    #[serde(default)]
+    pub segment_size_mb: u64,
+
+    /// Start a new file after this many minutes, 0 to disable
+    #[serde(default = "default_segment_minutes")]
+    pub segment_minutes: u64,
+}
+
+fn default_quality() -> u32 {
+    10000
+}
+
+fn default_segment_minutes() -> u64 {
+    60
+}
+
+impl Default for RecorderConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            output_dir: String::new(),
+            format: StreamFormat::default(),
+            quality: default_quality(),
+            segment_size_mb: 0,
+            segment_minutes: default_segment_minutes(),
+        }
+    }
+}
+
+/// A recorded file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recording {
+    pub id: i64,
+    pub room_id: u64,
+    /// Unix timestamp in milliseconds the recording of the live started,
+    /// shared by all files of one live
+    pub session_start: i64,
+    /// Unix timestamp in milliseconds the first byte of the file was written
+    pub start_time: i64,
+    /// Unix timestamp in milliseconds, None while the file is written
+    pub end_time: Option<i64>,
+    pub path: String,
+    /// File size in bytes
+    pub size: u64,
+}
+
+/// A stream URL of a live room
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamUrl {
+    pub url: String,
+    pub format: StreamFormat,
+}
+
+/// Provides the stream URLs of a room
+#[async_trait]
+pub trait PlayUrlSource: Send + Sync {
+    /// Stream URLs of a room, empty while the room is offline
+    async fn play_urls(&self, room_id: u64, quality: u32) -> Result<Vec<StreamUrl>>;
+}
+
+#[async_trait]
+impl PlayUrlSource for BiliApi {
+    async fn play_urls(&self, room_id: u64, quality: u32) -> Result<Vec<StreamUrl>> {
+        let info = self.get_play_info(room_id, quality).await?;
+        let playurl_info = match info.playurl_info {
+            Some(playurl_info) if info.live_status == 1 => playurl_info,
+            _ => return Ok(Vec::new()),
+        };
+        let mut urls = Vec::new();
+        for stream in playurl_info.playurl.stream {
+            for format in stream.format {
+                let format_kind = match format.format_name.as_str() {
+                    "flv" => StreamFormat::Flv,
+                    "ts" | "fmp4" => StreamFormat::Hls,
+                    _ => continue,
+                };
+                // Not every player handles HEVC in FLV, stay with AVC
+                for codec in format.codec.iter().filter(|c| c.codec_name == "avc") {
+                    urls.extend(codec.urls().into_iter().map(|url| StreamUrl {
+                        url,
+                        format: format_kind,
+                    }));
+                }
+            }
+        }
+        Ok(urls)
+    }
+}
+
+/// Shared API, the cookies of the current login are used for each request
+#[async_trait]
+impl PlayUrlSource for RwLock<BiliApi> {
+    async fn play_urls(&self, room_id: u64, quality: u32) -> Result<Vec<StreamUrl>> {
+        let api = self.read().clone();
+        api.play_urls(room_id, quality).await
+    }
+}
+
+/// Recording status reported to the UI: (recording, message)
+pub type RecorderStatusCallback = Arc<dyn Fn(bool, String) + Send + Sync>;
+
+fn now_millis() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+/// When to start a new file, zero disables a limit
+#[derive(Debug, Clone, Copy)]
+struct SegmentLimits {
+    max_bytes: u64,
+    max_duration: Duration,
+}
+
+impl From<&RecorderConfig> for SegmentLimits {
+    fn from(config: &RecorderConfig) -> Self {
+        Self {
+            max_bytes: config.segment_size_mb * 1024 * 1024,
+            max_duration: Duration::from_secs(config.segment_minutes * 60),
+        }
+    }
+}
+
+struct OpenFile {
+    file: BufWriter<File>,
+    recording: Recording,
+    opened: Instant,
+}
+
+/// Writes the files of one recording and keeps their database rows up to date
+struct SegmentWriter {
+    database: Database,
+    dir: PathBuf,
+    room_id: u64,
+    session_start: i64,
+    limits: SegmentLimits,
+    index: u32,
+    current: Option<OpenFile>,
+    on_status: RecorderStatusCallback,
+}
+
+impl SegmentWriter {
+    fn is_open(&self) -> bool {
+        self.current.is_some()
+    }
+
+    /// Whether the open file reached a limit
+    fn is_full(&self) -> bool {
+        match self.current {
+            Some(ref current) => {
+                (self.limits.max_bytes > 0 && current.recording.size >= self.limits.max_bytes)
+                    || (!self.limits.max_duration.is_zero()
+                        && current.opened.elapsed() >= self.limits.max_duration)
+            }
+            None => false,
+        }
+    }
+
+    /// Close the open file and start a new one with `header`
+    async fn open(&mut self, extension: &str, header: &[u8]) -> Result<()> {
+        self.close().await?;
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+
+        self.index += 1;
+        let stamp = Local
+            .timestamp_millis_opt(self.session_start)
+            .single()
+            .map(|t| t.format("%Y%m%d_%H%M%S").to_string())
+            .unwrap_or_else(|| self.session_start.to_string());
+        let path = self.dir.join(format!(
+            "{}_{}_{:03}.{}",
+            self.room_id, stamp, self.index, extension
+        ));
+        let file = File::create(&path)
+            .await
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+
+        let mut recording = Recording {
+            id: 0,
+            room_id: self.room_id,
+            session_start: self.session_start,
+            start_time: now_millis(),
+            end_time: None,
+            path: path.to_string_lossy().into_owned(),
+            size: 0,
+        };
+        recording.id = self.database.insert_recording(&recording)?;
+        info!("Recording room {} to {}", self.room_id, recording.path);
+        (self.on_status)(true, format!("正在录制: {}", recording.path));
+
+        self.current = Some(OpenFile {
+            file: BufWriter::new(file),
+            recording,
+            opened: Instant::now(),
+        });
+        self.write(header).await
+    }
+
+    async fn write(&mut self, data: &[u8]) -> Result<()> {
+        let current = self
+            .current
+            .as_mut()
+            .ok_or_else(|| anyhow!("No recording file is open"))?;
+        current.file.write_all(data).await?;
+        current.recording.size += data.len() as u64;
+        Ok(())
+    }
+
+    /// Flush the open file and store its end time and size
+    async fn close(&mut self) -> Result<()> {
+        if let Some(mut current) = self.current.take() {
+            let flushed = current.file.flush().await;
+            self.database.finish_recording(
+                current.recording.id,
+                now_millis(),
+                current.recording.size,
+            )?;
+            flushed?;
+        }
+        Ok(())
+    }
+}
+
+/// A tag of an FLV stream
+#[derive(Debug, Clone)]
+struct FlvTag {
+    tag_type: u8,
+    timestamp: u32,
+    data: Vec<u8>,
+}
+
+impl FlvTag {
+    /// AVC/HEVC decoder configuration or AAC audio specific config
+    fn is_sequence_header(&self) -> bool {
+        if self.data.len() < 2 || self.data[1] != 0 {
+            return false;
+        }
+        match self.tag_type {
+            TAG_VIDEO => matches!(self.data[0] & 0x0f, 7 | 12),
+            TAG_AUDIO => self.data[0] >> 4 == 10,
+            _ => false,
+        }
+    }
+
+    fn is_keyframe(&self) -> bool {
+        self.tag_type == TAG_VIDEO
+            && self.data.first().is_some_and(|b| b >> 4 == 1)
+            && !self.is_sequence_header()
+    }
+
+    /// Append the tag with its previous tag size
+    fn encode(&self, timestamp: u32, out: &mut Vec<u8>) {
+        let size = self.data.len() as u32;
+        out.push(self.tag_type);
+        out.extend_from_slice(&size.to_be_bytes()[1..]);
+        out.extend_from_slice(&timestamp.to_be_bytes()[1..]);
+        out.push((timestamp >> 24) as u8);
+        out.extend_from_slice(&[0, 0, 0]);
+        out.extend_from_slice(&self.data);
+        out.extend_from_slice(&(size + FLV_TAG_HEADER_SIZE as u32).to_be_bytes());
+    }
+}
+
+/// Incremental FLV parser
+#[derive(Default)]
+struct FlvReader {
+    buf: Vec<u8>,
+    pos: usize,
+    /// File header, set once it was read
+    header: Option<Vec<u8>>,
+}
+
+impl FlvReader {
+    fn push(&mut self, data: &[u8]) {
+        if self.pos > 0 && self.pos * 2 >= self.buf.len() {
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+        }
+        self.buf.extend_from_slice(data);
+    }
+
+    /// The next complete tag, None if more data is needed
+    fn next_tag(&mut self) -> Result<Option<FlvTag>> {
+        let available = &self.buf[self.pos..];
+        if self.header.is_none() {
+            if available.len() < 9 {
+                return Ok(None);
+            }
+            if &available[..3] != b"FLV" {
+                bail!("Not an FLV stream");
+            }
+            let header_size = u32::from_be_bytes(available[5..9].try_into()?) as usize;
+            // The header is followed by PreviousTagSize0
+            if available.len() < header_size + 4 {
+                return Ok(None);
+            }
+            self.header = Some(available[..header_size].to_vec());
+            self.pos += header_size + 4;
+            return self.next_tag();
+        }
+
+        if available.len() < FLV_TAG_HEADER_SIZE {
+            return Ok(None);
+        }
+        let size = u32::from_be_bytes([0, available[1], available[2], available[3]]) as usize;
+        let total = FLV_TAG_HEADER_SIZE + size + 4;
+        if available.len() < total {
+            return Ok(None);
+        }
+        let tag = FlvTag {
+            tag_type: available[0] & 0x1f,
+            timestamp: u32::from_be_bytes([available[7], available[4], available[5], available[6]]),
+            data: available[FLV_TAG_HEADER_SIZE..FLV_TAG_HEADER_SIZE + size].to_vec(),
+        };
+        self.pos += total;
+        Ok(Some(tag))
+    }
+}
+
+/// Splits an FLV stream into files that each start with the stream header,
+/// the metadata and the codec headers followed by a keyframe at time 0
+#[derive(Default)]
+struct FlvSplitter {
+    metadata: Option<FlvTag>,
+    video_header: Option<FlvTag>,
+    audio_header: Option<FlvTag>,
+    /// Stream timestamp of the first keyframe of the open file
+    base: u32,
+}
+
+impl FlvSplitter {
+    async fn write_tag(
+        &mut self,
+        tag: FlvTag,
+        header: &[u8],
+        writer: &mut SegmentWriter,
+    ) -> Result<()> {
+        if tag.is_sequence_header() {
+            if tag.tag_type == TAG_VIDEO {
+                self.video_header = Some(tag.clone());
+            } else {
+                self.audio_header = Some(tag.clone());
+            }
+        } else if tag.tag_type == TAG_SCRIPT && !writer.is_open() {
+            self.metadata = Some(tag);
+            return Ok(());
+        }
+
+        if tag.is_keyframe() && (!writer.is_open() || writer.is_full()) {
+            self.base = tag.timestamp;
+            let mut head = header.to_vec();
+            head.extend_from_slice(&[0, 0, 0, 0]);
+            for cached in [&self.metadata, &self.video_header, &self.audio_header]
+                .into_iter()
+                .flatten()
+            {
+                cached.encode(0, &mut head);
+            }
+            writer.open("flv", &head).await?;
+        }
+
+        // Wait for the first keyframe, codec headers are written with the file header
+        if !writer.is_open() {
+            return Ok(());
+        }
+        let mut out = Vec::with_capacity(tag.data.len() + FLV_TAG_HEADER_SIZE + 4);
+        tag.encode(tag.timestamp.saturating_sub(self.base), &mut out);
+        writer.write(&out).await
+    }
+}
+
+/// Download an FLV stream until it ends
+async fn download_flv(client: &Client, url: &str, writer: &mut SegmentWriter) -> Result<()> {
+    let mut response = client.get(url).send().await?.error_for_status()?;
+    let mut reader = FlvReader::default();
+    let mut splitter = FlvSplitter::default();
+    while let Some(chunk) = timeout(STALL_TIMEOUT, response.chunk())
+        .await
+        .context("Stream stalled")??
+    {
+        reader.push(&chunk);
+        while let Some(tag) = reader.next_tag()? {
+            let header = reader.header.as_deref().unwrap_or_default();
+            splitter.write_tag(tag, header, writer).await?;
+        }
+    }
+    Ok(())
+}
+
+/// A parsed HLS playlist
+#[derive(Debug, Default, PartialEq)]
+struct Playlist {
+    target_duration: f64,
+    /// URI of the fMP4 initialization section
+    init: Option<String>,
+    /// (media sequence number, URI) of the segments
+    segments: Vec<(u64, String)>,
+    ended: bool,
+    /// First variant of a master playlist
+    variant: Option<String>,
+}
+
+fn parse_playlist(text: &str) -> Result<Playlist> {
+    if !text.trim_start().starts_with("#EXTM3U") {
+        bail!("Not an HLS playlist");
+    }
+    let mut playlist = Playlist {
+        target_duration: 1.0,
+        ..Default::default()
+    };
+    let mut sequence = 0u64;
+    let mut variant_next = false;
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if let Some(value) = line.strip_prefix("#EXT-X-TARGETDURATION:") {
+            playlist.target_duration = value.parse().unwrap_or(1.0);
+        } else if let Some(value) = line.strip_prefix("#EXT-X-MEDIA-SEQUENCE:") {
+            sequence = value.parse().context("Invalid media sequence")?;
+        } else if let Some(value) = line.strip_prefix("#EXT-X-MAP:") {
+            playlist.init = value
+                .split(',')
+                .find_map(|attr| attr.trim().strip_prefix("URI="))
+                .map(|uri| uri.trim_matches('"').to_string());
+        } else if line.starts_with("#EXT-X-STREAM-INF") {
+            variant_next = true;
+        } else if line == "#EXT-X-ENDLIST" {
+            playlist.ended = true;
+        } else if !line.starts_with('#') {
+            if variant_next {
+                playlist.variant.get_or_insert_with(|| line.to_string());
+                variant_next = false;
+            } else {
+                playlist.segments.push((sequence, line.to_string()));
+                sequence += 1;
+            }
+        }
+    }
+    Ok(playlist)
+}
+
+async fn fetch(client: &Client, url: Url) -> Result<Vec<u8>> {
+    let response = client.get(url).send().await?.error_for_status()?;
+    let body = timeout(STALL_TIMEOUT, response.bytes())
+        .await
+        .context("Download timed out")??;
+    Ok(body.to_vec())
+}
+
+/// Download an HLS stream by polling its playlist until it ends
+async fn download_hls(client: &Client, url: &str, writer: &mut SegmentWriter) -> Result<()> {
+    let mut playlist_url = Url::parse(url)?;
+    let mut init: Option<Vec<u8>> = None;
+    let mut last_sequence: Option<u64> = None;
+    let mut last_segment = Instant::now();
+    loop {
+        let text = fetch(client, playlist_url.clone()).await?;
+        let playlist = parse_playlist(&String::from_utf8_lossy(&text))?;
+        if let Some(ref variant) = playlist.variant {
+            playlist_url = playlist_url.join(variant)?;
+            continue;
+        }
+        if init.is_none() {
+            if let Some(ref uri) = playlist.init {
+                init = Some(fetch(client, playlist_url.join(uri)?).await?);
+            }
+        }
+        // Start at the newest segment instead of the whole live window
+        if last_sequence.is_none() && playlist.segments.len() > 1 {
+            last_sequence = playlist.segments.iter().rev().nth(1).map(|(seq, _)| *seq);
+        }
+
+        for (sequence, uri) in &playlist.segments {
+            if last_sequence.is_some_and(|last| *sequence <= last) {
+                continue;
+            }
+            let data = fetch(client, playlist_url.join(uri)?).await?;
+            if !writer.is_open() || writer.is_full() {
+                let extension = if init.is_some() { "mp4" } else { "ts" };
+                writer
+                    .open(extension, init.as_deref().unwrap_or_default())
+                    .await?;
+            }
+            writer.write(&data).await?;
+            last_sequence = Some(*sequence);
+            last_segment = Instant::now();
+        }
+
+        if playlist.ended {
+            return Ok(());
+        }
+        if last_segment.elapsed() > STALL_TIMEOUT {
+            bail!("No new HLS segments");
+        }
+        let interval = (playlist.target_duration / 2.0).clamp(0.5, 5.0);
+        tokio::time::sleep(Duration::from_secs_f64(interval)).await;
+    }
+}
+
+/// Record one connection to the stream, returns false if the room is offline
+async fn record_stream(
+    source: &dyn PlayUrlSource,
+    client: &Client,
+    writer: &mut SegmentWriter,
+    quality: u32,
+    format: StreamFormat,
+    attempt: usize,
+) -> Result<bool> {
+    let mut urls = source.play_urls(writer.room_id, quality).await?;
+    if urls.is_empty() {
+        return Ok(false);
+    }
+    // Preferred protocol first, other CDN hosts are tried on later attempts
+    urls.sort_by_key(|url| url.format != format);
+    let preferred = urls
+        .iter()
+        .filter(|url| url.format == urls[0].format)
+        .count();
+    let url = &urls[attempt % preferred];
+    match url.format {
+        StreamFormat::Flv => download_flv(client, &url.url, writer).await?,
+        StreamFormat::Hls => download_hls(client, &url.url, writer).await?,
+    }
+    Ok(true)
+}
+
+/// Record a room until it goes offline or `stop` is set
+async fn record_room(
+    source: Arc<dyn PlayUrlSource>,
+    client: Client,
+    mut writer: SegmentWriter,
+    quality: u32,
+    format: StreamFormat,
+    mut stop: watch::Receiver<bool>,
+) {
+    let room_id = writer.room_id;
+    let on_status = writer.on_status.clone();
+    let mut delay = Duration::from_secs(1);
+    let mut attempt = 0;
+    loop {
+        let files = writer.index;
+        let result = tokio::select! {
+            result = record_stream(&*source, &client, &mut writer, quality, format, attempt) => Some(result),
+            _ = stop.wait_for(|stopped| *stopped) => None,
+        };
+        // A new connection starts a new file
+        if let Err(e) = writer.close().await {
+            warn!("Failed to finish recording of room {}: {}", room_id, e);
+        }
+        match result {
+            None => break,
+            Some(Ok(false)) => {
+                info!("Room {} is offline, recording finished", room_id);
+                break;
+            }
+            Some(Ok(true)) => info!("Stream of room {} ended, reconnecting", room_id),
+            Some(Err(e)) => {
+                warn!("Recording room {} failed: {:#}", room_id, e);
+                on_status(false, format!("录制中断，正在重连: {}", e));
+            }
+        }
+        if writer.index > files {
+            delay = Duration::from_secs(1);
+        }
+        attempt += 1;
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = stop.wait_for(|stopped| *stopped) => break,
+        }
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+    on_status(false, "录制已停止".to_string());
+}
+
+enum RecorderCommand {
+    Start(u64),
+    Stop,
+    Reconfigure(RecorderConfig),
+}
+
+/// Starts and stops recordings from live events
+#[derive(Clone)]
+pub struct RecorderHandle {
+    config: Arc<RwLock<RecorderConfig>>,
+    room_id: Arc<AtomicU64>,
+    live: Arc<AtomicBool>,
+    tx: mpsc::UnboundedSender<RecorderCommand>,
+}
+
+impl RecorderHandle {
+    /// Events `notify` reacts to
+    pub const EVENT_TYPES: &'static [EventType] =
+        &[EventType::UpdateRoom, EventType::LiveStart, EventType::LiveEnd];
+
+    /// Follow the room and its live status
+    pub fn notify(&self, event: &Event) {
+        match event {
+            Event::UpdateRoom {
+                room_id,
+                live_status,
+                ..
+            } => {
+                let room_id = room_id.real_id();
+                if self.room_id.swap(room_id, Ordering::Relaxed) != room_id {
+                    let _ = self.tx.send(RecorderCommand::Stop);
+                }
+                self.live.store(*live_status == 1, Ordering::Relaxed);
+                if *live_status == 1 {
+                    self.start();
+                }
+            }
+            Event::LiveStart => {
+                self.live.store(true, Ordering::Relaxed);
+                self.start();
+            }
+            Event::LiveEnd => {
+                self.live.store(false, Ordering::Relaxed);
+                let _ = self.tx.send(RecorderCommand::Stop);
+            }
+            _ => {}
+        }
+    }
+
+    /// Replace the settings, they apply from the next recording. Enabling the
+    /// recorder while the room is live starts a recording right away
+    pub fn reconfigure(&self, config: RecorderConfig) {
+        *self.config.write() = config.clone();
+        let _ = self.tx.send(RecorderCommand::Reconfigure(config));
+        if self.live.load(Ordering::Relaxed) {
+            self.start();
+        }
+    }
+
+    fn start(&self) {
+        let room_id = self.room_id.load(Ordering::Relaxed);
+        if room_id != 0 && self.config.read().enabled {
+            let _ = self.tx.send(RecorderCommand::Start(room_id));
+        }
+    }
+}
+
+struct ActiveRecording {
+    room_id: u64,
+    stop: watch::Sender<bool>,
+    task: JoinHandle<()>,
+}
+
+/// Runs the recordings started by a `RecorderHandle`
+pub struct RecorderWorker {
+    config: RecorderConfig,
+    default_dir: PathBuf,
+    source: Arc<dyn PlayUrlSource>,
+    database: Database,
+    client: SharedClient,
+    rx: mpsc::UnboundedReceiver<RecorderCommand>,
+    on_status: RecorderStatusCallback,
+    active: Option<ActiveRecording>,
+}
+
+/// Create a handle and the worker it feeds, recordings go to `default_dir`
+/// unless the config names a directory
+pub fn channel(
+    config: RecorderConfig,
+    default_dir: PathBuf,
+    source: Arc<dyn PlayUrlSource>,
+    database: Database,
+    on_status: RecorderStatusCallback,
+) -> Result<(RecorderHandle, RecorderWorker)> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let handle = RecorderHandle {
+        config: Arc::new(RwLock::new(config.clone())),
+        room_id: Arc::new(AtomicU64::new(0)),
+        live: Arc::new(AtomicBool::new(false)),
+        tx,
+    };
+    let worker = RecorderWorker {
+        config,
+        default_dir,
+        source,
+        database,
+        client: stream_client()?,
+        rx,
+        on_status,
+        active: None,
+    };
+    Ok((handle, worker))
+}
+
+/// HTTP client for stream downloads, the CDN checks the referer
+fn stream_client() -> Result<SharedClient> {
+    let mut headers = header::HeaderMap::new();
+    headers.insert(
+        header::USER_AGENT,
+        header::HeaderValue::from_static(
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36",
+        ),
+    );
+    headers.insert(
+        header::REFERER,
+        header::HeaderValue::from_static("https://live.bilibili.com/"),
+    );
+    SharedClient::streaming(move |builder| builder.default_headers(headers.clone()))
+}
+
+impl RecorderWorker {
+    /// Process commands until all handles are dropped
+    pub async fn run(mut self) {
+        while let Some(command) = self.rx.recv().await {
+            match command {
+                RecorderCommand::Start(room_id) => self.start(room_id).await,
+                RecorderCommand::Stop => self.stop().await,
+                RecorderCommand::Reconfigure(config) => {
+                    self.config = config;
+                    if !self.config.enabled {
+                        self.stop().await;
+                    }
+                }
+            }
+        }
+        self.stop().await;
+    }
+
+    async fn start(&mut self, room_id: u64) {
+        if let Some(ref active) = self.active {
+            if active.room_id == room_id && !active.task.is_finished() {
+                return;
+            }
+        }
+        self.stop().await;
+
+        let dir = if self.config.output_dir.trim().is_empty() {
+            self.default_dir.clone()
+        } else {
+            PathBuf::from(self.config.output_dir.trim())
+        };
+        let writer = SegmentWriter {
+            database: self.database.clone(),
+            dir,
+            room_id,
+            session_start: now_millis(),
+            limits: SegmentLimits::from(&self.config),
+            index: 0,
+            current: None,
+            on_status: self.on_status.clone(),
+        };
+        let (stop, stop_rx) = watch::channel(false);
+        info!("Start recording room {}", room_id);
+        let task = tokio::spawn(record_room(
+            self.source.clone(),
+            self.client.get(),
+            writer,
+            self.config.quality,
+            self.config.format,
+            stop_rx,
+        ));
+        self.active = Some(ActiveRecording {
+            room_id,
+            stop,
+            task,
+        });
+    }
+
+    /// Stop the active recording and wait until its file is closed
+    async fn stop(&mut self) {
+        if let Some(active) = self.active.take() {
+            let _ = active.stop.send(true);
+            let _ = active.task.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    /// Play URL source that is live for a fixed number of connections
+    struct LocalSource {
+        url: String,
+        remaining: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl PlayUrlSource for LocalSource {
+        async fn play_urls(&self, _room_id: u64, _quality: u32) -> Result<Vec<StreamUrl>> {
+            let live = self
+                .remaining
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok();
+            if !live {
+                return Ok(Vec::new());
+            }
+            Ok(vec![StreamUrl {
+                url: self.url.clone(),
+                format: StreamFormat::Flv,
+            }])
+        }
+    }
+
+    fn tag(tag_type: u8, timestamp: u32, data: Vec<u8>) -> FlvTag {
+        FlvTag {
+            tag_type,
+            timestamp,
+            data,
+        }
+    }
+
+    /// An FLV stream with codec headers and four one second GOPs
+    fn flv_stream() -> Vec<u8> {
+        let mut out = b"FLV\x01\x05\x00\x00\x00\x09\x00\x00\x00\x00".to_vec();
+        tag(TAG_SCRIPT, 0, b"onMetaData".to_vec()).encode(0, &mut out);
+        tag(TAG_VIDEO, 0, vec![0x17, 0, 0, 0, 0]).encode(0, &mut out);
+        tag(TAG_AUDIO, 0, vec![0xaf, 0, 0x12, 0x10]).encode(0, &mut out);
+        for i in 0..4u32 {
+            let base = 5000 + i * 1000;
+            let mut keyframe = vec![0x17, 1, 0, 0, 0];
+            keyframe.resize(600, 0);
+            tag(TAG_VIDEO, base, keyframe).encode(base, &mut out);
+            tag(TAG_AUDIO, base + 10, vec![0xaf, 1, 0]).encode(base + 10, &mut out);
+            let mut frame = vec![0x27, 1, 0, 0, 0];
+            frame.resize(600, 0);
+            tag(TAG_VIDEO, base + 500, frame).encode(base + 500, &mut out);
+        }
+        out
+    }
+
+    /// HTTP server that sends the FLV stream and closes the connection
+    async fn local_stream() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/live.flv", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = [0u8; 1024];
+                let _ = stream.read(&mut request).await;
+                let head =
+                    "HTTP/1.1 200 OK\r\nContent-Type: video/x-flv\r\nConnection: close\r\n\r\n";
+                let _ = stream.write_all(head.as_bytes()).await;
+                let _ = stream.write_all(&flv_stream()).await;
+                let _ = stream.shutdown().await;
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn test_parse_playlist() {
+        let playlist = parse_playlist(
+            "#EXTM3U\n#EXT-X-TARGETDURATION:2\n#EXT-X-MEDIA-SEQUENCE:41\n\
+             #EXT-X-MAP:URI=\"h1.m4s\"\n#EXTINF:1.0,\n41.m4s\n#EXTINF:1.0,\n42.m4s\n",
+        )
+        .unwrap();
+        assert_eq!(playlist.target_duration, 2.0);
+        assert_eq!(playlist.init.as_deref(), Some("h1.m4s"));
+        assert_eq!(
+            playlist.segments,
+            vec![(41, "41.m4s".to_string()), (42, "42.m4s".to_string())]
+        );
+        assert!(!playlist.ended);
+
+        let master = parse_playlist("#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=1\nlive.m3u8\n").unwrap();
+        assert_eq!(master.variant.as_deref(), Some("live.m3u8"));
+    }
+
+    #[tokio::test]
+    async fn test_record_local_stream() {
+        let database = Database::in_memory().unwrap();
+        let dir =
+            std::env::temp_dir().join(format!("jlivertool-recorder-{}", uuid::Uuid::new_v4()));
+        let source = Arc::new(LocalSource {
+            url: local_stream().await,
+            remaining: AtomicUsize::new(2),
+        });
+        let writer = SegmentWriter {
+            database: database.clone(),
+            dir: dir.clone(),
+            room_id: 1,
+            session_start: now_millis(),
+            // Every keyframe after the first one starts a new file
+            limits: SegmentLimits {
+                max_bytes: 1000,
+                max_duration: Duration::ZERO,
+            },
+            index: 0,
+            current: None,
+            on_status: Arc::new(|_, _| {}),
+        };
+        let (_stop, stop_rx) = watch::channel(false);
+
+        // The stream drops once, the recorder reconnects and stops when the
+        // room is offline
+        record_room(
+            source,
+            stream_client().unwrap().get(),
+            writer,
+            10000,
+            StreamFormat::Flv,
+            stop_rx,
+        )
+        .await;
+
+        let recordings = database.get_recordings(1).unwrap();
+        assert_eq!(recordings.len(), 8);
+        for recording in &recordings {
+            assert_eq!(recording.session_start, recordings[0].session_start);
+            assert!(recording.end_time.unwrap() >= recording.start_time);
+
+            let content = std::fs::read(&recording.path).unwrap();
+            assert_eq!(content.len() as u64, recording.size);
+            let mut reader = FlvReader::default();
+            reader.push(&content);
+            let mut tags = Vec::new();
+            while let Some(tag) = reader.next_tag().unwrap() {
+                tags.push(tag);
+            }
+            // Every file is playable on its own and starts at 0
+            let types: Vec<u8> = tags.iter().take(4).map(|t| t.tag_type).collect();
+            assert_eq!(types, vec![TAG_SCRIPT, TAG_VIDEO, TAG_AUDIO, TAG_VIDEO]);
+            assert!(tags[3].is_keyframe());
+            assert_eq!(tags[3].timestamp, 0);
+            assert_eq!(tags.last().unwrap().timestamp, 500);
+        }
+
+        // Exports of the live are aligned with its first file
+        let from = recordings[0].session_start / 1000 - 60;
+        let first = database
+            .first_recording_in(1, from, now_millis() / 1000 + 1)
+            .unwrap();
+        assert_eq!(first.as_ref(), recordings.first());
+        assert!(database.first_recording_in(1, 0, from).unwrap().is_none());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,300 @@
+//! Data retention and database compaction
+//!
+//! A worker applies the retention policy on a schedule: old danmus and paid
+//! records are deleted, old free gifts are merged per user and day, then the
+//! database is compacted. The same run can be triggered from the settings.
+
+use crate::database::Database;
+use anyhow::Result;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::info;
+
+/// Delay of the first scheduled run after startup
+const STARTUP_DELAY: Duration = Duration::from_secs(60);
+
+const DAY_SECS: i64 = 24 * 60 * 60;
+
+/// Retention settings, 0 days keeps data forever
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionConfig {
+    /// Days danmus are kept
+    #[serde(default)]
+    pub danmu_days: u32,
+
+    /// Days gifts, guards and superchats are kept
+    #[serde(default)]
+    pub paid_days: u32,
+
+    /// Free gifts older than this are merged into one record per user,
+    /// gift and day
+    #[serde(default)]
+    pub downsample_days: u32,
+
+    /// Hours between scheduled runs
+    #[serde(default = "default_interval_hours")]
+    pub interval_hours: u32,
+}
+
+fn default_interval_hours() -> u32 {
+    24
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            danmu_days: 0,
+            paid_days: 0,
+            downsample_days: 0,
+            interval_hours: default_interval_hours(),
+        }
+    }
+}
+
+impl RetentionConfig {
+    fn cutoff(days: u32, now: i64) -> Option<i64> {
+        (days > 0).then(|| now - days as i64 * DAY_SECS)
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_hours.max(1) as u64 * 60 * 60)
+    }
+}
+
+/// Records removed by a run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RetentionReport {
+    pub danmus_deleted: usize,
+    pub paid_deleted: usize,
+    pub gifts_merged: usize,
+}
+
+impl RetentionReport {
+    /// Summary shown in the settings
+    pub fn summary(&self) -> String {
+        format!(
+            "删除 {} 条弹幕、{} 条付费记录，合并 {} 条免费礼物",
+            self.danmus_deleted, self.paid_deleted, self.gifts_merged
+        )
+    }
+}
+
+/// Apply the retention policy at unix time `now`, then compact the database
+pub fn apply(database: &Database, config: &RetentionConfig, now: i64) -> Result<RetentionReport> {
+    let mut report = RetentionReport::default();
+    if let Some(before) = RetentionConfig::cutoff(config.danmu_days, now) {
+        report.danmus_deleted = database.delete_danmus_before(before)?;
+    }
+    if let Some(before) = RetentionConfig::cutoff(config.paid_days, now) {
+        report.paid_deleted = database.delete_paid_records_before(before)?;
+    }
+    if let Some(before) = RetentionConfig::cutoff(config.downsample_days, now) {
+        report.gifts_merged = database.downsample_free_gifts_before(before)?;
+    }
+    database.compact()?;
+    Ok(report)
+}
+
+/// Called with the result of every run
+pub type RetentionCallback = Box<dyn Fn(Result<RetentionReport>) + Send + Sync>;
+
+enum RetentionCommand {
+    Reconfigure(RetentionConfig),
+    RunNow,
+}
+
+/// Reconfigure or trigger the retention worker
+#[derive(Clone)]
+pub struct RetentionHandle {
+    config: Arc<RwLock<RetentionConfig>>,
+    tx: mpsc::UnboundedSender<RetentionCommand>,
+}
+
+impl RetentionHandle {
+    pub fn config(&self) -> RetentionConfig {
+        self.config.read().clone()
+    }
+
+    /// Replace the settings, the schedule restarts from now
+    pub fn reconfigure(&self, config: RetentionConfig) {
+        *self.config.write() = config.clone();
+        let _ = self.tx.send(RetentionCommand::Reconfigure(config));
+    }
+
+    /// Apply the policy and compact without waiting for the schedule
+    pub fn run_now(&self) {
+        let _ = self.tx.send(RetentionCommand::RunNow);
+    }
+}
+
+/// Runs retention on a schedule and on demand, one run at a time
+pub struct RetentionWorker {
+    database: Database,
+    config: RetentionConfig,
+    rx: mpsc::UnboundedReceiver<RetentionCommand>,
+    on_result: RetentionCallback,
+}
+
+/// Create a handle and the worker it feeds
+pub fn channel(
+    database: Database,
+    config: RetentionConfig,
+    on_result: RetentionCallback,
+) -> (RetentionHandle, RetentionWorker) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let handle = RetentionHandle {
+        config: Arc::new(RwLock::new(config.clone())),
+        tx,
+    };
+    let worker = RetentionWorker {
+        database,
+        config,
+        rx,
+        on_result,
+    };
+    (handle, worker)
+}
+
+impl RetentionWorker {
+    /// Run until all handles are dropped
+    pub async fn run(mut self) {
+        let mut next = tokio::time::Instant::now() + STARTUP_DELAY;
+        loop {
+            tokio::select! {
+                command = self.rx.recv() => match command {
+                    Some(RetentionCommand::Reconfigure(config)) => {
+                        self.config = config;
+                        next = tokio::time::Instant::now() + self.config.interval();
+                    }
+                    Some(RetentionCommand::RunNow) => {
+                        self.run_once().await;
+                        next = tokio::time::Instant::now() + self.config.interval();
+                    }
+                    None => break,
+                },
+                _ = tokio::time::sleep_until(next) => {
+                    self.run_once().await;
+                    next = tokio::time::Instant::now() + self.config.interval();
+                }
+            }
+        }
+    }
+
+    async fn run_once(&self) {
+        let database = self.database.clone();
+        let config = self.config.clone();
+        let now = chrono::Utc::now().timestamp();
+        let result = tokio::task::spawn_blocking(move || apply(&database, &config, now))
+            .await
+            .unwrap_or_else(|e| Err(e.into()));
+        if let Ok(report) = &result {
+            info!("Retention run finished: {:?}", report);
+        }
+        (self.on_result)(result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{DanmuMessage, GiftInfo, GiftMessage};
+    use crate::types::Sender;
+
+    fn gift(id: &str, uid: u64, coin_type: &str, num: u32, timestamp: i64) -> GiftMessage {
+        GiftMessage {
+            id: id.to_string(),
+            room: 1,
+            gift_info: GiftInfo {
+                id: 31036,
+                name: "小心心".to_string(),
+                price: if coin_type == "gold" { 100 } else { 0 },
+                coin_type: coin_type.to_string(),
+                img_basic: String::new(),
+                img_dynamic: String::new(),
+                gif: String::new(),
+                webp: String::new(),
+            },
+            sender: Sender {
+                uid,
+                ..Default::default()
+            },
+            action: "投喂".to_string(),
+            num,
+            timestamp,
+            archived: false,
+        }
+    }
+
+    fn danmu(timestamp: i64) -> DanmuMessage {
+        DanmuMessage {
+            sender: Sender::default(),
+            content: "你好".to_string(),
+            is_generated: false,
+            is_special: false,
+            is_mirror: false,
+            emoji_content: None,
+            side_index: -1,
+            reply_uname: None,
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn test_apply_retention() {
+        let db = Database::in_memory().unwrap();
+        let now = 100 * DAY_SECS;
+        let old = now - 40 * DAY_SECS;
+        db.insert_danmus_batch(1, &[danmu(old), danmu(now - 60)]).unwrap();
+        db.insert_gifts_batch(&[
+            // Same user, gift and day: merged into one record
+            gift("a", 1, "silver", 1, old),
+            gift("b", 1, "silver", 2, old + 60),
+            gift("c", 1, "silver", 3, old + 120),
+            // Alone in its group, paid, or recent: left alone
+            gift("d", 2, "silver", 1, old),
+            gift("e", 1, "gold", 1, old),
+            gift("f", 1, "silver", 1, now - 60),
+        ])
+        .unwrap();
+
+        let config = RetentionConfig {
+            danmu_days: 30,
+            paid_days: 0,
+            downsample_days: 7,
+            ..Default::default()
+        };
+        let report = apply(&db, &config, now).unwrap();
+        assert_eq!(
+            report,
+            RetentionReport {
+                danmus_deleted: 1,
+                paid_deleted: 0,
+                gifts_merged: 2,
+            }
+        );
+        assert_eq!(db.get_danmus_between(1, 0, now).unwrap().len(), 1);
+
+        let gifts = db.get_gifts_between(1, 0, now).unwrap();
+        assert_eq!(gifts.len(), 4);
+        let merged = gifts.iter().find(|g| g.id.starts_with("merged-")).unwrap();
+        assert_eq!((merged.num, merged.timestamp), (6, old));
+
+        // A second run finds nothing new to merge
+        assert_eq!(apply(&db, &config, now).unwrap().gifts_merged, 0);
+
+        let config = RetentionConfig {
+            paid_days: 30,
+            ..Default::default()
+        };
+        assert_eq!(apply(&db, &config, now).unwrap().paid_deleted, 3);
+
+        let stats = db.stats().unwrap();
+        assert!(stats.total_bytes > 0);
+        let gifts = stats.tables.iter().find(|t| t.name == "gifts").unwrap();
+        assert_eq!(gifts.rows, 1);
+        assert!(gifts.bytes > 0);
+    }
+}
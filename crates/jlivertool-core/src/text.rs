@@ -0,0 +1,25 @@
+//! Text layout metrics shared by the danmu list and subtitle export
+//!
+//! Both lay text out without a font at hand, so they use the same estimate
+//! to keep on-screen wrapping and exported subtitle lanes consistent.
+
+/// Estimate the rendered width of a string in pixels.
+/// CJK characters are approximately `font_size` wide.
+/// ASCII characters are approximately `font_size * 0.55` wide.
+pub fn estimate_text_width(text: &str, font_size: f32) -> f32 {
+    text.chars()
+        .map(|ch| if ch.is_ascii() { font_size * 0.55 } else { font_size })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_text_width() {
+        assert_eq!(estimate_text_width("", 20.0), 0.0);
+        assert_eq!(estimate_text_width("ab", 20.0), 22.0);
+        assert_eq!(estimate_text_width("弹幕a", 20.0), 51.0);
+    }
+}
@@ -1,6 +1,7 @@
 //! TTS Manager - orchestrates TTS functionality
 
 use parking_lot::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::thread;
 use tokio::sync::mpsc;
@@ -96,6 +97,8 @@ pub struct TtsManager {
     command_tx: mpsc::UnboundedSender<TtsCommand>,
     enabled: Arc<Mutex<TtsEnabled>>,
     volume: Arc<Mutex<f32>>,
+    /// Messages waiting in the worker queue
+    queue_len: Arc<AtomicUsize>,
 }
 
 impl TtsManager {
@@ -104,17 +107,20 @@ impl TtsManager {
         let (command_tx, command_rx) = mpsc::unbounded_channel();
         let enabled = Arc::new(Mutex::new(TtsEnabled::default()));
         let volume = Arc::new(Mutex::new(1.0f32));
+        let queue_len = Arc::new(AtomicUsize::new(0));
 
         // Spawn worker thread
         let volume_clone = volume.clone();
+        let queue_len_clone = queue_len.clone();
         thread::spawn(move || {
-            Self::worker_thread(command_rx, volume_clone);
+            Self::worker_thread(command_rx, volume_clone, queue_len_clone);
         });
 
         Self {
             command_tx,
             enabled,
             volume,
+            queue_len,
         }
     }
 
@@ -122,6 +128,7 @@ impl TtsManager {
     fn worker_thread(
         mut command_rx: mpsc::UnboundedReceiver<TtsCommand>,
         volume: Arc<Mutex<f32>>,
+        queue_len: Arc<AtomicUsize>,
     ) {
         let mut queue = TtsQueue::new();
 
@@ -180,7 +187,9 @@ impl TtsManager {
                 }
 
                 // Process queue
-                if let Some(message) = queue.pop() {
+                let next = queue.pop();
+                queue_len.store(queue.len(), Ordering::Relaxed);
+                if let Some(message) = next {
                     let current_volume = *volume.lock();
 
                     #[cfg(target_os = "macos")]
@@ -221,6 +230,11 @@ impl TtsManager {
         }
     }
 
+    /// Number of messages waiting to be spoken
+    pub fn queue_len(&self) -> usize {
+        self.queue_len.load(Ordering::Relaxed)
+    }
+
     /// Set which message types are enabled for TTS
     pub fn set_enabled(&self, enabled: TtsEnabled) {
         *self.enabled.lock() = enabled;
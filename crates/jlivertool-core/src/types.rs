@@ -280,3 +280,176 @@ impl InteractAction {
         }
     }
 }
+
+/// A field in a plugin's settings schema (declared in meta.json)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginSettingField {
+    pub key: String,
+    pub label: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(flatten)]
+    pub kind: PluginSettingKind,
+    #[serde(default)]
+    pub default: Option<serde_json::Value>,
+}
+
+/// Typed plugin setting kinds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum PluginSettingKind {
+    String,
+    Number {
+        #[serde(default)]
+        min: Option<f64>,
+        #[serde(default)]
+        max: Option<f64>,
+    },
+    Bool,
+    /// Hex color string such as "#ff6699"
+    #[serde(alias = "colour")]
+    Color,
+    Select { options: Vec<PluginSelectOption> },
+}
+
+/// An option of a select setting
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginSelectOption {
+    pub value: String,
+    pub label: String,
+}
+
+impl PluginSettingField {
+    /// Default value, falling back to the zero value of the field type
+    pub fn default_value(&self) -> serde_json::Value {
+        if let Some(ref value) = self.default {
+            return value.clone();
+        }
+        match &self.kind {
+            PluginSettingKind::String => serde_json::Value::String(String::new()),
+            PluginSettingKind::Number { min, .. } => serde_json::json!(min.unwrap_or(0.0)),
+            PluginSettingKind::Bool => serde_json::Value::Bool(false),
+            PluginSettingKind::Color => serde_json::Value::String("#ffffff".to_string()),
+            PluginSettingKind::Select { options } => options
+                .first()
+                .map(|o| serde_json::Value::String(o.value.clone()))
+                .unwrap_or(serde_json::Value::Null),
+        }
+    }
+
+    /// Check and normalize a value against the field type
+    pub fn validate(&self, value: &serde_json::Value) -> Option<serde_json::Value> {
+        match &self.kind {
+            PluginSettingKind::String => value.as_str().map(|s| serde_json::json!(s)),
+            PluginSettingKind::Number { min, max } => {
+                let mut n = value
+                    .as_f64()
+                    .or_else(|| value.as_str().and_then(|s| s.trim().parse().ok()))?;
+                if let Some(min) = min {
+                    n = n.max(*min);
+                }
+                if let Some(max) = max {
+                    n = n.min(*max);
+                }
+                Some(serde_json::json!(n))
+            }
+            PluginSettingKind::Bool => value.as_bool().map(serde_json::Value::Bool),
+            PluginSettingKind::Color => {
+                let s = value.as_str()?.trim();
+                let hex = s.strip_prefix('#')?;
+                let valid = matches!(hex.len(), 3 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit());
+                valid.then(|| serde_json::json!(s))
+            }
+            PluginSettingKind::Select { options } => {
+                let s = value.as_str()?;
+                options.iter().any(|o| o.value == s).then(|| serde_json::json!(s))
+            }
+        }
+    }
+}
+
+/// Resolve effective plugin settings: stored values that match the schema, defaults otherwise
+pub fn resolve_plugin_settings(
+    schema: &[PluginSettingField],
+    stored: &serde_json::Map<String, serde_json::Value>,
+) -> serde_json::Map<String, serde_json::Value> {
+    schema
+        .iter()
+        .map(|field| {
+            let value = stored
+                .get(&field.key)
+                .and_then(|v| field.validate(v))
+                .unwrap_or_else(|| field.default_value());
+            (field.key.clone(), value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schema() -> Vec<PluginSettingField> {
+        serde_json::from_value(json!([
+            {"key": "title", "label": "标题", "type": "string", "default": "弹幕"},
+            {"key": "size", "label": "字号", "type": "number", "min": 12, "max": 48},
+            {"key": "shadow", "label": "阴影", "type": "bool"},
+            {"key": "color", "label": "颜色", "type": "colour"},
+            {"key": "align", "label": "对齐", "type": "select", "options": [
+                {"value": "left", "label": "左"},
+                {"value": "right", "label": "右"}
+            ]}
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_validate_plugin_setting() {
+        let fields = schema();
+        let [title, size, shadow, color, align] = &fields[..] else {
+            panic!("unexpected schema");
+        };
+
+        assert_eq!(title.validate(&json!("你好")), Some(json!("你好")));
+        assert_eq!(title.validate(&json!(1)), None);
+
+        // Numbers are clamped, numeric strings from text inputs are accepted
+        assert_eq!(size.validate(&json!(20)), Some(json!(20.0)));
+        assert_eq!(size.validate(&json!(100)), Some(json!(48.0)));
+        assert_eq!(size.validate(&json!(" 8 ")), Some(json!(12.0)));
+        assert_eq!(size.validate(&json!("big")), None);
+
+        assert_eq!(shadow.validate(&json!(true)), Some(json!(true)));
+        assert_eq!(shadow.validate(&json!("true")), None);
+
+        assert_eq!(color.validate(&json!(" #f69 ")), Some(json!("#f69")));
+        assert_eq!(color.validate(&json!("#ff6699cc")), Some(json!("#ff6699cc")));
+        assert_eq!(color.validate(&json!("ff6699")), None);
+        assert_eq!(color.validate(&json!("#ff66zz")), None);
+
+        assert_eq!(align.validate(&json!("right")), Some(json!("right")));
+        assert_eq!(align.validate(&json!("center")), None);
+    }
+
+    #[test]
+    fn test_resolve_plugin_settings() {
+        let stored = json!({
+            "title": "礼物",
+            "size": "100",
+            "shadow": "yes",
+            "unknown": 1
+        });
+        let resolved = resolve_plugin_settings(&schema(), stored.as_object().unwrap());
+        assert_eq!(
+            serde_json::Value::Object(resolved),
+            json!({
+                "title": "礼物",
+                "size": 48.0,
+                "shadow": false,
+                "color": "#ffffff",
+                "align": "left"
+            })
+        );
+    }
+}
@@ -1,7 +1,6 @@
 //! GitHub release update checker
 
 use anyhow::Result;
-use reqwest::Client;
 use serde::Deserialize;
 use std::time::Duration;
 use tracing::{debug, info, warn};
@@ -32,7 +31,7 @@ pub struct UpdateInfo {
 pub async fn check_for_update(current_version: &str) -> Result<UpdateInfo> {
     info!("Checking for updates (current: v{})", current_version);
 
-    let client = Client::builder()
+    let client = crate::network::client_builder()?
         .timeout(Duration::from_secs(10))
         .user_agent("JLiverTool")
         .build()?;
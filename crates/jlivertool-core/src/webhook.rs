@@ -0,0 +1,437 @@
+//! Outbound webhooks
+//!
+//! Events from the `EventBus` are encoded once (the app uses the plugin event
+//! JSON), filtered per endpoint, batched and POSTed as
+//! `{"delivery_id", "sent_at", "events": [...]}`. Bodies are signed with
+//! HMAC-SHA256 when the endpoint has a secret, failed requests are retried
+//! with exponential backoff and every delivery is logged in the database.
+
+use crate::database::Database;
+use crate::events::{channel_matches, Event, Subscription};
+use crate::network::SharedClient;
+use anyhow::Result;
+use hmac::{Hmac, Mac};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+/// Header with the HMAC-SHA256 signature of the body, `sha256=<hex>`
+pub const SIGNATURE_HEADER: &str = "X-JLiver-Signature";
+/// Header with the delivery id, the same for all attempts of a delivery
+pub const DELIVERY_HEADER: &str = "X-JLiver-Delivery";
+
+/// How often pending batches are checked for their interval
+const FLUSH_TICK: Duration = Duration::from_millis(200);
+/// Delay before the first retry, doubled for every further retry
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// A webhook target
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookEndpoint {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+
+    pub url: String,
+
+    /// Event channels to send, e.g. "new_gift" or "NewGift", empty for all
+    #[serde(default)]
+    pub events: Vec<String>,
+
+    /// Signing secret, requests are not signed if empty
+    #[serde(default)]
+    pub secret: String,
+
+    /// Number of events per request
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+
+    /// Longest time in milliseconds an event waits for its batch to fill
+    #[serde(default = "default_batch_interval_ms")]
+    pub batch_interval_ms: u64,
+
+    /// Retries after the first attempt
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_batch_size() -> usize {
+    1
+}
+
+fn default_batch_interval_ms() -> u64 {
+    2000
+}
+
+fn default_max_retries() -> u32 {
+    3
+}
+
+impl WebhookEndpoint {
+    /// Whether events of a channel are sent to this endpoint
+    pub fn wants(&self, channel: &str) -> bool {
+        self.enabled
+            && (self.events.is_empty() || self.events.iter().any(|e| channel_matches(e, channel)))
+    }
+}
+
+/// A delivery as stored in the database
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub url: String,
+    /// Comma separated channels of the delivered events
+    pub channels: String,
+    pub event_count: usize,
+    /// HTTP status of the last attempt, 0 if there was no response
+    pub status: u16,
+    pub success: bool,
+    pub attempts: u32,
+    /// Error of the last failed attempt
+    pub error: String,
+    pub timestamp: i64,
+}
+
+/// Encode an event as (channel, JSON), None for events that aren't sent
+pub type EventEncoder = Arc<dyn Fn(&Event) -> Option<(String, Value)> + Send + Sync>;
+
+/// `sha256=<hex HMAC-SHA256 of the body>`
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body);
+    format!("sha256={:x}", mac.finalize().into_bytes())
+}
+
+/// Events waiting to be sent to one endpoint
+struct Batch {
+    endpoint: WebhookEndpoint,
+    channels: Vec<String>,
+    events: Vec<Value>,
+    started: Instant,
+}
+
+impl Batch {
+    fn is_due(&self) -> bool {
+        self.started.elapsed() >= Duration::from_millis(self.endpoint.batch_interval_ms)
+    }
+}
+
+/// Sends bus events to the configured endpoints
+#[derive(Clone)]
+pub struct WebhookDispatcher {
+    endpoints: Arc<RwLock<Vec<WebhookEndpoint>>>,
+    database: Database,
+    encoder: EventEncoder,
+    client: SharedClient,
+    retry_delay: Duration,
+}
+
+impl WebhookDispatcher {
+    pub fn new(
+        database: Database,
+        endpoints: Vec<WebhookEndpoint>,
+        encoder: EventEncoder,
+    ) -> Result<Self> {
+        let client = SharedClient::new(|builder| {
+            builder
+                .timeout(Duration::from_secs(10))
+                .user_agent(concat!("JLiverTool/", env!("CARGO_PKG_VERSION")))
+        })?;
+        Ok(Self {
+            endpoints: Arc::new(RwLock::new(endpoints)),
+            database,
+            encoder,
+            client,
+            retry_delay: RETRY_DELAY,
+        })
+    }
+
+    /// Replace the endpoints, pending batches keep their old settings
+    pub fn set_endpoints(&self, endpoints: Vec<WebhookEndpoint>) {
+        *self.endpoints.write() = endpoints;
+    }
+
+    /// Dispatch events until the bus is dropped
+    pub async fn run(self, mut subscription: Subscription) {
+        let mut batches: HashMap<String, Batch> = HashMap::new();
+        let mut workers: HashMap<String, mpsc::UnboundedSender<Batch>> = HashMap::new();
+        let mut tick = tokio::time::interval(FLUSH_TICK);
+
+        loop {
+            tokio::select! {
+                received = subscription.recv() => match received {
+                    Some(event) => self.enqueue(&event, &mut batches, &mut workers),
+                    None => break,
+                },
+                _ = tick.tick() => {
+                    let due: Vec<String> = batches
+                        .iter()
+                        .filter(|(_, batch)| batch.is_due())
+                        .map(|(url, _)| url.clone())
+                        .collect();
+                    for url in due {
+                        if let Some(batch) = batches.remove(&url) {
+                            self.send(batch, &mut workers);
+                        }
+                    }
+                }
+            }
+        }
+
+        for (_, batch) in batches.drain() {
+            self.send(batch, &mut workers);
+        }
+    }
+
+    fn enqueue(
+        &self,
+        event: &Event,
+        batches: &mut HashMap<String, Batch>,
+        workers: &mut HashMap<String, mpsc::UnboundedSender<Batch>>,
+    ) {
+        let endpoints = self.endpoints.read().clone();
+        if !endpoints.iter().any(|e| e.enabled) {
+            return;
+        }
+        let Some((channel, value)) = (self.encoder)(event) else {
+            return;
+        };
+
+        for endpoint in endpoints.into_iter().filter(|e| e.wants(&channel)) {
+            let url = endpoint.url.clone();
+            let batch = batches.entry(url.clone()).or_insert_with(|| Batch {
+                endpoint: endpoint.clone(),
+                channels: Vec::new(),
+                events: Vec::new(),
+                started: Instant::now(),
+            });
+            batch.channels.push(channel.clone());
+            batch.events.push(value.clone());
+            if batch.events.len() >= endpoint.batch_size.max(1) {
+                if let Some(batch) = batches.remove(&url) {
+                    self.send(batch, workers);
+                }
+            }
+        }
+    }
+
+    /// Hand a batch to the worker of its endpoint, deliveries of an endpoint
+    /// are made in order
+    fn send(&self, batch: Batch, workers: &mut HashMap<String, mpsc::UnboundedSender<Batch>>) {
+        let url = batch.endpoint.url.clone();
+        let batch = match workers.get(&url) {
+            Some(worker) => match worker.send(batch) {
+                Ok(()) => return,
+                Err(mpsc::error::SendError(batch)) => batch,
+            },
+            None => batch,
+        };
+
+        let (tx, mut rx) = mpsc::unbounded_channel::<Batch>();
+        let _ = tx.send(batch);
+        workers.insert(url, tx);
+        let dispatcher = self.clone();
+        tokio::spawn(async move {
+            while let Some(batch) = rx.recv().await {
+                let delivery = dispatcher.deliver(batch).await;
+                if let Err(e) = dispatcher.database.insert_webhook_delivery(&delivery) {
+                    warn!("Failed to log webhook delivery: {}", e);
+                }
+            }
+        });
+    }
+
+    async fn deliver(&self, batch: Batch) -> WebhookDelivery {
+        let endpoint = &batch.endpoint;
+        let delivery_id = uuid::Uuid::new_v4().to_string();
+        let body = json!({
+            "delivery_id": delivery_id,
+            "sent_at": chrono::Utc::now().timestamp(),
+            "events": batch.events,
+        })
+        .to_string();
+
+        let mut delivery = WebhookDelivery {
+            url: endpoint.url.clone(),
+            channels: batch.channels.join(","),
+            event_count: batch.events.len(),
+            ..Default::default()
+        };
+
+        for attempt in 0..=endpoint.max_retries {
+            if attempt > 0 {
+                let delay = self.retry_delay * 2u32.pow((attempt - 1).min(16));
+                tokio::time::sleep(delay.min(MAX_RETRY_DELAY)).await;
+            }
+            delivery.attempts = attempt + 1;
+
+            let mut request = self
+                .client
+                .get()
+                .post(&endpoint.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(DELIVERY_HEADER, &delivery_id)
+                .body(body.clone());
+            if !endpoint.secret.is_empty() {
+                request = request.header(SIGNATURE_HEADER, sign(&endpoint.secret, body.as_bytes()));
+            }
+
+            match request.send().await {
+                Ok(response) => {
+                    let status = response.status();
+                    delivery.status = status.as_u16();
+                    if status.is_success() {
+                        delivery.success = true;
+                        delivery.error.clear();
+                        break;
+                    }
+                    delivery.error = format!("HTTP {}", status);
+                    // Client errors other than rate limiting won't succeed on retry
+                    if !status.is_server_error() && status != reqwest::StatusCode::TOO_MANY_REQUESTS
+                    {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    delivery.status = 0;
+                    delivery.error = e.to_string();
+                }
+            }
+            debug!(
+                "Webhook delivery to {} failed (attempt {}): {}",
+                endpoint.url, delivery.attempts, delivery.error
+            );
+        }
+
+        if !delivery.success {
+            warn!(
+                "Webhook delivery to {} failed after {} attempts: {}",
+                endpoint.url, delivery.attempts, delivery.error
+            );
+        }
+        delivery.timestamp = chrono::Utc::now().timestamp();
+        delivery
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::EventBus;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// HTTP server answering with the given statuses in turn, reports the
+    /// signature header and body of each request
+    async fn mock_server(
+        statuses: Vec<u16>,
+    ) -> (String, mpsc::UnboundedReceiver<(String, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            for status in statuses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut data = Vec::new();
+                let mut buf = [0u8; 4096];
+                let (head, body) = loop {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    data.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&data).to_string();
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        let length = head
+                            .lines()
+                            .find_map(|l| {
+                                l.to_ascii_lowercase()
+                                    .strip_prefix("content-length:")
+                                    .map(|v| v.trim().parse::<usize>().unwrap())
+                            })
+                            .unwrap_or(0);
+                        if body.len() >= length {
+                            break (head.to_string(), body.to_string());
+                        }
+                    }
+                };
+                let signature = head
+                    .lines()
+                    .find_map(|l| l.strip_prefix("x-jliver-signature: ").map(str::to_string))
+                    .unwrap_or_default();
+                let _ = tx.send((signature, body));
+                let response = format!(
+                    "HTTP/1.1 {} X\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (url, rx)
+    }
+
+    #[tokio::test]
+    async fn test_batched_signed_delivery_with_retry() {
+        let (url, mut requests) = mock_server(vec![500, 200]).await;
+        let db = Database::in_memory().unwrap();
+        let encoder: EventEncoder = Arc::new(|event| match event {
+            Event::LiveStart => Some(("live_start".to_string(), json!({ "type": "LiveStart" }))),
+            Event::LiveEnd => Some(("live_end".to_string(), json!({ "type": "LiveEnd" }))),
+            Event::UpdateOnline { count } => Some((
+                "update_online".to_string(),
+                json!({ "type": "UpdateOnline", "data": { "count": count } }),
+            )),
+            _ => None,
+        });
+        let endpoint = WebhookEndpoint {
+            enabled: true,
+            url,
+            events: vec!["live_start".to_string(), "LiveEnd".to_string()],
+            secret: "secret".to_string(),
+            batch_size: 2,
+            batch_interval_ms: 60_000,
+            max_retries: 3,
+        };
+        let mut dispatcher = WebhookDispatcher::new(db.clone(), vec![endpoint], encoder).unwrap();
+        dispatcher.retry_delay = Duration::from_millis(10);
+
+        let bus = EventBus::new();
+        tokio::spawn(dispatcher.run(bus.subscribe()));
+        bus.emit(Event::UpdateOnline { count: 1 });
+        bus.emit(Event::LiveStart);
+        bus.emit(Event::LiveEnd);
+
+        let (first_signature, first_body) = requests.recv().await.unwrap();
+        let (signature, body) = requests.recv().await.unwrap();
+        assert_eq!(first_body, body);
+        assert_eq!(first_signature, signature);
+        assert_eq!(signature, sign("secret", body.as_bytes()));
+        let payload: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            payload["events"],
+            json!([{ "type": "LiveStart" }, { "type": "LiveEnd" }])
+        );
+
+        let mut deliveries = Vec::new();
+        for _ in 0..100 {
+            deliveries = db.get_webhook_deliveries(10).unwrap();
+            if !deliveries.is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(deliveries.len(), 1);
+        assert!(deliveries[0].success);
+        assert_eq!(deliveries[0].attempts, 2);
+        assert_eq!(deliveries[0].status, 200);
+        assert_eq!(deliveries[0].channels, "live_start,live_end");
+    }
+}
@@ -1,13 +1,128 @@
+//! Plugin event schema
+//!
+//! Events are serialized as `{"type": "<Variant>", "data": {...}}`. The schema is
+//! versioned by `PLUGIN_EVENT_SCHEMA_VERSION`, which is sent in the `Welcome`
+//! message. Fields of schema version 1 (`msg`, `msg_type`, `medal_name`,
+//! `medal_level`, `medal_room_id`, `price`) are kept so existing plugins keep working.
+
+pub use jlivertool_core::events::channel_matches;
 use jlivertool_core::events::Event;
-use jlivertool_core::messages::{DanmuMessage, GiftMessage, GuardMessage, InteractMessage, SuperChatMessage};
+use jlivertool_core::goals::Goal;
+use jlivertool_core::messages::{
+    AnchorLotAwardMessage, AnchorLotStartMessage, DanmuMessage, EntryEffectMessage, GiftMessage,
+    GuardMessage, InteractMessage, LikeClickMessage, LotteryWinner, RedPocketAward,
+    RedPocketStartMessage, RoomBlockMessage, RoomSilentMessage, SuperChatMessage,
+};
+use jlivertool_core::types::{EmojiContent, MedalInfo, Sender};
 use serde::Serialize;
 
+/// Version of the plugin event schema
+///
+/// - 1: danmu, gift, guard, superchat, interact, room and live status events
+/// - 2: full sender/medal/emoji data, exact prices (`unit_price`, `price_yuan`),
+///   real danmu timestamps, and
+///   entry effect, room change, warning, cut off and connection status events
+/// - 3: like, watched count, red pocket, anchor lottery, superchat deletion,
+///   room silence and user block events
+/// - 4: goal progress events
+pub const PLUGIN_EVENT_SCHEMA_VERSION: u32 = 4;
+
+/// Fan medal of a sender
 #[derive(Debug, Clone, Serialize)]
-pub struct SerializableDanmu {
+pub struct SerializableMedal {
+    pub name: String,
+    pub level: u8,
+    pub anchor_uname: String,
+    pub anchor_room_id: u64,
+    pub guard_level: u8,
+    /// Colors as 0xRRGGBB integers
+    pub color: u32,
+    pub color_border: u32,
+    pub color_start: u32,
+    pub color_end: u32,
+    pub is_lighted: bool,
+}
+
+impl SerializableMedal {
+    /// Returns None if the sender wears no medal
+    fn from_medal_info(medal: &MedalInfo) -> Option<Self> {
+        if medal.medal_name.is_empty() {
+            return None;
+        }
+        Some(Self {
+            name: medal.medal_name.clone(),
+            level: medal.medal_level,
+            anchor_uname: medal.anchor_uname.clone(),
+            anchor_room_id: medal.anchor_roomid,
+            guard_level: medal.guard_level,
+            color: medal.medal_color,
+            color_border: medal.medal_color_border,
+            color_start: medal.medal_color_start,
+            color_end: medal.medal_color_end,
+            is_lighted: medal.is_lighted,
+        })
+    }
+}
+
+/// Emoji sent as a danmu
+#[derive(Debug, Clone, Serialize)]
+pub struct SerializableEmoji {
+    /// Emoji identifier, e.g. "official_147"
+    pub unique: String,
+    pub url: String,
+    pub width: u32,
+    pub height: u32,
+    pub is_dynamic: bool,
+}
+
+impl From<&EmojiContent> for SerializableEmoji {
+    fn from(emoji: &EmojiContent) -> Self {
+        Self {
+            unique: emoji.emoticon_unique.clone(),
+            url: emoji.url.clone(),
+            width: emoji.width,
+            height: emoji.height,
+            is_dynamic: emoji.is_dynamic != 0,
+        }
+    }
+}
+
+/// Sender fields shared by all user events
+#[derive(Debug, Clone, Serialize)]
+pub struct SerializableSender {
     pub uid: u64,
     pub uname: String,
+    pub face: String,
+    /// Guard level of the sender in this room (0 none, 1 总督, 2 提督, 3 舰长)
+    pub guard_level: u8,
+    pub medal: Option<SerializableMedal>,
+}
+
+impl From<&Sender> for SerializableSender {
+    fn from(sender: &Sender) -> Self {
+        Self {
+            uid: sender.uid,
+            uname: sender.uname.clone(),
+            face: sender.face.clone(),
+            guard_level: sender.medal_info.guard_level,
+            medal: SerializableMedal::from_medal_info(&sender.medal_info),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SerializableDanmu {
+    #[serde(flatten)]
+    pub sender: SerializableSender,
     pub msg: String,
+    /// Unix timestamp (seconds) the danmu was sent at
     pub timestamp: i64,
+    pub emoji: Option<SerializableEmoji>,
+    pub reply_uname: Option<String>,
+    pub is_special: bool,
+    pub is_generated: bool,
+    pub is_mirror: bool,
+    // Schema v1 fields
     pub medal_name: Option<String>,
     pub medal_level: Option<u32>,
     pub medal_room_id: Option<u64>,
@@ -30,12 +145,21 @@ impl From<&DanmuMessage> for SerializableDanmu {
         } else {
             None
         };
+        let timestamp = if msg.timestamp > 0 {
+            msg.timestamp
+        } else {
+            chrono::Utc::now().timestamp()
+        };
 
         Self {
-            uid: msg.sender.uid,
-            uname: msg.sender.uname.clone(),
+            sender: (&msg.sender).into(),
             msg: msg.content.clone(),
-            timestamp: chrono::Utc::now().timestamp(),
+            timestamp,
+            emoji: msg.emoji_content.as_ref().map(SerializableEmoji::from),
+            reply_uname: msg.reply_uname.clone(),
+            is_special: msg.is_special,
+            is_generated: msg.is_generated,
+            is_mirror: msg.is_mirror,
             medal_name,
             medal_level,
             medal_room_id,
@@ -45,22 +169,40 @@ impl From<&DanmuMessage> for SerializableDanmu {
 
 #[derive(Debug, Clone, Serialize)]
 pub struct SerializableGift {
-    pub uid: u64,
-    pub uname: String,
+    pub id: String,
+    #[serde(flatten)]
+    pub sender: SerializableSender,
+    pub gift_id: u64,
     pub gift_name: String,
+    pub action: String,
     pub num: u32,
+    /// "gold" for paid gifts, "silver" for free gifts
+    pub coin_type: String,
+    /// Price of a single gift in yuan
+    pub unit_price: f64,
+    /// Total price in whole yuan, truncated as in schema v1
     pub price: u32,
+    /// Total price in yuan (unit_price * num), not truncated
+    pub price_yuan: f64,
     pub timestamp: i64,
 }
 
 impl From<&GiftMessage> for SerializableGift {
     fn from(msg: &GiftMessage) -> Self {
+        // Gift prices are in 1/1000 yuan
+        let unit_price = msg.gift_info.price as f64 / 1000.0;
+        let total = msg.gift_info.price * msg.num as u64;
         Self {
-            uid: msg.sender.uid,
-            uname: msg.sender.uname.clone(),
+            id: msg.id.clone(),
+            sender: (&msg.sender).into(),
+            gift_id: msg.gift_info.id,
             gift_name: msg.gift_info.name.clone(),
+            action: msg.action.clone(),
             num: msg.num,
-            price: (msg.gift_info.price * msg.num as u64 / 1000) as u32,
+            coin_type: msg.gift_info.coin_type.clone(),
+            unit_price,
+            price: (total / 1000) as u32,
+            price_yuan: total as f64 / 1000.0,
             timestamp: msg.timestamp,
         }
     }
@@ -68,22 +210,33 @@ impl From<&GiftMessage> for SerializableGift {
 
 #[derive(Debug, Clone, Serialize)]
 pub struct SerializableGuard {
-    pub uid: u64,
-    pub uname: String,
-    pub guard_level: u32,
+    pub id: String,
+    /// `guard_level` is the purchased level (1 总督, 2 提督, 3 舰长)
+    #[serde(flatten)]
+    pub sender: SerializableSender,
     pub num: u32,
+    /// Duration unit, e.g. "月"
+    pub unit: String,
+    /// Price in whole yuan, truncated as in schema v1
     pub price: u32,
+    /// Price in yuan, not truncated
+    pub price_yuan: f64,
     pub timestamp: i64,
 }
 
 impl From<&GuardMessage> for SerializableGuard {
     fn from(msg: &GuardMessage) -> Self {
+        let mut sender: SerializableSender = (&msg.sender).into();
+        // The purchased level takes precedence over the level carried by the medal
+        sender.guard_level = msg.guard_level;
         Self {
-            uid: msg.sender.uid,
-            uname: msg.sender.uname.clone(),
-            guard_level: msg.guard_level as u32,
+            id: msg.id.clone(),
+            sender,
             num: msg.num,
+            unit: msg.unit.clone(),
+            // Guard prices are in 1/1000 yuan
             price: (msg.price / 1000) as u32,
+            price_yuan: msg.price as f64 / 1000.0,
             timestamp: msg.timestamp,
         }
     }
@@ -91,20 +244,30 @@ impl From<&GuardMessage> for SerializableGuard {
 
 #[derive(Debug, Clone, Serialize)]
 pub struct SerializableSuperChat {
-    pub uid: u64,
-    pub uname: String,
+    pub id: String,
+    #[serde(flatten)]
+    pub sender: SerializableSender,
     pub message: String,
-    pub price: u32,
+    /// Price in yuan
+    pub price: u64,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub background_color: String,
+    pub background_bottom_color: String,
     pub timestamp: i64,
 }
 
 impl From<&SuperChatMessage> for SerializableSuperChat {
     fn from(msg: &SuperChatMessage) -> Self {
         Self {
-            uid: msg.sender.uid,
-            uname: msg.sender.uname.clone(),
+            id: msg.id.clone(),
+            sender: (&msg.sender).into(),
             message: msg.message.clone(),
-            price: msg.price as u32,
+            price: msg.price,
+            start_time: msg.start_time,
+            end_time: msg.end_time,
+            background_color: msg.background_color.clone(),
+            background_bottom_color: msg.background_bottom_color.clone(),
             timestamp: msg.timestamp,
         }
     }
@@ -112,23 +275,123 @@ impl From<&SuperChatMessage> for SerializableSuperChat {
 
 #[derive(Debug, Clone, Serialize)]
 pub struct SerializableInteract {
-    pub uid: u64,
-    pub uname: String,
+    #[serde(flatten)]
+    pub sender: SerializableSender,
+    /// 1 enter, 2 follow, 3 share, 4 special follow, 5 mutual follow
     pub msg_type: u32,
     pub timestamp: i64,
 }
 
 impl From<&InteractMessage> for SerializableInteract {
     fn from(msg: &InteractMessage) -> Self {
+        Self {
+            sender: (&msg.sender).into(),
+            msg_type: msg.action as u32,
+            timestamp: chrono::Utc::now().timestamp(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SerializableEntryEffect {
+    pub uid: u64,
+    pub uname: String,
+    /// Guard level of the entering user, 0 for non-guard entry effects
+    pub privilege_type: u8,
+    pub timestamp: i64,
+}
+
+impl From<&EntryEffectMessage> for SerializableEntryEffect {
+    fn from(msg: &EntryEffectMessage) -> Self {
         Self {
             uid: msg.sender.uid,
             uname: msg.sender.uname.clone(),
-            msg_type: msg.action as u32,
+            privilege_type: msg.privilege_type,
             timestamp: chrono::Utc::now().timestamp(),
         }
     }
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct SerializableLikeClick {
+    #[serde(flatten)]
+    pub sender: SerializableSender,
+    pub like_text: String,
+    pub timestamp: i64,
+}
+
+impl From<&LikeClickMessage> for SerializableLikeClick {
+    fn from(msg: &LikeClickMessage) -> Self {
+        Self {
+            sender: (&msg.sender).into(),
+            like_text: msg.like_text.clone(),
+            timestamp: chrono::Utc::now().timestamp(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SerializableRedPocket {
+    pub lot_id: u64,
+    #[serde(flatten)]
+    pub sender: SerializableSender,
+    /// Danmu viewers must send to join
+    pub danmu: String,
+    /// Total value in yuan
+    pub total_price: f64,
+    pub awards: Vec<RedPocketAward>,
+    pub start_time: i64,
+    pub end_time: i64,
+}
+
+impl From<&RedPocketStartMessage> for SerializableRedPocket {
+    fn from(msg: &RedPocketStartMessage) -> Self {
+        Self {
+            lot_id: msg.lot_id,
+            sender: (&msg.sender).into(),
+            danmu: msg.danmu.clone(),
+            total_price: msg.total_price as f64 / 1000.0,
+            awards: msg.awards.clone(),
+            start_time: msg.start_time,
+            end_time: msg.end_time,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SerializableGoal {
+    pub id: i64,
+    pub room_id: u64,
+    pub title: String,
+    /// Target in yuan
+    pub target: f64,
+    /// Current value in yuan
+    pub progress: f64,
+    /// Progress between 0.0 and 1.0
+    pub ratio: f64,
+    /// Counted record types: "gift", "guard", "superchat"
+    pub sources: Vec<&'static str>,
+    pub start_time: i64,
+    /// Set once the goal is completed, it won't be updated again
+    pub completed_at: Option<i64>,
+}
+
+impl From<&Goal> for SerializableGoal {
+    fn from(goal: &Goal) -> Self {
+        Self {
+            id: goal.id,
+            room_id: goal.room_id,
+            title: goal.title.clone(),
+            target: goal.target as f64 / 1000.0,
+            progress: goal.progress as f64 / 1000.0,
+            ratio: goal.ratio(),
+            sources: goal.sources.iter().map(|kind| kind.as_str()).collect(),
+            start_time: goal.start_time,
+            completed_at: goal.completed_at,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 #[serde(tag = "type", content = "data")]
 pub enum PluginEvent {
@@ -137,16 +400,59 @@ pub enum PluginEvent {
     NewGuard(SerializableGuard),
     NewSuperChat(SerializableSuperChat),
     NewInteract(SerializableInteract),
+    NewEntryEffect(SerializableEntryEffect),
     UpdateRoom {
         room_id: u64,
         title: String,
         live_status: u8,
+        area_id: u64,
+    },
+    RoomChange {
+        title: String,
+        area_name: String,
+        parent_area_name: String,
     },
     UpdateOnline {
         count: u64,
     },
     LiveStart,
     LiveEnd,
+    Warning {
+        msg: String,
+    },
+    CutOff {
+        msg: String,
+    },
+    ConnectionStatus {
+        connected: bool,
+    },
+    NewLikeClick(SerializableLikeClick),
+    UpdateLikeCount {
+        count: u64,
+    },
+    UpdateWatched {
+        count: u64,
+        text: String,
+    },
+    RedPocketStart(SerializableRedPocket),
+    RedPocketWinners {
+        lot_id: u64,
+        winners: Vec<LotteryWinner>,
+    },
+    AnchorLotStart(AnchorLotStartMessage),
+    AnchorLotAward(AnchorLotAwardMessage),
+    SuperChatDeleted {
+        ids: Vec<String>,
+    },
+    RoomSilent(RoomSilentMessage),
+    UserBlocked(RoomBlockMessage),
+    GoalProgress(SerializableGoal),
+    /// Body of a WebSocket message without a typed event, only delivered to
+    /// clients that subscribe to "raw"
+    Raw {
+        cmd: String,
+        body: serde_json::Value,
+    },
 }
 
 impl PluginEvent {
@@ -157,13 +463,39 @@ impl PluginEvent {
             PluginEvent::NewGuard(_) => "new_guard",
             PluginEvent::NewSuperChat(_) => "new_superchat",
             PluginEvent::NewInteract(_) => "new_interact",
+            PluginEvent::NewEntryEffect(_) => "new_entry_effect",
             PluginEvent::UpdateRoom { .. } => "update_room",
+            PluginEvent::RoomChange { .. } => "room_change",
             PluginEvent::UpdateOnline { .. } => "update_online",
             PluginEvent::LiveStart => "live_start",
             PluginEvent::LiveEnd => "live_end",
+            PluginEvent::Warning { .. } => "warning",
+            PluginEvent::CutOff { .. } => "cut_off",
+            PluginEvent::ConnectionStatus { .. } => "connection_status",
+            PluginEvent::NewLikeClick(_) => "new_like_click",
+            PluginEvent::UpdateLikeCount { .. } => "update_like_count",
+            PluginEvent::UpdateWatched { .. } => "update_watched",
+            PluginEvent::RedPocketStart(_) => "red_pocket_start",
+            PluginEvent::RedPocketWinners { .. } => "red_pocket_winners",
+            PluginEvent::AnchorLotStart(_) => "anchor_lot_start",
+            PluginEvent::AnchorLotAward(_) => "anchor_lot_award",
+            PluginEvent::SuperChatDeleted { .. } => "superchat_deleted",
+            PluginEvent::RoomSilent(_) => "room_silent",
+            PluginEvent::UserBlocked(_) => "user_blocked",
+            PluginEvent::GoalProgress(_) => "goal_progress",
+            PluginEvent::Raw { .. } => "raw",
         }
     }
 
+    /// Check whether a subscription name refers to this event's channel
+    /// The raw channel is opt-in and not covered by "*"
+    pub fn matches_channel(&self, name: &str) -> bool {
+        if name == "*" && matches!(self, PluginEvent::Raw { .. }) {
+            return false;
+        }
+        channel_matches(name, self.channel())
+    }
+
     /// Convert a core Event to a PluginEvent if applicable
     pub fn from_core_event(event: &Event) -> Option<Self> {
         match event {
@@ -172,15 +504,114 @@ impl PluginEvent {
             Event::NewGuard(msg) => Some(PluginEvent::NewGuard(msg.into())),
             Event::NewSuperChat(msg) => Some(PluginEvent::NewSuperChat(msg.into())),
             Event::NewInteract(msg) => Some(PluginEvent::NewInteract(msg.into())),
-            Event::UpdateRoom { room_id, title, live_status, .. } => Some(PluginEvent::UpdateRoom {
+            Event::NewEntryEffect(msg) => Some(PluginEvent::NewEntryEffect(msg.into())),
+            Event::UpdateRoom {
+                room_id,
+                title,
+                live_status,
+                area_id,
+            } => Some(PluginEvent::UpdateRoom {
                 room_id: room_id.real_id(),
                 title: title.clone(),
                 live_status: *live_status,
+                area_id: *area_id,
+            }),
+            Event::RoomChange(msg) => Some(PluginEvent::RoomChange {
+                title: msg.title.clone(),
+                area_name: msg.area_name.clone(),
+                parent_area_name: msg.parent_area_name.clone(),
             }),
             Event::UpdateOnline { count } => Some(PluginEvent::UpdateOnline { count: *count }),
             Event::LiveStart => Some(PluginEvent::LiveStart),
             Event::LiveEnd => Some(PluginEvent::LiveEnd),
+            Event::Warning(msg) => Some(PluginEvent::Warning {
+                msg: msg.msg.clone(),
+            }),
+            Event::CutOff(msg) => Some(PluginEvent::CutOff {
+                msg: msg.msg.clone(),
+            }),
+            Event::ConnectionStatus { connected } => Some(PluginEvent::ConnectionStatus {
+                connected: *connected,
+            }),
+            Event::NewLikeClick(msg) => Some(PluginEvent::NewLikeClick(msg.into())),
+            Event::UpdateLikeCount { count } => {
+                Some(PluginEvent::UpdateLikeCount { count: *count })
+            }
+            Event::UpdateWatched { count, text } => Some(PluginEvent::UpdateWatched {
+                count: *count,
+                text: text.clone(),
+            }),
+            Event::RedPocketStart(msg) => Some(PluginEvent::RedPocketStart(msg.into())),
+            Event::RedPocketWinners(msg) => Some(PluginEvent::RedPocketWinners {
+                lot_id: msg.lot_id,
+                winners: msg.winners.clone(),
+            }),
+            Event::AnchorLotStart(msg) => Some(PluginEvent::AnchorLotStart(msg.clone())),
+            Event::AnchorLotAward(msg) => Some(PluginEvent::AnchorLotAward(msg.clone())),
+            Event::SuperChatDeleted { ids } => {
+                Some(PluginEvent::SuperChatDeleted { ids: ids.clone() })
+            }
+            Event::RoomSilent(msg) => Some(PluginEvent::RoomSilent(msg.clone())),
+            Event::UserBlocked(msg) => Some(PluginEvent::UserBlocked(msg.clone())),
+            Event::GoalProgress(goal) => Some(PluginEvent::GoalProgress(goal.into())),
             _ => None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jlivertool_core::messages::GiftInfo;
+
+    #[test]
+    fn test_gift_price_keeps_fraction_and_v1_fields() {
+        let gift = GiftMessage {
+            id: "g1".to_string(),
+            room: 1,
+            gift_info: GiftInfo {
+                id: 31036,
+                name: "小花花".to_string(),
+                price: 100,
+                coin_type: "gold".to_string(),
+                img_basic: String::new(),
+                img_dynamic: String::new(),
+                gif: String::new(),
+                webp: String::new(),
+            },
+            sender: Sender {
+                uid: 42,
+                uname: "viewer".to_string(),
+                ..Default::default()
+            },
+            action: "投喂".to_string(),
+            num: 3,
+            timestamp: 1_700_000_000,
+            archived: false,
+        };
+
+        let json = serde_json::to_value(PluginEvent::NewGift((&gift).into())).unwrap();
+        assert_eq!(json["type"], "NewGift");
+        assert_eq!(json["data"]["uid"], 42);
+        assert_eq!(json["data"]["uname"], "viewer");
+        assert_eq!(json["data"]["price"], 0);
+        assert!((json["data"]["price_yuan"].as_f64().unwrap() - 0.3).abs() < 1e-9);
+        assert!((json["data"]["unit_price"].as_f64().unwrap() - 0.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_matches_channel() {
+        let event = PluginEvent::LiveStart;
+        assert!(event.matches_channel("*"));
+        assert!(event.matches_channel("live_start"));
+        assert!(event.matches_channel("LiveStart"));
+        assert!(!event.matches_channel("live_end"));
+
+        let raw = PluginEvent::Raw {
+            cmd: "LIKE_INFO_V3_CLICK".to_string(),
+            body: serde_json::Value::Null,
+        };
+        assert!(!raw.matches_channel("*"));
+        assert!(raw.matches_channel("raw"));
+    }
+}
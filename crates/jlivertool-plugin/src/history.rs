@@ -0,0 +1,84 @@
+//! Historical event replay for plugins
+//!
+//! Rebuilds plugin events from the database so plugins opened mid-stream
+//! can restore their state.
+
+use anyhow::Result;
+use jlivertool_core::database::Database;
+use serde::Deserialize;
+
+use crate::events::{channel_matches, PluginEvent};
+
+/// Default number of events returned by a history query
+pub const DEFAULT_HISTORY_LIMIT: usize = 200;
+/// Maximum number of events returned by a history query
+pub const MAX_HISTORY_LIMIT: usize = 5000;
+
+/// Channels that can be answered from the database
+pub const HISTORY_CHANNELS: [&str; 4] = ["new_danmu", "new_gift", "new_guard", "new_superchat"];
+
+/// History query parameters
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct HistoryQuery {
+    /// Channels to include, empty or "*" means all history channels
+    #[serde(default)]
+    pub channels: Vec<String>,
+    /// Unix timestamp (seconds), only events at or after it are returned
+    #[serde(default)]
+    pub since: Option<i64>,
+    /// Maximum number of events, the latest ones are kept
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// Room to query, defaults to the current room
+    #[serde(default)]
+    pub room_id: Option<u64>,
+}
+
+impl HistoryQuery {
+    fn wants(&self, channel: &str) -> bool {
+        self.channels.is_empty()
+            || self
+                .channels
+                .iter()
+                .any(|c| channel_matches(c, channel))
+    }
+}
+
+/// Load history events in chronological order
+pub fn load_history(database: &Database, room_id: u64, query: &HistoryQuery) -> Result<Vec<PluginEvent>> {
+    let since = query.since.unwrap_or(0);
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_HISTORY_LIMIT)
+        .min(MAX_HISTORY_LIMIT);
+    // Messages still waiting for the writer belong to the history too
+    database.flush();
+
+    let mut events: Vec<(i64, PluginEvent)> = Vec::new();
+    if query.wants("new_danmu") {
+        for (timestamp, danmu) in database.get_timed_danmus_since(room_id, since, limit)? {
+            let event = PluginEvent::NewDanmu((&danmu).into());
+            events.push((timestamp, event));
+        }
+    }
+    if query.wants("new_gift") {
+        for gift in database.get_gifts_since(room_id, since, limit)? {
+            events.push((gift.timestamp, PluginEvent::NewGift((&gift).into())));
+        }
+    }
+    if query.wants("new_guard") {
+        for guard in database.get_guards_since(room_id, since, limit)? {
+            events.push((guard.timestamp, PluginEvent::NewGuard((&guard).into())));
+        }
+    }
+    if query.wants("new_superchat") {
+        for sc in database.get_superchats_since(room_id, since, limit)? {
+            events.push((sc.timestamp, PluginEvent::NewSuperChat((&sc).into())));
+        }
+    }
+
+    // Stable sort keeps per-table order for events in the same second
+    events.sort_by_key(|(timestamp, _)| *timestamp);
+    let skip = events.len().saturating_sub(limit);
+    Ok(events.into_iter().skip(skip).map(|(_, event)| event).collect())
+}
@@ -1,8 +1,10 @@
 //! HTTP server for serving plugin files
 //!
-//! Serves plugin files from the plugins directory and the jliver-api.js script.
-//! Automatically injects the jliver-api.js script into HTML files.
+//! Serves plugin files from the plugins directory, the jliver-api.js script,
+//! the built-in overlays and the Prometheus metrics. Automatically injects the
+//! jliver-api.js script into HTML files.
 
+use crate::overlay::render_overlay_page;
 use anyhow::Result;
 use axum::{
     body::Body,
@@ -11,23 +13,31 @@ use axum::{
     routing::get,
     Router,
 };
+use jlivertool_core::filter::DisplayFilter;
+use jlivertool_core::metrics::{Metrics, METRICS_CONTENT_TYPE};
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use tokio::net::TcpListener;
 use tower_http::cors::{Any, CorsLayer};
 
 /// The jliver-api.js script content (embedded at compile time)
-const JLIVER_API_JS: &str = include_str!("jliver-api.js");
+pub(crate) const JLIVER_API_JS: &str = include_str!("jliver-api.js");
 
 /// Shared state for the HTTP server
 #[derive(Clone)]
 struct ServerState {
     plugins_dir: PathBuf,
+    ws_port: u16,
+    display_filter: Arc<RwLock<DisplayFilter>>,
+    metrics: Option<Arc<Metrics>>,
 }
 
 /// HTTP server for serving plugin files
 pub struct PluginHttpServer {
     port: u16,
+    ws_port: u16,
+    display_filter: Arc<RwLock<DisplayFilter>>,
+    metrics: Option<Arc<Metrics>>,
     shutdown_tx: Option<tokio::sync::oneshot::Sender<()>>,
 }
 
@@ -36,10 +46,28 @@ impl PluginHttpServer {
     pub fn new() -> Self {
         Self {
             port: 0,
+            ws_port: 0,
+            display_filter: Arc::new(RwLock::new(DisplayFilter::default())),
+            metrics: None,
             shutdown_tx: None,
         }
     }
 
+    /// Set the WebSocket port the overlay pages connect to
+    pub fn set_ws_port(&mut self, port: u16) {
+        self.ws_port = port;
+    }
+
+    /// Set the display filter served to the overlay pages
+    pub fn set_display_filter(&mut self, filter: Arc<RwLock<DisplayFilter>>) {
+        self.display_filter = filter;
+    }
+
+    /// Set the metrics served at /metrics
+    pub fn set_metrics(&mut self, metrics: Arc<Metrics>) {
+        self.metrics = Some(metrics);
+    }
+
     /// Start the HTTP server
     /// If port is 0, a random available port will be used
     pub async fn start(&mut self, plugins_dir: PathBuf) -> Result<u16> {
@@ -63,7 +91,12 @@ impl PluginHttpServer {
         log::info!("Serving plugins from: {:?}", plugins_dir);
 
         // Create shared state
-        let state = Arc::new(ServerState { plugins_dir });
+        let state = Arc::new(ServerState {
+            plugins_dir,
+            ws_port: self.ws_port,
+            display_filter: self.display_filter.clone(),
+            metrics: self.metrics.clone(),
+        });
 
         // Configure CORS to allow WebSocket connections from plugins
         let cors = CorsLayer::new()
@@ -74,6 +107,9 @@ impl PluginHttpServer {
         // Build router
         let app = Router::new()
             .route("/jliver-api.js", get(serve_api_script))
+            .route("/metrics", get(serve_metrics))
+            .route("/overlay/filter", get(serve_overlay_filter))
+            .route("/overlay/{kind}", get(serve_overlay))
             .route("/{plugin_id}/{*path}", get(serve_plugin_file))
             .layer(cors)
             .with_state(state);
@@ -124,6 +160,55 @@ async fn serve_api_script() -> Response<Body> {
         .unwrap()
 }
 
+/// Serve a built-in overlay page
+async fn serve_overlay(
+    State(state): State<Arc<ServerState>>,
+    Path(kind): Path<String>,
+) -> Response<Body> {
+    match render_overlay_page(&kind, state.ws_port) {
+        Some(page) => Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+            .header(header::CACHE_CONTROL, "no-cache")
+            .body(Body::from(page))
+            .unwrap(),
+        None => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::from("Overlay not found"))
+            .unwrap(),
+    }
+}
+
+/// Serve the current display filter as JSON
+async fn serve_overlay_filter(State(state): State<Arc<ServerState>>) -> Response<Body> {
+    let filter = *state
+        .display_filter
+        .read()
+        .unwrap_or_else(|e| e.into_inner());
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .body(Body::from(serde_json::to_string(&filter).unwrap_or_default()))
+        .unwrap()
+}
+
+/// Serve the metrics in the Prometheus text format
+async fn serve_metrics(State(state): State<Arc<ServerState>>) -> Response<Body> {
+    match state.metrics {
+        Some(ref metrics) => Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, METRICS_CONTENT_TYPE)
+            .header(header::CACHE_CONTROL, "no-cache")
+            .body(Body::from(metrics.render()))
+            .unwrap(),
+        None => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::from("Metrics are not available"))
+            .unwrap(),
+    }
+}
+
 /// Serve a plugin file
 async fn serve_plugin_file(
     State(state): State<Arc<ServerState>>,
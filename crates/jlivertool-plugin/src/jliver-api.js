@@ -4,9 +4,11 @@
 (function() {
     'use strict';
 
-    // Get WebSocket port from URL parameters
+    // Get WebSocket port from URL parameters, built-in overlays set it on the page
     const urlParams = new URLSearchParams(window.location.search);
-    const wsPort = urlParams.get('ws_port');
+    const wsPort = urlParams.get('ws_port') || window.JLIVER_WS_PORT;
+    // Plugin id scopes persistent storage; fall back to the plugin folder name
+    const pluginId = urlParams.get('plugin_id') || window.location.pathname.split('/')[1];
 
     if (!wsPort) {
         console.error('JLiverTool: No WebSocket port provided');
@@ -19,6 +21,16 @@
     let requestId = 0;
     const pendingRequests = new Map();
     const eventCallbacks = new Map();
+    const settingsCallbacks = [];
+    // Subscriptions are re-sent on every (re)connect; replay only happens once
+    const subscriptions = [];
+    let currentSettings = {};
+    let schemaVersion = null;
+
+    // 'new_danmu', 'NewDanmu' and 'newdanmu' all name the same channel
+    function channelKey(name) {
+        return name === '*' ? name : name.replace(/_/g, '').toLowerCase();
+    }
 
     // Connect to WebSocket server
     function connect() {
@@ -34,6 +46,9 @@
                 clearTimeout(reconnectTimer);
                 reconnectTimer = null;
             }
+            // Identify this plugin so the server can deliver its settings
+            sendMessage({ type: 'Hello', plugin_id: pluginId });
+            subscriptions.forEach(sendSubscription);
         };
 
         ws.onmessage = function(event) {
@@ -67,24 +82,36 @@
     function handleMessage(msg) {
         switch (msg.type) {
             case 'Welcome':
-                console.log('JLiverTool: Server welcome, port:', msg.port);
+                // Servers before schema versioning did not send schema_version
+                schemaVersion = msg.schema_version || 1;
+                console.log('JLiverTool: Server welcome, port:', msg.port, 'schema:', schemaVersion);
                 break;
 
             case 'Event':
                 // Dispatch event to registered callbacks
-                const eventType = msg.Event.type.toLowerCase();
-                const callbacks = eventCallbacks.get(eventType) || [];
+                const callbacks = eventCallbacks.get(channelKey(msg.Event.type)) || [];
                 const allCallbacks = eventCallbacks.get('*') || [];
 
                 [...callbacks, ...allCallbacks].forEach(cb => {
                     try {
-                        cb(msg.Event);
+                        cb(msg.Event, { replay: !!msg.replay });
                     } catch (e) {
                         console.error('JLiverTool: Event callback error', e);
                     }
                 });
                 break;
 
+            case 'Settings':
+                currentSettings = msg.values || {};
+                settingsCallbacks.forEach(cb => {
+                    try {
+                        cb(currentSettings);
+                    } catch (e) {
+                        console.error('JLiverTool: Settings callback error', e);
+                    }
+                });
+                break;
+
             case 'Response':
                 // Resolve pending request
                 const resolver = pendingRequests.get(msg.id);
@@ -117,6 +144,11 @@
         }
     }
 
+    function sendSubscription(sub) {
+        sendMessage(Object.assign({ type: 'Subscribe' }, sub));
+        sub.replay = false;
+    }
+
     function request(method, params) {
         return new Promise((resolve, reject) => {
             const id = String(++requestId);
@@ -141,26 +173,46 @@
 
     // Public API
     window.jliverAPI = {
+        // Plugin event schema version reported by the server, null until connected
+        get schemaVersion() {
+            return schemaVersion;
+        },
+
         // Register event callback
         // Channels: 'new_danmu', 'new_gift', 'new_guard', 'new_superchat',
-        //           'new_interact', 'update_room', 'update_online', 'live_start', 'live_end'
-        // Use '*' to receive all events
-        register: function(channel, callback) {
-            const channelLower = channel.toLowerCase();
-            if (!eventCallbacks.has(channelLower)) {
-                eventCallbacks.set(channelLower, []);
+        //           'new_interact', 'new_entry_effect', 'update_room', 'room_change',
+        //           'update_online', 'live_start', 'live_end', 'warning', 'cut_off',
+        //           'connection_status', 'new_like_click', 'update_like_count',
+        //           'update_watched', 'red_pocket_start', 'red_pocket_winners',
+        //           'anchor_lot_start', 'anchor_lot_award', 'superchat_deleted',
+        //           'room_silent', 'user_blocked', 'goal_progress'
+        // Type names such as 'NewDanmu' are accepted too. Use '*' to receive all events
+        // 'raw' delivers every WebSocket message body and must be registered explicitly
+        // Options: { replay: true, since: <unix seconds>, limit: <n> } sends stored
+        // events of the channel first, with the callback's second argument { replay: true }
+        register: function(channel, callback, options) {
+            const key = channelKey(channel);
+            if (!eventCallbacks.has(key)) {
+                eventCallbacks.set(key, []);
             }
-            eventCallbacks.get(channelLower).push(callback);
+            eventCallbacks.get(key).push(callback);
 
-            // Subscribe on server
-            sendMessage({
-                type: 'Subscribe',
-                channels: [channelLower]
-            });
+            // Subscribe on server, or on connect if not connected yet
+            const opts = options || {};
+            const sub = {
+                channels: [key],
+                replay: !!opts.replay,
+                since: opts.since,
+                limit: opts.limit
+            };
+            subscriptions.push(sub);
+            if (ws && ws.readyState === WebSocket.OPEN) {
+                sendSubscription(sub);
+            }
 
             // Return unregister function
             return function unregister() {
-                const callbacks = eventCallbacks.get(channelLower);
+                const callbacks = eventCallbacks.get(key);
                 if (callbacks) {
                     const idx = callbacks.indexOf(callback);
                     if (idx >= 0) {
@@ -180,6 +232,54 @@
             }
         },
 
+        // Query stored events (new_danmu, new_gift, new_guard, new_superchat)
+        // Options: { channels, since: <unix seconds>, limit, room_id }
+        getHistory: function(options) {
+            return request('getHistory', options || {}).then(res => res.events);
+        },
+
+        // Active gift goals of the current room, same fields as goal_progress events
+        getGoals: function() {
+            return request('getGoals', {}).then(res => res.goals);
+        },
+
+        // Persistent key-value storage, scoped to this plugin
+        storage: {
+            get: function(key) {
+                return request('storage.get', { plugin_id: pluginId, key: key })
+                    .then(res => res.value);
+            },
+            set: function(key, value) {
+                return request('storage.set', { plugin_id: pluginId, key: key, value: value });
+            },
+            list: function() {
+                return request('storage.list', { plugin_id: pluginId })
+                    .then(res => res.keys);
+            },
+            delete: function(key) {
+                return request('storage.delete', { plugin_id: pluginId, key: key })
+                    .then(res => res.existed);
+            }
+        },
+
+        // Plugin settings declared in meta.json
+        settings: {
+            // Latest values received from the server
+            get: function() {
+                return currentSettings;
+            },
+            // Called on connect and whenever the user changes settings
+            onChange: function(callback) {
+                settingsCallbacks.push(callback);
+                return function unregister() {
+                    const idx = settingsCallbacks.indexOf(callback);
+                    if (idx >= 0) {
+                        settingsCallbacks.splice(idx, 1);
+                    }
+                };
+            }
+        },
+
         // Connection status
         isConnected: function() {
             return ws && ws.readyState === WebSocket.OPEN;
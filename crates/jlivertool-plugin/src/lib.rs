@@ -1,12 +1,16 @@
 pub mod events;
+pub mod history;
 pub mod http_server;
 pub mod ipc;
 pub mod manager;
+pub mod overlay;
 pub mod plugin;
+pub mod storage;
 pub mod ws_server;
 
 pub use events::PluginEvent;
 pub use http_server::PluginHttpServer;
 pub use manager::PluginManager;
 pub use plugin::{Plugin, PluginMeta, PluginState};
+pub use storage::PluginStorage;
 pub use ws_server::PluginWsServer;
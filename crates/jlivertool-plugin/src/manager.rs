@@ -1,14 +1,19 @@
 use anyhow::{Context, Result};
+use jlivertool_core::database::Database;
+use jlivertool_core::filter::DisplayFilter;
+use jlivertool_core::metrics::Metrics;
 use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
 use std::sync::{Arc, RwLock};
 use tokio::sync::broadcast;
 
 use crate::events::PluginEvent;
 use crate::http_server::PluginHttpServer;
 use crate::plugin::{Plugin, PluginMeta, PluginState};
-use crate::ws_server::PluginWsServer;
+use crate::storage::PluginStorage;
+use crate::ws_server::{PluginWsServer, SettingsUpdate};
 
 /// GitHub API response for repository contents
 #[derive(Debug, serde::Deserialize)]
@@ -26,6 +31,10 @@ pub struct PluginManager {
     ws_server: Option<PluginWsServer>,
     http_server: Option<PluginHttpServer>,
     plugins_dir: Option<PathBuf>,
+    storage: Option<Arc<PluginStorage>>,
+    database: Option<Database>,
+    display_filter: Arc<RwLock<DisplayFilter>>,
+    metrics: Option<Arc<Metrics>>,
 }
 
 impl PluginManager {
@@ -35,9 +44,43 @@ impl PluginManager {
             ws_server: None,
             http_server: None,
             plugins_dir: None,
+            storage: None,
+            database: None,
+            display_filter: Arc::new(RwLock::new(DisplayFilter::default())),
+            metrics: None,
         }
     }
 
+    /// Set the database used to answer plugin history requests
+    /// Must be called before the WebSocket server is started
+    pub fn set_database(&mut self, database: Database) {
+        self.database = Some(database);
+    }
+
+    /// Set the directory used for per-plugin persistent storage
+    /// Must be called before the WebSocket server is started
+    pub fn set_storage_dir(&mut self, dir: PathBuf) {
+        self.storage = Some(Arc::new(PluginStorage::new(dir)));
+    }
+
+    /// Set the metrics served by the HTTP server, the number of plugin clients
+    /// is added to them. Must be called before the servers are started
+    pub fn set_metrics(&mut self, metrics: Arc<Metrics>) {
+        self.metrics = Some(metrics);
+    }
+
+    /// Update the display filter applied by the built-in overlays
+    pub fn set_display_filter(&self, filter: DisplayFilter) {
+        if let Ok(mut current) = self.display_filter.write() {
+            *current = filter;
+        }
+    }
+
+    /// Get the plugin storage (if configured)
+    pub fn storage(&self) -> Option<Arc<PluginStorage>> {
+        self.storage.clone()
+    }
+
     /// Get the WebSocket server (if started)
     pub fn ws_server(&self) -> Option<&PluginWsServer> {
         self.ws_server.as_ref()
@@ -57,7 +100,22 @@ impl PluginManager {
     /// If port is 0, a random available port will be used
     pub async fn start_ws_server_on_port(&mut self, port: u16) -> Result<u16> {
         let mut server = PluginWsServer::new();
+        if let Some(ref storage) = self.storage {
+            server.set_storage(storage.clone());
+        }
+        server.set_plugins(self.plugins.clone());
+        if let Some(ref database) = self.database {
+            server.set_database(database.clone());
+        }
         server.start_on_port(port).await?;
+        if let Some(ref metrics) = self.metrics {
+            let clients = server.client_counter();
+            metrics.register_gauge(
+                "jlivertool_plugin_clients",
+                "Connected plugin and overlay clients",
+                move || clients.load(std::sync::atomic::Ordering::Relaxed) as f64,
+            );
+        }
         let actual_port = server.port();
         self.ws_server = Some(server);
         Ok(actual_port)
@@ -80,6 +138,14 @@ impl PluginManager {
     pub async fn start_http_server_on_port(&mut self, plugins_dir: PathBuf, port: u16) -> Result<u16> {
         self.plugins_dir = Some(plugins_dir.clone());
         let mut server = PluginHttpServer::new();
+        server.set_display_filter(self.display_filter.clone());
+        if let Some(ref metrics) = self.metrics {
+            server.set_metrics(metrics.clone());
+        }
+        // Overlay pages connect to the WebSocket server, start it first
+        if let Some(ref ws_server) = self.ws_server {
+            server.set_ws_port(ws_server.port());
+        }
         let actual_port = server.start_on_port(plugins_dir, port).await?;
         self.http_server = Some(server);
         Ok(actual_port)
@@ -115,6 +181,12 @@ impl PluginManager {
         self.ws_server.as_ref().map(|s| s.event_sender())
     }
 
+    /// Get the counter of clients subscribed to raw WebSocket messages
+    /// Returns None if the WebSocket server hasn't been started
+    pub fn get_raw_subscriber_counter(&self) -> Option<Arc<AtomicUsize>> {
+        self.ws_server.as_ref().map(|s| s.raw_subscriber_counter())
+    }
+
     pub fn load_plugin(&self, path: PathBuf) -> Result<String> {
         let meta_path = path.join("meta.json");
         let meta_content = fs::read_to_string(&meta_path)
@@ -177,16 +249,63 @@ impl PluginManager {
         self.plugins.read().unwrap().get(plugin_id).cloned()
     }
 
+    /// Get the effective settings values of a plugin
+    pub fn get_plugin_settings(&self, plugin_id: &str) -> serde_json::Map<String, serde_json::Value> {
+        let schema = match self.get_plugin(plugin_id) {
+            Some(plugin) => plugin.meta.settings,
+            None => return serde_json::Map::new(),
+        };
+        match self.storage {
+            Some(ref storage) => storage.get_settings(plugin_id, &schema).unwrap_or_else(|e| {
+                log::warn!("Failed to load settings for plugin {}: {}", plugin_id, e);
+                jlivertool_core::types::resolve_plugin_settings(&schema, &serde_json::Map::new())
+            }),
+            None => jlivertool_core::types::resolve_plugin_settings(&schema, &serde_json::Map::new()),
+        }
+    }
+
+    /// Save settings values of a plugin and push them to its connections
+    /// Returns the effective settings after the update
+    pub fn set_plugin_settings(
+        &self,
+        plugin_id: &str,
+        values: &serde_json::Map<String, serde_json::Value>,
+    ) -> Result<serde_json::Map<String, serde_json::Value>> {
+        let plugin = self
+            .get_plugin(plugin_id)
+            .ok_or_else(|| anyhow::anyhow!("Plugin not found: {}", plugin_id))?;
+        let storage = self
+            .storage
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Plugin storage is not configured"))?;
+        let values = storage.set_settings(plugin_id, &plugin.meta.settings, values)?;
+
+        if let Some(ref server) = self.ws_server {
+            server.notify_settings_changed(SettingsUpdate {
+                plugin_id: plugin_id.to_string(),
+                values: values.clone(),
+            });
+        }
+        Ok(values)
+    }
+
     /// Clear all loaded plugins
     pub fn clear_plugins(&self) {
         self.plugins.write().unwrap().clear();
     }
 
-    /// Remove a plugin by ID and delete its directory
+    /// Remove a plugin by ID and delete its directory and stored data
     pub fn remove_plugin(&self, plugin_id: &str, plugin_path: &PathBuf) -> Result<()> {
         // Remove from loaded plugins
         self.plugins.write().unwrap().remove(plugin_id);
 
+        // Delete the plugin's persistent storage
+        if let Some(ref storage) = self.storage {
+            if let Err(e) = storage.remove_namespace(plugin_id) {
+                log::warn!("Failed to remove storage for plugin {}: {}", plugin_id, e);
+            }
+        }
+
         // Delete the plugin directory
         if plugin_path.exists() && plugin_path.is_dir() {
             fs::remove_dir_all(plugin_path)?;
@@ -274,7 +393,7 @@ impl PluginManager {
         let mut path = String::new();
         let mut found = false;
 
-        let client = reqwest::Client::new();
+        let client = jlivertool_core::network::client_builder()?.build()?;
 
         // Try each possible split point
         for i in 1..=branch_path_parts.len() {
@@ -360,7 +479,7 @@ impl PluginManager {
 
         log::debug!("Fetching GitHub contents: {}", api_url);
 
-        let client = reqwest::Client::new();
+        let client = jlivertool_core::network::client_builder()?.build()?;
         let response = client
             .get(&api_url)
             .header("User-Agent", "JLiverTool")
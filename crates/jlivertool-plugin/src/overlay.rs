@@ -0,0 +1,47 @@
+//! Built-in browser source overlays
+//!
+//! The HTTP server serves `/overlay/danmu`, `/overlay/gifts`, `/overlay/superchat`
+//! and `/overlay/goal` as transparent pages for OBS browser sources. Each page
+//! embeds jliver-api.js and receives events like any plugin. Colors follow the
+//! app themes and can be overridden with query parameters named after the
+//! `ThemeColors` fields, see `overlay/overlay.js`. Messages are filtered with the
+//! main view's `DisplayFilter`, served at `/overlay/filter`.
+
+use crate::http_server::JLIVER_API_JS;
+
+const OVERLAY_HTML: &str = include_str!("overlay/overlay.html");
+const OVERLAY_CSS: &str = include_str!("overlay/overlay.css");
+const OVERLAY_JS: &str = include_str!("overlay/overlay.js");
+
+/// Overlay pages served under `/overlay/`
+pub const OVERLAY_KINDS: [&str; 4] = ["danmu", "gifts", "superchat", "goal"];
+
+/// Build the page of an overlay, None for unknown kinds
+pub fn render_overlay_page(kind: &str, ws_port: u16) -> Option<String> {
+    if !OVERLAY_KINDS.contains(&kind) {
+        return None;
+    }
+    Some(
+        OVERLAY_HTML
+            .replace("{{css}}", OVERLAY_CSS)
+            .replace("{{api}}", JLIVER_API_JS)
+            .replace("{{overlay}}", OVERLAY_JS)
+            .replace("{{ws_port}}", &ws_port.to_string())
+            .replace("{{kind}}", kind),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_overlay_page() {
+        let page = render_overlay_page("superchat", 8081).unwrap();
+        assert!(page.contains("window.JLIVER_WS_PORT = 8081;"));
+        assert!(page.contains("window.JLIVER_OVERLAY = 'superchat';"));
+        assert!(page.contains("window.jliverAPI"));
+        assert!(!page.contains("{{"));
+        assert!(render_overlay_page("../danmu", 8081).is_none());
+    }
+}
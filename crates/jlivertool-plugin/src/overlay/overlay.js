@@ -0,0 +1,357 @@
+// JLiverTool built-in overlays
+// Served by the plugin HTTP server at /overlay/<kind>, events arrive through jliverAPI
+//
+// Query parameters:
+//   theme          light, dark, dracula, catppuccin, blueberry, ayu-light, ayu-dark
+//   main_bg, gift_bg, border, uname_color, font_color, live_color, danmu_special_bg
+//                  Override a theme color, hex without '#' (e.g. ff871e) or any CSS color
+//   font_size      Font size in px (default 18)
+//   font_family    CSS font family
+//   max            Maximum number of items kept on screen
+//   fade           Seconds after which items fade out, 0 keeps them
+//   min_price      gifts: minimum price in yuan, free gifts are hidden unless 0
+//   goal           goal: id of a goal created in the app, or 'active' for the oldest one
+//   title, target, since, sources
+//                  goal without 'goal': label, target in yuan, unix start time
+//                  (default: page load) and comma separated gift,guard,superchat sources
+
+(function() {
+    'use strict';
+
+    const kind = window.JLIVER_OVERLAY;
+    const params = new URLSearchParams(window.location.search);
+    const root = document.getElementById('overlay');
+    const now = () => Math.floor(Date.now() / 1000);
+
+    // Mirrors ThemeColors in jlivertool-ui/src/theme.rs; main_bg defaults to transparent
+    const THEMES = {
+        'light': { gift_bg: '#ffffff', border: 'rgba(179, 179, 179, 0.3)', uname_color: '#3b82f6', font_color: '#2e384d', live_color: '#ed3b6b', danmu_special_bg: 'rgb(229, 241, 249)' },
+        'dark': { gift_bg: 'rgb(20, 20, 20)', border: 'rgba(255, 255, 255, 0.31)', uname_color: '#999999', font_color: '#e8e8e8', live_color: '#ed3b6b', danmu_special_bg: 'rgb(20, 31, 39)' },
+        'dracula': { gift_bg: 'rgb(33, 34, 41)', border: '#5d6d98', uname_color: '#ac8be0', font_color: '#eaece9', live_color: '#f981c6', danmu_special_bg: 'rgb(20, 31, 39)' },
+        'catppuccin': { gift_bg: 'rgb(54, 59, 73)', border: '#5d6d98', uname_color: '#a6d189', font_color: '#eaece9', live_color: '#ef9f76', danmu_special_bg: 'rgb(20, 31, 39)' },
+        'blueberry': { gift_bg: 'rgb(30, 37, 51)', border: '#393e5a', uname_color: '#288260', font_color: '#e4ece6', live_color: '#df4577', danmu_special_bg: 'rgb(36, 41, 57)' },
+        'ayu-light': { gift_bg: '#ffffff', border: '#dcdee1', uname_color: '#ff871e', font_color: '#6a7681', live_color: '#d14047', danmu_special_bg: 'rgb(240, 241, 242)' },
+        'ayu-dark': { gift_bg: 'rgb(40, 37, 48)', border: '#393e5a', uname_color: '#ff8922', font_color: '#b3b1ad', live_color: '#c85d55', danmu_special_bg: 'rgb(0, 6, 15)' }
+    };
+    const THEME_KEYS = ['main_bg', 'gift_bg', 'border', 'uname_color', 'font_color', 'live_color', 'danmu_special_bg'];
+
+    // Guard icons, same as the main view
+    const GUARD_ICONS = {
+        1: 'https://i0.hdslb.com/bfs/activity-plat/static/20211222/627754775478985e330c25a90ec7baf0/icon-guard1.png@44w_44h.webp',
+        2: 'https://i0.hdslb.com/bfs/activity-plat/static/20211222/627754775478985e330c25a90ec7baf0/icon-guard2.png@44w_44h.webp',
+        3: 'https://i0.hdslb.com/bfs/activity-plat/static/20211222/627754775478985e330c25a90ec7baf0/icon-guard3.png@44w_44h.webp'
+    };
+    const GUARD_NAMES = { 1: '总督', 2: '提督', 3: '舰长' };
+    const INTERACT_ACTIONS = { 1: '进入直播间', 2: '关注了直播间', 3: '分享了直播间', 4: '特别关注了直播间', 5: '与主播互粉了' };
+
+    function cssColor(value) {
+        return /^[0-9a-fA-F]{3,8}$/.test(value) ? '#' + value : value;
+    }
+
+    function numberParam(name, fallback) {
+        const value = parseFloat(params.get(name));
+        return isNaN(value) ? fallback : value;
+    }
+
+    function applyTheme() {
+        const theme = Object.assign({ main_bg: 'transparent' }, THEMES[params.get('theme')] || THEMES.dark);
+        THEME_KEYS.forEach(key => {
+            if (params.has(key)) {
+                theme[key] = cssColor(params.get(key));
+            }
+        });
+        const style = document.documentElement.style;
+        THEME_KEYS.forEach(key => style.setProperty('--' + key.replace(/_/g, '-'), theme[key]));
+        style.setProperty('--font-size', numberParam('font_size', 18) + 'px');
+        if (params.has('font_family')) {
+            style.setProperty('--font-family', params.get('font_family'));
+        }
+    }
+
+    // Same rules as DisplayFilter in jlivertool-core/src/filter.rs, values come from the app
+    let filter = { medal_display: true, interact_display: false, guard_effect: true, level_effect: false };
+
+    function refreshFilter() {
+        fetch('/overlay/filter')
+            .then(res => res.json())
+            .then(value => { filter = value; })
+            .catch(() => {});
+    }
+
+    const showsDanmu = danmu => !danmu.is_generated;
+    const showsInteract = () => filter.interact_display;
+    const showsEntryEffect = entry => entry.privilege_type >= 1 && entry.privilege_type <= 3
+        ? filter.guard_effect
+        : filter.level_effect;
+    const showsMedal = medal => filter.medal_display && !!medal && !!medal.name && medal.is_lighted;
+
+    // Medal colors by level, same as Colors::medal_colors
+    function medalColors(level) {
+        if (level <= 10) return ['rgba(87, 98, 167, 0.6)', 'rgba(87, 98, 167, 0.6)'];
+        if (level <= 20) return ['rgba(199, 112, 164, 0.6)', 'rgba(199, 112, 164, 0.6)'];
+        if (level <= 30) return ['rgba(63, 180, 246, 0.6)', 'rgba(63, 180, 246, 0.6)'];
+        if (level <= 40) return ['rgba(76, 125, 255, 0.6)', 'rgba(76, 125, 255, 0.6)'];
+        return ['rgba(167, 115, 241, 0.6)', '#d47aff'];
+    }
+
+    function el(tag, className, text) {
+        const node = document.createElement(tag);
+        if (className) node.className = className;
+        if (text !== undefined) node.textContent = text;
+        return node;
+    }
+
+    function guardIcon(level) {
+        if (!GUARD_ICONS[level]) return null;
+        const icon = el('img', 'guard-icon');
+        icon.src = GUARD_ICONS[level];
+        return icon;
+    }
+
+    function medalBadge(medal) {
+        const [bg, border] = medalColors(medal.level);
+        const badge = el('span', 'medal');
+        badge.style.borderColor = border;
+        const name = el('span', 'medal-name');
+        name.style.background = bg;
+        const icon = guardIcon(medal.guard_level);
+        if (icon) name.appendChild(icon);
+        name.appendChild(document.createTextNode(medal.name));
+        const level = el('span', 'medal-level', String(medal.level));
+        level.style.color = bg.replace(/, 0\.6\)$/, ')');
+        badge.appendChild(name);
+        badge.appendChild(level);
+        return badge;
+    }
+
+    // Medal (or guard icon without medal) and user name
+    function senderNodes(sender) {
+        const nodes = [];
+        if (showsMedal(sender.medal)) {
+            nodes.push(medalBadge(sender.medal));
+        } else {
+            const icon = guardIcon(sender.guard_level);
+            if (icon) nodes.push(icon);
+        }
+        nodes.push(el('span', 'uname', sender.uname));
+        return nodes;
+    }
+
+    function row(className, children) {
+        const node = el('div', 'item ' + (className || ''));
+        children.forEach(child => child && node.appendChild(child));
+        return node;
+    }
+
+    // Keep at most `max` items and fade them out after `fade` seconds
+    const maxItems = numberParam('max', 50);
+    const fadeSeconds = numberParam('fade', 0);
+
+    function push(node) {
+        root.appendChild(node);
+        while (root.children.length > maxItems) {
+            root.removeChild(root.firstChild);
+        }
+        if (fadeSeconds > 0) {
+            setTimeout(() => {
+                node.classList.add('fading');
+                setTimeout(() => node.remove(), 500);
+            }, fadeSeconds * 1000);
+        }
+    }
+
+    function danmuNode(danmu) {
+        const nodes = senderNodes(danmu);
+        nodes.push(el('span', 'muted', ':'));
+        if (danmu.emoji) {
+            const img = el('img', 'emoji');
+            img.src = danmu.emoji.url;
+            img.alt = danmu.msg;
+            nodes.push(img);
+        } else {
+            const text = danmu.reply_uname ? '@' + danmu.reply_uname + ' ' + danmu.msg : danmu.msg;
+            nodes.push(el('span', 'content', text));
+        }
+        return row(danmu.is_special ? 'special' : '', nodes);
+    }
+
+    function giftNode(gift) {
+        const nodes = senderNodes(gift);
+        nodes.push(el('span', 'content', `${gift.action} ${gift.gift_name} x${gift.num}`));
+        if (gift.coin_type === 'gold') {
+            nodes.push(el('span', 'price', '¥' + gift.price_yuan.toFixed(1)));
+        }
+        return row('card', nodes);
+    }
+
+    function guardNode(guard) {
+        const nodes = senderNodes(guard);
+        nodes.push(el('span', 'content', `开通了 ${guard.num}${guard.unit}${GUARD_NAMES[guard.guard_level] || ''}`));
+        nodes.push(el('span', 'price', '¥' + guard.price_yuan.toFixed(0)));
+        return row('card', nodes);
+    }
+
+    function superchatNode(sc) {
+        const node = el('div', 'item card superchat');
+        node.dataset.id = sc.id;
+        node.style.background = sc.background_bottom_color || 'var(--gift-bg)';
+        const header = el('div', 'sc-header');
+        header.style.background = sc.background_color || 'var(--gift-bg)';
+        senderNodes(sc).forEach(child => header.appendChild(child));
+        header.appendChild(el('span', 'price', '¥' + sc.price));
+        node.appendChild(header);
+        node.appendChild(el('div', 'sc-body', sc.message));
+        return node;
+    }
+
+    function setupDanmu() {
+        const replay = { replay: true, since: now() - 300, limit: maxItems };
+        jliverAPI.register('new_danmu', event => {
+            if (showsDanmu(event.data)) push(danmuNode(event.data));
+        }, replay);
+        jliverAPI.register('new_gift', event => {
+            if (event.data.coin_type === 'gold') push(giftNode(event.data));
+        });
+        jliverAPI.register('new_guard', event => push(guardNode(event.data)));
+        jliverAPI.register('new_superchat', event => push(superchatNode(event.data)));
+        jliverAPI.register('new_interact', event => {
+            if (!showsInteract()) return;
+            const action = INTERACT_ACTIONS[event.data.msg_type] || '';
+            push(row('muted', senderNodes(event.data).concat([el('span', 'content', action)])));
+        });
+        jliverAPI.register('new_like_click', event => {
+            if (!showsInteract()) return;
+            push(row('muted', senderNodes(event.data).concat([el('span', 'content', event.data.like_text)])));
+        });
+        jliverAPI.register('new_entry_effect', event => {
+            if (!showsEntryEffect(event.data)) return;
+            push(row('muted', [
+                guardIcon(event.data.privilege_type),
+                el('span', 'uname', event.data.uname),
+                el('span', 'content', '进入直播间')
+            ]));
+        });
+    }
+
+    function setupGifts() {
+        const minPrice = numberParam('min_price', 0.1);
+        const replay = { replay: true, since: now() - 600, limit: maxItems };
+        jliverAPI.register('new_gift', event => {
+            const gift = event.data;
+            const paid = gift.coin_type === 'gold';
+            if ((paid && gift.price_yuan >= minPrice) || (!paid && minPrice <= 0)) {
+                push(giftNode(gift));
+            }
+        }, replay);
+        jliverAPI.register('new_guard', event => push(guardNode(event.data)), replay);
+    }
+
+    function setupSuperchat() {
+        // Superchats stay until their end time unless fade is set
+        jliverAPI.register('new_superchat', event => {
+            const sc = event.data;
+            if (sc.end_time && sc.end_time <= now()) return;
+            const node = superchatNode(sc);
+            push(node);
+            if (fadeSeconds <= 0 && sc.end_time) {
+                setTimeout(() => node.remove(), (sc.end_time - now()) * 1000);
+            }
+        }, { replay: true, since: now() - 3600, limit: maxItems });
+        jliverAPI.register('superchat_deleted', event => {
+            event.data.ids.forEach(id => {
+                const node = root.querySelector(`[data-id="${CSS.escape(id)}"]`);
+                if (node) node.remove();
+            });
+        });
+    }
+
+    function setupGoal() {
+        const goal = el('div', 'goal');
+        const header = el('div', 'goal-title');
+        const label = el('span', '', params.get('title') || '目标');
+        const amount = el('span', 'price');
+        header.appendChild(label);
+        header.appendChild(amount);
+        const bar = el('div', 'goal-bar');
+        const fill = el('div', 'goal-fill');
+        bar.appendChild(fill);
+        goal.appendChild(header);
+        goal.appendChild(bar);
+        root.appendChild(goal);
+
+        function render(value, target) {
+            amount.textContent = `¥${value.toFixed(1)} / ¥${target}`;
+            fill.style.width = Math.min(100, target > 0 ? value / target * 100 : 100) + '%';
+        }
+
+        if (params.has('goal')) {
+            followGoal(params.get('goal'), label, render);
+        } else {
+            countGoal(render);
+        }
+    }
+
+    // Show a goal created in the app, by id or 'active' for the oldest active goal
+    function followGoal(wanted, label, render) {
+        let goalId = wanted === 'active' ? null : Number(wanted);
+
+        function show(goal) {
+            if (goalId === null && !goal.completed_at) goalId = goal.id;
+            if (goal.id !== goalId) return;
+            label.textContent = goal.title;
+            render(goal.progress, goal.target);
+            if (goal.completed_at && wanted === 'active') goalId = null;
+        }
+
+        // Requests fail until the WebSocket is connected, retry until the goals are loaded
+        function load() {
+            jliverAPI.getGoals()
+                .then(goals => goals.forEach(show))
+                .catch(() => setTimeout(load, 3000));
+        }
+
+        jliverAPI.register('goal_progress', event => show(event.data));
+        setTimeout(load, 500);
+        render(0, 0);
+    }
+
+    // Count the goal in the page from gifts, guards and superchats
+    function countGoal(render) {
+        const target = numberParam('target', 1000);
+        const since = numberParam('since', now());
+        const sources = (params.get('sources') || 'gift,guard,superchat').split(',').map(s => s.trim());
+        // Ids already counted, replayed events may overlap live ones
+        const seen = new Set();
+        let value = 0;
+
+        function add(data, price) {
+            if (seen.has(data.id) || data.timestamp < since) return;
+            seen.add(data.id);
+            value += price;
+            render(value, target);
+        }
+
+        const replay = { replay: true, since: since, limit: 5000 };
+        if (sources.includes('gift')) {
+            jliverAPI.register('new_gift', event => {
+                if (event.data.coin_type === 'gold') add(event.data, event.data.price_yuan);
+            }, replay);
+        }
+        if (sources.includes('guard')) {
+            jliverAPI.register('new_guard', event => add(event.data, event.data.price_yuan), replay);
+        }
+        if (sources.includes('superchat')) {
+            jliverAPI.register('new_superchat', event => add(event.data, event.data.price), replay);
+        }
+        render(value, target);
+    }
+
+    const SETUP = { danmu: setupDanmu, gifts: setupGifts, superchat: setupSuperchat, goal: setupGoal };
+
+    applyTheme();
+    refreshFilter();
+    setInterval(refreshFilter, 10000);
+    if (window.jliverAPI && SETUP[kind]) {
+        SETUP[kind]();
+    }
+})();
@@ -1,3 +1,4 @@
+use jlivertool_core::types::PluginSettingField;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
@@ -11,6 +12,9 @@ pub struct PluginMeta {
     #[serde(default)]
     pub url: Option<String>,
     pub index: String,
+    /// Optional settings schema rendered as a form in the settings window
+    #[serde(default)]
+    pub settings: Vec<PluginSettingField>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -0,0 +1,235 @@
+//! Persistent key-value storage for plugins
+//!
+//! Each plugin gets its own namespace, stored as a JSON file under the
+//! storage directory (`<data_dir>/plugin_data/<plugin_id>.json`). Unlike the
+//! browser's localStorage, the data survives changes to the HTTP port.
+//! Values of the plugin's settings schema are kept next to it in
+//! `<plugin_id>.settings.json`.
+
+use anyhow::{anyhow, bail, Context, Result};
+use jlivertool_core::types::{resolve_plugin_settings, PluginSettingField};
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Maximum length of a single key in bytes
+pub const MAX_KEY_LEN: usize = 256;
+/// Maximum serialized size of a single value in bytes
+pub const MAX_VALUE_SIZE: usize = 256 * 1024;
+/// Maximum number of keys per plugin
+pub const MAX_KEYS: usize = 1000;
+/// Maximum serialized size of a plugin namespace in bytes
+pub const MAX_NAMESPACE_SIZE: usize = 5 * 1024 * 1024;
+
+/// Per-plugin key-value storage backed by JSON files
+pub struct PluginStorage {
+    dir: PathBuf,
+    // Serializes read-modify-write cycles on namespace files
+    lock: Mutex<()>,
+}
+
+impl PluginStorage {
+    /// Create a storage rooted at the given directory
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Get the storage directory
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Get a value, returns None if the key does not exist
+    pub fn get(&self, plugin_id: &str, key: &str) -> Result<Option<Value>> {
+        let _guard = self.lock.lock().unwrap();
+        let map = self.load(plugin_id)?;
+        Ok(map.get(key).cloned())
+    }
+
+    /// Set a value, enforcing the key, value and namespace quotas
+    pub fn set(&self, plugin_id: &str, key: &str, value: Value) -> Result<()> {
+        if key.is_empty() {
+            bail!("Storage key must not be empty");
+        }
+        if key.len() > MAX_KEY_LEN {
+            bail!("Storage key exceeds {} bytes", MAX_KEY_LEN);
+        }
+        let value_size = serde_json::to_string(&value)?.len();
+        if value_size > MAX_VALUE_SIZE {
+            bail!("Storage value exceeds {} bytes", MAX_VALUE_SIZE);
+        }
+
+        let _guard = self.lock.lock().unwrap();
+        let mut map = self.load(plugin_id)?;
+        if !map.contains_key(key) && map.len() >= MAX_KEYS {
+            bail!("Storage quota exceeded: at most {} keys", MAX_KEYS);
+        }
+        map.insert(key.to_string(), value);
+
+        let content = serde_json::to_string(&map)?;
+        if content.len() > MAX_NAMESPACE_SIZE {
+            bail!(
+                "Storage quota exceeded: at most {} bytes",
+                MAX_NAMESPACE_SIZE
+            );
+        }
+        self.save(plugin_id, &content)
+    }
+
+    /// List all keys in a plugin's namespace
+    pub fn list(&self, plugin_id: &str) -> Result<Vec<String>> {
+        let _guard = self.lock.lock().unwrap();
+        let map = self.load(plugin_id)?;
+        Ok(map.keys().cloned().collect())
+    }
+
+    /// Delete a key, returns whether it existed
+    pub fn delete(&self, plugin_id: &str, key: &str) -> Result<bool> {
+        let _guard = self.lock.lock().unwrap();
+        let mut map = self.load(plugin_id)?;
+        if map.remove(key).is_none() {
+            return Ok(false);
+        }
+        let content = serde_json::to_string(&map)?;
+        self.save(plugin_id, &content)?;
+        Ok(true)
+    }
+
+    /// Get effective settings for a plugin, filling unset fields with schema defaults
+    pub fn get_settings(
+        &self,
+        plugin_id: &str,
+        schema: &[PluginSettingField],
+    ) -> Result<Map<String, Value>> {
+        let _guard = self.lock.lock().unwrap();
+        let stored = self.read_map(&self.settings_path(plugin_id)?)?;
+        Ok(resolve_plugin_settings(schema, &stored))
+    }
+
+    /// Store settings values, dropping unknown keys and values of the wrong type
+    /// Returns the effective settings after the update
+    pub fn set_settings(
+        &self,
+        plugin_id: &str,
+        schema: &[PluginSettingField],
+        values: &Map<String, Value>,
+    ) -> Result<Map<String, Value>> {
+        let _guard = self.lock.lock().unwrap();
+        let path = self.settings_path(plugin_id)?;
+        let mut stored = self.read_map(&path)?;
+        for field in schema {
+            if let Some(value) = values.get(&field.key).and_then(|v| field.validate(v)) {
+                stored.insert(field.key.clone(), value);
+            }
+        }
+        self.write_file(&path, &serde_json::to_string(&stored)?)?;
+        Ok(resolve_plugin_settings(schema, &stored))
+    }
+
+    /// Remove all data stored by a plugin, including its settings
+    pub fn remove_namespace(&self, plugin_id: &str) -> Result<()> {
+        let _guard = self.lock.lock().unwrap();
+        for path in [
+            self.namespace_path(plugin_id)?,
+            self.settings_path(plugin_id)?,
+        ] {
+            if path.exists() {
+                fs::remove_file(&path)?;
+                log::info!("Removed plugin storage: {:?}", path);
+            }
+        }
+        Ok(())
+    }
+
+    fn settings_path(&self, plugin_id: &str) -> Result<PathBuf> {
+        Ok(self
+            .namespace_path(plugin_id)?
+            .with_extension("settings.json"))
+    }
+
+    fn namespace_path(&self, plugin_id: &str) -> Result<PathBuf> {
+        let valid = !plugin_id.is_empty()
+            && plugin_id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+            && !plugin_id.starts_with('.');
+        if !valid {
+            return Err(anyhow!("Invalid plugin id: {}", plugin_id));
+        }
+        Ok(self.dir.join(format!("{}.json", plugin_id)))
+    }
+
+    fn load(&self, plugin_id: &str) -> Result<Map<String, Value>> {
+        self.read_map(&self.namespace_path(plugin_id)?)
+    }
+
+    fn read_map(&self, path: &Path) -> Result<Map<String, Value>> {
+        if !path.exists() {
+            return Ok(Map::new());
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read plugin storage at {:?}", path))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse plugin storage at {:?}", path))
+    }
+
+    fn save(&self, plugin_id: &str, content: &str) -> Result<()> {
+        self.write_file(&self.namespace_path(plugin_id)?, content)
+    }
+
+    fn write_file(&self, path: &Path, content: &str) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        // Write to a temporary file first so a crash never leaves a truncated namespace
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, content)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_storage(name: &str) -> PluginStorage {
+        let dir = std::env::temp_dir().join(format!(
+            "jlivertool-storage-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        PluginStorage::new(dir)
+    }
+
+    #[test]
+    fn test_storage_roundtrip() {
+        let storage = temp_storage("roundtrip");
+        storage.set("demo", "count", serde_json::json!(3)).unwrap();
+        assert_eq!(
+            storage.get("demo", "count").unwrap(),
+            Some(serde_json::json!(3))
+        );
+        assert_eq!(storage.list("demo").unwrap(), vec!["count".to_string()]);
+        assert!(storage.delete("demo", "count").unwrap());
+        assert_eq!(storage.get("demo", "count").unwrap(), None);
+
+        storage.set("demo", "a", serde_json::json!("x")).unwrap();
+        storage.remove_namespace("demo").unwrap();
+        assert!(storage.list("demo").unwrap().is_empty());
+        let _ = fs::remove_dir_all(storage.dir());
+    }
+
+    #[test]
+    fn test_storage_rejects_invalid_input() {
+        let storage = temp_storage("invalid");
+        assert!(storage.set("../evil", "k", Value::Null).is_err());
+        assert!(storage.set("demo", "", Value::Null).is_err());
+        let big = "x".repeat(MAX_VALUE_SIZE + 1);
+        assert!(storage.set("demo", "big", Value::String(big)).is_err());
+        let _ = fs::remove_dir_all(storage.dir());
+    }
+}
@@ -3,38 +3,83 @@
 //! Provides a local WebSocket server that plugins can connect to for:
 //! - Receiving events (danmu, gifts, etc.)
 //! - Calling APIs (user info, room info, etc.)
+//! - Persisting plugin data (storage.get/set/list/delete)
+//! - Receiving the plugin's settings values on connect and on change
+//! - Querying or replaying historical events (getHistory, replay on subscribe)
+//! - Reading the active gift goals of the room (getGoals)
 
 use anyhow::Result;
 use futures::{SinkExt, StreamExt};
+use jlivertool_core::database::Database;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::{broadcast, mpsc, RwLock};
 use tokio_tungstenite::{accept_async, tungstenite::Message};
 
-use crate::events::PluginEvent;
+use crate::events::{channel_matches, PluginEvent, SerializableGoal, PLUGIN_EVENT_SCHEMA_VERSION};
+use crate::history::{load_history, HistoryQuery};
+use crate::plugin::Plugin;
+use crate::storage::PluginStorage;
+
+/// Loaded plugins, shared with the plugin manager
+pub type SharedPlugins = Arc<std::sync::RwLock<HashMap<String, Plugin>>>;
+
+/// Settings update for a single plugin
+#[derive(Debug, Clone)]
+pub struct SettingsUpdate {
+    pub plugin_id: String,
+    pub values: serde_json::Map<String, serde_json::Value>,
+}
 
 /// WebSocket message from server to client
 #[derive(Debug, Clone, Serialize)]
 #[serde(tag = "type")]
 pub enum WsServerMessage {
-    /// Welcome message with server info
-    Welcome { port: u16 },
+    /// Welcome message with server info and the plugin event schema version
+    Welcome {
+        port: u16,
+        schema_version: u32,
+        server_version: String,
+    },
     /// Event broadcast - contains the event in the "Event" field
-    Event { #[serde(rename = "Event")] event: PluginEvent },
+    /// `replay` is set for historical events sent after a replaying Subscribe
+    Event {
+        #[serde(rename = "Event")]
+        event: Box<PluginEvent>,
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        replay: bool,
+    },
     /// Response to a request
     Response { id: String, data: serde_json::Value },
     /// Error response
     Error { id: Option<String>, message: String },
+    /// Current settings values of the plugin, sent after Hello and on change
+    Settings {
+        values: serde_json::Map<String, serde_json::Value>,
+    },
 }
 
 /// WebSocket message from client to server
 #[derive(Debug, Clone, Deserialize)]
 #[serde(tag = "type")]
 pub enum WsClientMessage {
+    /// Identify the plugin behind this connection
+    Hello { plugin_id: String },
     /// Subscribe to specific event channels
-    Subscribe { channels: Vec<String> },
+    /// With `replay` set, stored events of these channels are sent first
+    Subscribe {
+        channels: Vec<String>,
+        #[serde(default)]
+        replay: bool,
+        #[serde(default)]
+        since: Option<i64>,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
     /// Unsubscribe from event channels
     Unsubscribe { channels: Vec<String> },
     /// API request
@@ -48,6 +93,96 @@ pub enum WsClientMessage {
 /// Client connection state
 struct ClientState {
     subscribed_channels: Vec<String>,
+    plugin_id: Option<String>,
+    /// Live events held back while history is replayed, so they follow it
+    held_events: Option<Vec<PluginEvent>>,
+}
+
+impl ClientState {
+    fn subscribes_raw(&self) -> bool {
+        self.subscribed_channels
+            .iter()
+            .any(|c| c != "*" && channel_matches(c, "raw"))
+    }
+}
+
+/// Shared resources available to API request handlers
+#[derive(Clone, Default)]
+struct ApiContext {
+    storage: Option<Arc<PluginStorage>>,
+    plugins: Option<SharedPlugins>,
+    database: Option<Database>,
+    current_room: Arc<AtomicU64>,
+    /// Number of open client connections
+    clients: Arc<AtomicUsize>,
+    /// Number of clients subscribed to the raw channel
+    raw_subscribers: Arc<AtomicUsize>,
+}
+
+impl ApiContext {
+    /// Resolve the effective settings of a plugin without blocking the server
+    async fn plugin_settings(
+        &self,
+        plugin_id: &str,
+    ) -> Result<serde_json::Map<String, serde_json::Value>> {
+        let storage = self
+            .storage
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("Plugin storage is not available"))?;
+        let schema = self
+            .plugins
+            .as_ref()
+            .and_then(|plugins| {
+                plugins
+                    .read()
+                    .unwrap()
+                    .get(plugin_id)
+                    .map(|p| p.meta.settings.clone())
+            })
+            .unwrap_or_default();
+        let plugin_id = plugin_id.to_string();
+        tokio::task::spawn_blocking(move || storage.get_settings(&plugin_id, &schema)).await?
+    }
+
+    /// Load history events from the database without blocking the server
+    async fn history(&self, mut query: HistoryQuery) -> Result<(u64, Vec<PluginEvent>)> {
+        let database = self
+            .database
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("Database is not available"))?;
+        let room_id = query
+            .room_id
+            .take()
+            .unwrap_or_else(|| self.current_room.load(Ordering::Relaxed));
+        if room_id == 0 {
+            anyhow::bail!("No room is connected");
+        }
+        let events =
+            tokio::task::spawn_blocking(move || load_history(&database, room_id, &query)).await??;
+        Ok((room_id, events))
+    }
+
+    /// Load the active goals of the current room with their progress
+    async fn goals(&self) -> Result<(u64, Vec<SerializableGoal>)> {
+        let database = self
+            .database
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("Database is not available"))?;
+        let room_id = self.current_room.load(Ordering::Relaxed);
+        if room_id == 0 {
+            anyhow::bail!("No room is connected");
+        }
+        let goals = tokio::task::spawn_blocking(move || -> Result<Vec<SerializableGoal>> {
+            database.flush();
+            let mut goals = database.get_active_goals(room_id)?;
+            for goal in &mut goals {
+                goal.progress = database.get_goal_progress(goal)?;
+            }
+            Ok(goals.iter().map(SerializableGoal::from).collect())
+        })
+        .await??;
+        Ok((room_id, goals))
+    }
 }
 
 /// Plugin WebSocket server
@@ -55,24 +190,79 @@ pub struct PluginWsServer {
     port: u16,
     event_tx: broadcast::Sender<PluginEvent>,
     shutdown_tx: Option<mpsc::Sender<()>>,
+    settings_tx: broadcast::Sender<SettingsUpdate>,
+    storage: Option<Arc<PluginStorage>>,
+    plugins: Option<SharedPlugins>,
+    database: Option<Database>,
+    current_room: Arc<AtomicU64>,
+    clients: Arc<AtomicUsize>,
+    raw_subscribers: Arc<AtomicUsize>,
 }
 
 impl PluginWsServer {
     /// Create a new plugin WebSocket server
     pub fn new() -> Self {
         let (event_tx, _) = broadcast::channel(1000);
+        let (settings_tx, _) = broadcast::channel(16);
         Self {
             port: 0,
             event_tx,
             shutdown_tx: None,
+            settings_tx,
+            storage: None,
+            plugins: None,
+            database: None,
+            current_room: Arc::new(AtomicU64::new(0)),
+            clients: Arc::new(AtomicUsize::new(0)),
+            raw_subscribers: Arc::new(AtomicUsize::new(0)),
         }
     }
 
+    /// Set the database used to answer history requests
+    /// Must be called before the server is started
+    pub fn set_database(&mut self, database: Database) {
+        self.database = Some(database);
+    }
+
+    /// Set the room history requests default to
+    /// The room is also picked up from UpdateRoom events automatically
+    pub fn set_current_room(&self, room_id: u64) {
+        self.current_room.store(room_id, Ordering::Relaxed);
+    }
+
+    /// Set the loaded plugins, used to look up settings schemas
+    /// Must be called before the server is started
+    pub fn set_plugins(&mut self, plugins: SharedPlugins) {
+        self.plugins = Some(plugins);
+    }
+
+    /// Push updated settings values to the connections of a plugin
+    pub fn notify_settings_changed(&self, update: SettingsUpdate) {
+        let _ = self.settings_tx.send(update);
+    }
+
+    /// Set the storage backing the storage.* API methods
+    /// Must be called before the server is started
+    pub fn set_storage(&mut self, storage: Arc<PluginStorage>) {
+        self.storage = Some(storage);
+    }
+
     /// Get the port the server is listening on
     pub fn port(&self) -> u16 {
         self.port
     }
 
+    /// Counter of open client connections
+    pub fn client_counter(&self) -> Arc<AtomicUsize> {
+        self.clients.clone()
+    }
+
+    /// Counter of clients subscribed to the raw channel
+    /// Raw messages need not be broadcast while it is zero
+    pub fn raw_subscriber_counter(&self) -> Arc<AtomicUsize> {
+        self.raw_subscribers.clone()
+    }
+
     /// Get the WebSocket URL for plugins to connect to
     pub fn ws_url(&self) -> String {
         format!("ws://127.0.0.1:{}", self.port)
@@ -105,6 +295,30 @@ impl PluginWsServer {
         log::info!("Plugin WebSocket server listening on {}", addr);
 
         let event_tx = self.event_tx.clone();
+        let ctx = ApiContext {
+            storage: self.storage.clone(),
+            plugins: self.plugins.clone(),
+            database: self.database.clone(),
+            current_room: self.current_room.clone(),
+            clients: self.clients.clone(),
+            raw_subscribers: self.raw_subscribers.clone(),
+        };
+
+        // Track the current room for history requests
+        let current_room = self.current_room.clone();
+        let mut room_rx = self.event_tx.subscribe();
+        tokio::spawn(async move {
+            loop {
+                match room_rx.recv().await {
+                    Ok(PluginEvent::UpdateRoom { room_id, .. }) => {
+                        current_room.store(room_id, Ordering::Relaxed);
+                    }
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+        let settings_tx = self.settings_tx.clone();
         let (shutdown_tx, mut shutdown_rx) = mpsc::channel::<()>(1);
         self.shutdown_tx = Some(shutdown_tx);
 
@@ -116,7 +330,14 @@ impl PluginWsServer {
                         match result {
                             Ok((stream, addr)) => {
                                 let event_rx = event_tx.subscribe();
-                                tokio::spawn(handle_connection(stream, addr, event_rx));
+                                let settings_rx = settings_tx.subscribe();
+                                tokio::spawn(handle_connection(
+                                    stream,
+                                    addr,
+                                    event_rx,
+                                    settings_rx,
+                                    ctx.clone(),
+                                ));
                             }
                             Err(e) => {
                                 log::error!("Failed to accept connection: {}", e);
@@ -153,6 +374,8 @@ async fn handle_connection(
     stream: TcpStream,
     addr: SocketAddr,
     mut event_rx: broadcast::Receiver<PluginEvent>,
+    mut settings_rx: broadcast::Receiver<SettingsUpdate>,
+    ctx: ApiContext,
 ) {
     log::info!("New plugin connection from {}", addr);
 
@@ -165,14 +388,21 @@ async fn handle_connection(
     };
 
     let (mut ws_sender, mut ws_receiver) = ws_stream.split();
+    ctx.clients.fetch_add(1, Ordering::Relaxed);
 
     // Client state
     let state = Arc::new(RwLock::new(ClientState {
         subscribed_channels: vec!["*".to_string()], // Subscribe to all by default
+        plugin_id: None,
+        held_events: None,
     }));
 
     // Send welcome message
-    let welcome = WsServerMessage::Welcome { port: addr.port() };
+    let welcome = WsServerMessage::Welcome {
+        port: addr.port(),
+        schema_version: PLUGIN_EVENT_SCHEMA_VERSION,
+        server_version: env!("CARGO_PKG_VERSION").to_string(),
+    };
     if let Ok(msg) = serde_json::to_string(&welcome) {
         let _ = ws_sender.send(Message::Text(msg)).await;
     }
@@ -187,16 +417,23 @@ async fn handle_connection(
         loop {
             match event_rx.recv().await {
                 Ok(event) => {
-                    let state = state_clone.read().await;
-                    let channel = event.channel();
+                    let mut state = state_clone.write().await;
 
                     // Check if client is subscribed to this channel
-                    let subscribed = state.subscribed_channels.iter().any(|c| {
-                        c == "*" || c.eq_ignore_ascii_case(channel)
-                    });
+                    let subscribed = state
+                        .subscribed_channels
+                        .iter()
+                        .any(|c| event.matches_channel(c));
 
                     if subscribed {
-                        let msg = WsServerMessage::Event { event };
+                        if let Some(held) = state.held_events.as_mut() {
+                            held.push(event);
+                            continue;
+                        }
+                        let msg = WsServerMessage::Event {
+                            event: Box::new(event),
+                            replay: false,
+                        };
                         if let Ok(json) = serde_json::to_string(&msg) {
                             if outgoing_tx_clone.send(json).await.is_err() {
                                 break;
@@ -214,6 +451,34 @@ async fn handle_connection(
         }
     });
 
+    // Spawn task to forward settings changes of the identified plugin
+    let state_clone = state.clone();
+    let outgoing_tx_clone = outgoing_tx.clone();
+    let settings_forward_task = tokio::spawn(async move {
+        loop {
+            match settings_rx.recv().await {
+                Ok(update) => {
+                    let is_target = state_clone.read().await.plugin_id.as_deref()
+                        == Some(update.plugin_id.as_str());
+                    if is_target {
+                        let msg = WsServerMessage::Settings {
+                            values: update.values,
+                        };
+                        if let Ok(json) = serde_json::to_string(&msg) {
+                            if outgoing_tx_clone.send(json).await.is_err() {
+                                break;
+                            }
+                        }
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => {
+                    break;
+                }
+            }
+        }
+    });
+
     // Spawn task to send outgoing messages
     let send_task = tokio::spawn(async move {
         while let Some(msg) = outgoing_rx.recv().await {
@@ -229,22 +494,20 @@ async fn handle_connection(
     // Handle incoming messages from client
     while let Some(msg) = ws_receiver.next().await {
         match msg {
-            Ok(Message::Text(text)) => {
-                match serde_json::from_str::<WsClientMessage>(&text) {
-                    Ok(client_msg) => {
-                        handle_client_message(client_msg, &state, &outgoing_tx_for_handler).await;
-                    }
-                    Err(e) => {
-                        let error = WsServerMessage::Error {
-                            id: None,
-                            message: format!("Invalid message format: {}", e),
-                        };
-                        if let Ok(json) = serde_json::to_string(&error) {
-                            let _ = outgoing_tx_for_handler.send(json).await;
-                        }
+            Ok(Message::Text(text)) => match serde_json::from_str::<WsClientMessage>(&text) {
+                Ok(client_msg) => {
+                    handle_client_message(client_msg, &state, &ctx, &outgoing_tx_for_handler).await;
+                }
+                Err(e) => {
+                    let error = WsServerMessage::Error {
+                        id: None,
+                        message: format!("Invalid message format: {}", e),
+                    };
+                    if let Ok(json) = serde_json::to_string(&error) {
+                        let _ = outgoing_tx_for_handler.send(json).await;
                     }
                 }
-            }
+            },
             Ok(Message::Close(_)) => {
                 log::info!("Client {} disconnected", addr);
                 break;
@@ -261,7 +524,12 @@ async fn handle_connection(
     }
 
     event_forward_task.abort();
+    settings_forward_task.abort();
     send_task.abort();
+    if state.read().await.subscribes_raw() {
+        ctx.raw_subscribers.fetch_sub(1, Ordering::Relaxed);
+    }
+    ctx.clients.fetch_sub(1, Ordering::Relaxed);
     log::info!("Connection closed for {}", addr);
 }
 
@@ -269,25 +537,107 @@ async fn handle_connection(
 async fn handle_client_message(
     msg: WsClientMessage,
     state: &Arc<RwLock<ClientState>>,
+    ctx: &ApiContext,
     outgoing_tx: &mpsc::Sender<String>,
 ) {
     match msg {
-        WsClientMessage::Subscribe { channels } => {
-            let mut state = state.write().await;
-            for channel in channels {
-                if !state.subscribed_channels.contains(&channel) {
-                    state.subscribed_channels.push(channel);
+        WsClientMessage::Hello { plugin_id } => {
+            log::debug!("Client identified as plugin {}", plugin_id);
+            state.write().await.plugin_id = Some(plugin_id.clone());
+            match ctx.plugin_settings(&plugin_id).await {
+                Ok(values) => {
+                    let msg = WsServerMessage::Settings { values };
+                    if let Ok(json) = serde_json::to_string(&msg) {
+                        let _ = outgoing_tx.send(json).await;
+                    }
+                }
+                Err(e) => {
+                    log::warn!("Failed to load settings for plugin {}: {}", plugin_id, e);
+                }
+            }
+        }
+        WsClientMessage::Subscribe {
+            channels,
+            replay,
+            since,
+            limit,
+        } => {
+            {
+                let mut state = state.write().await;
+                let had_raw = state.subscribes_raw();
+                for channel in &channels {
+                    if !state.subscribed_channels.contains(channel) {
+                        state.subscribed_channels.push(channel.clone());
+                    }
+                }
+                if !had_raw && state.subscribes_raw() {
+                    ctx.raw_subscribers.fetch_add(1, Ordering::Relaxed);
+                }
+                if replay {
+                    state.held_events.get_or_insert_with(Vec::new);
+                }
+                log::debug!("Client subscribed to: {:?}", state.subscribed_channels);
+            }
+
+            if replay {
+                let query = HistoryQuery {
+                    channels,
+                    since,
+                    limit,
+                    room_id: None,
+                };
+                match ctx.history(query).await {
+                    Ok((_, events)) => {
+                        for event in events {
+                            let msg = WsServerMessage::Event {
+                                event: Box::new(event),
+                                replay: true,
+                            };
+                            if let Ok(json) = serde_json::to_string(&msg) {
+                                if outgoing_tx.send(json).await.is_err() {
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        let error = WsServerMessage::Error {
+                            id: None,
+                            message: format!("Replay failed: {}", e),
+                        };
+                        if let Ok(json) = serde_json::to_string(&error) {
+                            let _ = outgoing_tx.send(json).await;
+                        }
+                    }
+                }
+
+                // Live events received meanwhile follow the replay, the lock
+                // keeps newer ones from overtaking them
+                let mut state = state.write().await;
+                for event in state.held_events.take().unwrap_or_default() {
+                    let msg = WsServerMessage::Event {
+                        event: Box::new(event),
+                        replay: false,
+                    };
+                    if let Ok(json) = serde_json::to_string(&msg) {
+                        if outgoing_tx.send(json).await.is_err() {
+                            break;
+                        }
+                    }
                 }
             }
-            log::debug!("Client subscribed to: {:?}", state.subscribed_channels);
         }
         WsClientMessage::Unsubscribe { channels } => {
             let mut state = state.write().await;
+            let had_raw = state.subscribes_raw();
             state.subscribed_channels.retain(|c| !channels.contains(c));
+            if had_raw && !state.subscribes_raw() {
+                ctx.raw_subscribers.fetch_sub(1, Ordering::Relaxed);
+            }
             log::debug!("Client unsubscribed, remaining: {:?}", state.subscribed_channels);
         }
         WsClientMessage::Request { id, method, params } => {
-            let response = handle_api_request(&method, params).await;
+            let response = handle_api_request(&method, params, ctx).await;
             let msg = match response {
                 Ok(data) => WsServerMessage::Response { id, data },
                 Err(e) => WsServerMessage::Error {
@@ -303,7 +653,11 @@ async fn handle_client_message(
 }
 
 /// Handle an API request from a plugin
-async fn handle_api_request(method: &str, params: serde_json::Value) -> Result<serde_json::Value> {
+async fn handle_api_request(
+    method: &str,
+    params: serde_json::Value,
+    ctx: &ApiContext,
+) -> Result<serde_json::Value> {
     match method {
         "openUrl" => {
             let url = params
@@ -319,6 +673,100 @@ async fn handle_api_request(method: &str, params: serde_json::Value) -> Result<s
                 "name": "JLiverTool Plugin Server"
             }))
         }
+        "getSettings" => {
+            let plugin_id = params
+                .get("plugin_id")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Missing plugin_id parameter"))?;
+            Ok(serde_json::Value::Object(
+                ctx.plugin_settings(plugin_id).await?,
+            ))
+        }
+        "getHistory" => {
+            let query: HistoryQuery = serde_json::from_value(params)?;
+            let (room_id, events) = ctx.history(query).await?;
+            Ok(serde_json::json!({ "room_id": room_id, "events": events }))
+        }
+        "getGoals" => {
+            let (room_id, goals) = ctx.goals().await?;
+            Ok(serde_json::json!({ "room_id": room_id, "goals": goals }))
+        }
+        "storage.get" | "storage.set" | "storage.list" | "storage.delete" => {
+            handle_storage_request(method, params, ctx).await
+        }
+        _ => Err(anyhow::anyhow!("Unknown method: {}", method)),
+    }
+}
+
+/// Handle a storage.* request on a blocking thread, storage writes files
+async fn handle_storage_request(
+    method: &str,
+    params: serde_json::Value,
+    ctx: &ApiContext,
+) -> Result<serde_json::Value> {
+    let storage = ctx
+        .storage
+        .clone()
+        .ok_or_else(|| anyhow::anyhow!("Plugin storage is not available"))?;
+    let method = method.to_string();
+    tokio::task::spawn_blocking(move || storage_request(&storage, &method, &params)).await?
+}
+
+/// Run a storage.* request, scoped to the plugin given in params
+fn storage_request(
+    storage: &PluginStorage,
+    method: &str,
+    params: &serde_json::Value,
+) -> Result<serde_json::Value> {
+    let plugin_id = params
+        .get("plugin_id")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow::anyhow!("Missing plugin_id parameter"))?;
+    let key = || {
+        params
+            .get("key")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Missing key parameter"))
+    };
+
+    match method {
+        "storage.get" => {
+            let value = storage.get(plugin_id, key()?)?;
+            Ok(serde_json::json!({ "value": value }))
+        }
+        "storage.set" => {
+            let value = params
+                .get("value")
+                .cloned()
+                .unwrap_or(serde_json::Value::Null);
+            storage.set(plugin_id, key()?, value)?;
+            Ok(serde_json::json!({"success": true}))
+        }
+        "storage.list" => {
+            let keys = storage.list(plugin_id)?;
+            Ok(serde_json::json!({ "keys": keys }))
+        }
+        "storage.delete" => {
+            let existed = storage.delete(plugin_id, key()?)?;
+            Ok(serde_json::json!({ "success": true, "existed": existed }))
+        }
         _ => Err(anyhow::anyhow!("Unknown method: {}", method)),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subscribes_raw_ignores_case() {
+        let state = |channels: &[&str]| ClientState {
+            subscribed_channels: channels.iter().map(|c| c.to_string()).collect(),
+            plugin_id: None,
+            held_events: None,
+        };
+        assert!(state(&["Raw"]).subscribes_raw());
+        assert!(state(&["new_danmu", "RAW"]).subscribes_raw());
+        assert!(!state(&["*"]).subscribes_raw());
+    }
+}
@@ -0,0 +1,837 @@
+//! Offline stand-in for the Bilibili services used by JLiverTool
+//!
+//! `FakeBili` serves room_init, get_info, getDanmuInfo, nav, the QR login
+//! flow and the room management endpoints (areas, Room/update, announcement,
+//! cover upload) over HTTP, and a danmu WebSocket that speaks the binary protocol:
+//! it checks the auth packet, answers heartbeats and sends scripted command
+//! messages such as `DANMU_MSG` and `SEND_GIFT`. Point a `BiliApi` at it with
+//! `FakeBili::api`, or start the app with `JLIVERTOOL_API_BASE` set to
+//! `FakeBili::base_url` (see the `jlivertool-fake-bili` binary).
+
+pub mod messages;
+pub mod protocol;
+
+use anyhow::Result;
+use axum::{
+    body::Bytes,
+    extract::{Query, State},
+    http::{header, HeaderMap},
+    routing::{get, post},
+    Form, Json, Router,
+};
+use futures::{SinkExt, StreamExt};
+use jlivertool_core::bilibili::api::{BiliApi, Endpoints};
+use jlivertool_core::bilibili::ws::MessageOp;
+use jlivertool_core::types::Cookies;
+use parking_lot::{Mutex, RwLock};
+use protocol::{parse_client_packet, Compression};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, watch};
+use tokio_tungstenite::tungstenite::Message;
+
+/// Token handed out by getDanmuInfo and expected in the auth packet
+pub const DANMU_TOKEN: &str = "fake-danmu-token";
+/// Cookies the QR login hands out
+pub const LOGIN_SESSDATA: &str = "fake-sessdata";
+pub const LOGIN_BILI_JCT: &str = "fake-bili-jct";
+
+/// The room the fake serves
+#[derive(Debug, Clone)]
+pub struct FakeRoom {
+    pub room_id: u64,
+    pub short_id: u64,
+    /// Streamer uid
+    pub uid: u64,
+    pub title: String,
+    pub live_status: u8,
+    pub area_id: u64,
+    pub area_name: String,
+    pub parent_area_id: u64,
+    pub parent_area_name: String,
+    pub description: String,
+    pub tags: Vec<String>,
+    /// Announcement (主播公告)
+    pub news: String,
+    /// Cover URL
+    pub cover: String,
+}
+
+impl Default for FakeRoom {
+    fn default() -> Self {
+        Self {
+            room_id: 21484828,
+            short_id: 0,
+            uid: 61639371,
+            title: "测试直播间".to_string(),
+            live_status: 1,
+            area_id: 371,
+            area_name: "虚拟日常".to_string(),
+            parent_area_id: 9,
+            parent_area_name: "虚拟主播".to_string(),
+            description: String::new(),
+            tags: vec!["测试".to_string()],
+            news: String::new(),
+            cover: String::new(),
+        }
+    }
+}
+
+/// Step of the QR login, advanced by the test
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum QrStep {
+    NeedScan,
+    NeedConfirm,
+    Confirmed,
+}
+
+struct FakeState {
+    room: RwLock<FakeRoom>,
+    ws_port: u16,
+    /// Viewer uid the QR login logs in as
+    login_uid: u64,
+    qr: Mutex<HashMap<String, QrStep>>,
+    script: RwLock<Vec<Value>>,
+    compression: RwLock<Compression>,
+    popularity: AtomicU32,
+    auth_packets: Mutex<Vec<Value>>,
+    clients: AtomicUsize,
+    push_tx: broadcast::Sender<Vec<Value>>,
+}
+
+/// A running fake, stopped when dropped
+pub struct FakeBili {
+    http_addr: SocketAddr,
+    ws_addr: SocketAddr,
+    state: Arc<FakeState>,
+    shutdown: watch::Sender<bool>,
+}
+
+impl FakeBili {
+    /// Serve `room` on two free local ports
+    pub async fn start(room: FakeRoom) -> Result<Self> {
+        let http_listener = TcpListener::bind("127.0.0.1:0").await?;
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await?;
+        let http_addr = http_listener.local_addr()?;
+        let ws_addr = ws_listener.local_addr()?;
+
+        let (push_tx, _) = broadcast::channel(256);
+        let state = Arc::new(FakeState {
+            room: RwLock::new(room),
+            ws_port: ws_addr.port(),
+            login_uid: 10001,
+            qr: Mutex::new(HashMap::new()),
+            script: RwLock::new(Vec::new()),
+            compression: RwLock::new(Compression::default()),
+            popularity: AtomicU32::new(1234),
+            auth_packets: Mutex::new(Vec::new()),
+            clients: AtomicUsize::new(0),
+            push_tx,
+        });
+        let (shutdown, shutdown_rx) = watch::channel(false);
+
+        let app = router(state.clone());
+        let mut http_shutdown = shutdown_rx.clone();
+        tokio::spawn(async move {
+            let _ = axum::serve(http_listener, app)
+                .with_graceful_shutdown(async move {
+                    let _ = http_shutdown.wait_for(|stopped| *stopped).await;
+                })
+                .await;
+        });
+        tokio::spawn(serve_danmu(ws_listener, state.clone(), shutdown_rx));
+
+        Ok(Self {
+            http_addr,
+            ws_addr,
+            state,
+            shutdown,
+        })
+    }
+
+    /// Base URL of every HTTP endpoint
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.http_addr)
+    }
+
+    /// Address of the danmu WebSocket
+    pub fn ws_url(&self) -> String {
+        format!("ws://{}/sub", self.ws_addr)
+    }
+
+    pub fn endpoints(&self) -> Endpoints {
+        Endpoints::local(&self.base_url())
+    }
+
+    /// API client talking to the fake
+    pub fn api(&self) -> Result<BiliApi> {
+        BiliApi::builder().endpoints(self.endpoints()).build()
+    }
+
+    /// API client logged in as the streamer of the room
+    pub fn streamer_api(&self) -> Result<BiliApi> {
+        let cookies = Cookies::from_query_string(&format!(
+            "DedeUserID={}&SESSDATA={}&bili_jct={}",
+            self.state.room.read().uid,
+            LOGIN_SESSDATA,
+            LOGIN_BILI_JCT
+        ));
+        Ok(self.api()?.with_cookies(cookies))
+    }
+
+    /// Messages every client receives right after its auth reply
+    pub fn script(&self, messages: Vec<Value>) {
+        *self.state.script.write() = messages;
+    }
+
+    /// Send messages to the connected clients
+    pub fn push(&self, messages: Vec<Value>) {
+        let _ = self.state.push_tx.send(messages);
+    }
+
+    /// Packing of command messages, brotli by default
+    pub fn set_compression(&self, compression: Compression) {
+        *self.state.compression.write() = compression;
+    }
+
+    /// Popularity returned in heartbeat replies
+    pub fn set_popularity(&self, popularity: u32) {
+        self.state.popularity.store(popularity, Ordering::Relaxed);
+    }
+
+    /// Change the live status reported by the HTTP API
+    pub fn set_live_status(&self, live_status: u8) {
+        self.state.room.write().live_status = live_status;
+    }
+
+    pub fn room(&self) -> FakeRoom {
+        self.state.room.read().clone()
+    }
+
+    /// Uid the QR login logs in as
+    pub fn login_uid(&self) -> u64 {
+        self.state.login_uid
+    }
+
+    /// Scan every pending QR code
+    pub fn scan_qr(&self) {
+        self.advance_qr(QrStep::NeedScan, QrStep::NeedConfirm);
+    }
+
+    /// Confirm the login of every scanned QR code
+    pub fn confirm_qr(&self) {
+        self.advance_qr(QrStep::NeedConfirm, QrStep::Confirmed);
+    }
+
+    fn advance_qr(&self, from: QrStep, to: QrStep) {
+        for step in self.state.qr.lock().values_mut() {
+            if *step == from {
+                *step = to;
+            }
+        }
+    }
+
+    /// Bodies of the auth packets received so far
+    pub fn auth_packets(&self) -> Vec<Value> {
+        self.state.auth_packets.lock().clone()
+    }
+
+    /// Number of authenticated danmu clients
+    pub fn client_count(&self) -> usize {
+        self.state.clients.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for FakeBili {
+    fn drop(&mut self) {
+        let _ = self.shutdown.send(true);
+    }
+}
+
+type AppState = State<Arc<FakeState>>;
+type Params = Query<HashMap<String, String>>;
+
+fn router(state: Arc<FakeState>) -> Router {
+    Router::new()
+        .route("/room/v1/Room/room_init", get(room_init))
+        .route("/room/v1/Room/get_info", get(room_info))
+        .route("/xlive/web-room/v1/index/getDanmuInfo", get(danmu_info))
+        .route("/x/web-interface/nav", get(nav))
+        .route("/x/passport-login/web/qrcode/generate", get(qr_generate))
+        .route("/x/passport-login/web/qrcode/poll", get(qr_poll))
+        .route("/room/v1/Area/getList", get(area_list))
+        .route("/room/v1/Room/update", post(room_update))
+        .route("/room_ex/v1/RoomNews/get", get(room_news))
+        .route(
+            "/xlive/app-blink/v1/index/updateRoomNews",
+            post(update_room_news),
+        )
+        .route("/x/upload/web/image", post(upload_image))
+        .route(
+            "/xlive/app-blink/v1/preLive/UpdatePreLiveInfo",
+            post(update_pre_live_info),
+        )
+        .fallback(|| async { error(-404, "啥都木有") })
+        .with_state(state)
+}
+
+fn ok(data: Value) -> Json<Value> {
+    Json(json!({ "code": 0, "message": "0", "ttl": 1, "data": data }))
+}
+
+fn error(code: i32, message: &str) -> Json<Value> {
+    Json(json!({ "code": code, "message": message, "ttl": 1, "data": null }))
+}
+
+/// The requested room id, if it names the fake room
+fn requested_room(room: &FakeRoom, params: &HashMap<String, String>, key: &str) -> bool {
+    let id: u64 = params.get(key).and_then(|id| id.parse().ok()).unwrap_or(0);
+    id != 0 && (id == room.room_id || id == room.short_id)
+}
+
+async fn room_init(State(state): AppState, Query(params): Params) -> Json<Value> {
+    let room = state.room.read().clone();
+    if !requested_room(&room, &params, "id") {
+        return error(60004, "直播间不存在");
+    }
+    ok(json!({
+        "room_id": room.room_id,
+        "short_id": room.short_id,
+        "uid": room.uid,
+        "live_status": room.live_status,
+        "live_time": 0,
+    }))
+}
+
+async fn room_info(State(state): AppState, Query(params): Params) -> Json<Value> {
+    let room = state.room.read().clone();
+    if !requested_room(&room, &params, "room_id") {
+        return error(1, "未找到该房间");
+    }
+    ok(json!({
+        "room_id": room.room_id,
+        "short_id": room.short_id,
+        "uid": room.uid,
+        "title": room.title,
+        "live_status": room.live_status,
+        "area_id": room.area_id,
+        "area_name": room.area_name,
+        "parent_area_id": room.parent_area_id,
+        "parent_area_name": room.parent_area_name,
+        "keyframe": room.cover,
+        "tags": room.tags.join(","),
+        "description": room.description,
+    }))
+}
+
+/// Parent areas with their children, child ids are strings like on the site
+fn areas() -> Value {
+    json!([
+        {
+            "id": 9,
+            "name": "虚拟主播",
+            "list": [
+                { "id": "371", "parent_id": "9", "name": "虚拟日常", "parent_name": "虚拟主播", "pinyin": "xunirichang" },
+                { "id": "697", "parent_id": "9", "name": "虚拟Gamer", "parent_name": "虚拟主播", "pinyin": "xunigamer" },
+            ],
+        },
+        {
+            "id": 6,
+            "name": "单机游戏",
+            "list": [
+                { "id": "236", "parent_id": "6", "name": "主机游戏", "parent_name": "单机游戏", "pinyin": "zhujiyouxi" },
+                { "id": "235", "parent_id": "6", "name": "其他单机", "parent_name": "单机游戏", "pinyin": "qitadanji" },
+            ],
+        },
+    ])
+}
+
+async fn area_list() -> Json<Value> {
+    ok(areas())
+}
+
+/// Whether a form carries the CSRF token of the logged-in user
+fn valid_csrf(form: &HashMap<String, String>) -> bool {
+    form.get("csrf").map(String::as_str) == Some(LOGIN_BILI_JCT)
+}
+
+async fn room_update(
+    State(state): AppState,
+    Form(form): Form<HashMap<String, String>>,
+) -> Json<Value> {
+    if !valid_csrf(&form) {
+        return error(-111, "csrf 校验失败");
+    }
+    let mut room = state.room.write();
+    if !requested_room(&room, &form, "room_id") {
+        return error(1, "未找到该房间");
+    }
+    if let Some(title) = form.get("title") {
+        room.title = title.clone();
+    }
+    if let Some(description) = form.get("description") {
+        room.description = description.clone();
+    }
+    if let Some(tag) = form.get("add_tag") {
+        room.tags.push(tag.clone());
+    }
+    if let Some(tag) = form.get("del_tag") {
+        room.tags.retain(|t| t != tag);
+    }
+    if let Some(area_id) = form.get("area_id") {
+        let area = areas()
+            .as_array()
+            .into_iter()
+            .flatten()
+            .flat_map(|group| group["list"].as_array().cloned().unwrap_or_default())
+            .find(|area| area["id"].as_str() == Some(area_id.as_str()));
+        let Some(area) = area else {
+            return error(60009, "分区不存在");
+        };
+        room.area_id = area_id.parse().unwrap_or(0);
+        room.area_name = area["name"].as_str().unwrap_or_default().to_string();
+        room.parent_area_id = area["parent_id"]
+            .as_str()
+            .and_then(|id| id.parse().ok())
+            .unwrap_or(0);
+        room.parent_area_name = area["parent_name"].as_str().unwrap_or_default().to_string();
+    }
+    ok(json!([]))
+}
+
+async fn room_news(State(state): AppState, Query(params): Params) -> Json<Value> {
+    let room = state.room.read().clone();
+    if !requested_room(&room, &params, "roomid") {
+        return error(1, "未找到该房间");
+    }
+    ok(
+        json!({ "roomid": room.room_id.to_string(), "uid": room.uid.to_string(), "content": room.news }),
+    )
+}
+
+async fn update_room_news(
+    State(state): AppState,
+    Form(form): Form<HashMap<String, String>>,
+) -> Json<Value> {
+    if !valid_csrf(&form) {
+        return error(-111, "csrf 校验失败");
+    }
+    let mut room = state.room.write();
+    if !requested_room(&room, &form, "room_id") {
+        return error(1, "未找到该房间");
+    }
+    room.news = form.get("content").cloned().unwrap_or_default();
+    ok(json!({}))
+}
+
+async fn upload_image(Query(params): Params, headers: HeaderMap, body: Bytes) -> Json<Value> {
+    if !valid_csrf(&params) {
+        return error(-111, "csrf 校验失败");
+    }
+    let multipart = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("multipart/form-data"));
+    if !multipart || body.is_empty() {
+        return error(-400, "请求错误");
+    }
+    ok(json!({
+        "location": format!("https://i0.hdslb.com/bfs/live/new_room_cover/fake-{}.jpg", body.len()),
+    }))
+}
+
+async fn update_pre_live_info(
+    State(state): AppState,
+    Form(form): Form<HashMap<String, String>>,
+) -> Json<Value> {
+    if !valid_csrf(&form) {
+        return error(-111, "csrf 校验失败");
+    }
+    if let Some(cover) = form.get("cover") {
+        state.room.write().cover = cover.clone();
+    }
+    ok(json!({}))
+}
+
+async fn danmu_info(State(state): AppState, Query(params): Params) -> Json<Value> {
+    // Unsigned requests are rejected like on the site
+    if !params.contains_key("w_rid") || !params.contains_key("wts") {
+        return error(-352, "-352");
+    }
+    if !requested_room(&state.room.read(), &params, "id") {
+        return error(1002002, "参数错误");
+    }
+    ok(json!({
+        "token": DANMU_TOKEN,
+        "host_list": [{
+            "host": "127.0.0.1",
+            "port": state.ws_port,
+            "wss_port": state.ws_port,
+            "ws_port": state.ws_port,
+        }],
+    }))
+}
+
+async fn nav(State(state): AppState, headers: HeaderMap) -> Json<Value> {
+    let logged_in = headers
+        .get(header::COOKIE)
+        .and_then(|cookie| cookie.to_str().ok())
+        .is_some_and(|cookie| cookie.contains(&format!("SESSDATA={}", LOGIN_SESSDATA)));
+    let wbi_img = json!({
+        "img_url": "https://i0.hdslb.com/bfs/wbi/7cd084941338484aae1ad9425b84077c.png",
+        "sub_url": "https://i0.hdslb.com/bfs/wbi/4932caff0ff746eab6f01bf08b70ac45.png",
+    });
+    if !logged_in {
+        return ok(json!({ "isLogin": false, "wbi_img": wbi_img }));
+    }
+    ok(json!({
+        "isLogin": true,
+        "mid": state.login_uid,
+        "uname": "测试用户",
+        "face": "",
+        "wbi_img": wbi_img,
+    }))
+}
+
+async fn qr_generate(State(state): AppState) -> Json<Value> {
+    let key = format!("fake-qrcode-{}", state.qr.lock().len() + 1);
+    state.qr.lock().insert(key.clone(), QrStep::NeedScan);
+    ok(json!({
+        "url": format!("https://passport.bilibili.com/h5-app/passport/login/scan?qrcode_key={}", key),
+        "qrcode_key": key,
+    }))
+}
+
+async fn qr_poll(State(state): AppState, Query(params): Params) -> Json<Value> {
+    let key = params.get("qrcode_key").cloned().unwrap_or_default();
+    let step = state.qr.lock().get(&key).copied();
+    let (code, message, url) = match step {
+        None => (86038, "二维码已失效", String::new()),
+        Some(QrStep::NeedScan) => (86101, "未扫码", String::new()),
+        Some(QrStep::NeedConfirm) => (86090, "二维码已扫码未确认", String::new()),
+        Some(QrStep::Confirmed) => (
+            0,
+            "",
+            format!(
+                "https://passport.biligame.com/crossDomain?DedeUserID={}&DedeUserID__ckMd5=0&Expires=15551000&SESSDATA={}&bili_jct={}&gourl=https%3A%2F%2Fwww.bilibili.com",
+                state.login_uid, LOGIN_SESSDATA, LOGIN_BILI_JCT
+            ),
+        ),
+    };
+    ok(json!({ "code": code, "message": message, "url": url, "refresh_token": "" }))
+}
+
+async fn serve_danmu(
+    listener: TcpListener,
+    state: Arc<FakeState>,
+    shutdown: watch::Receiver<bool>,
+) {
+    let mut stop = shutdown.clone();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let Ok((stream, _)) = accepted else { continue };
+                tokio::spawn(serve_danmu_client(stream, state.clone(), shutdown.clone()));
+            }
+            _ = stop.wait_for(|stopped| *stopped) => break,
+        }
+    }
+}
+
+/// One danmu connection: auth, scripted messages, then heartbeats and pushes
+async fn serve_danmu_client(
+    stream: TcpStream,
+    state: Arc<FakeState>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let Ok(ws) = tokio_tungstenite::accept_async(stream).await else {
+        return;
+    };
+    let (mut write, mut read) = ws.split();
+
+    // The first packet must authenticate with the token for the room
+    let auth = match read.next().await {
+        Some(Ok(Message::Binary(data))) => parse_client_packet(&data).ok(),
+        _ => None,
+    };
+    let Some(auth) = auth.filter(|packet| packet.op == MessageOp::Auth as u32) else {
+        let _ = write.send(Message::Close(None)).await;
+        return;
+    };
+    let body = auth.json().unwrap_or_default();
+    state.auth_packets.lock().push(body.clone());
+    let room_id = state.room.read().room_id;
+    if body.get("key").and_then(Value::as_str) != Some(DANMU_TOKEN)
+        || body.get("roomid").and_then(Value::as_u64) != Some(room_id)
+    {
+        let _ = write.send(Message::Close(None)).await;
+        return;
+    }
+
+    let mut push_rx = state.push_tx.subscribe();
+    if write
+        .send(Message::Binary(protocol::auth_reply()))
+        .await
+        .is_err()
+    {
+        return;
+    }
+    state.clients.fetch_add(1, Ordering::SeqCst);
+
+    let script = state.script.read().clone();
+    let compression = *state.compression.read();
+    let mut frames = protocol::message_frames(&script, compression).unwrap_or_default();
+    for frame in frames.drain(..) {
+        if write.send(Message::Binary(frame)).await.is_err() {
+            state.clients.fetch_sub(1, Ordering::SeqCst);
+            return;
+        }
+    }
+
+    loop {
+        let outgoing = tokio::select! {
+            incoming = read.next() => match incoming {
+                Some(Ok(Message::Binary(data))) => match parse_client_packet(&data) {
+                    Ok(packet) if packet.op == MessageOp::KeepAlive as u32 => {
+                        Some(vec![protocol::heartbeat_reply(state.popularity.load(Ordering::Relaxed))])
+                    }
+                    _ => Some(Vec::new()),
+                },
+                Some(Ok(Message::Close(_))) | None | Some(Err(_)) => break,
+                Some(Ok(_)) => Some(Vec::new()),
+            },
+            pushed = push_rx.recv() => match pushed {
+                Ok(messages) => {
+                    let compression = *state.compression.read();
+                    Some(protocol::message_frames(&messages, compression).unwrap_or_default())
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => Some(Vec::new()),
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            _ = shutdown.wait_for(|stopped| *stopped) => None,
+        };
+        let Some(outgoing) = outgoing else {
+            let _ = write.send(Message::Close(None)).await;
+            break;
+        };
+        for frame in outgoing {
+            if write.send(Message::Binary(frame)).await.is_err() {
+                state.clients.fetch_sub(1, Ordering::SeqCst);
+                return;
+            }
+        }
+    }
+    state.clients.fetch_sub(1, Ordering::SeqCst);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jlivertool_core::bilibili::api::QrCodeStatus;
+    use jlivertool_core::bilibili::ws::{BiliWebSocket, WsEvent, WsInfo};
+    use jlivertool_core::messages::{DanmuMessage, GiftMessage};
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    /// Connect like the backend does and collect events until `done`
+    async fn collect_events(
+        fake: &FakeBili,
+        room_short_id: u64,
+        mut done: impl FnMut(&[WsEvent]) -> bool,
+    ) -> Vec<WsEvent> {
+        let api = fake.api().unwrap();
+        let room = api.get_room(room_short_id).await.unwrap();
+        let danmu_info = api.get_danmu_info(room.real_id()).await.unwrap();
+        let host = danmu_info.host_list.first().unwrap();
+        let (ws, mut rx) = BiliWebSocket::new(WsInfo {
+            server: api.endpoints().danmu_ws_url(host),
+            room_id: room.real_id(),
+            uid: 0,
+            token: danmu_info.token,
+        });
+        let handle = tokio::spawn(async move { ws.connect().await });
+
+        let mut events = Vec::new();
+        while !done(&events) {
+            match timeout(Duration::from_secs(5), rx.recv()).await {
+                Ok(Some(event)) => events.push(event),
+                _ => break,
+            }
+        }
+        handle.abort();
+        events
+    }
+
+    fn command_messages(events: &[WsEvent]) -> Vec<Value> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                WsEvent::Message(body) => Some(body.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_room_apis() {
+        let fake = FakeBili::start(FakeRoom {
+            short_id: 510,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let api = fake.api().unwrap();
+
+        let room = api.get_room(510).await.unwrap();
+        assert_eq!(room.real_id(), 21484828);
+        let info = api.get_room_info(room.real_id()).await.unwrap();
+        assert_eq!(info.title, "测试直播间");
+        assert_eq!(info.live_status, 1);
+
+        fake.set_live_status(0);
+        assert_eq!(api.get_room_info(510).await.unwrap().live_status, 0);
+        assert!(api.get_room(1).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_room_management_apis() {
+        let fake = FakeBili::start(FakeRoom::default()).await.unwrap();
+        let room_id = fake.room().room_id;
+
+        let areas = fake.api().unwrap().get_area_list().await.unwrap();
+        assert_eq!(areas.groups.len(), 2);
+        assert_eq!(areas.find(236).unwrap().parent_name, "单机游戏");
+        assert_eq!(areas.search("主机")[0].id, 236);
+        assert_eq!(areas.search("xuni").len(), 2);
+
+        // Changes need the login of the streamer
+        assert!(fake
+            .api()
+            .unwrap()
+            .update_room_area(room_id, 236)
+            .await
+            .is_err());
+
+        let api = fake.streamer_api().unwrap();
+        api.update_room_area(room_id, 236).await.unwrap();
+        assert!(api.update_room_area(room_id, 1).await.is_err());
+        api.update_room_description(room_id, "简介").await.unwrap();
+        api.update_room_tags(room_id, &["唱歌".to_string(), "聊天".to_string()])
+            .await
+            .unwrap();
+        api.update_room_news(room_id, fake.room().uid, "公告")
+            .await
+            .unwrap();
+
+        let info = api.get_room_info(room_id).await.unwrap();
+        assert_eq!((info.area_id, info.parent_area_id), (236, 6));
+        assert_eq!(info.description, "简介");
+        assert_eq!(info.tags, "唱歌,聊天");
+        assert_eq!(api.get_room_news(room_id).await.unwrap(), "公告");
+
+        assert!(api
+            .upload_room_cover(vec![1, 2, 3], "cover.gif")
+            .await
+            .is_err());
+        let cover = api
+            .upload_room_cover(vec![0x89, b'P', b'N', b'G'], "cover.png")
+            .await
+            .unwrap();
+        assert_eq!(api.get_room_info(room_id).await.unwrap().keyframe, cover);
+    }
+
+    #[tokio::test]
+    async fn test_danmu_pipeline() {
+        for compression in [Compression::None, Compression::Deflate, Compression::Brotli] {
+            let fake = FakeBili::start(FakeRoom::default()).await.unwrap();
+            fake.set_compression(compression);
+            fake.set_popularity(4321);
+            fake.script(vec![
+                messages::danmu_msg_with_medal(1, "观众", "你好", "轴伊", 21, 21484828),
+                messages::send_gift(2, "金主", 31036, "小花花", 5, 100),
+            ]);
+
+            let events = collect_events(&fake, 21484828, |events| {
+                command_messages(events).len() >= 2
+                    && events
+                        .iter()
+                        .any(|e| matches!(e, WsEvent::HeartbeatReply(_)))
+            })
+            .await;
+
+            assert!(matches!(events[0], WsEvent::Connected));
+            assert!(events.iter().any(|e| matches!(e, WsEvent::Authenticated)));
+            assert!(events
+                .iter()
+                .any(|e| matches!(e, WsEvent::HeartbeatReply(4321))));
+
+            let messages = command_messages(&events);
+            let danmu = DanmuMessage::from_raw(&messages[0], None).unwrap();
+            assert_eq!(danmu.sender.uid, 1);
+            assert_eq!(danmu.content, "你好");
+            assert_eq!(danmu.sender.medal_info.medal_name, "轴伊");
+            assert_eq!(danmu.sender.medal_info.medal_level, 21);
+
+            let gift = GiftMessage::from_raw(&messages[1], 21484828).unwrap();
+            assert_eq!(gift.sender.uname, "金主");
+            assert_eq!(gift.gift_info.name, "小花花");
+            assert_eq!(gift.num, 5);
+
+            let auth = &fake.auth_packets()[0];
+            assert_eq!(auth["key"], DANMU_TOKEN);
+            assert_eq!(auth["protover"], 3);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_push_to_connected_client() {
+        let fake = Arc::new(FakeBili::start(FakeRoom::default()).await.unwrap());
+        let pusher = fake.clone();
+        tokio::spawn(async move {
+            while pusher.client_count() == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            pusher.push(vec![messages::preparing(21484828)]);
+        });
+
+        let events = collect_events(&fake, 21484828, |events| {
+            !command_messages(events).is_empty()
+        })
+        .await;
+        assert_eq!(command_messages(&events)[0]["cmd"], "PREPARING");
+    }
+
+    #[tokio::test]
+    async fn test_qr_login() {
+        let fake = FakeBili::start(FakeRoom::default()).await.unwrap();
+        let mut api = fake.api().unwrap();
+        assert!(!api.nav().await.unwrap().is_login);
+
+        let qr = api.qr_generate().await.unwrap();
+        let poll = |api: BiliApi, key: String| async move { api.qr_poll(&key).await.unwrap() };
+        assert_eq!(
+            poll(api.clone(), qr.qrcode_key.clone()).await.0,
+            QrCodeStatus::NeedScan
+        );
+        fake.scan_qr();
+        assert_eq!(
+            poll(api.clone(), qr.qrcode_key.clone()).await.0,
+            QrCodeStatus::NeedConfirm
+        );
+        fake.confirm_qr();
+        let (status, cookies) = poll(api.clone(), qr.qrcode_key.clone()).await;
+        assert_eq!(status, QrCodeStatus::Success);
+        let cookies = cookies.unwrap();
+        assert_eq!(cookies.sessdata, LOGIN_SESSDATA);
+        assert_eq!(cookies.dede_user_id, fake.login_uid().to_string());
+
+        api.set_cookies(Some(cookies));
+        let nav = api.nav().await.unwrap();
+        assert!(nav.is_login);
+        assert_eq!(nav.mid, Some(fake.login_uid()));
+    }
+}
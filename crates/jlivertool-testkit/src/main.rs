@@ -0,0 +1,37 @@
+//! Run the fake Bilibili services until Ctrl+C
+//!
+//! Start the app with the printed `JLIVERTOOL_API_BASE` to use it offline.
+//! A danmu is sent every few seconds and a gift every fifth one.
+
+use anyhow::Result;
+use jlivertool_testkit::{messages, FakeBili, FakeRoom};
+use std::time::Duration;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let fake = FakeBili::start(FakeRoom::default()).await?;
+    println!("JLIVERTOOL_API_BASE={}", fake.base_url());
+    println!("Danmu WebSocket: {}", fake.ws_url());
+    println!("Room: {}", fake.room().room_id);
+
+    let mut ticker = tokio::time::interval(Duration::from_secs(3));
+    let mut count = 0u64;
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                count += 1;
+                let mut batch = vec![messages::danmu_msg(
+                    1000 + count % 7,
+                    &format!("观众{}", count % 7),
+                    &format!("第 {} 条弹幕", count),
+                )];
+                if count.is_multiple_of(5) {
+                    batch.push(messages::send_gift(2000, "金主", 31036, "小花花", 1, 100));
+                }
+                fake.push(batch);
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+    Ok(())
+}
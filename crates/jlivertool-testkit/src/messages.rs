@@ -0,0 +1,97 @@
+//! Command messages in the shape the site sends them
+
+use serde_json::{json, Value};
+
+/// A `DANMU_MSG` without medal
+pub fn danmu_msg(uid: u64, uname: &str, content: &str) -> Value {
+    let now = chrono::Utc::now().timestamp_millis();
+    json!({
+        "cmd": "DANMU_MSG",
+        "info": [
+            [0, 1, 25, 16777215, now, 0, 0, "", 0, 0, 0, "", 0, "{}", "{}", {}],
+            content,
+            [uid, uname, 0, 0, 0, 10000, 1, ""],
+            [],
+            [0, 0, 9868950, ">50000", 0],
+            ["", ""],
+            0,
+            0,
+            null,
+            { "ts": now / 1000, "ct": "" },
+            0,
+            0,
+        ]
+    })
+}
+
+/// A `DANMU_MSG` with a fan medal of `anchor_roomid`
+pub fn danmu_msg_with_medal(
+    uid: u64,
+    uname: &str,
+    content: &str,
+    medal_name: &str,
+    medal_level: u8,
+    anchor_roomid: u64,
+) -> Value {
+    let mut message = danmu_msg(uid, uname, content);
+    message["info"][3] = json!([
+        medal_level,
+        medal_name,
+        "",
+        anchor_roomid,
+        6067854,
+        "",
+        0,
+        6067854,
+        6067854,
+        6067854,
+        0,
+        1,
+        0
+    ]);
+    message
+}
+
+/// A `SEND_GIFT`, `price` per gift in gold (1/1000 yuan)
+pub fn send_gift(
+    uid: u64,
+    uname: &str,
+    gift_id: u64,
+    gift_name: &str,
+    num: u32,
+    price: u64,
+) -> Value {
+    json!({
+        "cmd": "SEND_GIFT",
+        "data": {
+            "uid": uid,
+            "uname": uname,
+            "face": "",
+            "action": "投喂",
+            "giftId": gift_id,
+            "giftName": gift_name,
+            "num": num,
+            "price": price,
+            "coin_type": if price > 0 { "gold" } else { "silver" },
+            "total_coin": price * num as u64,
+            "timestamp": chrono::Utc::now().timestamp(),
+            "medal_info": {
+                "medal_level": 0,
+                "medal_name": "",
+                "anchor_uname": "",
+                "anchor_roomid": 0,
+                "guard_level": 0,
+            },
+        }
+    })
+}
+
+/// `LIVE`, the room went live
+pub fn live(room_id: u64) -> Value {
+    json!({ "cmd": "LIVE", "roomid": room_id, "live_time": chrono::Utc::now().timestamp() })
+}
+
+/// `PREPARING`, the room went offline
+pub fn preparing(room_id: u64) -> Value {
+    json!({ "cmd": "PREPARING", "roomid": room_id.to_string() })
+}
@@ -0,0 +1,115 @@
+//! Server side of the danmu binary protocol
+//!
+//! Packet format: [packetLen(4)][headerLen(2)][ver(2)][op(4)][seq(4)][body]
+
+use anyhow::{anyhow, Result};
+use jlivertool_core::bilibili::ws::{MessageOp, WsBodyVer};
+use serde_json::Value;
+use std::io::Write;
+
+const HEADER_LEN: usize = 16;
+
+/// How the fake packs command messages
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// One plain JSON packet per message
+    None,
+    /// Zlib packet wrapping all messages (ver 2)
+    Deflate,
+    /// Brotli packet wrapping all messages (ver 3), what the site sends
+    #[default]
+    Brotli,
+}
+
+/// A packet received from a client
+#[derive(Debug)]
+pub struct ClientPacket {
+    pub op: u32,
+    pub body: Vec<u8>,
+}
+
+impl ClientPacket {
+    /// Body as JSON, for auth packets
+    pub fn json(&self) -> Result<Value> {
+        Ok(serde_json::from_slice(&self.body)?)
+    }
+}
+
+/// Encode one packet
+pub fn packet(op: MessageOp, ver: WsBodyVer, body: &[u8]) -> Vec<u8> {
+    let mut buffer = Vec::with_capacity(HEADER_LEN + body.len());
+    buffer.extend_from_slice(&((HEADER_LEN + body.len()) as u32).to_be_bytes());
+    buffer.extend_from_slice(&(HEADER_LEN as u16).to_be_bytes());
+    buffer.extend_from_slice(&(ver as u16).to_be_bytes());
+    buffer.extend_from_slice(&(op as u32).to_be_bytes());
+    buffer.extend_from_slice(&1u32.to_be_bytes());
+    buffer.extend_from_slice(body);
+    buffer
+}
+
+/// Reply to the auth packet
+pub fn auth_reply() -> Vec<u8> {
+    packet(MessageOp::AuthReply, WsBodyVer::Heartbeat, br#"{"code":0}"#)
+}
+
+/// Reply to a heartbeat with the popularity count
+pub fn heartbeat_reply(popularity: u32) -> Vec<u8> {
+    packet(
+        MessageOp::KeepAliveReply,
+        WsBodyVer::Heartbeat,
+        &popularity.to_be_bytes(),
+    )
+}
+
+/// Encode command messages as WebSocket frames
+pub fn message_frames(messages: &[Value], compression: Compression) -> Result<Vec<Vec<u8>>> {
+    let plain = |message: &Value| -> Result<Vec<u8>> {
+        Ok(packet(
+            MessageOp::SendMsgReply,
+            WsBodyVer::Normal,
+            &serde_json::to_vec(message)?,
+        ))
+    };
+    if messages.is_empty() {
+        return Ok(Vec::new());
+    }
+    match compression {
+        Compression::None => messages.iter().map(plain).collect(),
+        Compression::Deflate | Compression::Brotli => {
+            let mut inner = Vec::new();
+            for message in messages {
+                inner.extend(plain(message)?);
+            }
+            let (ver, body) = if compression == Compression::Deflate {
+                let mut encoder =
+                    flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(&inner)?;
+                (WsBodyVer::Deflate, encoder.finish()?)
+            } else {
+                let mut body = Vec::new();
+                {
+                    let mut writer = brotli::CompressorWriter::new(&mut body, 4096, 5, 22);
+                    writer.write_all(&inner)?;
+                }
+                (WsBodyVer::Brotli, body)
+            };
+            Ok(vec![packet(MessageOp::SendMsgReply, ver, &body)])
+        }
+    }
+}
+
+/// Decode a packet sent by a client
+pub fn parse_client_packet(data: &[u8]) -> Result<ClientPacket> {
+    if data.len() < HEADER_LEN {
+        return Err(anyhow!("Packet too short"));
+    }
+    let packet_len = u32::from_be_bytes(data[0..4].try_into()?) as usize;
+    let header_len = u16::from_be_bytes(data[4..6].try_into()?) as usize;
+    if packet_len > data.len() || header_len > packet_len {
+        return Err(anyhow!("Invalid packet length"));
+    }
+    Ok(ClientPacket {
+        op: u32::from_be_bytes(data[8..12].try_into()?),
+        body: data[header_len..packet_len].to_vec(),
+    })
+}
@@ -7,10 +7,19 @@ use crate::views::MainView;
 use gpui::*;
 use gpui_component::init;
 use gpui_component::Root;
+use jlivertool_core::backup::BackupConfig;
+use jlivertool_core::bilibili::api::RoomSilence;
 use jlivertool_core::config::{ConfigStore, WindowConfig};
 use jlivertool_core::database::Database;
-use jlivertool_core::events::Event;
+use jlivertool_core::earnings::EarningKind;
+use jlivertool_core::events::Subscription as EventSubscription;
+use jlivertool_core::export::ExportFormat;
+use jlivertool_core::network::NetworkConfig;
+use jlivertool_core::obs::ObsConfig;
+use jlivertool_core::recorder::RecorderConfig;
+use jlivertool_core::retention::RetentionConfig;
 use jlivertool_core::types::WindowType;
+use jlivertool_core::webhook::WebhookEndpoint;
 use parking_lot::RwLock;
 use std::sync::atomic::AtomicBool;
 use std::sync::mpsc;
@@ -31,6 +40,21 @@ pub enum UiCommand {
     UpdateRoomTitle { room_id: u64, title: String },
     /// Start live streaming
     StartLive { room_id: u64, area_v2: u64 },
+    /// Load all live areas
+    RequestAreaList,
+    /// Change the area of the room
+    UpdateRoomArea { room_id: u64, area_id: u64 },
+    /// Load description, tags, announcement and cover of the room
+    RequestRoomDetails { room_id: u64 },
+    /// Save description, tags and announcement of the room
+    UpdateRoomDetails {
+        room_id: u64,
+        description: String,
+        tags: Vec<String>,
+        news: String,
+    },
+    /// Upload an image file as the room cover
+    UploadRoomCover { path: String },
     /// Stop live streaming
     StopLive { room_id: u64 },
     /// Update window display settings
@@ -75,6 +99,46 @@ pub enum UiCommand {
     UpdateTtsProvider(String),
     /// Test TTS
     TestTts,
+    /// Save OBS connection settings and rules
+    UpdateObsSettings(ObsConfig),
+    /// Connect to OBS with the saved settings
+    TestObsConnection,
+    /// Save proxy, timeout and certificate settings
+    UpdateNetworkSettings(NetworkConfig),
+    /// Reach the Bilibili API with the given, not yet saved, settings
+    TestNetwork(NetworkConfig),
+    /// Save data retention settings
+    UpdateRetentionSettings(RetentionConfig),
+    /// Load the size of each database table
+    RefreshDatabaseStats,
+    /// Apply retention and compact the database now
+    CompactDatabase,
+    /// Save scheduled backup settings
+    UpdateBackupSettings(BackupConfig),
+    /// Back up the database now
+    BackupNow,
+    /// List the backups on disk
+    RefreshBackups,
+    /// Replace the database with a backup, after backing up the current one
+    RestoreBackup(String),
+    /// Import the records of another database file, skipping duplicates
+    MergeDatabase(String),
+    /// Save stream recorder settings
+    UpdateRecorderSettings(RecorderConfig),
+    /// Save webhook endpoints
+    UpdateWebhooks(Vec<WebhookEndpoint>),
+    /// Load the latest webhook deliveries
+    RefreshWebhookDeliveries,
+    /// Start recording raw WebSocket frames of the room
+    StartSessionRecording,
+    /// Stop recording raw WebSocket frames
+    StopSessionRecording,
+    /// Replay a recorded session, speed 0 for as fast as possible
+    StartReplay { path: String, speed: f64 },
+    /// End the replay and reconnect to the room
+    StopReplay,
+    /// Load session recording and replay state
+    RefreshSessions,
     /// Refresh plugins list
     RefreshPlugins,
     /// Import plugin from GitHub URL
@@ -84,6 +148,11 @@ pub enum UiCommand {
         plugin_id: String,
         plugin_path: std::path::PathBuf,
     },
+    /// Update settings values of a plugin
+    SetPluginSettings {
+        plugin_id: String,
+        values: serde_json::Map<String, serde_json::Value>,
+    },
     /// Update advanced settings (max danmu count, log level)
     UpdateAdvancedSettings { max_danmu: usize, log_level: String },
     /// Clear all data (danmu, gifts, superchats)
@@ -96,23 +165,63 @@ pub enum UiCommand {
     UpdateAutoUpdateCheck(bool),
     /// Update plugin server ports
     UpdatePluginPorts { ws_port: u16, http_port: u16 },
+    /// Block (mute) a user, hours is `BLOCK_THIS_LIVE`, `BLOCK_PERMANENT` or a duration
+    MuteUser { room_id: u64, uid: u64, hours: i32 },
+    /// Unblock (unmute) a user
+    UnmuteUser { room_id: u64, uid: u64 },
+    /// Fetch blocked users of a room
+    FetchBlockedUsers { room_id: u64 },
+    /// Set room-wide silence, minutes 0 keeps it until lifted
+    SetRoomSilence {
+        room_id: u64,
+        silence: RoomSilence,
+        minutes: u32,
+    },
+    /// Appoint a room admin
+    AddRoomAdmin { uid: u64 },
+    /// Dismiss a room admin
+    RemoveRoomAdmin { uid: u64 },
+    /// Request a snapshot of received WebSocket cmds
+    RequestCmdStats,
+    /// Reset received WebSocket cmd statistics
+    ClearCmdStats,
+    /// Export danmaku of `[from, to)` to the exports folder
+    ExportDanmaku {
+        room_id: u64,
+        from: i64,
+        to: i64,
+        format: ExportFormat,
+    },
+    /// Export gifts, guards and superchats of `[from, to)` with daily and
+    /// per-user summaries to the exports folder
+    ExportEarnings { room_id: u64, from: i64, to: i64 },
+    /// Create a goal for the current room, target in 1/1000 yuan
+    CreateGoal {
+        title: String,
+        target: u64,
+        sources: Vec<EarningKind>,
+    },
+    /// Restart a goal from zero
+    ResetGoal { id: i64 },
+    /// Mark a goal as completed
+    CompleteGoal { id: i64 },
 }
 
-/// Wrapper for event receiver with a flag to indicate pending events
+/// Wrapper for the UI bus subscription with a flag to indicate pending events
 pub struct EventReceiver {
-    pub rx: mpsc::Receiver<Event>,
+    pub rx: EventSubscription,
     pub has_events: Arc<AtomicBool>,
 }
 
 impl EventReceiver {
-    pub fn new(rx: mpsc::Receiver<Event>, has_events: Arc<AtomicBool>) -> Self {
+    pub fn new(rx: EventSubscription, has_events: Arc<AtomicBool>) -> Self {
         Self { rx, has_events }
     }
 }
 
 /// Run the GPUI application
 pub fn run_app(
-    event_rx: mpsc::Receiver<Event>,
+    event_rx: EventSubscription,
     command_tx: mpsc::Sender<UiCommand>,
     database: Option<Arc<Database>>,
     config: Option<Arc<RwLock<ConfigStore>>>,
@@ -131,7 +240,7 @@ pub fn run_app(
 
 /// Run the GPUI application with plugin info
 pub fn run_app_with_plugins(
-    event_rx: mpsc::Receiver<Event>,
+    event_rx: EventSubscription,
     command_tx: mpsc::Sender<UiCommand>,
     database: Option<Arc<Database>>,
     config: Option<Arc<RwLock<ConfigStore>>>,
@@ -216,7 +325,7 @@ pub fn run_app_with_plugins(
 
 /// Run the GPUI application with plugin info and tray support
 pub fn run_app_with_tray(
-    event_rx: mpsc::Receiver<Event>,
+    event_rx: EventSubscription,
     command_tx: mpsc::Sender<UiCommand>,
     database: Option<Arc<Database>>,
     config: Option<Arc<RwLock<ConfigStore>>>,
@@ -235,6 +344,16 @@ pub fn run_app_with_tray(
         // Initialize gpui-component
         init(cx);
 
+        // Store messages still waiting for the database writer, `run` does
+        // not return on every platform
+        if let Some(database) = database.clone() {
+            cx.on_app_quit(move |_| {
+                database.close();
+                async {}
+            })
+            .detach();
+        }
+
         // Configure gpui-component theme colors based on current theme
         update_gpui_component_theme(cx);
 
@@ -5,6 +5,7 @@ use futures::future::BoxFuture;
 use gpui::http_client::{AsyncBody, HttpClient, Request, Response, Url};
 use http_body_util::BodyExt;
 use isahc::prelude::*;
+use jlivertool_core::network::{self, NetworkConfig};
 use parking_lot::RwLock;
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -76,7 +77,8 @@ impl ImageCache {
 
 /// HTTP client implementation using isahc with image caching
 pub struct IsahcHttpClient {
-    client: isahc::HttpClient,
+    /// Client and the network settings generation it was built for
+    client: RwLock<(u64, isahc::HttpClient)>,
     user_agent: gpui::http_client::http::HeaderValue,
     cache: Arc<RwLock<ImageCache>>,
     cache_ttl: Duration,
@@ -84,19 +86,46 @@ pub struct IsahcHttpClient {
 
 impl IsahcHttpClient {
     pub fn new() -> anyhow::Result<Arc<Self>> {
-        let client = isahc::HttpClient::builder()
-            .timeout(std::time::Duration::from_secs(30))
-            .redirect_policy(isahc::config::RedirectPolicy::Limit(10))
-            .build()?;
+        let generation = network::generation();
+        let client = Self::build_client(&network::current())?;
 
         Ok(Arc::new(Self {
-            client,
+            client: RwLock::new((generation, client)),
             user_agent: gpui::http_client::http::HeaderValue::from_static("JLiverTool/0.1"),
             cache: Arc::new(RwLock::new(ImageCache::new(500))), // Cache up to 500 images
             cache_ttl: Duration::from_secs(30 * 60),  // 30 minutes TTL
         }))
     }
 
+    /// Build a client with the proxy and timeouts of `config`
+    ///
+    /// The custom CA is not applied: curl would use it instead of the
+    /// system certificates rather than in addition to them.
+    fn build_client(config: &NetworkConfig) -> anyhow::Result<isahc::HttpClient> {
+        let mut builder = isahc::HttpClient::builder()
+            .timeout(config.request_timeout())
+            .connect_timeout(config.connect_timeout())
+            .redirect_policy(isahc::config::RedirectPolicy::Limit(10));
+        if config.proxy()?.is_some() {
+            builder = builder
+                .proxy(Some(config.proxy.trim().parse::<isahc::http::Uri>()?))
+                .proxy_blacklist(["localhost", "127.0.0.1", "::1"]);
+        }
+        Ok(builder.build()?)
+    }
+
+    /// Client for the current network settings
+    fn client(&self) -> isahc::HttpClient {
+        let generation = network::generation();
+        if self.client.read().0 != generation {
+            match Self::build_client(&network::current()) {
+                Ok(client) => *self.client.write() = (generation, client),
+                Err(e) => tracing::warn!("Invalid network settings for image loading: {}", e),
+            }
+        }
+        self.client.read().1.clone()
+    }
+
     /// Check if URL is likely an image request
     fn is_image_url(url: &str) -> bool {
         let lower = url.to_lowercase();
@@ -128,7 +157,7 @@ impl HttpClient for IsahcHttpClient {
         &self,
         req: Request<AsyncBody>,
     ) -> BoxFuture<'static, anyhow::Result<Response<AsyncBody>>> {
-        let client = self.client.clone();
+        let client = self.client();
         let url = req.uri().to_string();
         let is_image = Self::is_image_url(&url);
 
@@ -8,9 +8,12 @@
 use crate::theme::Colors;
 use gpui::*;
 use gpui_component::h_flex;
+use jlivertool_core::filter::DisplayFilter;
 use jlivertool_core::messages::{
-    DanmuMessage, EntryEffectMessage, GiftMessage, GuardMessage, InteractMessage, SuperChatMessage,
+    DanmuMessage, EntryEffectMessage, GiftMessage, GuardMessage, InteractMessage, LikeClickMessage,
+    SuperChatMessage,
 };
+use jlivertool_core::text::estimate_text_width;
 use regex::Regex;
 use std::sync::LazyLock;
 
@@ -98,6 +101,32 @@ pub enum DisplayMessage {
     Gift(GiftMessage),
     Guard(GuardMessage),
     SuperChat(SuperChatMessage),
+    LikeClick(LikeClickMessage),
+    Notice(NoticeMessage),
+}
+
+/// Kind of a room notice, decides its accent color
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum NoticeKind {
+    RedPocket,
+    Lottery,
+    Moderation,
+}
+
+/// One-line room notice (red pockets, lotteries, silence and blocks)
+#[derive(Clone)]
+pub struct NoticeMessage {
+    pub kind: NoticeKind,
+    pub text: String,
+}
+
+impl NoticeMessage {
+    pub fn new(kind: NoticeKind, text: impl Into<String>) -> Self {
+        Self {
+            kind,
+            text: text.into(),
+        }
+    }
 }
 
 /// A single row in the rendered danmu list.
@@ -132,27 +161,12 @@ pub enum RenderRow {
     },
 }
 
-/// Estimate the rendered width of a string in pixels.
-/// CJK characters are approximately `font_size` wide.
-/// ASCII characters are approximately `font_size * 0.55` wide.
-pub fn estimate_text_width(text: &str, font_size: f32) -> f32 {
-    let mut width = 0.0f32;
-    for ch in text.chars() {
-        if ch.is_ascii() {
-            width += font_size * 0.55;
-        } else {
-            width += font_size;
-        }
-    }
-    width
-}
-
 /// Estimate the pixel width of the prefix elements (medal badge + username + colon + gaps).
 pub fn estimate_danmu_prefix_width(
     danmu: &DanmuMessage,
     font_size: f32,
     lite_mode: bool,
-    medal_display: bool,
+    display_filter: &DisplayFilter,
 ) -> f32 {
     let mut width = 0.0f32;
 
@@ -162,7 +176,7 @@ pub fn estimate_danmu_prefix_width(
 
     let sender = &danmu.sender;
     let medal = &sender.medal_info;
-    let show_medal = medal_display && !medal.medal_name.is_empty() && medal.is_lighted && !lite_mode;
+    let show_medal = display_filter.shows_medal(medal) && !lite_mode;
 
     // Medal badge width (approximate)
     if show_medal {
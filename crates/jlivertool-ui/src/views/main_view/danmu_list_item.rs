@@ -1,15 +1,21 @@
 //! Danmu list item view
 //!
 //! This module contains the unified view for rendering different types of
-//! messages in the danmu list (danmu, interact, entry effect, gift, guard, superchat).
+//! messages in the danmu list (danmu, interact, entry effect, gift, guard, superchat,
+//! likes and room notices).
 
-use super::content_rendering::{guard_icon_url, guard_level_name, render_content_with_links, DisplayMessage, RenderRow};
+use super::content_rendering::{
+    guard_icon_url, guard_level_name, render_content_with_links, DisplayMessage, NoticeKind,
+    NoticeMessage, RenderRow,
+};
 use super::user_info_card::{SelectedUser, SelectedUserState};
 use crate::theme::Colors;
 use gpui::*;
 use gpui_component::h_flex;
+use jlivertool_core::filter::DisplayFilter;
 use jlivertool_core::messages::{
-    DanmuMessage, EntryEffectMessage, GiftMessage, GuardMessage, InteractMessage, SuperChatMessage,
+    DanmuMessage, EntryEffectMessage, GiftMessage, GuardMessage, InteractMessage, LikeClickMessage,
+    SuperChatMessage,
 };
 
 /// Unified view for rendering any display message type
@@ -18,7 +24,7 @@ pub struct DanmuListItemView {
     index: usize,
     font_size: f32,
     lite_mode: bool,
-    medal_display: bool,
+    display_filter: DisplayFilter,
     opacity: f32,
     selected_user: SelectedUserState,
 }
@@ -29,7 +35,7 @@ impl DanmuListItemView {
         index: usize,
         font_size: f32,
         lite_mode: bool,
-        medal_display: bool,
+        display_filter: DisplayFilter,
         opacity: f32,
         selected_user: SelectedUserState,
     ) -> Self {
@@ -38,7 +44,7 @@ impl DanmuListItemView {
             index,
             font_size,
             lite_mode,
-            medal_display,
+            display_filter,
             opacity,
             selected_user,
         }
@@ -59,7 +65,7 @@ impl DanmuListItemView {
         let row_height = self.row_height();
         let sender = &danmu.sender;
         let medal = &sender.medal_info;
-        let show_medal = self.medal_display && !medal.medal_name.is_empty() && medal.is_lighted;
+        let show_medal = self.display_filter.shows_medal(medal);
 
         // Use fixed height for uniform_list
         let mut el = h_flex()
@@ -307,6 +313,92 @@ impl DanmuListItemView {
         el
     }
 
+    fn render_like_click(&self, like: &LikeClickMessage) -> Div {
+        let font_size = self.font_size;
+        let lite_mode = self.lite_mode;
+        let opacity = self.opacity;
+        let row_height = self.row_height();
+
+        let like_text = if like.like_text.is_empty() {
+            "为主播点赞了"
+        } else {
+            like.like_text.as_str()
+        };
+
+        let mut el = h_flex()
+            .w_full()
+            .h(px(row_height))
+            .gap_1()
+            .items_center()
+            .rounded_sm()
+            .hover(|s| s.bg(Colors::bg_hover_with_opacity(opacity)))
+            .overflow_hidden();
+
+        if lite_mode {
+            el = el.px_1();
+        } else {
+            el = el.px_2();
+        }
+
+        el = el
+            .child(
+                div()
+                    .text_size(px(font_size))
+                    .text_color(Colors::text_muted())
+                    .child(like.sender.uname.clone()),
+            )
+            .child(
+                div()
+                    .text_size(px(font_size))
+                    .text_color(Colors::text_muted())
+                    .child(like_text.to_string()),
+            );
+
+        el
+    }
+
+    fn render_notice(&self, notice: &NoticeMessage) -> Div {
+        let font_size = self.font_size;
+        let lite_mode = self.lite_mode;
+        let opacity = self.opacity;
+        let row_height = self.row_height();
+
+        let accent = match notice.kind {
+            // Red #E5534B
+            NoticeKind::RedPocket => hsla(3.0 / 360.0, 0.74, 0.6, 1.0),
+            // Golden color #EDB83F
+            NoticeKind::Lottery => hsla(42.0 / 360.0, 0.85, 0.59, 1.0),
+            NoticeKind::Moderation => Colors::text_muted(),
+        };
+
+        let mut el = h_flex()
+            .w_full()
+            .h(px(row_height))
+            .gap_1()
+            .items_center()
+            .rounded_sm()
+            .border_l_2()
+            .border_color(accent)
+            .hover(|s| s.bg(Colors::bg_hover_with_opacity(opacity)))
+            .overflow_hidden();
+
+        if lite_mode {
+            el = el.px_1();
+        } else {
+            el = el.px_2();
+        }
+
+        el.child(
+            div()
+                .text_size(px(font_size))
+                .text_color(accent)
+                .whitespace_nowrap()
+                .overflow_hidden()
+                .text_ellipsis()
+                .child(notice.text.clone()),
+        )
+    }
+
     fn render_entry_effect(&self, entry: &EntryEffectMessage) -> Div {
         let font_size = self.font_size;
         let lite_mode = self.lite_mode;
@@ -614,6 +706,8 @@ impl DanmuListItemView {
                 DisplayMessage::Gift(gift) => self.render_gift(gift),
                 DisplayMessage::Guard(guard) => self.render_guard(guard),
                 DisplayMessage::SuperChat(sc) => self.render_superchat(sc),
+                DisplayMessage::LikeClick(like) => self.render_like_click(like),
+                DisplayMessage::Notice(notice) => self.render_notice(notice),
             },
             RenderRow::DanmuFirstLine { danmu, content_slice } => {
                 self.render_danmu_first_line(danmu, content_slice)
@@ -638,7 +732,7 @@ impl DanmuListItemView {
         let row_height = self.row_height();
         let sender = &danmu.sender;
         let medal = &sender.medal_info;
-        let show_medal = self.medal_display && !medal.medal_name.is_empty() && medal.is_lighted;
+        let show_medal = self.display_filter.shows_medal(medal);
 
         let mut el = h_flex()
             .w_full()
@@ -1,13 +1,14 @@
 //! Event processing for MainView
 
-use super::{DisplayMessage, MainView, MAX_DANMU_COUNT};
+use super::{DisplayMessage, MainView, NoticeKind, NoticeMessage, MAX_DANMU_COUNT};
 use gpui::Context;
 use jlivertool_core::events::Event;
+use jlivertool_core::filter::DisplayFilter;
 
 impl MainView {
     pub(super) fn process_events(&mut self, cx: &mut Context<Self>) {
         let mut list_modified = false;
-        while let Ok(event) = self.event_rx.try_recv() {
+        while let Some(event) = self.event_rx.try_recv() {
             match event {
                 Event::UpdateRoom {
                     room_id,
@@ -61,6 +62,8 @@ impl MainView {
 
                     if is_new_room {
                         self.live_status = live_status;
+                        self.like_count = 0;
+                        self.watched_text.clear();
                     }
 
                     // Update tray state
@@ -70,7 +73,7 @@ impl MainView {
                     self.online_count = count;
                 }
                 Event::NewDanmu(danmu) => {
-                    if !danmu.is_generated {
+                    if self.display_filter.shows_danmu(&danmu) {
                         let should_auto_scroll = self.is_at_bottom();
                         self.danmu_list.push_back(DisplayMessage::Danmu(danmu));
                         if should_auto_scroll {
@@ -83,7 +86,7 @@ impl MainView {
                     }
                 }
                 Event::NewInteract(interact) => {
-                    if self.interact_display {
+                    if self.display_filter.shows_interact() {
                         let should_auto_scroll = self.is_at_bottom();
                         self.danmu_list
                             .push_back(DisplayMessage::Interact(interact));
@@ -97,14 +100,7 @@ impl MainView {
                     }
                 }
                 Event::NewEntryEffect(entry) => {
-                    let is_guard_entry = entry.privilege_type >= 1 && entry.privilege_type <= 3;
-                    let should_display = if is_guard_entry {
-                        self.guard_effect
-                    } else {
-                        self.level_effect
-                    };
-
-                    if should_display {
+                    if self.display_filter.shows_entry_effect(&entry) {
                         let should_auto_scroll = self.is_at_bottom();
                         self.danmu_list
                             .push_back(DisplayMessage::EntryEffect(entry));
@@ -220,6 +216,12 @@ impl MainView {
                     max_danmu_count,
                     log_level,
                     auto_update_check,
+                    obs,
+                    webhooks,
+                    recorder,
+                    network,
+                    retention,
+                    backup,
                 } => {
                     crate::theme::set_theme(&theme);
 
@@ -246,14 +248,22 @@ impl MainView {
                         view.set_advanced_settings(max_danmu_count, log_level, cx);
                         // Set auto update check setting
                         view.set_auto_update_check(auto_update_check, cx);
+                        view.set_obs_config(obs, cx);
+                        view.set_webhooks(webhooks, cx);
+                        view.set_recorder_config(recorder, cx);
+                        view.set_network_config(network, cx);
+                        view.set_retention_config(retention, cx);
+                        view.set_backup_config(backup, cx);
                     });
                     self.opacity = opacity;
                     self.font_size = font_size;
                     self.lite_mode = lite_mode;
-                    self.medal_display = medal_display;
-                    self.interact_display = interact_display;
-                    self.guard_effect = guard_effect;
-                    self.level_effect = level_effect;
+                    self.display_filter = DisplayFilter {
+                        medal_display,
+                        interact_display,
+                        guard_effect,
+                        level_effect,
+                    };
                     self.always_on_top = always_on_top;
                     // Force rebuild of render rows
                     self.last_render_width = 0.0;
@@ -313,6 +323,8 @@ impl MainView {
                             version: p.version,
                             enabled: true,
                             path: p.path,
+                            settings: p.settings,
+                            setting_values: p.setting_values,
                         })
                         .collect();
                     self.set_plugins(ui_plugins, cx);
@@ -368,6 +380,209 @@ impl MainView {
                         view.set_update_status(status, cx);
                     });
                 }
+                Event::ModerationResult { message, .. } => {
+                    self.push_system_message(message);
+                    list_modified = true;
+                }
+                Event::BlockedUsersFetched { users, total } => {
+                    let message = if users.is_empty() {
+                        "禁言列表为空".to_string()
+                    } else {
+                        let names: Vec<String> = users
+                            .iter()
+                            .map(|u| format!("{}({})", u.tname, u.tuid))
+                            .collect();
+                        format!("禁言列表（共 {} 人）：{}", total, names.join("、"))
+                    };
+                    self.push_system_message(message);
+                    list_modified = true;
+                }
+                Event::UpdateLikeCount { count } => {
+                    self.like_count = count;
+                }
+                Event::UpdateWatched { text, .. } => {
+                    self.watched_text = text;
+                }
+                Event::NewLikeClick(like) => {
+                    if self.display_filter.shows_interact() {
+                        self.push_display_message(DisplayMessage::LikeClick(like));
+                        list_modified = true;
+                    }
+                }
+                Event::RedPocketStart(pocket) => {
+                    let text = format!(
+                        "{} 发出了 ¥{:.1} 红包，发送「{}」参与",
+                        pocket.sender.uname,
+                        pocket.total_price as f64 / 1000.0,
+                        pocket.danmu
+                    );
+                    self.push_display_message(DisplayMessage::Notice(NoticeMessage::new(
+                        NoticeKind::RedPocket,
+                        text,
+                    )));
+                    list_modified = true;
+                }
+                Event::RedPocketWinners(result) => {
+                    let text = format!("红包开奖：{}", winners_text(&result.winners));
+                    self.push_display_message(DisplayMessage::Notice(NoticeMessage::new(
+                        NoticeKind::RedPocket,
+                        text,
+                    )));
+                    list_modified = true;
+                }
+                Event::AnchorLotStart(lot) => {
+                    let requirement = if !lot.danmu.is_empty() {
+                        format!("发送「{}」参与", lot.danmu)
+                    } else if !lot.gift_name.is_empty() {
+                        format!("赠送 {}x{} 参与", lot.gift_name, lot.gift_num)
+                    } else {
+                        lot.require_text.clone()
+                    };
+                    let text = format!(
+                        "天选时刻：{}x{}，{}",
+                        lot.award_name, lot.award_num, requirement
+                    );
+                    self.push_display_message(DisplayMessage::Notice(NoticeMessage::new(
+                        NoticeKind::Lottery,
+                        text,
+                    )));
+                    list_modified = true;
+                }
+                Event::AnchorLotAward(award) => {
+                    let text = format!(
+                        "天选开奖（{}）：{}",
+                        award.award_name,
+                        winners_text(&award.winners)
+                    );
+                    self.push_display_message(DisplayMessage::Notice(NoticeMessage::new(
+                        NoticeKind::Lottery,
+                        text,
+                    )));
+                    list_modified = true;
+                }
+                Event::SuperChatDeleted { ids } => {
+                    let before = self.danmu_list.len();
+                    self.danmu_list.retain(|msg| match msg {
+                        DisplayMessage::SuperChat(sc) => !ids.contains(&sc.id),
+                        _ => true,
+                    });
+                    if self.danmu_list.len() != before {
+                        list_modified = true;
+                    }
+                    self.superchat_view.update(cx, |view, cx| {
+                        view.remove_superchats(&ids, cx);
+                    });
+                }
+                Event::RoomSilent(silent) => {
+                    let text = if !silent.enabled {
+                        "全场禁言已解除".to_string()
+                    } else {
+                        match silent.silence_type.as_str() {
+                            "level" => format!("全场禁言：用户等级低于 {} 级禁止发言", silent.level),
+                            "medal" => format!("全场禁言：粉丝勋章低于 {} 级禁止发言", silent.level),
+                            _ => "全场禁言已开启".to_string(),
+                        }
+                    };
+                    self.push_display_message(DisplayMessage::Notice(NoticeMessage::new(
+                        NoticeKind::Moderation,
+                        text,
+                    )));
+                    list_modified = true;
+                }
+                Event::UserBlocked(block) => {
+                    let operator = if block.operator == 2 { "主播" } else { "房管" };
+                    let text = format!("{} 已被{}禁言", block.uname, operator);
+                    self.push_display_message(DisplayMessage::Notice(NoticeMessage::new(
+                        NoticeKind::Moderation,
+                        text,
+                    )));
+                    list_modified = true;
+                }
+                Event::ExportFinished { message, .. } => {
+                    self.statistics_view.update(cx, |view, cx| {
+                        view.set_export_status(message, cx);
+                    });
+                }
+                Event::GoalProgress(goal) => {
+                    self.goals.retain(|g| g.id != goal.id);
+                    if goal.completed_at.is_none() {
+                        self.goals.push(goal.clone());
+                        self.goals.sort_by_key(|g| g.id);
+                    }
+                    self.statistics_view.update(cx, |view, cx| {
+                        view.update_goal(goal, cx);
+                    });
+                }
+                Event::GoalCommandResult { message, .. } => {
+                    self.statistics_view.update(cx, |view, cx| {
+                        view.set_goal_status(message, cx);
+                    });
+                }
+                Event::ObsStatus { connected, message } => {
+                    self.setting_view.update(cx, |view, cx| {
+                        view.set_obs_status(connected, message, cx);
+                    });
+                }
+                Event::NetworkStatus { success, message } => {
+                    self.setting_view.update(cx, |view, cx| {
+                        view.set_network_status(success, message, cx);
+                    });
+                }
+                Event::DatabaseStatus { stats, message } => {
+                    self.setting_view.update(cx, |view, cx| {
+                        view.set_database_status(stats, message, cx);
+                    });
+                }
+                Event::BackupStatus { backups, message } => {
+                    self.setting_view.update(cx, |view, cx| {
+                        view.set_backup_status(backups, message, cx);
+                    });
+                }
+                Event::RecorderStatus { recording, message } => {
+                    self.setting_view.update(cx, |view, cx| {
+                        view.set_recorder_status(recording, message, cx);
+                    });
+                }
+                Event::AreaList(areas) => {
+                    self.setting_view.update(cx, |view, cx| {
+                        view.set_area_list(areas, cx);
+                    });
+                }
+                Event::RoomDetails {
+                    description,
+                    tags,
+                    news,
+                    cover,
+                } => {
+                    self.setting_view.update(cx, |view, cx| {
+                        view.set_room_details(description, tags, news, cover, cx);
+                    });
+                }
+                Event::RoomEditResult { success, message } => {
+                    self.setting_view.update(cx, |view, cx| {
+                        view.set_room_edit_result(success, message, cx);
+                    });
+                }
+                Event::SessionStatus {
+                    recording,
+                    replaying,
+                    files,
+                    message,
+                } => {
+                    self.setting_view.update(cx, |view, cx| {
+                        view.set_session_status(recording, replaying, files, message, cx);
+                    });
+                }
+                Event::WebhookDeliveries(deliveries) => {
+                    self.setting_view.update(cx, |view, cx| {
+                        view.set_webhook_deliveries(deliveries, cx);
+                    });
+                }
+                Event::CmdStatsUpdated(stats) => {
+                    self.setting_view.update(cx, |view, cx| {
+                        view.set_cmd_stats(stats, cx);
+                    });
+                }
                 _ => {}
             }
         }
@@ -377,4 +592,36 @@ impl MainView {
             self.render_rows = std::rc::Rc::new(Vec::new());
         }
     }
+
+    /// Append a message, trimming and scrolling only if the list was at the bottom
+    fn push_display_message(&mut self, msg: DisplayMessage) {
+        let should_auto_scroll = self.is_at_bottom();
+        self.danmu_list.push_back(msg);
+        if should_auto_scroll {
+            while self.danmu_list.len() > MAX_DANMU_COUNT {
+                self.danmu_list.pop_front();
+            }
+            self.scroll_to_bottom();
+        }
+    }
+}
+
+/// Maximum number of winner names listed in a lottery notice
+const MAX_LISTED_WINNERS: usize = 5;
+
+/// Format lottery winners as "a、b、c 等 N 人"
+fn winners_text(winners: &[jlivertool_core::messages::LotteryWinner]) -> String {
+    if winners.is_empty() {
+        return "无人中奖".to_string();
+    }
+    let names: Vec<&str> = winners
+        .iter()
+        .take(MAX_LISTED_WINNERS)
+        .map(|w| w.uname.as_str())
+        .collect();
+    if winners.len() > MAX_LISTED_WINNERS {
+        format!("{} 等 {} 人", names.join("、"), winners.len())
+    } else {
+        names.join("、")
+    }
 }
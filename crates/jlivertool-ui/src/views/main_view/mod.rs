@@ -14,9 +14,10 @@ mod render;
 mod user_info_card;
 
 pub use content_rendering::{render_content_with_links, DisplayMessage, RenderRow};
-use content_rendering::{estimate_danmu_prefix_width, estimate_text_width, split_content_to_lines};
+use content_rendering::{NoticeKind, NoticeMessage};
+use content_rendering::{estimate_danmu_prefix_width, split_content_to_lines};
 use danmu_list_item::DanmuListItemView;
-use user_info_card::{SelectedUserState, UserInfoCard};
+use user_info_card::{CardModeration, SelectedUserState, UserInfoCard};
 
 use crate::app::UiCommand;
 use crate::tray::{TrayManager, TrayState};
@@ -26,8 +27,12 @@ use crate::views::SettingView;
 use crate::views::StatisticsView;
 use crate::views::SuperChatView;
 use gpui::*;
+use jlivertool_core::bilibili::api::{RoomSilence, BLOCK_PERMANENT, BLOCK_THIS_LIVE};
 use jlivertool_core::database::Database;
-use jlivertool_core::events::Event;
+use jlivertool_core::events::Subscription as EventSubscription;
+use jlivertool_core::filter::DisplayFilter;
+use jlivertool_core::goals::Goal;
+use jlivertool_core::text::estimate_text_width;
 use jlivertool_core::types::RoomId;
 use std::cell::Cell;
 use std::cell::RefCell;
@@ -45,13 +50,29 @@ const MAX_DANMU_COUNT: usize = 200;
 pub const AVAILABLE_COMMANDS: &[(&str, &str)] = &[
     ("/title", "修改直播间标题"),
     ("/bye", "关闭直播"),
+    ("/mute", "禁言用户: /mute <UID或用户名> [小时|永久]"),
+    ("/unmute", "解除禁言: /unmute <UID或用户名>"),
+    ("/mutelist", "查看禁言列表"),
+    ("/silence", "全场禁言: /silence <level|medal|off> [等级] [分钟]"),
     #[cfg(debug_assertions)]
     ("/debug", "调试: sc/gift/guard/danmu"),
 ];
 
+/// Commands that take arguments, completed with a trailing space
+const COMMANDS_WITH_ARGS: &[&str] = &["/title", "/mute", "/unmute", "/silence"];
+
+/// Text inserted into the input box when a command is picked from the popup
+pub(super) fn command_insert_text(cmd: &str) -> String {
+    if COMMANDS_WITH_ARGS.contains(&cmd) {
+        format!("{} ", cmd)
+    } else {
+        cmd.to_string()
+    }
+}
+
 /// Main window view state
 pub struct MainView {
-    event_rx: mpsc::Receiver<Event>,
+    event_rx: EventSubscription,
     #[allow(dead_code)] // Used indirectly through closures
     command_tx: mpsc::Sender<UiCommand>,
     /// Flag indicating there are pending events to process (used by timer)
@@ -62,6 +83,12 @@ pub struct MainView {
     live_status: u8,
     area_id: u64,
     online_count: u64,
+    /// Total likes of the current live, from LIKE_INFO_V3_UPDATE
+    like_count: u64,
+    /// Watched count text, e.g. "1.2万人看过"
+    watched_text: String,
+    /// Active gift goals, shown in the header
+    goals: Vec<Goal>,
     connected: bool,
     danmu_list: VecDeque<DisplayMessage>,
     /// Flattened render rows for the uniform_list (1 source message → 1-2 rows)
@@ -92,10 +119,7 @@ pub struct MainView {
     font_size: f32,
     // Window display settings
     lite_mode: bool,
-    medal_display: bool,
-    interact_display: bool,
-    guard_effect: bool,
-    level_effect: bool,
+    display_filter: DisplayFilter,
     // Always on top
     always_on_top: bool,
     // Flag to apply always_on_top on next render
@@ -153,7 +177,7 @@ pub struct UpdateDialogInfo {
 
 impl MainView {
     pub fn new(
-        event_rx: mpsc::Receiver<Event>,
+        event_rx: EventSubscription,
         command_tx: mpsc::Sender<UiCommand>,
         has_events: Arc<AtomicBool>,
         cx: &mut Context<Self>,
@@ -264,39 +288,38 @@ impl MainView {
                     });
                     // Update local settings and clean up messages if settings are disabled
                     let _ = entity.update(cx, |view, cx| {
-                        let old_interact_display = view.interact_display;
-                        let old_guard_effect = view.guard_effect;
-                        let old_level_effect = view.level_effect;
+                        let old_filter = view.display_filter;
+                        let filter = DisplayFilter {
+                            medal_display,
+                            interact_display,
+                            guard_effect,
+                            level_effect,
+                        };
 
                         view.lite_mode = lite_mode;
-                        view.medal_display = medal_display;
-                        view.interact_display = interact_display;
-                        view.guard_effect = guard_effect;
-                        view.level_effect = level_effect;
+                        view.display_filter = filter;
 
                         // Force rebuild of render rows on layout-affecting changes
                         view.last_render_width = 0.0;
                         view.render_rows_source_count = 0;
 
-                        // Remove interact messages if interact_display was disabled
-                        if old_interact_display && !interact_display {
-                            view.danmu_list
-                                .retain(|msg| !matches!(msg, DisplayMessage::Interact(_)));
+                        // Remove interact and like messages if interact_display was disabled
+                        if old_filter.shows_interact() && !filter.shows_interact() {
+                            view.danmu_list.retain(|msg| {
+                                !matches!(
+                                    msg,
+                                    DisplayMessage::Interact(_) | DisplayMessage::LikeClick(_)
+                                )
+                            });
                         }
 
                         // Remove entry effect messages based on settings changes
-                        if (old_guard_effect && !guard_effect)
-                            || (old_level_effect && !level_effect)
+                        if (old_filter.guard_effect && !guard_effect)
+                            || (old_filter.level_effect && !level_effect)
                         {
                             view.danmu_list.retain(|msg| {
                                 if let DisplayMessage::EntryEffect(entry) = msg {
-                                    let is_guard =
-                                        entry.privilege_type >= 1 && entry.privilege_type <= 3;
-                                    if is_guard {
-                                        guard_effect // Keep if guard_effect is still enabled
-                                    } else {
-                                        level_effect // Keep if level_effect is still enabled
-                                    }
+                                    filter.shows_entry_effect(entry)
                                 } else {
                                     true // Keep non-entry-effect messages
                                 }
@@ -354,6 +377,111 @@ impl MainView {
                 }
             });
 
+            view.on_obs_settings_change({
+                let tx = command_tx.clone();
+                move |config, _window, _cx| {
+                    let _ = tx.send(UiCommand::UpdateObsSettings(config));
+                }
+            });
+
+            view.on_obs_test({
+                let tx = command_tx.clone();
+                move |_window, _cx| {
+                    let _ = tx.send(UiCommand::TestObsConnection);
+                }
+            });
+
+            view.on_network_settings_change({
+                let tx = command_tx.clone();
+                move |config, _window, _cx| {
+                    let _ = tx.send(UiCommand::UpdateNetworkSettings(config));
+                }
+            });
+
+            view.on_network_test({
+                let tx = command_tx.clone();
+                move |config, _window, _cx| {
+                    let _ = tx.send(UiCommand::TestNetwork(config));
+                }
+            });
+
+            view.on_retention_settings_change({
+                let tx = command_tx.clone();
+                move |config, _window, _cx| {
+                    let _ = tx.send(UiCommand::UpdateRetentionSettings(config));
+                }
+            });
+
+            view.on_refresh_database_stats({
+                let tx = command_tx.clone();
+                move |_window, _cx| {
+                    let _ = tx.send(UiCommand::RefreshDatabaseStats);
+                }
+            });
+
+            view.on_compact_database({
+                let tx = command_tx.clone();
+                move |_window, _cx| {
+                    let _ = tx.send(UiCommand::CompactDatabase);
+                }
+            });
+
+            view.on_backup_settings_change({
+                let tx = command_tx.clone();
+                move |config, _window, _cx| {
+                    let _ = tx.send(UiCommand::UpdateBackupSettings(config));
+                }
+            });
+
+            view.on_backup_now({
+                let tx = command_tx.clone();
+                move |_window, _cx| {
+                    let _ = tx.send(UiCommand::BackupNow);
+                }
+            });
+
+            view.on_refresh_backups({
+                let tx = command_tx.clone();
+                move |_window, _cx| {
+                    let _ = tx.send(UiCommand::RefreshBackups);
+                }
+            });
+
+            view.on_restore_backup({
+                let tx = command_tx.clone();
+                move |path, _window, _cx| {
+                    let _ = tx.send(UiCommand::RestoreBackup(path));
+                }
+            });
+
+            view.on_merge_database({
+                let tx = command_tx.clone();
+                move |path, _window, _cx| {
+                    let _ = tx.send(UiCommand::MergeDatabase(path));
+                }
+            });
+
+            view.on_recorder_settings_change({
+                let tx = command_tx.clone();
+                move |config, _window, _cx| {
+                    let _ = tx.send(UiCommand::UpdateRecorderSettings(config));
+                }
+            });
+
+            view.on_webhooks_change({
+                let tx = command_tx.clone();
+                move |endpoints, _window, _cx| {
+                    let _ = tx.send(UiCommand::UpdateWebhooks(endpoints));
+                }
+            });
+
+            view.on_refresh_webhook_deliveries({
+                let tx = command_tx.clone();
+                move |_window, _cx| {
+                    let _ = tx.send(UiCommand::RefreshWebhookDeliveries);
+                }
+            });
+
             view.on_plugin_open({
                 let entity = entity.clone();
                 move |plugin_id, plugin_name, plugin_path, window, cx| {
@@ -399,6 +527,13 @@ impl MainView {
                 }
             });
 
+            view.on_plugin_settings_change({
+                let tx = command_tx.clone();
+                move |plugin_id, values, _window, _cx| {
+                    let _ = tx.send(UiCommand::SetPluginSettings { plugin_id, values });
+                }
+            });
+
             view.on_advanced_settings_change({
                 let tx = command_tx.clone();
                 move |max_danmu, log_level, _window, _cx| {
@@ -441,6 +576,92 @@ impl MainView {
                 }
             });
 
+            view.on_refresh_cmd_stats({
+                let tx = command_tx.clone();
+                move |_window, _cx| {
+                    let _ = tx.send(UiCommand::RequestCmdStats);
+                }
+            });
+
+            view.on_clear_cmd_stats({
+                let tx = command_tx.clone();
+                move |_window, _cx| {
+                    let _ = tx.send(UiCommand::ClearCmdStats);
+                }
+            });
+
+            view.on_request_area_list({
+                let tx = command_tx.clone();
+                move |_window, _cx| {
+                    let _ = tx.send(UiCommand::RequestAreaList);
+                }
+            });
+
+            view.on_update_room_area({
+                let tx = command_tx.clone();
+                move |room_id, area_id, _window, _cx| {
+                    let _ = tx.send(UiCommand::UpdateRoomArea { room_id, area_id });
+                }
+            });
+
+            view.on_request_room_details({
+                let tx = command_tx.clone();
+                move |room_id, _window, _cx| {
+                    let _ = tx.send(UiCommand::RequestRoomDetails { room_id });
+                }
+            });
+
+            view.on_update_room_details({
+                let tx = command_tx.clone();
+                move |room_id, description, tags, news, _window, _cx| {
+                    let _ = tx.send(UiCommand::UpdateRoomDetails {
+                        room_id,
+                        description,
+                        tags,
+                        news,
+                    });
+                }
+            });
+
+            view.on_upload_room_cover({
+                let tx = command_tx.clone();
+                move |path, _window, _cx| {
+                    let _ = tx.send(UiCommand::UploadRoomCover { path });
+                }
+            });
+
+            view.on_session_record({
+                let tx = command_tx.clone();
+                move |start, _window, _cx| {
+                    let _ = tx.send(if start {
+                        UiCommand::StartSessionRecording
+                    } else {
+                        UiCommand::StopSessionRecording
+                    });
+                }
+            });
+
+            view.on_start_replay({
+                let tx = command_tx.clone();
+                move |path, speed, _window, _cx| {
+                    let _ = tx.send(UiCommand::StartReplay { path, speed });
+                }
+            });
+
+            view.on_stop_replay({
+                let tx = command_tx.clone();
+                move |_window, _cx| {
+                    let _ = tx.send(UiCommand::StopReplay);
+                }
+            });
+
+            view.on_refresh_sessions({
+                let tx = command_tx.clone();
+                move |_window, _cx| {
+                    let _ = tx.send(UiCommand::RefreshSessions);
+                }
+            });
+
             view.on_plugin_port_change({
                 let tx = command_tx.clone();
                 move |ws_port, http_port, _window, _cx| {
@@ -449,6 +670,38 @@ impl MainView {
             });
         });
 
+        statistics_view.update(cx, |view, _cx| {
+            let tx = command_tx.clone();
+            view.on_export(move |room_id, from, to, format| {
+                let _ = tx.send(UiCommand::ExportDanmaku {
+                    room_id,
+                    from,
+                    to,
+                    format,
+                });
+            });
+            let tx = command_tx.clone();
+            view.on_export_earnings(move |room_id, from, to| {
+                let _ = tx.send(UiCommand::ExportEarnings { room_id, from, to });
+            });
+            let tx = command_tx.clone();
+            view.on_create_goal(move |title, target, sources| {
+                let _ = tx.send(UiCommand::CreateGoal {
+                    title,
+                    target,
+                    sources,
+                });
+            });
+            let tx = command_tx.clone();
+            view.on_reset_goal(move |id| {
+                let _ = tx.send(UiCommand::ResetGoal { id });
+            });
+            let tx = command_tx.clone();
+            view.on_complete_goal(move |id| {
+                let _ = tx.send(UiCommand::CompleteGoal { id });
+            });
+        });
+
         let this = Self {
             event_rx,
             command_tx,
@@ -458,6 +711,9 @@ impl MainView {
             live_status: 0,
             area_id: 0,
             online_count: 0,
+            like_count: 0,
+            watched_text: String::new(),
+            goals: Vec::new(),
             connected: false,
             danmu_list: VecDeque::with_capacity(MAX_DANMU_COUNT),
             render_rows: Rc::new(Vec::new()),
@@ -474,10 +730,7 @@ impl MainView {
             opacity: 1.0,
             font_size: 14.0,
             lite_mode: false,
-            medal_display: true,
-            interact_display: false,
-            guard_effect: true,
-            level_effect: false,
+            display_filter: DisplayFilter::default(),
             always_on_top: false,
             pending_always_on_top: None,
             click_through: false,
@@ -659,6 +912,120 @@ impl MainView {
         }
     }
 
+    /// Handle text submitted from the input box: commands or a danmu
+    pub(super) fn submit_input(&mut self, text: String) {
+        let Some(room_id) = self.room.as_ref().map(|r| r.real_id()) else {
+            return;
+        };
+
+        let mut args = text.split_whitespace();
+        let command = match args.next().unwrap_or_default() {
+            "/title" if text.starts_with("/title ") => {
+                let title = text["/title ".len()..].trim().to_string();
+                if title.is_empty() {
+                    return;
+                }
+                UiCommand::UpdateRoomTitle { room_id, title }
+            }
+            "/bye" if text == "/bye" => UiCommand::StopLive { room_id },
+            "/mute" => {
+                let Some(uid) = args.next().and_then(|target| self.resolve_uid(target)) else {
+                    self.push_system_message("用法：/mute <UID或用户名> [小时|永久]，用户名需在弹幕列表中出现过".to_string());
+                    return;
+                };
+                let hours = match args.next() {
+                    None => BLOCK_THIS_LIVE,
+                    Some("永久") => BLOCK_PERMANENT,
+                    Some(h) => match h.parse::<i32>() {
+                        Ok(h) if h > 0 => h,
+                        _ => {
+                            self.push_system_message(format!("无效的禁言时长：{}", h));
+                            return;
+                        }
+                    },
+                };
+                UiCommand::MuteUser { room_id, uid, hours }
+            }
+            "/unmute" => {
+                let Some(uid) = args.next().and_then(|target| self.resolve_uid(target)) else {
+                    self.push_system_message("用法：/unmute <UID或用户名>".to_string());
+                    return;
+                };
+                UiCommand::UnmuteUser { room_id, uid }
+            }
+            "/mutelist" if text == "/mutelist" => UiCommand::FetchBlockedUsers { room_id },
+            "/silence" => {
+                let mode = args.next().unwrap_or_default();
+                let level = args.next().and_then(|v| v.parse::<u32>().ok());
+                let minutes = args.next().and_then(|v| v.parse::<u32>().ok()).unwrap_or(0);
+                let silence = match (mode, level) {
+                    ("off", _) => RoomSilence::Off,
+                    ("level", Some(level)) => RoomSilence::Level(level),
+                    ("medal", Some(level)) => RoomSilence::Medal(level),
+                    _ => {
+                        self.push_system_message("用法：/silence <level|medal> <等级> [分钟] 或 /silence off".to_string());
+                        return;
+                    }
+                };
+                UiCommand::SetRoomSilence {
+                    room_id,
+                    silence,
+                    minutes,
+                }
+            }
+            _ => UiCommand::SendDanmu {
+                room_id,
+                message: text.clone(),
+            },
+        };
+        let _ = self.command_tx.send(command);
+    }
+
+    /// Resolve a UID or a username seen in the danmu list to a UID
+    fn resolve_uid(&self, target: &str) -> Option<u64> {
+        if let Ok(uid) = target.parse::<u64>() {
+            return Some(uid);
+        }
+        self.danmu_list.iter().rev().find_map(|msg| {
+            let sender = match msg {
+                DisplayMessage::Danmu(m) => &m.sender,
+                DisplayMessage::Interact(m) => &m.sender,
+                DisplayMessage::EntryEffect(m) => &m.sender,
+                DisplayMessage::Gift(m) => &m.sender,
+                DisplayMessage::Guard(m) => &m.sender,
+                DisplayMessage::SuperChat(m) => &m.sender,
+                DisplayMessage::LikeClick(m) => &m.sender,
+                DisplayMessage::Notice(_) => return None,
+            };
+            (sender.uid != 0 && sender.uname == target).then_some(sender.uid)
+        })
+    }
+
+    /// Show a local notice in the danmu list, e.g. results of moderation commands
+    pub(super) fn push_system_message(&mut self, content: String) {
+        let danmu = jlivertool_core::messages::DanmuMessage {
+            sender: jlivertool_core::types::Sender {
+                uname: "系统".to_string(),
+                ..Default::default()
+            },
+            content,
+            is_generated: true,
+            is_special: false,
+            is_mirror: false,
+            emoji_content: None,
+            side_index: -1,
+            reply_uname: None,
+            timestamp: chrono::Utc::now().timestamp(),
+        };
+        self.danmu_list.push_back(DisplayMessage::Danmu(danmu));
+        while self.danmu_list.len() > MAX_DANMU_COUNT {
+            self.danmu_list.pop_front();
+        }
+        self.render_rows_source_count = 0;
+        self.render_rows = Rc::new(Vec::new());
+        self.scroll_to_bottom();
+    }
+
     /// Handle debug commands (only available in debug builds)
     #[cfg(debug_assertions)]
     pub(super) fn handle_debug_command(&mut self, args: &str) {
@@ -752,6 +1119,7 @@ impl MainView {
                     emoji_content: None,
                     side_index: -1,
                     reply_uname: None,
+                    timestamp: now,
                 };
                 self.danmu_list.push_back(DisplayMessage::Danmu(danmu));
             }
@@ -786,7 +1154,7 @@ impl MainView {
                 available_width,
                 self.font_size,
                 self.lite_mode,
-                self.medal_display,
+                self.display_filter,
             );
         }
         self.render_rows = Rc::new(rows);
@@ -822,7 +1190,7 @@ impl MainView {
                 available_width,
                 self.font_size,
                 self.lite_mode,
-                self.medal_display,
+                self.display_filter,
             );
         }
 
@@ -837,7 +1205,7 @@ impl MainView {
         available_width: f32,
         font_size: f32,
         lite_mode: bool,
-        medal_display: bool,
+        display_filter: DisplayFilter,
     ) {
         match msg {
             DisplayMessage::Danmu(danmu) => {
@@ -848,7 +1216,7 @@ impl MainView {
                 }
 
                 let prefix_width =
-                    estimate_danmu_prefix_width(danmu, font_size, lite_mode, medal_display);
+                    estimate_danmu_prefix_width(danmu, font_size, lite_mode, &display_filter);
                 let first_line_content_width = available_width - prefix_width;
                 // Continuation line has only padding, no prefix
                 let padding = if lite_mode { 4.0 * 2.0 } else { 8.0 * 2.0 };
@@ -1358,7 +1726,7 @@ impl MainView {
     /// Open plugin in the system's default browser
     fn open_plugin_in_browser(
         &self,
-        _plugin_id: String,
+        plugin_id: String,
         _plugin_name: String,
         plugin_path: PathBuf,
         _window: &mut Window,
@@ -1391,8 +1759,11 @@ impl MainView {
 
         // Build the URL for the plugin
         let url = format!(
-            "http://127.0.0.1:{}/{}/index.html?ws_port={}",
-            http_port, folder_name, ws_port
+            "http://127.0.0.1:{}/{}/index.html?ws_port={}&plugin_id={}",
+            http_port,
+            folder_name,
+            ws_port,
+            urlencoding::encode(&plugin_id)
         );
 
         tracing::info!("Opening plugin in browser: {}", url);
@@ -1,6 +1,6 @@
 //! Render methods for MainView
 
-use super::{DanmuListItemView, MainView, UserInfoCard};
+use super::{CardModeration, DanmuListItemView, MainView, UserInfoCard};
 use crate::app::UiCommand;
 use crate::components::draggable_area;
 use crate::theme::Colors;
@@ -67,7 +67,41 @@ impl MainView {
                                             .child(format!("{}", self.online_count)),
                                     ),
                                 )
-                            }),
+                            })
+                            .when(is_live && self.like_count > 0, |this| {
+                                this.child(
+                                    div()
+                                        .text_size(px(11.0))
+                                        .text_color(header_text_color)
+                                        .child(format!("赞 {}", self.like_count)),
+                                )
+                            })
+                            .when(is_live && !self.watched_text.is_empty(), |this| {
+                                this.child(
+                                    div()
+                                        .text_size(px(11.0))
+                                        .text_color(header_text_color)
+                                        .child(self.watched_text.clone()),
+                                )
+                            })
+                            .children(
+                                self.goals
+                                    .iter()
+                                    .filter(|goal| {
+                                        self.room.as_ref().map(|r| r.real_id()) == Some(goal.room_id)
+                                    })
+                                    .map(|goal| {
+                                        div()
+                                            .text_size(px(11.0))
+                                            .text_color(header_text_color)
+                                            .child(format!(
+                                                "{} ¥{:.0}/{:.0}",
+                                                goal.title,
+                                                goal.progress as f64 / 1000.0,
+                                                goal.target as f64 / 1000.0
+                                            ))
+                                    }),
+                            ),
                     ),
             )
             .child(
@@ -371,8 +405,6 @@ impl MainView {
         use super::AVAILABLE_COMMANDS;
 
         let opacity = self.opacity;
-        let command_tx = self.command_tx.clone();
-        let room_id = self.room.as_ref().map(|r| r.real_id());
 
         struct CommandInputWrapper {
             input: Entity<gpui_component::input::InputState>,
@@ -384,7 +416,7 @@ impl MainView {
             |window, cx| {
                 let input = cx.new(|cx| {
                     gpui_component::input::InputState::new(window, cx)
-                        .placeholder("发送弹幕或输入命令 (/title, /bye)...")
+                        .placeholder("发送弹幕或输入命令 (/title, /mute, /bye)...")
                 });
                 CommandInputWrapper { input }
             },
@@ -393,7 +425,6 @@ impl MainView {
         let input_state = state.read(cx).input.clone();
 
         if self.input_state.as_ref() != Some(&input_state) {
-            let command_tx_enter = self.command_tx.clone();
             let pending_clear = self.pending_input_clear.clone();
             let show_popup = self.show_command_popup.clone();
             let selected_idx = self.selected_command_index.clone();
@@ -408,12 +439,8 @@ impl MainView {
                                 let idx = selected_idx.get();
                                 if idx < AVAILABLE_COMMANDS.len() {
                                     let (cmd, _) = AVAILABLE_COMMANDS[idx];
-                                    // Set the command text with a trailing space for /title
-                                    let new_text = if cmd == "/title" {
-                                        format!("{} ", cmd)
-                                    } else {
-                                        cmd.to_string()
-                                    };
+                                    // Commands with arguments get a trailing space
+                                    let new_text = super::command_insert_text(cmd);
                                     *pending_cmd.borrow_mut() = Some(new_text);
                                     show_popup.set(false);
                                     selected_idx.set(0);
@@ -438,22 +465,7 @@ impl MainView {
                                 return;
                             }
 
-                            if let Some(room_id) = this.room.as_ref().map(|r| r.real_id()) {
-                                if let Some(title) = text.strip_prefix("/title ") {
-                                    let title = title.trim().to_string();
-                                    if !title.is_empty() {
-                                        let _ = command_tx_enter
-                                            .send(UiCommand::UpdateRoomTitle { room_id, title });
-                                    }
-                                } else if text == "/bye" {
-                                    let _ = command_tx_enter.send(UiCommand::StopLive { room_id });
-                                } else {
-                                    let _ = command_tx_enter.send(UiCommand::SendDanmu {
-                                        room_id,
-                                        message: text,
-                                    });
-                                }
-                            }
+                            this.submit_input(text);
 
                             pending_clear.set(true);
                             show_popup.set(false);
@@ -599,11 +611,7 @@ impl MainView {
                                 let idx = selected_idx_for_key.get();
                                 if idx < AVAILABLE_COMMANDS.len() {
                                     let (cmd, _) = AVAILABLE_COMMANDS[idx];
-                                    let new_text = if cmd == "/title" {
-                                        format!("{} ", cmd)
-                                    } else {
-                                        cmd.to_string()
-                                    };
+                                    let new_text = super::command_insert_text(cmd);
                                     *pending_cmd_for_key.borrow_mut() = Some(new_text);
                                     show_popup_for_key.set(false);
                                     selected_idx_for_key.set(0);
@@ -639,44 +647,24 @@ impl MainView {
                                     .text_size(px(12.0))
                                     .text_color(Colors::button_text())
                                     .child("发送")
-                                    .on_click({
-                                        move |_event, window, cx| {
-                                            let text = input_state_for_click
-                                                .read(cx)
-                                                .text()
-                                                .to_string()
-                                                .trim()
-                                                .to_string();
-                                            if text.is_empty() {
-                                                return;
-                                            }
+                                    .on_click(cx.listener(move |this, _event, window, cx| {
+                                        let text = input_state_for_click
+                                            .read(cx)
+                                            .text()
+                                            .to_string()
+                                            .trim()
+                                            .to_string();
+                                        if text.is_empty() {
+                                            return;
+                                        }
 
-                                            if let Some(room_id) = room_id {
-                                                if let Some(title) = text.strip_prefix("/title ") {
-                                                    let title = title.trim().to_string();
-                                                    if !title.is_empty() {
-                                                        let _ =
-                                                            command_tx.send(UiCommand::UpdateRoomTitle {
-                                                                room_id,
-                                                                title,
-                                                            });
-                                                    }
-                                                } else if text == "/bye" {
-                                                    let _ =
-                                                        command_tx.send(UiCommand::StopLive { room_id });
-                                                } else {
-                                                    let _ = command_tx.send(UiCommand::SendDanmu {
-                                                        room_id,
-                                                        message: text,
-                                                    });
-                                                }
-                                            }
+                                        this.submit_input(text);
 
-                                            input_state_for_click.update(cx, |state, cx| {
-                                                state.set_value("", window, cx);
-                                            });
-                                        }
-                                    }),
+                                        input_state_for_click.update(cx, |state, cx| {
+                                            state.set_value("", window, cx);
+                                        });
+                                        cx.notify();
+                                    })),
                             ),
                     )
                     // Command autocomplete popup
@@ -713,11 +701,7 @@ impl MainView {
                                                 .when(is_selected, |s| s.bg(Colors::bg_hover_with_opacity(opacity)))
                                                 .hover(|s| s.bg(Colors::bg_hover_with_opacity(opacity)))
                                                 .on_mouse_down(MouseButton::Left, move |_, window, cx| {
-                                                    let new_text = if cmd_str == "/title" {
-                                                        format!("{} ", cmd_str)
-                                                    } else {
-                                                        cmd_str.clone()
-                                                    };
+                                                    let new_text = super::command_insert_text(&cmd_str);
                                                     // Set the value directly since we have window access
                                                     input_for_focus.update(cx, |state, cx| {
                                                         state.set_value(&new_text, window, cx);
@@ -758,7 +742,7 @@ impl MainView {
     ) -> impl IntoElement {
         let font_size = self.font_size;
         let lite_mode = self.lite_mode;
-        let medal_display = self.medal_display;
+        let display_filter = self.display_filter;
         let opacity = self.opacity;
         let scroll_handle = self.scroll_handle.clone();
         let selected_user = self.selected_user.clone();
@@ -783,7 +767,7 @@ impl MainView {
                                     ix,
                                     font_size,
                                     lite_mode,
-                                    medal_display,
+                                    display_filter,
                                     opacity,
                                     selected_user,
                                 ).render_element()
@@ -890,6 +874,16 @@ impl Render for MainView {
             Vec::new()
         };
 
+        // Moderation actions need a login; admins of other rooms may moderate too
+        let moderation = match (self.logged_in_uid, self.room.as_ref()) {
+            (Some(uid), Some(room)) if self.logged_in => Some(CardModeration {
+                room_id: room.real_id(),
+                is_owner: uid == room.owner_uid(),
+                command_tx: self.command_tx.clone(),
+            }),
+            _ => None,
+        };
+
         let show_update_dialog = self.show_update_dialog;
         let update_info = self.update_info.clone();
 
@@ -918,7 +912,7 @@ impl Render for MainView {
                                 .relative()
                                 .w_full()
                                 .max_w(px(300.0))
-                                .child(UserInfoCard::render_element(&selected, history))
+                                .child(UserInfoCard::render_element(&selected, history, moderation))
                                 .child(
                                     div()
                                         .id("close-card-btn")
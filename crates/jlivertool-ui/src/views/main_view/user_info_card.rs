@@ -4,16 +4,18 @@
 //! information about a user when clicked in the danmu list.
 
 use super::content_rendering::guard_icon_url;
+use crate::app::UiCommand;
 use crate::theme::Colors;
 use chrono::{Local, TimeZone};
 use gpui::prelude::FluentBuilder;
 use gpui::*;
 use gpui_component::h_flex;
 use gpui_component::v_flex;
-use jlivertool_core::bilibili::api::UserInfoData;
+use jlivertool_core::bilibili::api::{UserInfoData, BLOCK_PERMANENT, BLOCK_THIS_LIVE};
 use jlivertool_core::types::Sender;
 use std::cell::RefCell;
 use std::rc::Rc;
+use std::sync::mpsc;
 
 /// Selected user data with optional fetched info
 #[derive(Clone)]
@@ -26,6 +28,15 @@ pub struct SelectedUser {
 /// Shared state for user info popup
 pub type SelectedUserState = Rc<RefCell<Option<SelectedUser>>>;
 
+/// Moderation context for the card, present when logged in and connected to a room
+#[derive(Clone)]
+pub struct CardModeration {
+    pub room_id: u64,
+    /// Whether the logged-in user owns the room and can manage admins
+    pub is_owner: bool,
+    pub command_tx: mpsc::Sender<UiCommand>,
+}
+
 /// User info card for tooltip
 pub struct UserInfoCard;
 
@@ -34,6 +45,7 @@ impl UserInfoCard {
     pub fn render_element(
         selected: &SelectedUser,
         danmu_history: Vec<(String, i64)>,
+        moderation: Option<CardModeration>,
     ) -> impl IntoElement {
         let sender = &selected.sender;
         let uid = sender.uid;
@@ -427,6 +439,10 @@ impl UserInfoCard {
                             }),
                     ),
             )
+            // Moderation buttons row
+            .when_some(moderation, |this, moderation| {
+                this.child(Self::render_moderation_row(uid, moderation))
+            })
             // Danmu history section
             .when(!danmu_history.is_empty(), |this| {
                 this.child(
@@ -502,4 +518,47 @@ impl UserInfoCard {
                 )
             })
     }
+
+    fn render_moderation_row(uid: u64, moderation: CardModeration) -> impl IntoElement {
+        let room_id = moderation.room_id;
+        let mut actions = vec![
+            ("mute-btn", "禁言本场", UiCommand::MuteUser {
+                room_id,
+                uid,
+                hours: BLOCK_THIS_LIVE,
+            }),
+            ("mute-permanent-btn", "永久禁言", UiCommand::MuteUser {
+                room_id,
+                uid,
+                hours: BLOCK_PERMANENT,
+            }),
+            ("unmute-btn", "解除禁言", UiCommand::UnmuteUser { room_id, uid }),
+        ];
+        if moderation.is_owner {
+            actions.push(("add-admin-btn", "设为房管", UiCommand::AddRoomAdmin { uid }));
+            actions.push(("remove-admin-btn", "取消房管", UiCommand::RemoveRoomAdmin { uid }));
+        }
+
+        h_flex()
+            .w_full()
+            .flex_wrap()
+            .gap_1()
+            .children(actions.into_iter().map(|(id, label, command)| {
+                let tx = moderation.command_tx.clone();
+                div()
+                    .id(id)
+                    .px_2()
+                    .py(px(4.0))
+                    .rounded(px(4.0))
+                    .cursor_pointer()
+                    .bg(Colors::bg_hover())
+                    .hover(|s| s.opacity(0.8))
+                    .text_size(px(11.0))
+                    .text_color(Colors::text_primary())
+                    .child(label)
+                    .on_click(move |_, _, _| {
+                        let _ = tx.send(command.clone());
+                    })
+            }))
+    }
 }
@@ -12,7 +12,11 @@ use gpui_component::h_flex;
 use gpui_component::select::{Select, SelectEvent, SelectState};
 use gpui_component::v_flex;
 use gpui_component::Sizable;
+use gpui_component::input::{Input, InputState, MaskPattern};
 use jlivertool_core::database::{Database, TimeBasedStats, TimeSeriesPoint};
+use jlivertool_core::earnings::EarningKind;
+use jlivertool_core::export::ExportFormat;
+use jlivertool_core::goals::Goal;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -220,6 +224,18 @@ fn generate_minute_options() -> Vec<String> {
     (0..60).map(|m| format!("{:02}", m)).collect()
 }
 
+/// Callback to export danmaku: (room_id, from, to, format)
+type ExportCallback = Arc<dyn Fn(u64, i64, i64, ExportFormat) + Send + Sync>;
+
+/// Callback to export gifts, guards and superchats: (room_id, from, to)
+type ExportEarningsCallback = Arc<dyn Fn(u64, i64, i64) + Send + Sync>;
+
+/// Callback to create a goal: (title, target in 1/1000 yuan, sources)
+type CreateGoalCallback = Arc<dyn Fn(String, u64, Vec<EarningKind>) + Send + Sync>;
+
+/// Callback to reset or complete a goal: (goal id)
+type GoalCallback = Arc<dyn Fn(i64) + Send + Sync>;
+
 /// Statistics view state
 pub struct StatisticsView {
     database: Option<Arc<Database>>,
@@ -244,6 +260,19 @@ pub struct StatisticsView {
     custom_start_minute: u32,
     custom_end_hour: u32,
     custom_end_minute: u32,
+    // Danmaku and earnings export
+    on_export: Option<ExportCallback>,
+    on_export_earnings: Option<ExportEarningsCallback>,
+    export_status: Option<String>,
+    // Gift goals
+    goals: Vec<Goal>,
+    goal_title_input: Option<Entity<InputState>>,
+    goal_target_input: Option<Entity<InputState>>,
+    goal_sources: Vec<EarningKind>,
+    on_create_goal: Option<CreateGoalCallback>,
+    on_reset_goal: Option<GoalCallback>,
+    on_complete_goal: Option<GoalCallback>,
+    goal_status: Option<String>,
 }
 
 impl StatisticsView {
@@ -285,6 +314,98 @@ impl StatisticsView {
             custom_start_minute: 0,
             custom_end_hour: 23,
             custom_end_minute: 59,
+            on_export: None,
+            on_export_earnings: None,
+            export_status: None,
+            goals: Vec::new(),
+            goal_title_input: None,
+            goal_target_input: None,
+            goal_sources: EarningKind::ALL.to_vec(),
+            on_create_goal: None,
+            on_reset_goal: None,
+            on_complete_goal: None,
+            goal_status: None,
+        }
+    }
+
+    /// Set callback to export danmaku of the selected range
+    pub fn on_export<F>(&mut self, callback: F)
+    where
+        F: Fn(u64, i64, i64, ExportFormat) + Send + Sync + 'static,
+    {
+        self.on_export = Some(Arc::new(callback));
+    }
+
+    /// Set callback to export gifts, guards and superchats of the selected range
+    pub fn on_export_earnings<F>(&mut self, callback: F)
+    where
+        F: Fn(u64, i64, i64) + Send + Sync + 'static,
+    {
+        self.on_export_earnings = Some(Arc::new(callback));
+    }
+
+    /// Set callback to create a goal
+    pub fn on_create_goal<F>(&mut self, callback: F)
+    where
+        F: Fn(String, u64, Vec<EarningKind>) + Send + Sync + 'static,
+    {
+        self.on_create_goal = Some(Arc::new(callback));
+    }
+
+    /// Set callback to restart a goal from zero
+    pub fn on_reset_goal<F>(&mut self, callback: F)
+    where
+        F: Fn(i64) + Send + Sync + 'static,
+    {
+        self.on_reset_goal = Some(Arc::new(callback));
+    }
+
+    /// Set callback to mark a goal as completed
+    pub fn on_complete_goal<F>(&mut self, callback: F)
+    where
+        F: Fn(i64) + Send + Sync + 'static,
+    {
+        self.on_complete_goal = Some(Arc::new(callback));
+    }
+
+    /// Add or update a goal, completed goals are removed
+    pub fn update_goal(&mut self, goal: Goal, cx: &mut Context<Self>) {
+        self.goals.retain(|g| g.id != goal.id);
+        if goal.completed_at.is_none() {
+            self.goals.push(goal);
+            self.goals.sort_by_key(|g| g.id);
+        }
+        cx.notify();
+    }
+
+    /// Show the result of a goal command
+    pub fn set_goal_status(&mut self, status: String, cx: &mut Context<Self>) {
+        self.goal_status = Some(status);
+        cx.notify();
+    }
+
+    /// Show the result of an export
+    pub fn set_export_status(&mut self, status: String, cx: &mut Context<Self>) {
+        self.export_status = Some(status);
+        cx.notify();
+    }
+
+    /// Currently selected time range as `[start, end)` unix timestamps
+    fn selected_range(&self) -> Option<(i64, i64)> {
+        if self.use_custom_range {
+            let start_date = self.custom_start_date?;
+            let end_date = self.custom_end_date?;
+            let start = naive_date_time_to_timestamp(
+                start_date,
+                self.custom_start_hour,
+                self.custom_start_minute,
+            );
+            let end =
+                naive_date_time_to_timestamp(end_date, self.custom_end_hour, self.custom_end_minute);
+            (end > start).then_some((start, end))
+        } else {
+            let now = chrono::Utc::now().timestamp();
+            Some((now - self.period.total_seconds(), now + 1))
         }
     }
 
@@ -309,6 +430,8 @@ impl StatisticsView {
     /// Refresh statistics from database
     pub fn refresh_stats(&mut self) {
         if let (Some(db), Some(room_id)) = (&self.database, self.room_id) {
+            // Count messages still waiting for the writer
+            db.flush();
             if self.use_custom_range {
                 // Custom time range mode - calculate timestamps from date and time
                 if let (Some(start_date), Some(end_date)) = (self.custom_start_date, self.custom_end_date) {
@@ -445,6 +568,266 @@ impl StatisticsView {
             )
     }
 
+    /// Render danmaku and earnings export buttons
+    fn render_export_buttons(&self, cx: &mut Context<Self>) -> impl IntoElement {
+        let button = |format: ExportFormat, label: &'static str| {
+            div()
+                .id(SharedString::from(format!("export-{}", format.extension())))
+                .px_2()
+                .py_1()
+                .rounded_md()
+                .cursor_pointer()
+                .text_size(px(11.0))
+                .bg(Colors::bg_hover())
+                .text_color(Colors::text_secondary())
+                .hover(|s| s.bg(Colors::bg_secondary()))
+                .child(label)
+                .on_click(cx.listener(move |this, _event, _window, cx| {
+                    let (Some(room_id), Some((from, to))) = (this.room_id, this.selected_range())
+                    else {
+                        this.export_status = Some("请先选择有效的直播间和时间范围".to_string());
+                        cx.notify();
+                        return;
+                    };
+                    if let Some(ref cb) = this.on_export {
+                        cb(room_id, from, to, format);
+                        this.export_status = Some("正在导出…".to_string());
+                        cx.notify();
+                    }
+                }))
+        };
+
+        h_flex()
+            .gap_1()
+            .child(button(ExportFormat::Xml, "导出 XML"))
+            .child(button(ExportFormat::Ass, "导出 ASS"))
+            .child(
+                div()
+                    .id("export-earnings")
+                    .px_2()
+                    .py_1()
+                    .rounded_md()
+                    .cursor_pointer()
+                    .text_size(px(11.0))
+                    .bg(Colors::bg_hover())
+                    .text_color(Colors::text_secondary())
+                    .hover(|s| s.bg(Colors::bg_secondary()))
+                    .child("导出收益")
+                    .on_click(cx.listener(|this, _event, _window, cx| {
+                        let (Some(room_id), Some((from, to))) =
+                            (this.room_id, this.selected_range())
+                        else {
+                            this.export_status = Some("请先选择有效的直播间和时间范围".to_string());
+                            cx.notify();
+                            return;
+                        };
+                        if let Some(ref cb) = this.on_export_earnings {
+                            cb(room_id, from, to);
+                            this.export_status = Some("正在导出…".to_string());
+                            cx.notify();
+                        }
+                    })),
+            )
+    }
+
+    /// Render the goal form and the active goals of the room
+    fn render_goals(&mut self, window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        if self.goal_title_input.is_none() {
+            self.goal_title_input =
+                Some(cx.new(|cx| InputState::new(window, cx).placeholder("目标名称")));
+        }
+        if self.goal_target_input.is_none() {
+            self.goal_target_input = Some(cx.new(|cx| {
+                InputState::new(window, cx)
+                    .placeholder("金额")
+                    .mask_pattern(MaskPattern::number(None))
+            }));
+        }
+        let title_input = self.goal_title_input.clone().unwrap();
+        let target_input = self.goal_target_input.clone().unwrap();
+
+        let source_button = |kind: EarningKind, label: &'static str, selected: bool| {
+            div()
+                .id(SharedString::from(format!("goal-source-{}", kind.as_str())))
+                .px_2()
+                .py_1()
+                .rounded_md()
+                .cursor_pointer()
+                .text_size(px(11.0))
+                .when(selected, |this| this.bg(Colors::accent()).text_color(gpui::white()))
+                .when(!selected, |this| {
+                    this.bg(Colors::bg_hover())
+                        .text_color(Colors::text_secondary())
+                })
+                .child(label)
+                .on_click(cx.listener(move |this, _event, _window, cx| {
+                    if this.goal_sources.contains(&kind) {
+                        this.goal_sources.retain(|k| *k != kind);
+                    } else {
+                        this.goal_sources.push(kind);
+                    }
+                    cx.notify();
+                }))
+        };
+
+        let form = h_flex()
+            .w_full()
+            .gap_1()
+            .items_center()
+            .child(div().flex_1().child(Input::new(&title_input).small()))
+            .child(div().w(px(72.0)).child(Input::new(&target_input).small()))
+            .child(source_button(
+                EarningKind::Gift,
+                "礼物",
+                self.goal_sources.contains(&EarningKind::Gift),
+            ))
+            .child(source_button(
+                EarningKind::Guard,
+                "舰长",
+                self.goal_sources.contains(&EarningKind::Guard),
+            ))
+            .child(source_button(
+                EarningKind::SuperChat,
+                "SC",
+                self.goal_sources.contains(&EarningKind::SuperChat),
+            ))
+            .child(
+                div()
+                    .id("create-goal")
+                    .px_2()
+                    .py_1()
+                    .rounded_md()
+                    .cursor_pointer()
+                    .text_size(px(11.0))
+                    .bg(Colors::accent())
+                    .text_color(gpui::white())
+                    .hover(|s| s.opacity(0.8))
+                    .child("创建目标")
+                    .on_click(cx.listener({
+                        let title_input = title_input.clone();
+                        let target_input = target_input.clone();
+                        move |this, _event, window, cx| {
+                            let title = title_input.read(cx).value().trim().to_string();
+                            let target = target_input.read(cx).value().parse::<f64>().unwrap_or(0.0);
+                            if this.room_id.is_none() || target <= 0.0 || this.goal_sources.is_empty() {
+                                this.goal_status = Some("请填写目标金额并至少选择一种来源".to_string());
+                                cx.notify();
+                                return;
+                            }
+                            let title = if title.is_empty() { "目标".to_string() } else { title };
+                            if let Some(ref cb) = this.on_create_goal {
+                                cb(title, (target * 1000.0).round() as u64, this.goal_sources.clone());
+                            }
+                            title_input.update(cx, |state, cx| state.set_value("", window, cx));
+                            target_input.update(cx, |state, cx| state.set_value("", window, cx));
+                            cx.notify();
+                        }
+                    })),
+            );
+
+        let goals: Vec<Goal> = self
+            .goals
+            .iter()
+            .filter(|g| Some(g.room_id) == self.room_id)
+            .cloned()
+            .collect();
+
+        v_flex()
+            .w_full()
+            .p_2()
+            .gap_2()
+            .rounded_md()
+            .bg(Colors::bg_secondary())
+            .child(
+                div()
+                    .text_size(px(10.0))
+                    .text_color(Colors::text_muted())
+                    .child("收益目标"),
+            )
+            .child(form)
+            .when_some(self.goal_status.clone(), |this, status| {
+                this.child(
+                    div()
+                        .text_size(px(11.0))
+                        .text_color(Colors::text_muted())
+                        .child(status),
+                )
+            })
+            .children(goals.into_iter().map(|goal| {
+                let id = goal.id;
+                let action_button = |name: &'static str, label: &'static str| {
+                    div()
+                        .id(SharedString::from(format!("goal-{}-{}", name, id)))
+                        .px_2()
+                        .rounded_md()
+                        .cursor_pointer()
+                        .text_size(px(10.0))
+                        .bg(Colors::bg_hover())
+                        .text_color(Colors::text_secondary())
+                        .hover(|s| s.bg(Colors::bg_primary()))
+                        .child(label)
+                };
+                v_flex()
+                    .w_full()
+                    .gap_1()
+                    .child(
+                        h_flex()
+                            .w_full()
+                            .gap_2()
+                            .items_center()
+                            .child(
+                                div()
+                                    .flex_1()
+                                    .text_size(px(12.0))
+                                    .text_color(Colors::text_primary())
+                                    .child(goal.title.clone()),
+                            )
+                            .child(
+                                div()
+                                    .text_size(px(11.0))
+                                    .text_color(if goal.is_reached() {
+                                        Colors::accent()
+                                    } else {
+                                        Colors::warning()
+                                    })
+                                    .child(format!(
+                                        "¥{:.2} / ¥{:.2}",
+                                        goal.progress as f64 / 1000.0,
+                                        goal.target as f64 / 1000.0
+                                    )),
+                            )
+                            .child(action_button("reset", "重置").on_click(cx.listener(
+                                move |this, _event, _window, _cx| {
+                                    if let Some(ref cb) = this.on_reset_goal {
+                                        cb(id);
+                                    }
+                                },
+                            )))
+                            .child(action_button("complete", "完成").on_click(cx.listener(
+                                move |this, _event, _window, _cx| {
+                                    if let Some(ref cb) = this.on_complete_goal {
+                                        cb(id);
+                                    }
+                                },
+                            ))),
+                    )
+                    .child(
+                        div()
+                            .w_full()
+                            .h(px(6.0))
+                            .rounded(px(3.0))
+                            .bg(Colors::bg_hover())
+                            .child(
+                                div()
+                                    .h_full()
+                                    .rounded(px(3.0))
+                                    .bg(Colors::accent())
+                                    .w(relative(goal.ratio() as f32)),
+                            ),
+                    )
+            }))
+    }
+
     /// Render custom time range inputs
     fn render_custom_range_inputs(
         &mut self,
@@ -950,6 +1333,7 @@ impl Render for StatisticsView {
         } else {
             None
         };
+        let goals = self.render_goals(window, cx);
 
         v_flex()
             .size_full()
@@ -1005,7 +1389,8 @@ impl Render for StatisticsView {
                                             .child("统计区间"),
                                     )
                                     .child(self.render_mode_selector(cx)),
-                            ),
+                            )
+                            .child(self.render_export_buttons(cx)),
                     )
                     // Period selector or custom range inputs
                     .child(
@@ -1019,8 +1404,18 @@ impl Render for StatisticsView {
                                 this.children(custom_range_inputs)
                             }),
                     )
+                    .when_some(self.export_status.clone(), |this, status| {
+                        this.child(
+                            div()
+                                .text_size(px(11.0))
+                                .text_color(Colors::text_muted())
+                                .child(status),
+                        )
+                    })
                     // Summary stats
                     .child(self.render_summary())
+                    // Gift goals
+                    .child(goals)
                     // Charts
                     .child(self.render_charts()),
             )
@@ -161,6 +161,12 @@ impl SuperChatView {
         cx.notify();
     }
 
+    /// Remove superchats deleted upstream (already removed from the database)
+    pub fn remove_superchats(&mut self, ids: &[String], cx: &mut Context<Self>) {
+        self.sc_list.retain(|sc| !ids.contains(&sc.id));
+        cx.notify();
+    }
+
     fn is_at_bottom(&self) -> bool {
         if self.sc_list.len() <= 1 {
             return true;
@@ -0,0 +1,425 @@
+//! Command line subcommands
+//!
+//! `jlivertool export` writes stored danmaku of a room as Bilibili XML or ASS
+//! subtitles, `jlivertool earnings` writes gifts, guards and superchats as CSV
+//! or JSONL. Both run without starting the UI. `jlivertool db` backs up,
+//! restores and merges the database. `jlivertool replay` starts the UI fed
+//! from a recorded WebSocket session instead of a live room.
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{Local, NaiveDateTime, TimeZone};
+use jlivertool_core::backup;
+use jlivertool_core::config::ConfigStore;
+use jlivertool_core::database::Database;
+use jlivertool_core::earnings::{
+    export_earnings, EarningKind, EarningsFilter, EarningsFormat, EarningsReport,
+};
+use jlivertool_core::export::{export_to_file, ExportFormat, ExportOptions};
+use std::path::PathBuf;
+
+/// Default gap between two sessions, in minutes
+const DEFAULT_SESSION_GAP_MINUTES: i64 = 30;
+
+const EXPORT_USAGE: &str = "\
+Usage: jlivertool export --room <room_id> [options]
+
+Range (defaults to the latest session):
+  --session <n|last>     Export the n-th session listed by --list-sessions
+  --from <time>          Range start, unix seconds or \"YYYY-MM-DD HH:MM[:SS]\"
+  --to <time>            Range end (default: now)
+  --list-sessions        List sessions of the room and exit
+  --gap <minutes>        Silence that splits sessions (default: 30)
+
+Output:
+  -o, --output <path>    Output file (default: <room>_<start>.<format>)
+  --format <xml|ass>     Output format (default: from extension, or xml)
+  --start <time>         Time mapped to 0:00 of the video
+                         (default: start of the recording, or range start)
+  --offset <seconds>     Shift all messages, may be negative
+  --no-gifts             Leave out gifts
+  --no-superchats        Leave out superchats
+  --width <px>           ASS video width (default: 1920)
+  --height <px>          ASS video height (default: 1080)
+  --font-size <px>       ASS font size (default: 48)";
+
+const EARNINGS_USAGE: &str = "\
+Usage: jlivertool earnings [options]
+
+Filters:
+  --room <room_id>       Only this room (default: all rooms)
+  --from <time>          Range start, unix seconds or \"YYYY-MM-DD HH:MM[:SS]\"
+  --to <time>            Range end, exclusive
+  --type <types>         Comma separated gift,guard,superchat (default: all)
+  --archived <mode>      all, exclude or only (default: all)
+
+Output:
+  --report <report>      records, daily or users (default: records)
+  -o, --output <path>    Output file (default: earnings_<room>_<report>.<format>)
+  --format <csv|jsonl>   Output format (default: from extension, or csv)";
+
+const DB_USAGE: &str = "\
+Usage: jlivertool db <command> [path]
+
+Commands:
+  backup [path]          Back up the database (default: a new file in the backup directory)
+  list [dir]             List backups (default: in the backup directory)
+  restore <path>         Replace the database with a backup, the current data
+                         is backed up first. Close JLiverTool before restoring.
+  merge <path>           Import gifts, guards, superchats, lotteries and danmus
+                         of another database, skipping records already stored";
+
+const REPLAY_USAGE: &str = "\
+Usage: jlivertool replay <file.jlrec> [options]
+
+Starts the UI with messages from a recorded WebSocket session.
+Sessions are recorded from the developer tab of the settings.
+
+Options:
+  --speed <x|max>        Playback speed, max for as fast as possible (default: 1)";
+
+/// Session replay requested on the command line
+pub struct ReplayOptions {
+    pub path: PathBuf,
+    /// Times real time, 0 for as fast as possible
+    pub speed: f64,
+}
+
+/// Run a subcommand if the arguments name one
+/// Returns None when the UI should start
+pub fn run(args: &[String]) -> Option<Result<()>> {
+    match args.first().map(String::as_str) {
+        Some("export") => Some(run_export(&args[1..])),
+        Some("earnings") => Some(run_earnings(&args[1..])),
+        Some("db") => Some(run_db(&args[1..])),
+        Some("replay") if args.iter().any(|a| a == "-h" || a == "--help") => {
+            println!("{}", REPLAY_USAGE);
+            Some(Ok(()))
+        }
+        _ => None,
+    }
+}
+
+/// Replay options if the UI should start in replay mode
+pub fn replay_options(args: &[String]) -> Result<Option<ReplayOptions>> {
+    if args.first().map(String::as_str) != Some("replay") {
+        return Ok(None);
+    }
+    let mut path: Option<PathBuf> = None;
+    let mut speed = 1.0;
+
+    let mut iter = args[1..].iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--speed" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| anyhow!("Missing value for {}", arg))?;
+                speed = parse_speed(value)?;
+            }
+            other if other.starts_with('-') => {
+                bail!("Unknown argument: {}\n\n{}", other, REPLAY_USAGE)
+            }
+            other => path = Some(PathBuf::from(other)),
+        }
+    }
+
+    let path = path.ok_or_else(|| anyhow!("A recording is required\n\n{}", REPLAY_USAGE))?;
+    if !path.is_file() {
+        bail!("Recording not found: {}", path.display());
+    }
+    Ok(Some(ReplayOptions { path, speed }))
+}
+
+fn parse_speed(value: &str) -> Result<f64> {
+    if value == "max" {
+        return Ok(0.0);
+    }
+    let speed: f64 = value
+        .trim_end_matches('x')
+        .parse()
+        .context("Invalid speed")?;
+    if speed <= 0.0 || !speed.is_finite() {
+        bail!("Speed must be positive");
+    }
+    Ok(speed)
+}
+
+fn run_export(args: &[String]) -> Result<()> {
+    let mut room_id: Option<u64> = None;
+    let mut session: Option<String> = None;
+    let mut from: Option<i64> = None;
+    let mut to: Option<i64> = None;
+    let mut start: Option<i64> = None;
+    let mut list_sessions = false;
+    let mut gap_minutes = DEFAULT_SESSION_GAP_MINUTES;
+    let mut output: Option<PathBuf> = None;
+    let mut format: Option<ExportFormat> = None;
+    let mut options = ExportOptions::default();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || {
+            iter.next()
+                .cloned()
+                .ok_or_else(|| anyhow!("Missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--room" => room_id = Some(value()?.parse().context("Invalid room id")?),
+            "--session" => session = Some(value()?),
+            "--from" => from = Some(parse_time(&value()?)?),
+            "--to" => to = Some(parse_time(&value()?)?),
+            "--start" => start = Some(parse_time(&value()?)?),
+            "--list-sessions" => list_sessions = true,
+            "--gap" => gap_minutes = value()?.parse().context("Invalid gap")?,
+            "-o" | "--output" => output = Some(PathBuf::from(value()?)),
+            "--format" => {
+                let name = value()?;
+                format = Some(
+                    ExportFormat::parse(&name)
+                        .ok_or_else(|| anyhow!("Unknown format: {}", name))?,
+                );
+            }
+            "--offset" => options.offset = value()?.parse().context("Invalid offset")?,
+            "--no-gifts" => options.include_gifts = false,
+            "--no-superchats" => options.include_superchats = false,
+            "--width" => options.width = value()?.parse().context("Invalid width")?,
+            "--height" => options.height = value()?.parse().context("Invalid height")?,
+            "--font-size" => options.font_size = value()?.parse().context("Invalid font size")?,
+            "-h" | "--help" => {
+                println!("{}", EXPORT_USAGE);
+                return Ok(());
+            }
+            other => bail!("Unknown argument: {}\n\n{}", other, EXPORT_USAGE),
+        }
+    }
+
+    let room_id = room_id.ok_or_else(|| anyhow!("--room is required\n\n{}", EXPORT_USAGE))?;
+
+    let config = ConfigStore::new()?;
+    let database = Database::new(&config.data_dir().join("jlivertool.db"))?;
+    let sessions = database.get_live_sessions(room_id, gap_minutes * 60)?;
+
+    if list_sessions {
+        if sessions.is_empty() {
+            println!("No danmu stored for room {}", room_id);
+        }
+        for (i, s) in sessions.iter().enumerate() {
+            println!(
+                "{:>3}  {} - {}  {} danmus",
+                i + 1,
+                format_time(s.start),
+                format_time(s.end),
+                s.danmu_count
+            );
+        }
+        return Ok(());
+    }
+
+    let (range_start, range_end) = match (from, session) {
+        (Some(from), _) => (from, to.unwrap_or_else(|| chrono::Utc::now().timestamp())),
+        (None, session) => {
+            let session = match session.as_deref() {
+                None | Some("last") => sessions.last(),
+                Some(n) => {
+                    let n: usize = n.parse().context("Invalid session")?;
+                    n.checked_sub(1).and_then(|i| sessions.get(i))
+                }
+            }
+            .ok_or_else(|| anyhow!("Session not found, see --list-sessions"))?;
+            // End is exclusive
+            (session.start, session.end + 1)
+        }
+    };
+
+    // Without --start, 0:00 is the start of the recorded video if there is one
+    options.start_time = match start {
+        Some(start) => start,
+        None => database
+            .first_recording_in(room_id, range_start, range_end)?
+            .map_or(range_start, |recording| recording.start_time / 1000),
+    };
+
+    let format = format
+        .or_else(|| output.as_deref().and_then(ExportFormat::from_path))
+        .unwrap_or(ExportFormat::Xml);
+    let output = output.unwrap_or_else(|| {
+        let stamp = Local
+            .timestamp_opt(range_start, 0)
+            .single()
+            .map(|t| t.format("%Y%m%d_%H%M").to_string())
+            .unwrap_or_else(|| range_start.to_string());
+        PathBuf::from(format!("{}_{}.{}", room_id, stamp, format.extension()))
+    });
+
+    let count = export_to_file(
+        &database,
+        room_id,
+        range_start,
+        range_end,
+        format,
+        &options,
+        &output,
+    )?;
+    println!("Exported {} messages to {}", count, output.display());
+    Ok(())
+}
+
+fn run_earnings(args: &[String]) -> Result<()> {
+    let mut filter = EarningsFilter::default();
+    let mut report = EarningsReport::Records;
+    let mut output: Option<PathBuf> = None;
+    let mut format: Option<EarningsFormat> = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || {
+            iter.next()
+                .cloned()
+                .ok_or_else(|| anyhow!("Missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--room" => filter.records.room_id = Some(value()?.parse().context("Invalid room id")?),
+            "--from" => filter.records.from = Some(parse_time(&value()?)?),
+            "--to" => filter.records.to = Some(parse_time(&value()?)?),
+            "--type" => {
+                filter.kinds = value()?
+                    .split(',')
+                    .map(|name| {
+                        EarningKind::parse(name.trim())
+                            .ok_or_else(|| anyhow!("Unknown type: {}", name))
+                    })
+                    .collect::<Result<_>>()?;
+            }
+            "--archived" => {
+                filter.records.archived = match value()?.as_str() {
+                    "all" => None,
+                    "exclude" => Some(false),
+                    "only" => Some(true),
+                    other => bail!("Unknown archived mode: {}", other),
+                };
+            }
+            "--report" => {
+                let name = value()?;
+                report = EarningsReport::parse(&name)
+                    .ok_or_else(|| anyhow!("Unknown report: {}", name))?;
+            }
+            "-o" | "--output" => output = Some(PathBuf::from(value()?)),
+            "--format" => {
+                let name = value()?;
+                format = Some(
+                    EarningsFormat::parse(&name)
+                        .ok_or_else(|| anyhow!("Unknown format: {}", name))?,
+                );
+            }
+            "-h" | "--help" => {
+                println!("{}", EARNINGS_USAGE);
+                return Ok(());
+            }
+            other => bail!("Unknown argument: {}\n\n{}", other, EARNINGS_USAGE),
+        }
+    }
+
+    let format = format
+        .or_else(|| output.as_deref().and_then(EarningsFormat::from_path))
+        .unwrap_or(EarningsFormat::Csv);
+    let output = output.unwrap_or_else(|| {
+        let room = filter
+            .records
+            .room_id
+            .map(|id| id.to_string())
+            .unwrap_or_else(|| "all".to_string());
+        PathBuf::from(format!(
+            "earnings_{}_{}.{}",
+            room,
+            report.as_str(),
+            format.extension()
+        ))
+    });
+
+    let config = ConfigStore::new()?;
+    let database = Database::new(&config.data_dir().join("jlivertool.db"))?;
+    let count = export_earnings(&database, &filter, report, format, &output)?;
+    println!("Exported {} rows to {}", count, output.display());
+    Ok(())
+}
+
+fn run_db(args: &[String]) -> Result<()> {
+    let command = match args.first().map(String::as_str) {
+        None | Some("-h") | Some("--help") => {
+            println!("{}", DB_USAGE);
+            return Ok(());
+        }
+        Some(command) => command,
+    };
+    let path = args.get(1).map(PathBuf::from);
+    if args.len() > 2 {
+        bail!("Unexpected argument: {}\n\n{}", args[2], DB_USAGE);
+    }
+
+    let config = ConfigStore::new()?;
+    let backup_dir = config
+        .get_config()
+        .backup
+        .dir_or(&config.data_dir().join("backups"));
+    let database = Database::new(&config.data_dir().join("jlivertool.db"))?;
+    let result = match (command, path) {
+        ("backup", Some(path)) => database.backup_to(&path).map(|()| {
+            println!("Backed up to {}", path.display());
+        }),
+        ("backup", None) => {
+            let keep = config.get_config().backup.keep;
+            backup::create_backup(&database, &backup_dir, keep).map(|path| {
+                println!("Backed up to {}", path.display());
+            })
+        }
+        ("list", dir) => backup::list_backups(&dir.unwrap_or(backup_dir)).map(|backups| {
+            for backup in backups {
+                println!(
+                    "{}  {:>10}  {}",
+                    format_time(backup.created),
+                    backup.size,
+                    backup.path.display()
+                );
+            }
+        }),
+        ("restore", Some(path)) => backup::create_backup(&database, &backup_dir, 0)
+            .and_then(|saved| {
+                database.restore_from(&path)?;
+                println!("Restored {}, previous data saved to {}", path.display(), saved.display());
+                Ok(())
+            }),
+        ("merge", Some(path)) => database.merge_from(&path).map(|report| {
+            println!(
+                "Imported {} danmus, {} gifts, {} guards, {} superchats, {} lotteries",
+                report.danmus, report.gifts, report.guards, report.superchats, report.lotteries
+            );
+        }),
+        ("restore" | "merge", None) => Err(anyhow!("A database file is required\n\n{}", DB_USAGE)),
+        (other, _) => Err(anyhow!("Unknown command: {}\n\n{}", other, DB_USAGE)),
+    };
+    database.close();
+    result
+}
+
+/// Parse unix seconds or a local "YYYY-MM-DD HH:MM[:SS]" time
+fn parse_time(value: &str) -> Result<i64> {
+    if let Ok(timestamp) = value.parse::<i64>() {
+        return Ok(timestamp);
+    }
+    let naive = NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M"))
+        .with_context(|| format!("Invalid time: {}", value))?;
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|t| t.timestamp())
+        .ok_or_else(|| anyhow!("Invalid local time: {}", value))
+}
+
+fn format_time(timestamp: i64) -> String {
+    Local
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| timestamp.to_string())
+}
@@ -5,24 +5,45 @@
 // Hide console window on Windows in release builds
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod cli;
+
 use anyhow::Result;
-use jlivertool_core::bilibili::api::{BiliApi, QrCodeStatus};
+use chrono::TimeZone;
+use jlivertool_core::backup::{self, BackupHandle};
+use jlivertool_core::bilibili::api::{
+    split_tags, BiliApi, Endpoints, QrCodeStatus, RoomSilence, BLOCK_PERMANENT, BLOCK_THIS_LIVE,
+};
+use jlivertool_core::bilibili::replay::{SessionRecorder, SessionReplay, SESSION_EXTENSION};
 use jlivertool_core::bilibili::ws::{BiliWebSocket, WsEvent, WsInfo};
+use jlivertool_core::cmd_stats::CmdStats;
 use jlivertool_core::config::ConfigStore;
-use jlivertool_core::database::Database;
-use jlivertool_core::events::Event;
+use jlivertool_core::database::{Database, RecordFilter};
+use jlivertool_core::earnings::{export_earnings, EarningsFilter, EarningsFormat, EarningsReport};
+use jlivertool_core::events::{Event, EventBus, EventType, LagPolicy, SubscribeOptions};
+use jlivertool_core::export::{export_to_file, ExportOptions};
+use jlivertool_core::filter::DisplayFilter;
+use jlivertool_core::goals::{Goal, GoalTracker};
 use jlivertool_core::messages::{
-    CutOffMessage, DanmuMessage, EntryEffectMessage, GiftMessage, GuardMessage, InteractMessage,
-    OnlineRankCountMessage, RoomChangeMessage, SuperChatMessage, WarningMessage,
+    AnchorLotAwardMessage, AnchorLotStartMessage, CutOffMessage, DanmuMessage, EntryEffectMessage,
+    GiftMessage, GuardMessage, InteractMessage, LikeClickMessage, LikeCountMessage,
+    OnlineRankCountMessage, RedPocketStartMessage, RedPocketWinnerMessage, RoomBlockMessage,
+    RoomChangeMessage, RoomSilentMessage, SuperChatDeleteMessage, SuperChatMessage, WarningMessage,
+    WatchedChangeMessage,
 };
+use jlivertool_core::metrics::Metrics;
+use jlivertool_core::network;
+use jlivertool_core::obs::{self, ObsHandle};
+use jlivertool_core::recorder::{self, RecorderHandle};
+use jlivertool_core::retention::{self, RetentionHandle};
 use jlivertool_core::tts::{TtsEnabled, TtsManager, TtsMessage};
 use jlivertool_core::types::RoomId;
+use jlivertool_core::webhook::{EventEncoder, WebhookDispatcher};
 use jlivertool_plugin::PluginManager;
 use jlivertool_ui::{run_app_with_tray, PluginInfo, UiCommand};
 use notify_rust::Notification;
 use parking_lot::RwLock;
-use std::path::PathBuf;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::mpsc;
 use std::sync::Arc;
 use tokio::sync::mpsc as tokio_mpsc;
@@ -66,54 +87,134 @@ fn init_logging(data_dir: &PathBuf) -> Result<tracing_appender::non_blocking::Wo
 enum BackendCommand {
     /// Change to a new room
     ChangeRoom(RoomId),
+    /// Replace the live connection with a recorded session, speed 0 for max
+    Replay { path: PathBuf, speed: f64 },
+    /// End the replay and reconnect to the room
+    StopReplay,
     /// Stop the backend (reserved for future use)
     #[allow(dead_code)]
     Stop,
 }
 
+/// Raw WebSocket session recording and replay, shared by backend and commands
+#[derive(Clone)]
+struct Sessions {
+    recorder: SessionRecorder,
+    replaying: Arc<parking_lot::Mutex<Option<PathBuf>>>,
+    dir: PathBuf,
+}
+
+impl Sessions {
+    fn new(dir: PathBuf) -> Self {
+        Self {
+            recorder: SessionRecorder::new(),
+            replaying: Arc::new(parking_lot::Mutex::new(None)),
+            dir,
+        }
+    }
+
+    /// Current state with the recorded sessions, newest first
+    fn status(&self, message: impl Into<String>) -> Event {
+        let mut files: Vec<(std::time::SystemTime, String)> = std::fs::read_dir(&self.dir)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .map(|entry| entry.path())
+                    .filter(|path| {
+                        path.extension().and_then(|e| e.to_str()) == Some(SESSION_EXTENSION)
+                    })
+                    .map(|path| {
+                        let modified = path
+                            .metadata()
+                            .and_then(|m| m.modified())
+                            .unwrap_or(std::time::UNIX_EPOCH);
+                        (modified, path.display().to_string())
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        files.sort_by(|a, b| b.0.cmp(&a.0));
+
+        Event::SessionStatus {
+            recording: self.recorder.path().map(|p| p.display().to_string()),
+            replaying: self.replaying.lock().as_ref().map(|p| p.display().to_string()),
+            files: files.into_iter().map(|(_, path)| path).collect(),
+            message: message.into(),
+        }
+    }
+}
+
 /// Event sender wrapper that sets a flag when events are sent
-/// Also broadcasts events to plugins if a plugin event sender is set
+/// Events are published on a bus, the flag tells the UI to pick them up
 #[derive(Clone)]
 struct EventSender {
-    tx: mpsc::Sender<Event>,
+    bus: EventBus,
     has_events: Arc<AtomicBool>,
-    plugin_tx: Option<tokio::sync::broadcast::Sender<jlivertool_plugin::PluginEvent>>,
 }
 
 impl EventSender {
-    fn new(tx: mpsc::Sender<Event>, has_events: Arc<AtomicBool>) -> Self {
-        Self {
-            tx,
-            has_events,
-            plugin_tx: None,
-        }
+    fn new(bus: EventBus, has_events: Arc<AtomicBool>) -> Self {
+        Self { bus, has_events }
     }
 
-    fn with_plugin_sender(
-        mut self,
-        plugin_tx: tokio::sync::broadcast::Sender<jlivertool_plugin::PluginEvent>,
-    ) -> Self {
-        self.plugin_tx = Some(plugin_tx);
-        self
+    fn send(&self, event: Event) {
+        self.bus.emit(event);
+        self.has_events.store(true, Ordering::Relaxed);
     }
+}
 
-    fn send(&self, event: Event) -> Result<(), mpsc::SendError<Event>> {
-        // Broadcast to plugins if sender is available
+/// Forwards undecoded WebSocket messages to plugins subscribed to raw
+/// Raw messages are not events, so they don't go through the bus
+#[derive(Clone, Default)]
+struct RawForwarder {
+    plugin_tx: Option<tokio::sync::broadcast::Sender<jlivertool_plugin::PluginEvent>>,
+    /// Plugin clients subscribed to raw messages
+    subscribers: Arc<AtomicUsize>,
+}
+
+impl RawForwarder {
+    fn send(&self, cmd: &str, body: &serde_json::Value) {
+        if self.subscribers.load(Ordering::Relaxed) == 0 {
+            return;
+        }
         if let Some(ref plugin_tx) = self.plugin_tx {
-            if let Some(plugin_event) = jlivertool_plugin::PluginEvent::from_core_event(&event) {
-                let _ = plugin_tx.send(plugin_event);
-            }
+            let _ = plugin_tx.send(jlivertool_plugin::PluginEvent::Raw {
+                cmd: cmd.to_string(),
+                body: body.clone(),
+            });
         }
+    }
+}
+
+/// Queue capacity of the UI subscription, which is drained every frame
+const UI_QUEUE_CAPACITY: usize = 10_000;
+
+/// Publish every event of `from` on `to` as well
+fn forward_events(from: &EventBus, to: &EventBus) {
+    let to = to.clone();
+    from.on_all(move |event| to.emit(event.clone()));
+}
 
-        let result = self.tx.send(event);
-        if result.is_ok() {
-            self.has_events.store(true, Ordering::Relaxed);
+/// Bilibili endpoints, `JLIVERTOOL_API_BASE` points all of them at one server
+/// such as the fake of jlivertool-testkit
+fn api_endpoints() -> Endpoints {
+    match std::env::var("JLIVERTOOL_API_BASE") {
+        Ok(base) if !base.trim().is_empty() => {
+            warn!("Using Bilibili API at {}", base);
+            Endpoints::local(&base)
         }
-        result
+        _ => Endpoints::default(),
     }
 }
 
 fn main() -> Result<()> {
+    // Command line subcommands run without the UI
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(result) = cli::run(&args) {
+        return result;
+    }
+    let replay = cli::replay_options(&args)?;
+
     // Get data directory and initialize logging
     let data_dir = get_data_dir();
     std::fs::create_dir_all(&data_dir)?;
@@ -129,20 +230,51 @@ fn main() -> Result<()> {
     info!("========================================");
 
     // Create channels for communication
-    let (event_tx, event_rx) = mpsc::channel::<Event>();
     let (command_tx, command_rx) = mpsc::channel::<UiCommand>();
 
     // Create flag for pending events (shared between sender and UI)
     let has_events = Arc::new(AtomicBool::new(false));
 
+    // Displayed events, live and replayed, for the UI and plugins
+    let ui_bus = EventBus::new();
+    let event_rx = ui_bus.subscribe_with(
+        SubscribeOptions::new("ui")
+            .capacity(UI_QUEUE_CAPACITY)
+            .lag(LagPolicy::Coalesce),
+    );
+
     // Shared API client and config
     let config = Arc::new(RwLock::new(ConfigStore::new()?));
-    let api = Arc::new(RwLock::new(BiliApi::new()?));
+    network::set(config.read().get_config().network);
+    let api = Arc::new(RwLock::new(
+        BiliApi::builder().endpoints(api_endpoints()).build()?,
+    ));
+
+    // Initialize database
+    let db_path = config.read().data_dir().join("jlivertool.db");
+    let database = Arc::new(Database::new(&db_path)?);
+    info!("Database initialized at {:?}", db_path);
+
+    // Prometheus metrics, served by the plugin HTTP server at /metrics
+    let metrics = Arc::new(Metrics::new());
+
+    // Async bus handlers run here, off the UI thread
+    let handler_runtime = tokio::runtime::Builder::new_multi_thread()
+        .thread_name("event-handlers")
+        .enable_all()
+        .build()?;
 
     // Initialize plugin manager (note: plugins are loaded but webview windows
     // need to be created separately due to thread safety constraints)
     let plugin_manager = Arc::new(parking_lot::Mutex::new(PluginManager::new()));
     let plugins_dir = config.read().data_dir().join("plugins");
+    {
+        let mut pm = plugin_manager.lock();
+        pm.set_storage_dir(config.read().data_dir().join("plugin_data"));
+        pm.set_database((*database).clone());
+        pm.set_display_filter(DisplayFilter::from_config(&config.read()));
+        pm.set_metrics(metrics.clone());
+    }
 
     match plugin_manager.lock().scan_plugins_dir(&plugins_dir) {
         Ok(loaded) => {
@@ -155,18 +287,18 @@ fn main() -> Result<()> {
         }
     }
     // Convert plugins to UI-compatible format
-    let ui_plugins: Vec<PluginInfo> = plugin_manager
-        .lock()
-        .get_plugins_with_paths()
+    let ui_plugins: Vec<PluginInfo> = plugin_info_events(&plugin_manager.lock())
         .into_iter()
-        .map(|(meta, path)| PluginInfo {
-            id: meta.id,
-            name: meta.name,
-            author: meta.author,
-            desc: meta.desc,
-            version: meta.version,
+        .map(|p| PluginInfo {
+            id: p.id,
+            name: p.name,
+            author: p.author,
+            desc: p.desc,
+            version: p.version,
             enabled: true, // All loaded plugins are enabled by default
-            path,
+            path: p.path,
+            settings: p.settings,
+            setting_values: p.setting_values,
         })
         .collect();
 
@@ -185,6 +317,7 @@ fn main() -> Result<()> {
                 u16,
                 u16,
                 tokio::sync::broadcast::Sender<jlivertool_plugin::PluginEvent>,
+                Arc<AtomicUsize>,
             )>,
         >();
         std::thread::spawn(move || {
@@ -231,14 +364,14 @@ fn main() -> Result<()> {
                     }
                 };
 
-                // Get the event sender
+                // Get the event sender and the raw channel subscriber count
                 let event_sender = {
                     let pm = plugin_manager_clone.lock();
-                    pm.get_event_sender()
+                    pm.get_event_sender().zip(pm.get_raw_subscriber_counter())
                 };
 
-                if let Some(sender) = event_sender {
-                    let _ = port_tx.send(Some((ws_port, http_port, sender)));
+                if let Some((sender, raw_subscribers)) = event_sender {
+                    let _ = port_tx.send(Some((ws_port, http_port, sender, raw_subscribers)));
                 } else {
                     let _ = port_tx.send(None);
                 }
@@ -251,25 +384,161 @@ fn main() -> Result<()> {
         });
         // Wait for the ports and event sender to be available
         match port_rx.recv().unwrap_or(None) {
-            Some((ws_port, http_port, sender)) => (Some(ws_port), Some(http_port), Some(sender)),
+            Some((ws_port, http_port, sender, raw_subscribers)) => {
+                (Some(ws_port), Some(http_port), Some((sender, raw_subscribers)))
+            }
             None => (None, None, None),
         }
     };
 
-    // Create event sender with optional plugin broadcasting
-    let event_sender = {
-        let sender = EventSender::new(event_tx.clone(), has_events.clone());
-        if let Some(plugin_tx) = plugin_event_tx {
-            sender.with_plugin_sender(plugin_tx)
-        } else {
-            sender
-        }
+    // Undecoded messages go to plugins directly, they are not events
+    let raw_forwarder = match plugin_event_tx {
+        Some((plugin_tx, subscribers)) => RawForwarder {
+            plugin_tx: Some(plugin_tx),
+            subscribers,
+        },
+        None => RawForwarder::default(),
     };
 
-    // Initialize database
-    let db_path = config.read().data_dir().join("jlivertool.db");
-    let database = Arc::new(Database::new(&db_path)?);
-    info!("Database initialized at {:?}", db_path);
+    // Live events drive storage, metrics, TTS, OBS, the recorder and webhooks,
+    // and are forwarded to the display bus
+    let event_bus = EventBus::new();
+    forward_events(&event_bus, &ui_bus);
+    let event_sender = EventSender::new(event_bus.clone(), has_events.clone());
+
+    // OBS actions run on their own thread, status reports go to the UI only
+    let (obs_handle, obs_worker) = {
+        let status_sender = event_sender.clone();
+        obs::channel(
+            config.read().get_config().obs,
+            Box::new(move |connected, message| {
+                status_sender.send(Event::ObsStatus { connected, message });
+            }),
+        )
+    };
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Failed to create tokio runtime for OBS");
+        runtime.block_on(obs_worker.run());
+    });
+    event_bus.on(ObsHandle::EVENT_TYPES.iter().copied(), {
+        let obs_handle = obs_handle.clone();
+        move |event| obs_handle.notify(event)
+    });
+
+    // Recordings run on their own thread, status reports go to the UI only
+    let (recorder_handle, recorder_worker) = {
+        let status_sender = event_sender.clone();
+        recorder::channel(
+            config.read().get_config().recorder,
+            config.read().data_dir().join("recordings"),
+            api.clone(),
+            (*database).clone(),
+            Arc::new(move |recording, message| {
+                status_sender.send(Event::RecorderStatus { recording, message });
+            }),
+        )?
+    };
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("Failed to create tokio runtime for recorder");
+        runtime.block_on(recorder_worker.run());
+    });
+    event_bus.on(RecorderHandle::EVENT_TYPES.iter().copied(), {
+        let recorder_handle = recorder_handle.clone();
+        move |event| recorder_handle.notify(event)
+    });
+
+    // Retention and compaction run on their own thread, results go to the UI only
+    let (retention_handle, retention_worker) = {
+        let status_sender = event_sender.clone();
+        let stats_database = (*database).clone();
+        retention::channel(
+            (*database).clone(),
+            config.read().get_config().retention,
+            Box::new(move |result| {
+                let message = match result {
+                    Ok(report) => report.summary(),
+                    Err(e) => {
+                        warn!("Retention run failed: {:#}", e);
+                        format!("整理失败: {:#}", e)
+                    }
+                };
+                status_sender.send(Event::DatabaseStatus {
+                    stats: stats_database.stats().unwrap_or_default(),
+                    message,
+                });
+            }),
+        )
+    };
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Failed to create tokio runtime for retention");
+        runtime.block_on(retention_worker.run());
+    });
+
+    // Backups run on their own thread, results go to the UI only
+    let backup_default_dir = config.read().data_dir().join("backups");
+    let (backup_handle, backup_worker) = {
+        let status_sender = event_sender.clone();
+        let backup_config = config.read().get_config().backup;
+        let config = config.clone();
+        let default_dir = backup_default_dir.clone();
+        backup::channel(
+            (*database).clone(),
+            backup_config,
+            backup_default_dir.clone(),
+            Box::new(move |result| {
+                let message = match result {
+                    Ok(path) => format!("已备份到 {}", path.display()),
+                    Err(e) => {
+                        error!("Backup failed: {:#}", e);
+                        format!("备份失败：{:#}", e)
+                    }
+                };
+                let dir = config.read().get_config().backup.dir_or(&default_dir);
+                send_backup_status(&status_sender, &dir, message);
+            }),
+        )
+    };
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Failed to create tokio runtime for backups");
+        runtime.block_on(backup_worker.run());
+    });
+
+    // Webhooks receive the plugin event JSON of every event on the bus
+    let encoder: EventEncoder = Arc::new(|event| {
+        let plugin_event = jlivertool_plugin::PluginEvent::from_core_event(event)?;
+        let value = serde_json::to_value(&plugin_event).ok()?;
+        Some((plugin_event.channel().to_string(), value))
+    });
+    let webhook_dispatcher = WebhookDispatcher::new(
+        (*database).clone(),
+        config.read().get_config().webhooks,
+        encoder,
+    )?;
+    {
+        let dispatcher = webhook_dispatcher.clone();
+        let subscription = event_bus.subscribe_with(SubscribeOptions::new("webhooks"));
+        std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("Failed to create tokio runtime for webhooks");
+            runtime.block_on(dispatcher.run(subscription));
+        });
+    }
+    metrics.add_event_bus(event_bus.clone());
+    metrics.add_event_bus(ui_bus.clone());
 
     // Initialize TTS manager
     let tts_manager = Arc::new(TtsManager::new());
@@ -283,8 +552,77 @@ fn main() -> Result<()> {
         });
         tts_manager.set_volume(cfg.tts_volume);
     }
+    {
+        let tts_manager = tts_manager.clone();
+        metrics.register_gauge(
+            "jlivertool_tts_queue_length",
+            "Messages waiting to be spoken",
+            move || tts_manager.queue_len() as f64,
+        );
+    }
     info!("TTS manager initialized");
 
+    // Room of the live connection, for storing its messages
+    let live_room = Arc::new(AtomicU64::new(0));
+    {
+        let _guard = handler_runtime.enter();
+        if let Some(ref plugin_tx) = raw_forwarder.plugin_tx {
+            let plugin_tx = plugin_tx.clone();
+            ui_bus.spawn_handler(SubscribeOptions::new("plugins"), move |event| {
+                if let Some(plugin_event) = jlivertool_plugin::PluginEvent::from_core_event(&event)
+                {
+                    let _ = plugin_tx.send(plugin_event);
+                }
+                async {}
+            });
+        }
+        let metrics = metrics.clone();
+        event_bus.spawn_handler(SubscribeOptions::new("metrics"), move |event| {
+            metrics.observe(&event);
+            async {}
+        });
+        let tts_manager = tts_manager.clone();
+        event_bus.spawn_handler(
+            SubscribeOptions::new("tts").types([
+                EventType::NewDanmu,
+                EventType::NewGift,
+                EventType::NewSuperChat,
+            ]),
+            move |event| {
+                match event {
+                    Event::NewDanmu(danmu) => {
+                        tts_manager.speak(TtsMessage::danmu(&danmu.sender.uname, &danmu.content))
+                    }
+                    Event::NewGift(gift) => tts_manager.speak(TtsMessage::gift(
+                        &gift.sender.uname,
+                        &gift.gift_info.name,
+                        gift.num,
+                    )),
+                    Event::NewSuperChat(sc) => tts_manager.speak(TtsMessage::superchat(
+                        &sc.sender.uname,
+                        sc.price,
+                        &sc.message,
+                    )),
+                    _ => {}
+                }
+                async {}
+            },
+        );
+    }
+    // Storage runs inside emit, so no message is dropped and each is stored
+    // under the room that was connected when it arrived
+    event_bus.on(Database::STORED_EVENT_TYPES.iter().copied(), {
+        let database = database.clone();
+        let live_room = live_room.clone();
+        move |event| database.store_event(live_room.load(Ordering::Relaxed), event)
+    });
+
+    // Statistics of received WebSocket cmds for the developer panel
+    let cmd_stats = Arc::new(CmdStats::new());
+
+    // Gift goals of the connected room
+    let goal_tracker = Arc::new(GoalTracker::new((*database).clone()));
+
     // Set cookies if available
     {
         let config_read = config.read();
@@ -293,15 +631,29 @@ fn main() -> Result<()> {
         }
     }
 
+    // Raw WebSocket sessions for reproducing bugs and benchmarks
+    let sessions = Sessions::new(config.read().data_dir().join("sessions"));
+
     // Create channel for backend control
     let (backend_cmd_tx, backend_cmd_rx) = tokio_mpsc::unbounded_channel::<BackendCommand>();
+    if let Some(replay) = replay {
+        info!("Replaying session {:?} at speed {}", replay.path, replay.speed);
+        let _ = backend_cmd_tx.send(BackendCommand::Replay {
+            path: replay.path,
+            speed: replay.speed,
+        });
+    }
 
     // Start backend in separate thread
     let event_sender_clone = event_sender.clone();
+    let ui_bus_clone = ui_bus.clone();
+    let raw_forwarder_clone = raw_forwarder.clone();
+    let live_room_clone = live_room.clone();
     let config_clone = config.clone();
     let api_clone = api.clone();
-    let db_clone = database.clone();
-    let tts_clone = tts_manager.clone();
+    let cmd_stats_clone = cmd_stats.clone();
+    let goal_tracker_clone = goal_tracker.clone();
+    let sessions_clone = sessions.clone();
     std::thread::spawn(move || {
         let runtime = tokio::runtime::Builder::new_multi_thread()
             .enable_all()
@@ -311,10 +663,14 @@ fn main() -> Result<()> {
         runtime.block_on(async move {
             if let Err(e) = run_backend(
                 event_sender_clone,
+                ui_bus_clone,
+                raw_forwarder_clone,
+                live_room_clone,
                 config_clone,
                 api_clone,
-                db_clone,
-                tts_clone,
+                cmd_stats_clone,
+                goal_tracker_clone,
+                sessions_clone,
                 backend_cmd_rx,
             )
             .await
@@ -331,6 +687,14 @@ fn main() -> Result<()> {
     let tts_clone = tts_manager.clone();
     let plugin_manager_clone = plugin_manager.clone();
     let db_clone_for_commands = database.clone();
+    let cmd_stats_clone = cmd_stats.clone();
+    let goal_tracker_clone = goal_tracker.clone();
+    let obs_clone = obs_handle.clone();
+    let webhook_clone = webhook_dispatcher.clone();
+    let recorder_clone = recorder_handle.clone();
+    let retention_clone = retention_handle.clone();
+    let backup_clone = backup_handle.clone();
+    let sessions_clone = sessions.clone();
     std::thread::spawn(move || {
         let runtime = tokio::runtime::Builder::new_multi_thread()
             .enable_all()
@@ -346,6 +710,14 @@ fn main() -> Result<()> {
                 tts_clone,
                 plugin_manager_clone,
                 db_clone_for_commands,
+                cmd_stats_clone,
+                goal_tracker_clone,
+                obs_clone,
+                webhook_clone,
+                recorder_clone,
+                retention_clone,
+                backup_clone,
+                sessions_clone,
                 backend_cmd_tx,
             )
             .await;
@@ -375,7 +747,7 @@ fn main() -> Result<()> {
         // Set initial theme
         jlivertool_ui::set_theme(&cfg.theme);
 
-        let _ = event_sender.send(Event::ConfigLoaded {
+        event_sender.send(Event::ConfigLoaded {
             always_on_top: cfg.always_on_top,
             guard_effect: cfg.guard_effect,
             level_effect: cfg.level_effect,
@@ -392,7 +764,14 @@ fn main() -> Result<()> {
             max_danmu_count: cfg.max_danmu_count,
             log_level: cfg.log_level.clone(),
             auto_update_check: cfg.auto_update_check,
+            obs: cfg.obs.clone(),
+            webhooks: cfg.webhooks.clone(),
+            recorder: cfg.recorder.clone(),
+            network: cfg.network.clone(),
+            retention: cfg.retention.clone(),
+            backup: cfg.backup.clone(),
         });
+        send_backup_status(&event_sender, &cfg.backup.dir_or(&backup_default_dir), String::new());
 
         // Auto-check for updates on startup if enabled
         if cfg.auto_update_check {
@@ -407,7 +786,7 @@ fn main() -> Result<()> {
                 runtime.block_on(async move {
                     match jlivertool_core::check_for_update(&current_version).await {
                         Ok(update_info) => {
-                            let _ = event_tx.send(Event::UpdateCheckResult {
+                            event_tx.send(Event::UpdateCheckResult {
                                 has_update: update_info.has_update,
                                 current_version: update_info.current_version,
                                 latest_version: update_info.latest_version,
@@ -429,7 +808,7 @@ fn main() -> Result<()> {
     run_app_with_tray(
         event_rx,
         command_tx,
-        Some(database),
+        Some(database.clone()),
         Some(config),
         has_events,
         ui_plugins,
@@ -437,6 +816,10 @@ fn main() -> Result<()> {
         http_port,
     );
 
+    // Store messages still waiting for the background writer
+    database.close();
+    info!("Database closed");
+
     Ok(())
 }
 
@@ -472,7 +855,7 @@ async fn check_initial_login(
                         live_room: None,
                     };
                     info!("Login verified for user: {}", user_info.name);
-                    let _ = event_tx.send(Event::LoginStatusChanged {
+                    event_tx.send(Event::LoginStatusChanged {
                         logged_in: true,
                         user_info: Some(user_info),
                     });
@@ -483,7 +866,7 @@ async fn check_initial_login(
                         error!("Failed to clear cookies: {}", e);
                     }
                     api.write().set_cookies(None);
-                    let _ = event_tx.send(Event::LoginStatusChanged {
+                    event_tx.send(Event::LoginStatusChanged {
                         logged_in: false,
                         user_info: None,
                     });
@@ -491,7 +874,7 @@ async fn check_initial_login(
             }
             Err(e) => {
                 warn!("Failed to verify login: {}", e);
-                let _ = event_tx.send(Event::LoginStatusChanged {
+                event_tx.send(Event::LoginStatusChanged {
                     logged_in: false,
                     user_info: None,
                 });
@@ -500,6 +883,26 @@ async fn check_initial_login(
     }
 }
 
+/// Collect loaded plugins with their settings for the UI
+fn plugin_info_events(pm: &PluginManager) -> Vec<jlivertool_core::events::PluginInfoEvent> {
+    pm.get_plugins_with_paths()
+        .into_iter()
+        .map(|(meta, path)| {
+            let setting_values = pm.get_plugin_settings(&meta.id);
+            jlivertool_core::events::PluginInfoEvent {
+                id: meta.id,
+                name: meta.name,
+                author: meta.author,
+                desc: meta.desc,
+                version: meta.version,
+                path,
+                settings: meta.settings,
+                setting_values,
+            }
+        })
+        .collect()
+}
+
 /// Handle UI commands
 async fn handle_commands(
     command_rx: mpsc::Receiver<UiCommand>,
@@ -509,6 +912,14 @@ async fn handle_commands(
     tts_manager: Arc<TtsManager>,
     plugin_manager: Arc<parking_lot::Mutex<PluginManager>>,
     database: Arc<Database>,
+    cmd_stats: Arc<CmdStats>,
+    goal_tracker: Arc<GoalTracker>,
+    obs_handle: ObsHandle,
+    webhook_dispatcher: WebhookDispatcher,
+    recorder_handle: RecorderHandle,
+    retention_handle: RetentionHandle,
+    backup_handle: BackupHandle,
+    sessions: Sessions,
     backend_cmd_tx: tokio_mpsc::UnboundedSender<BackendCommand>,
 ) {
     while let Ok(command) = command_rx.recv() {
@@ -521,7 +932,7 @@ async fn handle_commands(
                 match api_read.qr_generate().await {
                     Ok(qr_data) => {
                         info!("QR code generated: {}", qr_data.url);
-                        let _ = event_tx.send(Event::QrCodeGenerated {
+                        event_tx.send(Event::QrCodeGenerated {
                             url: qr_data.url.clone(),
                             qrcode_key: qr_data.qrcode_key.clone(),
                         });
@@ -559,7 +970,7 @@ async fn handle_commands(
                 api.write().set_cookies(None);
 
                 // Notify UI
-                let _ = event_tx.send(Event::LoginStatusChanged {
+                event_tx.send(Event::LoginStatusChanged {
                     logged_in: false,
                     user_info: None,
                 });
@@ -582,10 +993,10 @@ async fn handle_commands(
                         match api_read.get_room_info(room.real_id()).await {
                             Ok(room_info) => {
                                 // Clear danmu list first
-                                let _ = event_tx.send(Event::ClearDanmuList);
+                                event_tx.send(Event::ClearDanmuList);
 
                                 // Notify UI about room update
-                                let _ = event_tx.send(Event::UpdateRoom {
+                                event_tx.send(Event::UpdateRoom {
                                     room_id: room.clone(),
                                     title: room_info.title,
                                     live_status: room_info.live_status,
@@ -600,8 +1011,8 @@ async fn handle_commands(
                             Err(e) => {
                                 warn!("Failed to get room info: {}", e);
                                 // Still update with basic info
-                                let _ = event_tx.send(Event::ClearDanmuList);
-                                let _ = event_tx.send(Event::UpdateRoom {
+                                event_tx.send(Event::ClearDanmuList);
+                                event_tx.send(Event::UpdateRoom {
                                     room_id: room.clone(),
                                     title: String::new(),
                                     live_status: 0,
@@ -645,6 +1056,77 @@ async fn handle_commands(
                     }
                 }
             }
+            UiCommand::RequestAreaList => {
+                let api_read = api.read().clone();
+                match api_read.get_area_list().await {
+                    Ok(areas) => {
+                        event_tx.send(Event::AreaList(areas));
+                    }
+                    Err(e) => {
+                        send_room_edit_result(&event_tx, Err(e), "");
+                    }
+                }
+            }
+            UiCommand::UpdateRoomArea { room_id, area_id } => {
+                info!("Updating room {} area to {}", room_id, area_id);
+                let api_read = api.read().clone();
+                let result = api_read.update_room_area(room_id, area_id).await;
+                send_room_edit_result(&event_tx, result, "分区已更新");
+            }
+            UiCommand::RequestRoomDetails { room_id } => {
+                let api_read = api.read().clone();
+                let details = async {
+                    let info = api_read.get_room_info(room_id).await?;
+                    let news = api_read.get_room_news(room_id).await?;
+                    anyhow::Ok((info, news))
+                };
+                match details.await {
+                    Ok((info, news)) => {
+                        event_tx.send(Event::RoomDetails {
+                            description: info.description,
+                            tags: split_tags(&info.tags)
+                                .into_iter()
+                                .map(String::from)
+                                .collect(),
+                            news,
+                            cover: info.keyframe,
+                        });
+                    }
+                    Err(e) => {
+                        send_room_edit_result(&event_tx, Err(e), "");
+                    }
+                }
+            }
+            UiCommand::UpdateRoomDetails {
+                room_id,
+                description,
+                tags,
+                news,
+            } => {
+                info!("Updating details of room {}", room_id);
+                let api_read = api.read().clone();
+                let result = async {
+                    let uid = api_read.get_room_info(room_id).await?.uid;
+                    api_read.update_room_description(room_id, &description).await?;
+                    api_read.update_room_tags(room_id, &tags).await?;
+                    api_read.update_room_news(room_id, uid, &news).await
+                };
+                send_room_edit_result(&event_tx, result.await, "直播间信息已保存");
+            }
+            UiCommand::UploadRoomCover { path } => {
+                info!("Uploading room cover {}", path);
+                let path = PathBuf::from(path);
+                let file_name = path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let api_read = api.read().clone();
+                let result = match tokio::fs::read(&path).await {
+                    Ok(image) => api_read.upload_room_cover(image, &file_name).await.map(|_| ()),
+                    Err(e) => Err(e.into()),
+                };
+                send_room_edit_result(&event_tx, result, "封面已更新");
+            }
             UiCommand::StopLive { room_id } => {
                 info!("Stopping live for room {}", room_id);
                 let api_read = api.read().clone();
@@ -669,7 +1151,7 @@ async fn handle_commands(
                             if let Some(ref data) = response.data {
                                 if data.need_face_auth && !data.qr.is_empty() {
                                     info!("Face auth required for starting live");
-                                    let _ = event_tx.send(Event::FaceAuthRequired {
+                                    event_tx.send(Event::FaceAuthRequired {
                                         qr_url: data.qr.clone(),
                                     });
                                     continue;
@@ -686,7 +1168,7 @@ async fn handle_commands(
 
                         if let Some(ref data) = response.data {
                             if let Some(ref rtmp) = data.rtmp {
-                                let _ = event_tx.send(Event::RtmpInfo {
+                                event_tx.send(Event::RtmpInfo {
                                     addr: rtmp.addr.clone(),
                                     code: rtmp.code.clone(),
                                 });
@@ -726,6 +1208,12 @@ async fn handle_commands(
                 if let Err(e) = config_write.set("level_effect", level_effect) {
                     error!("Failed to save level_effect: {}", e);
                 }
+                plugin_manager.lock().set_display_filter(DisplayFilter {
+                    medal_display,
+                    interact_display,
+                    guard_effect,
+                    level_effect,
+                });
             }
             UiCommand::UpdateOpacity(opacity) => {
                 info!("Updating opacity to {}", opacity);
@@ -771,7 +1259,7 @@ async fn handle_commands(
                     match api_read.get_user_info(uid).await {
                         Ok(user_info) => {
                             info!("User info fetched for {}: {}", uid, user_info.name);
-                            let _ = event_tx.send(Event::UserInfoFetched { uid, user_info });
+                            event_tx.send(Event::UserInfoFetched { uid, user_info });
                         }
                         Err(e) => {
                             error!("Failed to fetch user info for {}: {}", uid, e);
@@ -788,7 +1276,7 @@ async fn handle_commands(
                     match api_read.get_online_gold_rank(room_id, ruid, 1, 50).await {
                         Ok(data) => {
                             info!("Audience list fetched: {} users", data.item.len());
-                            let _ = event_tx.send(Event::AudienceListFetched { list: data.item });
+                            event_tx.send(Event::AudienceListFetched { list: data.item });
                         }
                         Err(e) => {
                             error!("Failed to fetch audience list: {}", e);
@@ -816,7 +1304,7 @@ async fn handle_commands(
                                 combined_list.len(),
                                 data.info.num
                             );
-                            let _ = event_tx.send(Event::GuardListFetched {
+                            event_tx.send(Event::GuardListFetched {
                                 list: combined_list,
                                 total: data.info.num,
                                 page,
@@ -888,6 +1376,195 @@ async fn handle_commands(
                 info!("Testing TTS");
                 tts_manager.test();
             }
+            UiCommand::UpdateObsSettings(obs_config) => {
+                info!(
+                    "Updating OBS settings: enabled={}, {} rules",
+                    obs_config.enabled,
+                    obs_config.rules.len()
+                );
+                if let Err(e) = config.write().set("obs", &obs_config) {
+                    error!("Failed to save obs settings: {}", e);
+                }
+                obs_handle.reconfigure(obs_config);
+            }
+            UiCommand::TestObsConnection => {
+                info!("Testing OBS connection");
+                obs_handle.test();
+            }
+            UiCommand::UpdateWebhooks(endpoints) => {
+                info!("Updating webhooks: {} endpoints", endpoints.len());
+                if let Err(e) = config.write().set("webhooks", &endpoints) {
+                    error!("Failed to save webhooks: {}", e);
+                }
+                webhook_dispatcher.set_endpoints(endpoints);
+            }
+            UiCommand::UpdateNetworkSettings(network_config) => {
+                info!("Updating network settings: proxy={:?}", network_config.proxy);
+                if let Err(e) = network_config.validate() {
+                    event_tx.send(Event::NetworkStatus {
+                        success: false,
+                        message: format!("设置无效: {}", e),
+                    });
+                    continue;
+                }
+                if let Err(e) = config.write().set("network", &network_config) {
+                    error!("Failed to save network settings: {}", e);
+                }
+                network::set(network_config);
+                event_tx.send(Event::NetworkStatus {
+                    success: true,
+                    message: "已保存，新建立的连接将使用新设置".to_string(),
+                });
+            }
+            UiCommand::TestNetwork(network_config) => {
+                let url = format!("{}/", api.read().endpoints().live_api);
+                let event_tx = event_tx.clone();
+                tokio::spawn(async move {
+                    let event = match network::test_connection(&network_config, &url).await {
+                        Ok(elapsed) => Event::NetworkStatus {
+                            success: true,
+                            message: format!("连接成功，耗时 {} ms", elapsed.as_millis()),
+                        },
+                        Err(e) => {
+                            warn!("Network test failed: {:#}", e);
+                            Event::NetworkStatus {
+                                success: false,
+                                message: format!("连接失败: {:#}", e),
+                            }
+                        }
+                    };
+                    event_tx.send(event);
+                });
+            }
+            UiCommand::UpdateRetentionSettings(retention_config) => {
+                info!("Updating retention settings: {:?}", retention_config);
+                if let Err(e) = config.write().set("retention", &retention_config) {
+                    error!("Failed to save retention settings: {}", e);
+                }
+                retention_handle.reconfigure(retention_config);
+                event_tx.send(Event::DatabaseStatus {
+                    stats: database.stats().unwrap_or_default(),
+                    message: "已保存".to_string(),
+                });
+            }
+            UiCommand::RefreshDatabaseStats => match database.stats() {
+                Ok(stats) => {
+                    event_tx.send(Event::DatabaseStatus {
+                        stats,
+                        message: String::new(),
+                    });
+                }
+                Err(e) => error!("Failed to load database stats: {}", e),
+            },
+            UiCommand::CompactDatabase => {
+                info!("Running retention and compaction now");
+                retention_handle.run_now();
+            }
+            UiCommand::UpdateBackupSettings(backup_config) => {
+                info!("Updating backup settings: {:?}", backup_config);
+                if let Err(e) = config.write().set("backup", &backup_config) {
+                    error!("Failed to save backup settings: {}", e);
+                }
+                backup_handle.reconfigure(backup_config);
+                send_backup_status(&event_tx, &backup_handle.dir(), "已保存".to_string());
+            }
+            UiCommand::BackupNow => {
+                info!("Backing up database now");
+                backup_handle.run_now();
+            }
+            UiCommand::RefreshBackups => {
+                send_backup_status(&event_tx, &backup_handle.dir(), String::new());
+            }
+            UiCommand::RestoreBackup(path) => {
+                let path = PathBuf::from(path);
+                info!("Restoring database from {}", path.display());
+                let dir = backup_handle.dir();
+                // Keep the current data in case the wrong backup was picked
+                let result = backup::create_backup(&database, &dir, 0)
+                    .and_then(|saved| database.restore_from(&path).map(|()| saved));
+                let message = match result {
+                    Ok(saved) => format!("已恢复，恢复前的数据备份在 {}", saved.display()),
+                    Err(e) => {
+                        error!("Failed to restore database: {:#}", e);
+                        format!("恢复失败：{:#}", e)
+                    }
+                };
+                send_backup_status(&event_tx, &dir, message);
+            }
+            UiCommand::MergeDatabase(path) => {
+                let path = PathBuf::from(path);
+                info!("Importing database {}", path.display());
+                let message = match database.merge_from(&path) {
+                    Ok(report) => {
+                        info!("Database import finished: {:?}", report);
+                        report.summary()
+                    }
+                    Err(e) => {
+                        error!("Failed to import database: {:#}", e);
+                        format!("导入失败：{:#}", e)
+                    }
+                };
+                send_backup_status(&event_tx, &backup_handle.dir(), message);
+            }
+            UiCommand::UpdateRecorderSettings(recorder_config) => {
+                info!(
+                    "Updating recorder settings: enabled={}, format={:?}",
+                    recorder_config.enabled, recorder_config.format
+                );
+                if let Err(e) = config.write().set("recorder", &recorder_config) {
+                    error!("Failed to save recorder settings: {}", e);
+                }
+                recorder_handle.reconfigure(recorder_config);
+            }
+            UiCommand::StartSessionRecording => {
+                let room_id = config.read().get_room().map(|r| r.real_id()).unwrap_or(0);
+                let path = sessions.dir.join(format!(
+                    "{}_{}.{}",
+                    room_id,
+                    chrono::Local::now().format("%Y%m%d_%H%M%S"),
+                    SESSION_EXTENSION
+                ));
+                let message = match sessions.recorder.start(&path, room_id) {
+                    Ok(()) => "开始录制原始消息".to_string(),
+                    Err(e) => {
+                        error!("Failed to start session recording: {}", e);
+                        format!("录制失败: {}", e)
+                    }
+                };
+                event_tx.send(sessions.status(message));
+            }
+            UiCommand::StopSessionRecording => {
+                let message = match sessions.recorder.stop() {
+                    Ok(Some(path)) => format!("已保存到 {}", path.display()),
+                    Ok(None) => String::new(),
+                    Err(e) => {
+                        error!("Failed to finish session recording: {}", e);
+                        format!("保存失败: {}", e)
+                    }
+                };
+                event_tx.send(sessions.status(message));
+            }
+            UiCommand::StartReplay { path, speed } => {
+                info!("Replaying session {} at speed {}", path, speed);
+                let _ = backend_cmd_tx.send(BackendCommand::Replay {
+                    path: PathBuf::from(path),
+                    speed,
+                });
+            }
+            UiCommand::StopReplay => {
+                let _ = backend_cmd_tx.send(BackendCommand::StopReplay);
+            }
+            UiCommand::RefreshSessions => {
+                event_tx.send(sessions.status(""));
+            }
+            UiCommand::RefreshWebhookDeliveries => match database.get_webhook_deliveries(50) {
+                Ok(deliveries) => {
+                    event_tx.send(Event::WebhookDeliveries(deliveries));
+                }
+                Err(e) => {
+                    error!("Failed to load webhook deliveries: {}", e);
+                }
+            },
             UiCommand::RefreshPlugins => {
                 info!("Refreshing plugins list");
                 let plugins_dir = config.read().data_dir().join("plugins");
@@ -907,21 +1584,10 @@ async fn handle_commands(
                 }
 
                 // Send updated plugin list to UI
-                let plugin_events: Vec<jlivertool_core::events::PluginInfoEvent> = pm
-                    .get_plugins_with_paths()
-                    .into_iter()
-                    .map(|(meta, path)| jlivertool_core::events::PluginInfoEvent {
-                        id: meta.id,
-                        name: meta.name,
-                        author: meta.author,
-                        desc: meta.desc,
-                        version: meta.version,
-                        path,
-                    })
-                    .collect();
+                let plugin_events = plugin_info_events(&pm);
                 info!("Sending {} plugins to UI", plugin_events.len());
 
-                let _ = event_tx.send(Event::PluginsRefreshed {
+                event_tx.send(Event::PluginsRefreshed {
                     plugins: plugin_events,
                 });
             }
@@ -946,36 +1612,21 @@ async fn handle_commands(
                             match load_result {
                                 Ok(plugin_id) => {
                                     info!("Successfully imported plugin: {}", plugin_id);
-                                    let _ = event_tx.send(Event::PluginImportResult {
+                                    event_tx.send(Event::PluginImportResult {
                                         success: true,
                                         message: format!("插件 {} 导入成功", plugin_id),
                                     });
 
                                     // Refresh plugins list
                                     let pm = pm.lock();
-                                    let plugin_events: Vec<
-                                        jlivertool_core::events::PluginInfoEvent,
-                                    > = pm
-                                        .get_plugins_with_paths()
-                                        .into_iter()
-                                        .map(|(meta, path)| {
-                                            jlivertool_core::events::PluginInfoEvent {
-                                                id: meta.id,
-                                                name: meta.name,
-                                                author: meta.author,
-                                                desc: meta.desc,
-                                                version: meta.version,
-                                                path,
-                                            }
-                                        })
-                                        .collect();
-                                    let _ = event_tx.send(Event::PluginsRefreshed {
+                                    let plugin_events = plugin_info_events(&pm);
+                                    event_tx.send(Event::PluginsRefreshed {
                                         plugins: plugin_events,
                                     });
                                 }
                                 Err(e) => {
                                     error!("Failed to load plugin: {}", e);
-                                    let _ = event_tx.send(Event::PluginImportResult {
+                                    event_tx.send(Event::PluginImportResult {
                                         success: false,
                                         message: format!("加载插件失败: {}", e),
                                     });
@@ -984,7 +1635,7 @@ async fn handle_commands(
                         }
                         Err(e) => {
                             error!("Failed to download plugin: {}", e);
-                            let _ = event_tx.send(Event::PluginImportResult {
+                            event_tx.send(Event::PluginImportResult {
                                 success: false,
                                 message: format!("下载失败: {}", e),
                             });
@@ -1004,19 +1655,8 @@ async fn handle_commands(
                         info!("Successfully removed plugin: {}", plugin_id);
 
                         // Send updated plugin list to UI
-                        let plugin_events: Vec<jlivertool_core::events::PluginInfoEvent> = pm
-                            .get_plugins_with_paths()
-                            .into_iter()
-                            .map(|(meta, path)| jlivertool_core::events::PluginInfoEvent {
-                                id: meta.id,
-                                name: meta.name,
-                                author: meta.author,
-                                desc: meta.desc,
-                                version: meta.version,
-                                path,
-                            })
-                            .collect();
-                        let _ = event_tx.send(Event::PluginsRefreshed {
+                        let plugin_events = plugin_info_events(&pm);
+                        event_tx.send(Event::PluginsRefreshed {
                             plugins: plugin_events,
                         });
                     }
@@ -1025,6 +1665,20 @@ async fn handle_commands(
                     }
                 }
             }
+            UiCommand::SetPluginSettings { plugin_id, values } => {
+                info!("Updating settings of plugin: {}", plugin_id);
+                let pm = plugin_manager.lock();
+                match pm.set_plugin_settings(&plugin_id, &values) {
+                    Ok(_) => {
+                        event_tx.send(Event::PluginsRefreshed {
+                            plugins: plugin_info_events(&pm),
+                        });
+                    }
+                    Err(e) => {
+                        error!("Failed to update plugin settings: {}", e);
+                    }
+                }
+            }
             UiCommand::UpdateAdvancedSettings {
                 max_danmu,
                 log_level,
@@ -1051,7 +1705,7 @@ async fn handle_commands(
                         error!("Failed to clear room data: {}", e);
                     } else {
                         info!("Room data cleared successfully");
-                        let _ = event_tx.send(Event::DataCleared);
+                        event_tx.send(Event::DataCleared);
                     }
                 }
             }
@@ -1083,7 +1737,7 @@ async fn handle_commands(
                 tokio::spawn(async move {
                     match jlivertool_core::check_for_update(&current_version).await {
                         Ok(update_info) => {
-                            let _ = event_tx.send(Event::UpdateCheckResult {
+                            event_tx.send(Event::UpdateCheckResult {
                                 has_update: update_info.has_update,
                                 current_version: update_info.current_version,
                                 latest_version: update_info.latest_version,
@@ -1092,7 +1746,7 @@ async fn handle_commands(
                             });
                         }
                         Err(e) => {
-                            let _ = event_tx.send(Event::UpdateCheckResult {
+                            event_tx.send(Event::UpdateCheckResult {
                                 has_update: false,
                                 current_version,
                                 latest_version: String::new(),
@@ -1118,10 +1772,271 @@ async fn handle_commands(
                     error!("Failed to save plugin_http_port: {}", e);
                 }
             }
+            UiCommand::MuteUser {
+                room_id,
+                uid,
+                hours,
+            } => {
+                info!(
+                    "Muting user {} in room {} for {} hours",
+                    uid, room_id, hours
+                );
+                let result = api.read().clone().block_user(room_id, uid, hours).await;
+                let duration = match hours {
+                    BLOCK_THIS_LIVE => "本场直播".to_string(),
+                    BLOCK_PERMANENT => "永久".to_string(),
+                    h => format!("{} 小时", h),
+                };
+                send_moderation_result(
+                    &event_tx,
+                    result,
+                    format!("已禁言用户 {}（{}）", uid, duration),
+                );
+            }
+            UiCommand::UnmuteUser { room_id, uid } => {
+                info!("Unmuting user {} in room {}", uid, room_id);
+                let result = api.read().clone().unblock_user(room_id, uid).await;
+                send_moderation_result(&event_tx, result, format!("已解除用户 {} 的禁言", uid));
+            }
+            UiCommand::FetchBlockedUsers { room_id } => {
+                let api_read = api.read().clone();
+                match api_read.get_blocked_users(room_id, 1).await {
+                    Ok(data) => {
+                        event_tx.send(Event::BlockedUsersFetched {
+                            users: data.data,
+                            total: data.total,
+                        });
+                    }
+                    Err(e) => {
+                        error!("Failed to fetch blocked users: {}", e);
+                        send_moderation_result(&event_tx, Err(e), String::new());
+                    }
+                }
+            }
+            UiCommand::SetRoomSilence {
+                room_id,
+                silence,
+                minutes,
+            } => {
+                info!(
+                    "Setting room {} silence to {:?} for {} minutes",
+                    room_id, silence, minutes
+                );
+                let result = api
+                    .read()
+                    .clone()
+                    .set_room_silence(room_id, silence, minutes)
+                    .await;
+                let message = match silence {
+                    RoomSilence::Off => "已解除全场禁言".to_string(),
+                    RoomSilence::Level(level) => {
+                        format!("已开启全场禁言：用户等级低于 {} 级", level)
+                    }
+                    RoomSilence::Medal(level) => {
+                        format!("已开启全场禁言：粉丝勋章低于 {} 级", level)
+                    }
+                };
+                send_moderation_result(&event_tx, result, message);
+            }
+            UiCommand::AddRoomAdmin { uid } => {
+                info!("Appointing room admin {}", uid);
+                let result = api.read().clone().add_room_admin(uid).await;
+                send_moderation_result(&event_tx, result, format!("已将用户 {} 设为房管", uid));
+            }
+            UiCommand::RemoveRoomAdmin { uid } => {
+                info!("Dismissing room admin {}", uid);
+                let result = api.read().clone().remove_room_admin(uid).await;
+                send_moderation_result(&event_tx, result, format!("已取消用户 {} 的房管", uid));
+            }
+            UiCommand::RequestCmdStats => {
+                event_tx.send(Event::CmdStatsUpdated(cmd_stats.snapshot()));
+            }
+            UiCommand::ClearCmdStats => {
+                cmd_stats.clear();
+                event_tx.send(Event::CmdStatsUpdated(Vec::new()));
+            }
+            UiCommand::ExportDanmaku {
+                room_id,
+                from,
+                to,
+                format,
+            } => {
+                let stamp = chrono::Local
+                    .timestamp_opt(from, 0)
+                    .single()
+                    .map(|t| t.format("%Y%m%d_%H%M").to_string())
+                    .unwrap_or_else(|| from.to_string());
+                let path = config.read().data_dir().join("exports").join(format!(
+                    "{}_{}.{}",
+                    room_id,
+                    stamp,
+                    format.extension()
+                ));
+                // Include messages still waiting for the background writer
+                database.flush();
+                // Align 0:00 with the recorded video if there is one
+                let start_time = match database.first_recording_in(room_id, from, to) {
+                    Ok(Some(recording)) => recording.start_time / 1000,
+                    _ => from,
+                };
+                let options = ExportOptions {
+                    start_time,
+                    ..Default::default()
+                };
+                info!("Exporting danmaku of room {} to {:?}", room_id, path);
+                let event =
+                    match export_to_file(&database, room_id, from, to, format, &options, &path) {
+                        Ok(count) => Event::ExportFinished {
+                            success: true,
+                            message: format!("已导出 {} 条到 {}", count, path.display()),
+                        },
+                        Err(e) => {
+                            error!("Failed to export danmaku: {}", e);
+                            Event::ExportFinished {
+                                success: false,
+                                message: format!("导出失败：{}", e),
+                            }
+                        }
+                    };
+                event_tx.send(event);
+            }
+            UiCommand::ExportEarnings { room_id, from, to } => {
+                let stamp = chrono::Local
+                    .timestamp_opt(from, 0)
+                    .single()
+                    .map(|t| t.format("%Y%m%d_%H%M").to_string())
+                    .unwrap_or_else(|| from.to_string());
+                let dir = config.read().data_dir().join("exports");
+                let filter = EarningsFilter {
+                    records: RecordFilter {
+                        room_id: Some(room_id),
+                        from: Some(from),
+                        to: Some(to),
+                        archived: None,
+                    },
+                    ..Default::default()
+                };
+                info!("Exporting earnings of room {} to {:?}", room_id, dir);
+                database.flush();
+                let result = [
+                    EarningsReport::Records,
+                    EarningsReport::Daily,
+                    EarningsReport::Users,
+                ]
+                .into_iter()
+                .map(|report| {
+                    let path = dir.join(format!(
+                        "{}_{}_earnings_{}.csv",
+                        room_id,
+                        stamp,
+                        report.as_str()
+                    ));
+                    export_earnings(&database, &filter, report, EarningsFormat::Csv, &path)
+                })
+                .collect::<Result<Vec<usize>>>();
+                let event = match result {
+                    Ok(counts) => Event::ExportFinished {
+                        success: true,
+                        message: format!("已导出 {} 条收益记录到 {}", counts[0], dir.display()),
+                    },
+                    Err(e) => {
+                        error!("Failed to export earnings: {}", e);
+                        Event::ExportFinished {
+                            success: false,
+                            message: format!("导出失败：{}", e),
+                        }
+                    }
+                };
+                event_tx.send(event);
+            }
+            UiCommand::CreateGoal {
+                title,
+                target,
+                sources,
+            } => {
+                info!("Creating goal {} (¥{:.2})", title, target as f64 / 1000.0);
+                let result = goal_tracker.create(&title, target, sources);
+                send_goal_result(&event_tx, result, "已创建目标");
+            }
+            UiCommand::ResetGoal { id } => {
+                info!("Resetting goal {}", id);
+                send_goal_result(&event_tx, goal_tracker.reset(id), "已重置目标");
+            }
+            UiCommand::CompleteGoal { id } => {
+                info!("Completing goal {}", id);
+                send_goal_result(&event_tx, goal_tracker.complete(id), "已完成目标");
+            }
         }
     }
 }
 
+/// Publish a changed goal and report the result of a goal command to the UI
+fn send_goal_result(event_tx: &EventSender, result: Result<Goal>, success_message: &str) {
+    let event = match result {
+        Ok(goal) => {
+            let message = format!("{}：{}", success_message, goal.title);
+            event_tx.send(Event::GoalProgress(goal));
+            Event::GoalCommandResult {
+                success: true,
+                message,
+            }
+        }
+        Err(e) => {
+            error!("Goal command failed: {}", e);
+            Event::GoalCommandResult {
+                success: false,
+                message: format!("操作失败：{}", e),
+            }
+        }
+    };
+    event_tx.send(event);
+}
+
+/// Report the result of a room edit to the UI
+fn send_room_edit_result(event_tx: &EventSender, result: Result<()>, success_message: &str) {
+    let event = match result {
+        Ok(()) => Event::RoomEditResult {
+            success: true,
+            message: success_message.to_string(),
+        },
+        Err(e) => {
+            error!("Room edit failed: {}", e);
+            Event::RoomEditResult {
+                success: false,
+                message: format!("操作失败：{}", e),
+            }
+        }
+    };
+    event_tx.send(event);
+}
+
+/// Report the result of a moderation action to the UI
+fn send_moderation_result(event_tx: &EventSender, result: Result<()>, success_message: String) {
+    let event = match result {
+        Ok(()) => Event::ModerationResult {
+            success: true,
+            message: success_message,
+        },
+        Err(e) => {
+            error!("Moderation action failed: {}", e);
+            Event::ModerationResult {
+                success: false,
+                message: format!("操作失败：{}", e),
+            }
+        }
+    };
+    event_tx.send(event);
+}
+
+/// Report the backups in `dir` and the result of a backup action to the UI
+fn send_backup_status(event_tx: &EventSender, dir: &Path, message: String) {
+    let backups = backup::list_backups(dir).unwrap_or_else(|e| {
+        warn!("Failed to list backups in {}: {}", dir.display(), e);
+        Vec::new()
+    });
+    event_tx.send(Event::BackupStatus { backups, message });
+}
+
 /// Poll QR login status
 async fn poll_qr_login(
     qrcode_key: String,
@@ -1135,7 +2050,7 @@ async fn poll_qr_login(
     loop {
         attempts += 1;
         if attempts > MAX_ATTEMPTS {
-            let _ = event_tx.send(Event::QrLoginStatus {
+            event_tx.send(Event::QrLoginStatus {
                 status: QrCodeStatus::Expired,
             });
             break;
@@ -1146,7 +2061,7 @@ async fn poll_qr_login(
         let api_read = api.read().clone();
         match api_read.qr_poll(&qrcode_key).await {
             Ok((status, cookies_opt)) => {
-                let _ = event_tx.send(Event::QrLoginStatus { status });
+                event_tx.send(Event::QrLoginStatus { status });
 
                 match status {
                     QrCodeStatus::Success => {
@@ -1181,12 +2096,12 @@ async fn poll_qr_login(
                                                 vip: Default::default(),
                                                 live_room: None,
                                             };
-                                        let _ = event_tx.send(Event::LoginStatusChanged {
+                                        event_tx.send(Event::LoginStatusChanged {
                                             logged_in: true,
                                             user_info: Some(user_info),
                                         });
                                     } else {
-                                        let _ = event_tx.send(Event::LoginStatusChanged {
+                                        event_tx.send(Event::LoginStatusChanged {
                                             logged_in: true,
                                             user_info: None,
                                         });
@@ -1194,7 +2109,7 @@ async fn poll_qr_login(
                                 }
                                 Err(e) => {
                                     warn!("Failed to get user info: {}", e);
-                                    let _ = event_tx.send(Event::LoginStatusChanged {
+                                    event_tx.send(Event::LoginStatusChanged {
                                         logged_in: true,
                                         user_info: None,
                                     });
@@ -1222,10 +2137,14 @@ async fn poll_qr_login(
 /// Run the backend service
 async fn run_backend(
     event_tx: EventSender,
+    ui_bus: EventBus,
+    raw_forwarder: RawForwarder,
+    live_room: Arc<AtomicU64>,
     config: Arc<RwLock<ConfigStore>>,
     api: Arc<RwLock<BiliApi>>,
-    database: Arc<Database>,
-    tts_manager: Arc<TtsManager>,
+    cmd_stats: Arc<CmdStats>,
+    goal_tracker: Arc<GoalTracker>,
+    sessions: Sessions,
     mut backend_cmd_rx: tokio_mpsc::UnboundedReceiver<BackendCommand>,
 ) -> Result<()> {
     // Get initial room to connect
@@ -1235,83 +2154,140 @@ async fn run_backend(
         .unwrap_or_else(|| RoomId::new(0, 21484828, 0)); // Default room
 
     let mut current_room = initial_room;
+    let mut pending_replay: Option<(PathBuf, f64)> = None;
 
     loop {
-        // Connect to current room
-        info!("Connecting to room {}", current_room.real_id());
-
-        let api_read = api.read().clone();
-
-        // Get room info
-        match api_read.get_room_info(current_room.real_id()).await {
-            Ok(room_info) => {
-                info!(
-                    "Room: {} ({}), Status: {}",
-                    room_info.title,
-                    current_room.real_id(),
-                    room_info.live_status
-                );
-
-                // Emit room update event
-                let _ = event_tx.send(Event::UpdateRoom {
-                    room_id: current_room.clone(),
-                    title: room_info.title.clone(),
-                    live_status: room_info.live_status,
-                    area_id: room_info.area_id,
-                });
-            }
-            Err(e) => {
-                warn!("Failed to get room info: {}", e);
-            }
-        }
-
-        // Get danmu info for WebSocket connection
-        let danmu_info = match api_read.get_danmu_info(current_room.real_id()).await {
-            Ok(info) => info,
-            Err(e) => {
-                error!("Failed to get danmu info: {}", e);
-                // Wait and retry
-                tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
-                continue;
+        // A replay replaces the connection and publishes on its own bus, which
+        // only reaches the display and a scratch database, so replayed
+        // messages are not stored twice or passed to TTS, OBS and webhooks
+        let mut replay_target: Option<(EventSender, Arc<GoalTracker>)> = None;
+        let (ws_handle, mut ws_event_rx, room_id) = if let Some((path, speed)) =
+            pending_replay.take()
+        {
+            let opened = SessionReplay::open(&path, speed).and_then(|(replay, rx)| {
+                let scratch = Database::in_memory()?;
+                Ok((replay, rx, scratch))
+            });
+            let (replay, ws_event_rx, scratch) = match opened {
+                Ok(opened) => opened,
+                Err(e) => {
+                    error!("Failed to open session {:?}: {}", path, e);
+                    event_tx.send(sessions.status(format!("回放失败: {}", e)));
+                    continue;
+                }
+            };
+            let room_id = replay.header().room_id;
+            info!("Replaying session {:?} of room {}", path, room_id);
+            *sessions.replaying.lock() = Some(path);
+            event_tx.send(sessions.status("回放中"));
+            let replay_bus = EventBus::new();
+            forward_events(&replay_bus, &ui_bus);
+            replay_bus.on(Database::STORED_EVENT_TYPES.iter().copied(), {
+                let scratch = scratch.clone();
+                move |event| scratch.store_event(room_id, event)
+            });
+            replay_target = Some((
+                EventSender::new(replay_bus, event_tx.has_events.clone()),
+                Arc::new(GoalTracker::new(scratch)),
+            ));
+
+            let ws_handle = tokio::spawn(async move {
+                if let Err(e) = replay.run().await {
+                    error!("Session replay error: {}", e);
+                }
+            });
+            (ws_handle, ws_event_rx, room_id)
+        } else {
+            // Connect to current room
+            info!("Connecting to room {}", current_room.real_id());
+            live_room.store(current_room.real_id(), Ordering::Relaxed);
+
+            let api_read = api.read().clone();
+
+            // Get room info
+            match api_read.get_room_info(current_room.real_id()).await {
+                Ok(room_info) => {
+                    info!(
+                        "Room: {} ({}), Status: {}",
+                        room_info.title,
+                        current_room.real_id(),
+                        room_info.live_status
+                    );
+
+                    // Emit room update event
+                    event_tx.send(Event::UpdateRoom {
+                        room_id: current_room.clone(),
+                        title: room_info.title.clone(),
+                        live_status: room_info.live_status,
+                        area_id: room_info.area_id,
+                    });
+                }
+                Err(e) => {
+                    warn!("Failed to get room info: {}", e);
+                }
             }
-        };
 
-        // Select best host
-        let host = match danmu_info.host_list.first() {
-            Some(h) => h,
-            None => {
-                error!("No WebSocket host available");
-                tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
-                continue;
+            // Load goals of the room with their progress
+            match goal_tracker.set_room(current_room.real_id()) {
+                Ok(goals) => {
+                    for goal in goals {
+                        event_tx.send(Event::GoalProgress(goal));
+                    }
+                }
+                Err(e) => {
+                    warn!("Failed to load goals: {}", e);
+                }
             }
-        };
 
-        let ws_info = WsInfo {
-            server: format!("wss://{}:{}/sub", host.host, host.wss_port),
-            room_id: current_room.real_id(),
-            uid: config
-                .read()
-                .get_cookies()
-                .map(|c| c.dede_user_id.parse().unwrap_or(0))
-                .unwrap_or(0),
-            token: danmu_info.token,
+            // Get danmu info for WebSocket connection
+            let danmu_info = match api_read.get_danmu_info(current_room.real_id()).await {
+                Ok(info) => info,
+                Err(e) => {
+                    error!("Failed to get danmu info: {}", e);
+                    // Wait and retry
+                    tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+                    continue;
+                }
+            };
+
+            // Select best host
+            let host = match danmu_info.host_list.first() {
+                Some(h) => h,
+                None => {
+                    error!("No WebSocket host available");
+                    tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+                    continue;
+                }
+            };
+
+            let ws_info = WsInfo {
+                server: api_read.endpoints().danmu_ws_url(host),
+                room_id: current_room.real_id(),
+                uid: config
+                    .read()
+                    .get_cookies()
+                    .map(|c| c.dede_user_id.parse().unwrap_or(0))
+                    .unwrap_or(0),
+                token: danmu_info.token,
+            };
+
+            // Create WebSocket connection
+            let (mut ws, ws_event_rx) = BiliWebSocket::new(ws_info);
+            ws.set_recorder(sessions.recorder.clone());
+
+            // Spawn WebSocket connection task
+            let ws_handle = tokio::spawn(async move {
+                if let Err(e) = ws.connect().await {
+                    error!("WebSocket connection error: {}", e);
+                }
+            });
+            (ws_handle, ws_event_rx, current_room.real_id())
         };
 
-        // Create WebSocket connection
-        let (ws, mut ws_event_rx) = BiliWebSocket::new(ws_info);
-
-        // Spawn WebSocket connection task
-        let ws_handle = tokio::spawn(async move {
-            if let Err(e) = ws.connect().await {
-                error!("WebSocket connection error: {}", e);
-            }
-        });
-
         // Handle WebSocket events and backend commands
-        let event_tx_clone = event_tx.clone();
-        let db_clone = database.clone();
-        let tts_clone = tts_manager.clone();
-        let room_id = current_room.real_id();
+        let replaying = replay_target.is_some();
+        let (event_tx_clone, goals_clone) =
+            replay_target.unwrap_or_else(|| (event_tx.clone(), goal_tracker.clone()));
 
         let mut should_reconnect = false;
         let mut new_room: Option<RoomId> = None;
@@ -1323,7 +2299,7 @@ async fn run_backend(
                     match event {
                         Some(WsEvent::Connected) => {
                             info!("WebSocket connected");
-                            let _ = event_tx_clone.send(Event::ConnectionStatus { connected: true });
+                            event_tx_clone.send(Event::ConnectionStatus { connected: true });
                         }
                         Some(WsEvent::Authenticated) => {
                             info!("WebSocket authenticated");
@@ -1332,21 +2308,32 @@ async fn run_backend(
                             // Only update online count from heartbeat if it's a reasonable value
                             // Heartbeat can return 1 when there's no valid data
                             if count > 1 {
-                                let _ = event_tx_clone.send(Event::UpdateOnline {
+                                event_tx_clone.send(Event::UpdateOnline {
                                     count: count as u64,
                                 });
                             }
                         }
                         Some(WsEvent::Message(body)) => {
+                            let body = Arc::new(body);
                             if let Some(cmd) = body.get("cmd").and_then(|v| v.as_str()) {
-                                handle_message(cmd, &body, room_id, &event_tx_clone, &db_clone, &tts_clone);
+                                let decoded = handle_message(
+                                    cmd,
+                                    &body,
+                                    room_id,
+                                    &event_tx_clone,
+                                    &goals_clone,
+                                );
+                                cmd_stats.record(cmd, &body, decoded);
+                                if !decoded {
+                                    raw_forwarder.send(cmd, &body);
+                                }
                             }
                         }
                         Some(WsEvent::Disconnected) => {
                             info!("WebSocket disconnected");
-                            let _ = event_tx_clone.send(Event::ConnectionStatus { connected: false });
-                            // Reconnect to same room
-                            should_reconnect = true;
+                            event_tx_clone.send(Event::ConnectionStatus { connected: false });
+                            // Reconnect to same room, right away after a replay
+                            should_reconnect = !replaying;
                             break;
                         }
                         Some(WsEvent::Error(err)) => {
@@ -1367,6 +2354,15 @@ async fn run_backend(
                             new_room = Some(room);
                             break;
                         }
+                        Some(BackendCommand::Replay { path, speed }) => {
+                            pending_replay = Some((path, speed));
+                            break;
+                        }
+                        Some(BackendCommand::StopReplay) => {
+                            if replaying {
+                                break;
+                            }
+                        }
                         Some(BackendCommand::Stop) => {
                             info!("Received stop command");
                             ws_handle.abort();
@@ -1385,6 +2381,11 @@ async fn run_backend(
         // Abort the WebSocket task
         ws_handle.abort();
 
+        if replaying {
+            *sessions.replaying.lock() = None;
+            event_tx.send(sessions.status("回放结束"));
+        }
+
         // Update current room if changed
         if let Some(room) = new_room {
             current_room = room;
@@ -1396,59 +2397,37 @@ async fn run_backend(
     }
 }
 
-/// Handle incoming WebSocket message
+/// Decode a WebSocket message into events
+/// Returns false if the cmd is not modeled by the core
 fn handle_message(
     cmd: &str,
     body: &serde_json::Value,
     room_id: u64,
     event_tx: &EventSender,
-    database: &Arc<Database>,
-    tts_manager: &Arc<TtsManager>,
-) {
+    goal_tracker: &GoalTracker,
+) -> bool {
     let base_cmd = cmd.split(':').next().unwrap_or(cmd);
 
     match base_cmd {
         "DANMU_MSG" => {
             if let Some(mut danmu) = DanmuMessage::from_raw(body, None) {
                 danmu.is_mirror = false;
-                // Store in database (only non-generated messages)
-                if !danmu.is_generated {
-                    if let Err(e) = database.insert_danmu(room_id, &danmu) {
-                        warn!("Failed to store danmu: {}", e);
-                    }
-                }
-                // TTS for danmu
-                tts_manager.speak(TtsMessage::danmu(&danmu.sender.uname, &danmu.content));
-                let _ = event_tx.send(Event::NewDanmu(danmu));
+                event_tx.send(Event::NewDanmu(danmu));
             }
         }
         "DANMU_MSG_MIRROR" => {
             if let Some(mut danmu) = DanmuMessage::from_raw(body, None) {
                 danmu.is_mirror = true;
-                // Store in database (only non-generated messages)
-                if !danmu.is_generated {
-                    if let Err(e) = database.insert_danmu(room_id, &danmu) {
-                        warn!("Failed to store danmu: {}", e);
-                    }
-                }
-                // TTS for danmu
-                tts_manager.speak(TtsMessage::danmu(&danmu.sender.uname, &danmu.content));
-                let _ = event_tx.send(Event::NewDanmu(danmu));
+                event_tx.send(Event::NewDanmu(danmu));
             }
         }
         "SEND_GIFT" => {
             if let Some(gift) = GiftMessage::from_raw(body, room_id) {
-                // Store in database
-                if let Err(e) = database.insert_gift(&gift) {
-                    warn!("Failed to store gift: {}", e);
-                }
-                // TTS for gift
-                tts_manager.speak(TtsMessage::gift(
-                    &gift.sender.uname,
-                    &gift.gift_info.name,
-                    gift.num,
-                ));
-                let _ = event_tx.send(Event::NewGift(gift));
+                let goals = goal_tracker.on_gift(&gift);
+                event_tx.send(Event::NewGift(gift));
+                for goal in goals {
+                    event_tx.send(Event::GoalProgress(goal));
+                }
             }
         }
         "USER_TOAST_MSG" => {
@@ -1460,11 +2439,11 @@ fn handle_message(
                     guard.num,
                     guard.price as f64 / 1000.0
                 );
-                // Store in database
-                if let Err(e) = database.insert_guard(&guard) {
-                    warn!("Failed to store guard: {}", e);
+                let goals = goal_tracker.on_guard(&guard);
+                event_tx.send(Event::NewGuard(guard));
+                for goal in goals {
+                    event_tx.send(Event::GoalProgress(goal));
                 }
-                let _ = event_tx.send(Event::NewGuard(guard));
             }
         }
         "SUPER_CHAT_MESSAGE" => {
@@ -1473,27 +2452,21 @@ fn handle_message(
                     "New superchat: {} sent ¥{} - {}",
                     sc.sender.uname, sc.price, sc.message
                 );
-                // Store in database
-                if let Err(e) = database.insert_superchat(&sc) {
-                    warn!("Failed to store superchat: {}", e);
-                }
-                // TTS for superchat
-                tts_manager.speak(TtsMessage::superchat(
-                    &sc.sender.uname,
-                    sc.price,
-                    &sc.message,
-                ));
-                let _ = event_tx.send(Event::NewSuperChat(sc));
+                let goals = goal_tracker.on_superchat(&sc);
+                event_tx.send(Event::NewSuperChat(sc));
+                for goal in goals {
+                    event_tx.send(Event::GoalProgress(goal));
+                }
             }
         }
         "INTERACT_WORD" => {
             if let Some(interact) = InteractMessage::from_raw(body) {
-                let _ = event_tx.send(Event::NewInteract(interact));
+                event_tx.send(Event::NewInteract(interact));
             }
         }
         "ENTRY_EFFECT" => {
             if let Some(entry) = EntryEffectMessage::from_raw(body) {
-                let _ = event_tx.send(Event::NewEntryEffect(entry));
+                event_tx.send(Event::NewEntryEffect(entry));
             }
         }
         "ROOM_CHANGE" => {
@@ -1502,19 +2475,19 @@ fn handle_message(
                     "Room changed: title={}, area={}/{}",
                     room_change.title, room_change.parent_area_name, room_change.area_name
                 );
-                let _ = event_tx.send(Event::RoomChange(room_change));
+                event_tx.send(Event::RoomChange(room_change));
             }
         }
         "ONLINE_RANK_COUNT" | "ONLINE_RANK_V2" => {
             if let Some(rank) = OnlineRankCountMessage::from_raw(body) {
-                let _ = event_tx.send(Event::UpdateOnline { count: rank.count });
+                event_tx.send(Event::UpdateOnline { count: rank.count });
             }
         }
         "LIVE" => {
-            let _ = event_tx.send(Event::LiveStart);
+            event_tx.send(Event::LiveStart);
         }
         "PREPARING" => {
-            let _ = event_tx.send(Event::LiveEnd);
+            event_tx.send(Event::LiveEnd);
         }
         "WARNING" => {
             if let Some(warning) = WarningMessage::from_raw(body) {
@@ -1527,7 +2500,7 @@ fn handle_message(
                 {
                     error!("Failed to show warning notification: {}", e);
                 }
-                let _ = event_tx.send(Event::Warning(warning));
+                event_tx.send(Event::Warning(warning));
             }
         }
         "CUT_OFF" => {
@@ -1541,9 +2514,76 @@ fn handle_message(
                 {
                     error!("Failed to show cutoff notification: {}", e);
                 }
-                let _ = event_tx.send(Event::CutOff(cutoff));
+                event_tx.send(Event::CutOff(cutoff));
+            }
+        }
+        "LIKE_INFO_V3_UPDATE" => {
+            if let Some(like) = LikeCountMessage::from_raw(body) {
+                event_tx.send(Event::UpdateLikeCount {
+                    count: like.click_count,
+                });
+            }
+        }
+        "LIKE_INFO_V3_CLICK" => {
+            if let Some(like) = LikeClickMessage::from_raw(body) {
+                event_tx.send(Event::NewLikeClick(like));
+            }
+        }
+        "WATCHED_CHANGE" => {
+            if let Some(watched) = WatchedChangeMessage::from_raw(body) {
+                event_tx.send(Event::UpdateWatched {
+                    count: watched.num,
+                    text: watched.text,
+                });
+            }
+        }
+        "POPULARITY_RED_POCKET_START" => {
+            if let Some(pocket) = RedPocketStartMessage::from_raw(body) {
+                info!(
+                    "Red pocket started: {} (¥{:.2})",
+                    pocket.sender.uname,
+                    pocket.total_price as f64 / 1000.0
+                );
+                event_tx.send(Event::RedPocketStart(pocket));
+            }
+        }
+        "POPULARITY_RED_POCKET_WINNER_LIST" => {
+            if let Some(result) = RedPocketWinnerMessage::from_raw(body) {
+                event_tx.send(Event::RedPocketWinners(result));
+            }
+        }
+        "ANCHOR_LOT_START" => {
+            if let Some(lot) = AnchorLotStartMessage::from_raw(body) {
+                info!(
+                    "Anchor lottery started: {} x{}",
+                    lot.award_name, lot.award_num
+                );
+                event_tx.send(Event::AnchorLotStart(lot));
+            }
+        }
+        "ANCHOR_LOT_AWARD" => {
+            if let Some(award) = AnchorLotAwardMessage::from_raw(body) {
+                event_tx.send(Event::AnchorLotAward(award));
+            }
+        }
+        "SUPER_CHAT_MESSAGE_DELETE" => {
+            if let Some(deleted) = SuperChatDeleteMessage::from_raw(body) {
+                info!("Superchats deleted: {:?}", deleted.ids);
+                event_tx.send(Event::SuperChatDeleted { ids: deleted.ids });
+            }
+        }
+        "ROOM_SILENT_ON" | "ROOM_SILENT_OFF" => {
+            if let Some(silent) = RoomSilentMessage::from_raw(body, base_cmd == "ROOM_SILENT_ON") {
+                event_tx.send(Event::RoomSilent(silent));
+            }
+        }
+        "ROOM_BLOCK_MSG" => {
+            if let Some(block) = RoomBlockMessage::from_raw(body) {
+                info!("User blocked: {} ({})", block.uname, block.uid);
+                event_tx.send(Event::UserBlocked(block));
             }
         }
-        _ => {}
+        _ => return false,
     }
+    true
 }